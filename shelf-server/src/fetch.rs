@@ -0,0 +1,349 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Fetching pages from external sites. Code that scrapes sites takes a
+//! `Fetcher` so that it can be tested offline against saved pages.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+#[derive(Debug)]
+pub struct FetchError {
+    pub url: String,
+    pub error: String,
+    /// Whether trying again might help: the network failed, the server
+    /// had an error, or it asked us to slow down.
+    pub transient: bool,
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Could not fetch {}: {}", self.url, self.error)
+    }
+}
+
+/// A downloaded file, such as a cover image.
+#[derive(Debug)]
+pub struct Download {
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+}
+
+#[async_trait::async_trait]
+pub trait Fetcher: Send + Sync {
+    /// Fetch a page as text.
+    async fn fetch(&self, url: &str) -> Result<String, FetchError>;
+
+    /// Fetch a file as bytes. Some sites refuse to serve images without
+    /// a referrer.
+    async fn download(&self, url: &str, referrer: Option<&str>) -> Result<Download, FetchError>;
+}
+
+/// How long to wait to connect to a site.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for a whole request, so a stalled site can't hold
+/// up a job forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Fetch pages over HTTP, like /proxy.
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new() -> HttpFetcher {
+        HttpFetcher {
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Could not create HTTP client"),
+        }
+    }
+}
+
+fn http_error(url: &str, err: reqwest::Error) -> FetchError {
+    let transient = match err.status() {
+        Some(status) => {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }
+        None => !err.is_builder(),
+    };
+    FetchError {
+        url: url.to_owned(),
+        error: format!("{}", err),
+        transient,
+    }
+}
+
+#[async_trait::async_trait]
+impl Fetcher for HttpFetcher {
+    async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        log::info!(target: crate::LOG_NAME, "Fetching {}", url);
+        let error = |err| http_error(url, err);
+        self.client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(error)?
+            .text()
+            .await
+            .map_err(error)
+    }
+
+    async fn download(&self, url: &str, referrer: Option<&str>) -> Result<Download, FetchError> {
+        log::info!(target: crate::LOG_NAME, "Downloading {}", url);
+        let error = |err| http_error(url, err);
+        let mut request = self.client.get(url);
+        if let Some(referrer) = referrer {
+            request = request.header(reqwest::header::REFERER, referrer);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(error)?;
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_owned();
+        let bytes = response.bytes().await.map_err(error)?;
+        Ok(Download {
+            bytes: bytes.to_vec(),
+            mime_type,
+        })
+    }
+}
+
+/// Wrap a fetcher to space out requests to the same host and to retry
+/// failed requests, for jobs that fetch many pages in a row.
+pub struct Throttled {
+    inner: Arc<dyn Fetcher>,
+    /// The minimum time between requests to a host.
+    interval: Duration,
+    /// How many times to retry a failed request.
+    retries: u32,
+    /// When the next request to each host may be made.
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl Throttled {
+    pub fn new(inner: Arc<dyn Fetcher>, interval: Duration, retries: u32) -> Throttled {
+        Throttled {
+            inner,
+            interval,
+            retries,
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a request to the URL's host is allowed.
+    async fn wait(&self, url: &str) {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_owned()))
+            .unwrap_or_default();
+        let now = Instant::now();
+        let at = {
+            let mut next = self.next.lock().await;
+            let at = next
+                .get(&host)
+                .cloned()
+                .filter(|at| *at > now)
+                .unwrap_or(now);
+            next.insert(host, at + self.interval);
+            at
+        };
+        tokio::time::delay_until(at).await;
+    }
+
+    /// Run a request, waiting longer after each transient failure.
+    async fn retry<T, F, Fut>(&self, url: &str, request: F) -> Result<T, FetchError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T, FetchError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.wait(url).await;
+            match request().await {
+                Ok(result) => return Ok(result),
+                Err(err) if err.transient && attempt < self.retries => {
+                    attempt += 1;
+                    log::warn!(
+                        target: crate::LOG_NAME,
+                        "{} (retry {} of {})",
+                        err,
+                        attempt,
+                        self.retries
+                    );
+                    tokio::time::delay_for(self.interval * attempt).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Fetcher for Throttled {
+    async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        self.retry(url, || self.inner.fetch(url)).await
+    }
+
+    async fn download(&self, url: &str, referrer: Option<&str>) -> Result<Download, FetchError> {
+        self.retry(url, || self.inner.download(url, referrer)).await
+    }
+}
+
+/// Serve saved pages instead of fetching them, for tests.
+#[cfg(test)]
+pub struct FixtureFetcher {
+    /// (URL substring, fixture file name) pairs, checked in order.
+    fixtures: Vec<(String, String)>,
+}
+
+#[cfg(test)]
+impl FixtureFetcher {
+    /// The directory fixtures are read from. These are shared with the
+    /// tests of the JavaScript importers.
+    pub const DIRECTORY: &'static str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/static/v2/src/import");
+
+    pub fn new() -> FixtureFetcher {
+        FixtureFetcher {
+            fixtures: Vec::new(),
+        }
+    }
+
+    /// Serve a fixture for URLs containing the given pattern.
+    pub fn with(mut self, pattern: &str, fixture: &str) -> FixtureFetcher {
+        self.fixtures.push((pattern.to_owned(), fixture.to_owned()));
+        self
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl Fetcher for FixtureFetcher {
+    async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        let bytes = self.download(url, None).await?.bytes;
+        String::from_utf8(bytes).map_err(|err| FetchError {
+            url: url.to_owned(),
+            error: err.to_string(),
+            transient: false,
+        })
+    }
+
+    async fn download(&self, url: &str, _referrer: Option<&str>) -> Result<Download, FetchError> {
+        let error = |error: String| FetchError {
+            url: url.to_owned(),
+            error,
+            transient: false,
+        };
+        let (_, fixture) = self
+            .fixtures
+            .iter()
+            .find(|(pattern, _)| url.contains(pattern.as_str()))
+            .ok_or_else(|| error("no fixture for URL".to_owned()))?;
+        let path = std::path::Path::new(Self::DIRECTORY).join(fixture);
+        let bytes = std::fs::read(&path).map_err(|err| error(format!("{}: {}", fixture, err)))?;
+        let mime_type = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => "text/html",
+            Some("json") => "application/json",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("png") => "image/png",
+            _ => "application/octet-stream",
+        };
+        Ok(Download {
+            bytes,
+            mime_type: mime_type.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Download, FetchError, Fetcher, FixtureFetcher, Throttled};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fails the first few requests.
+    struct Flaky {
+        failures: usize,
+        transient: bool,
+        requests: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Fetcher for Flaky {
+        async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+            if self.requests.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(FetchError {
+                    url: url.to_owned(),
+                    error: "503".to_owned(),
+                    transient: self.transient,
+                })
+            } else {
+                Ok("ok".to_owned())
+            }
+        }
+
+        async fn download(
+            &self,
+            url: &str,
+            referrer: Option<&str>,
+        ) -> Result<Download, FetchError> {
+            FixtureFetcher::new().download(url, referrer).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let flaky = Arc::new(Flaky {
+            failures: 2,
+            transient: true,
+            requests: AtomicUsize::new(0),
+        });
+        let fetcher = Throttled::new(flaky.clone(), Duration::from_millis(0), 2);
+        assert_eq!("ok", fetcher.fetch("https://example.com/").await.unwrap());
+        assert_eq!(3, flaky.requests.load(Ordering::SeqCst));
+
+        let flaky = Arc::new(Flaky {
+            failures: 2,
+            transient: true,
+            requests: AtomicUsize::new(0),
+        });
+        let fetcher = Throttled::new(flaky.clone(), Duration::from_millis(0), 1);
+        assert!(fetcher.fetch("https://example.com/").await.is_err());
+        assert_eq!(2, flaky.requests.load(Ordering::SeqCst));
+
+        // Errors like 404 are not retried
+        let flaky = Arc::new(Flaky {
+            failures: 1,
+            transient: false,
+            requests: AtomicUsize::new(0),
+        });
+        let fetcher = Throttled::new(flaky.clone(), Duration::from_millis(0), 2);
+        assert!(fetcher.fetch("https://example.com/").await.is_err());
+        assert_eq!(1, flaky.requests.load(Ordering::SeqCst));
+    }
+}
@@ -74,13 +74,27 @@ pub async fn item_get(
     }
 }
 
+// Helper to turn a shelf error into a 400
+fn reject_shelf_error(err: shelf::shelf::ShelfError) -> warp::Rejection {
+    use shelf::shelf::ShelfError;
+    let error = match err {
+        ShelfError::InvalidReference(r) => format!("Unrecognized reference to entity {}", r),
+        ShelfError::InvalidKey(r) => format!("Invalid key '{}'", r),
+        ShelfError::InvalidField(field, reason) => {
+            format!("Invalid field '{}': {}", field, reason)
+        }
+        ShelfError::DuplicateExternalId(id) => {
+            format!("External ID {} is already used by another item", id)
+        }
+    };
+    warp::reject::custom(model::BadRequest { error })
+}
+
 pub async fn item_post(
     key: String,
     item: shelf::item::Item,
     shelf: model::AppStateRef,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    use shelf::shelf::ShelfError;
-
     let decoded_key = decode_key(&key)?;
     log::info!(target: crate::LOG_NAME, "POST /item KEY: {}", item.key);
 
@@ -95,14 +109,7 @@ pub async fn item_post(
     }
 
     let mut state = shelf.lock().await;
-    state.shelf.replace_item(item).map_err(|e| match e {
-        ShelfError::InvalidReference(r) => warp::reject::custom(model::BadRequest {
-            error: format!("Unrecognized reference to entity {}", r),
-        }),
-        ShelfError::InvalidKey(r) => warp::reject::custom(model::BadRequest {
-            error: format!("Invalid key '{}'", r),
-        }),
-    })?;
+    state.shelf.replace_item(item).map_err(reject_shelf_error)?;
 
     state.save()?;
 
@@ -116,6 +123,75 @@ pub async fn item_post(
     ))
 }
 
+pub async fn item_delete(
+    key: String,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let decoded_key = decode_key(&key)?;
+    log::info!(target: crate::LOG_NAME, "DELETE /item KEY: {}", decoded_key);
+    let mut state = shelf.lock().await;
+    state
+        .shelf
+        .remove_item(&decoded_key)
+        .map_err(|_| warp::reject::not_found())?;
+    state.save()?;
+    Ok(warp::http::StatusCode::NO_CONTENT)
+}
+
+pub async fn item_rename(
+    key: String,
+    request: model::RenameRequest,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let decoded_key = decode_key(&key)?;
+    log::info!(
+        target: crate::LOG_NAME,
+        "Renaming item {} to {}",
+        decoded_key,
+        request.key
+    );
+    let mut state = shelf.lock().await;
+    state
+        .shelf
+        .rename_item(&decoded_key, &request.key)
+        .map_err(reject_shelf_error)?;
+    state.save()?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&model::CreateResponse { key: request.key }),
+        warp::http::StatusCode::ACCEPTED,
+    ))
+}
+
+pub async fn lookup(
+    params: model::LookupParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (provider, id) = shelf::external::Provider::parse_url(&params.url).ok_or_else(|| {
+        warp::reject::custom(model::BadRequest {
+            error: format!("Unrecognized URL: {}", params.url),
+        })
+    })?;
+    let shelf = &shelf.lock().await.shelf;
+    if let Some(item) = shelf.find_item_by_external_id(provider, &id) {
+        log::info!(
+            target: crate::LOG_NAME,
+            "GET /lookup {:?}:{} (found {})",
+            provider,
+            id,
+            item.key
+        );
+        Ok(warp::reply::json(item))
+    } else {
+        log::info!(
+            target: crate::LOG_NAME,
+            "GET /lookup {:?}:{} (not found)",
+            provider,
+            id
+        );
+        Err(warp::reject::not_found())
+    }
+}
+
 pub async fn person_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
     let shelf = &shelf.lock().await.shelf;
     let items: Vec<shelf::common::Person> = shelf.query_people().map(|p| p.clone()).collect();
@@ -195,6 +271,515 @@ pub async fn tag_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Inf
     Ok(warp::reply::json(&tags))
 }
 
+pub async fn stats(
+    params: model::StatsParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    Ok(warp::reply::json(&shelf.stats(params.year)))
+}
+
+pub async fn stats_time(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    Ok(warp::reply::json(&shelf.time_stats()))
+}
+
+pub async fn suggestions(
+    params: model::SuggestParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let defaults = shelf::suggest::Weights::default();
+    let options = shelf::suggest::SuggestOptions {
+        weights: shelf::suggest::Weights {
+            priority: params.priority.unwrap_or(defaults.priority),
+            series: params.series.unwrap_or(defaults.series),
+            tag_affinity: params.tag_affinity.unwrap_or(defaults.tag_affinity),
+            person_affinity: params.person_affinity.unwrap_or(defaults.person_affinity),
+            length: params.length.unwrap_or(defaults.length),
+            age: params.age.unwrap_or(defaults.age),
+        },
+        filter: shelf::filter::ItemFilter {
+            kinds: params.kind.into_iter().collect(),
+            tags: params.tag.into_iter().collect(),
+            ..Default::default()
+        },
+        today: Some(shelf.settings().today()),
+    };
+    let mut suggestions = shelf.suggest(&options);
+    suggestions.truncate(params.limit.unwrap_or(20));
+    Ok(warp::reply::json(&suggestions))
+}
+
+// The default and maximum page sizes for /activity.
+const ACTIVITY_PAGE_SIZE: usize = 50;
+const ACTIVITY_MAX_PAGE_SIZE: usize = 500;
+
+pub async fn activity(
+    params: model::ActivityParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let events = shelf.activity();
+    let limit = params
+        .limit
+        .unwrap_or(ACTIVITY_PAGE_SIZE)
+        .min(ACTIVITY_MAX_PAGE_SIZE);
+    let start = params.offset.min(events.len());
+    let end = (start + limit).min(events.len());
+    Ok(warp::reply::json(&model::ActivityPage {
+        total: events.len(),
+        offset: start,
+        events: &events[start..end],
+    }))
+}
+
+pub async fn activity_atom(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let events = shelf.activity();
+    let events = &events[..events.len().min(ACTIVITY_PAGE_SIZE)];
+    Ok(warp::reply::with_header(
+        shelf::activity::atom_feed("Shelf Activity", "urn:shelf:activity", events),
+        "Content-Type",
+        "application/atom+xml",
+    ))
+}
+
+pub async fn airing(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let now = chrono::Local::now().into();
+    Ok(warp::reply::json(&shelf.behind_schedule(now)))
+}
+
+// The default and maximum number of weeks in the airing calendar.
+const AIRING_CALENDAR_WEEKS: u32 = 2;
+const AIRING_CALENDAR_MAX_WEEKS: u32 = 26;
+
+pub async fn airing_ical(
+    params: model::AiringCalendarParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let now: chrono::DateTime<chrono::FixedOffset> = chrono::Local::now().into();
+    let weeks = params
+        .weeks
+        .unwrap_or(AIRING_CALENDAR_WEEKS)
+        .min(AIRING_CALENDAR_MAX_WEEKS);
+    let until = now + chrono::Duration::weeks(i64::from(weeks));
+    let episodes = shelf.upcoming_episodes(now, until);
+    Ok(warp::reply::with_header(
+        shelf::airing::ical_feed("Shelf Airing", &episodes, now),
+        "Content-Type",
+        "text/calendar; charset=utf-8",
+    ))
+}
+
+/// Check items for new chapters, adding them as entries if `append`.
+async fn check_releases(
+    key: Option<String>,
+    append: bool,
+    shelf: model::AppStateRef,
+) -> Result<Vec<crate::release::ReleaseReport>, warp::Rejection> {
+    // Don't hold the lock while fetching
+    let (items, fetcher) = {
+        let state = shelf.lock().await;
+        let items: Vec<shelf::item::Item> = match key {
+            Some(key) => {
+                let key = decode_key(&key)?;
+                vec![state
+                    .shelf
+                    .get_item(&key)
+                    .ok_or_else(warp::reject::not_found)?
+                    .clone()]
+            }
+            None => state
+                .shelf
+                .all_items()
+                .iter()
+                .filter(|item| crate::release::is_tracked(item))
+                .cloned()
+                .collect(),
+        };
+        (items, state.fetcher.clone())
+    };
+
+    let sources = crate::release::sources();
+    let mut reports = Vec::new();
+    for item in items.iter() {
+        if let Some(report) = crate::release::check(item, &sources, fetcher.as_ref()).await {
+            reports.push(report);
+        }
+    }
+
+    if append {
+        let mut state = shelf.lock().await;
+        for report in reports.iter_mut().filter(|report| !report.new.is_empty()) {
+            let mut item = match state.shelf.get_item(&report.item) {
+                Some(item) => item.clone(),
+                // Removed while we were fetching
+                None => continue,
+            };
+            if crate::release::append(&mut item, &report.new) > 0 {
+                state.shelf.replace_item(item).map_err(reject_shelf_error)?;
+                report.appended = true;
+            }
+        }
+        state.save()?;
+    }
+    Ok(reports)
+}
+
+pub async fn release_check(
+    params: model::ReleaseParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let reports = check_releases(params.key, false, shelf).await?;
+    Ok(warp::reply::json(&reports))
+}
+
+pub async fn release_append(
+    params: model::ReleaseParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let reports = check_releases(params.key, true, shelf).await?;
+    Ok(warp::reply::json(&reports))
+}
+
+/// Build a draft item from an external page. The item is not added to
+/// the shelf; people that already exist are left out of the draft.
+pub async fn import(
+    params: model::ImportParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Don't hold the lock while fetching
+    let fetcher = shelf.lock().await.fetcher.clone();
+    let mut draft = crate::importer::import(&params.url, fetcher.as_ref())
+        .await
+        .map_err(|err| {
+            log::warn!(target: crate::LOG_NAME, "Could not import {}: {}", params.url, err);
+            match err {
+                crate::importer::ImportError::Fetch(_) => {
+                    warp::reject::custom(model::ReqwestError {
+                        error: err.to_string(),
+                    })
+                }
+                _ => warp::reject::custom(model::BadRequest {
+                    error: err.to_string(),
+                }),
+            }
+        })?;
+    let state = shelf.lock().await;
+    draft
+        .people
+        .retain(|person| !state.shelf.query_people().any(|p| p.key == person.key));
+    Ok(warp::reply::json(&draft))
+}
+
+/// Import a list exported from another site. With `dry_run`, returns
+/// the plan instead of applying it.
+pub async fn import_file(
+    format: String,
+    params: model::ImportFileParams,
+    body: bytes::Bytes,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let bad_request = |err: shelf::import::ImportError| {
+        warp::reject::custom(model::BadRequest {
+            error: err.to_string(),
+        })
+    };
+    let format: shelf::import::Format = format.parse().map_err(bad_request)?;
+    let mut state = shelf.lock().await;
+    let plan = format.plan(&state.shelf, &body).map_err(bad_request)?;
+    if params.dry_run {
+        return Ok(warp::reply::json(&plan));
+    }
+    let report = plan.apply(&mut state.shelf);
+    log::info!(
+        target: crate::LOG_NAME,
+        "Imported {} new and {} updated items ({} errors)",
+        report.inserted,
+        report.updated,
+        report.errors.len()
+    );
+    state.save()?;
+    Ok(warp::reply::json(&report))
+}
+
+/// Export the items matching a filter as a CSV or JSON Lines table.
+pub async fn export(
+    params: model::ExportParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use shelf::export::table::{self, Column};
+
+    let bad_request = |error: String| warp::reject::custom(model::BadRequest { error });
+    let columns = match &params.columns {
+        Some(columns) => Column::parse_list(columns).map_err(bad_request)?,
+        None => Column::defaults(),
+    };
+    let filter = shelf::filter::ItemFilter {
+        kinds: params.kind.into_iter().collect(),
+        statuses: params.status.into_iter().collect(),
+        tags: params.tag.into_iter().collect(),
+        ..Default::default()
+    };
+    let shelf = &shelf.lock().await.shelf;
+    let (out, content_type) = match params.format.as_str() {
+        "csv" => {
+            let (out, _) = table::csv(shelf, &columns, &filter).map_err(|err| {
+                warp::reject::custom(model::InternalServerError::new(err.to_string()))
+            })?;
+            (out, "text/csv; charset=utf-8")
+        }
+        "jsonl" => (
+            table::json_lines(shelf, &columns, &filter).0,
+            "application/x-ndjson; charset=utf-8",
+        ),
+        format => return Err(bad_request(format!("Unknown export format {:?}", format))),
+    };
+    Ok(warp::reply::with_header(out, "Content-Type", content_type))
+}
+
+pub async fn job_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let jobs = shelf.lock().await.jobs.list();
+    Ok(warp::reply::json(&jobs))
+}
+
+pub async fn job_submit(
+    request: crate::jobs::JobRequest,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let crate::jobs::JobRequest::Import { urls } = &request {
+        if let Some(url) = urls.iter().find(|url| !crate::importer::is_supported(url)) {
+            return Err(warp::reject::custom(model::BadRequest {
+                error: format!("Cannot import {}", url),
+            }));
+        }
+    }
+    let job = shelf.lock().await.jobs.submit(request);
+    log::info!(target: crate::LOG_NAME, "Submitted job {}", job.id);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&job),
+        warp::http::StatusCode::ACCEPTED,
+    ))
+}
+
+pub async fn job_get(
+    id: u64,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match shelf.lock().await.jobs.get(id) {
+        Some(job) => Ok(warp::reply::json(&job)),
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Stream the state of a job as server-sent events until it finishes.
+pub async fn job_events(
+    id: u64,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use futures::StreamExt;
+
+    let jobs = shelf.lock().await.jobs.clone();
+    let events = jobs
+        .watch(id)
+        .ok_or_else(warp::reject::not_found)?
+        .map(|job| Ok::<_, Infallible>((warp::sse::event("progress"), warp::sse::json(job))));
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+pub async fn list_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let mut lists: Vec<&shelf::list::List> = shelf.query_lists().collect();
+    lists.sort_by_key(|list| &list.key);
+    Ok(warp::reply::json(&lists))
+}
+
+pub async fn list_get(
+    key: String,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    let shelf = &shelf.lock().await.shelf;
+    let list = shelf.get_list(&key).ok_or_else(warp::reject::not_found)?;
+    Ok(warp::reply::json(list))
+}
+
+pub async fn list_create(
+    list: shelf::list::List,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut state = shelf.lock().await;
+    let key = list.key.clone();
+    let created = state.shelf.insert_list(list).map_err(reject_shelf_error)?;
+    state.save()?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&model::CreateResponse { key }),
+        if created {
+            warp::http::StatusCode::CREATED
+        } else {
+            warp::http::StatusCode::ACCEPTED
+        },
+    ))
+}
+
+pub async fn list_delete(
+    key: String,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    let mut state = shelf.lock().await;
+    state
+        .shelf
+        .remove_list(&key)
+        .ok_or_else(warp::reject::not_found)?;
+    state.save()?;
+    Ok(warp::http::StatusCode::NO_CONTENT)
+}
+
+// Apply an edit to a copy of a list, then store it.
+async fn edit_list<F>(
+    key: &str,
+    shelf: model::AppStateRef,
+    edit: F,
+) -> Result<warp::reply::Json, warp::Rejection>
+where
+    F: FnOnce(&mut shelf::list::List) -> Result<(), warp::Rejection>,
+{
+    let mut state = shelf.lock().await;
+    let mut list = state
+        .shelf
+        .get_list(key)
+        .cloned()
+        .ok_or_else(warp::reject::not_found)?;
+    edit(&mut list)?;
+    state
+        .shelf
+        .insert_list(list.clone())
+        .map_err(reject_shelf_error)?;
+    state.save()?;
+    Ok(warp::reply::json(&list))
+}
+
+pub async fn list_insert_item(
+    key: String,
+    request: model::ListInsertRequest,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    edit_list(&key, shelf, |list| {
+        list.insert(
+            request.index,
+            shelf::list::ListEntry {
+                item: request.item,
+                note: request.note,
+            },
+        );
+        Ok(())
+    })
+    .await
+}
+
+pub async fn list_move_item(
+    key: String,
+    request: model::ListMoveRequest,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    edit_list(&key, shelf, |list| {
+        if list.move_item(&request.item, request.index) {
+            Ok(())
+        } else {
+            Err(warp::reject::custom(model::BadRequest {
+                error: format!("Item '{}' is not in the list", request.item),
+            }))
+        }
+    })
+    .await
+}
+
+pub async fn list_remove_item(
+    key: String,
+    item: String,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    let item = decode_key(&item)?;
+    edit_list(&key, shelf, |list| {
+        list.remove(&item)
+            .map(|_| ())
+            .ok_or_else(warp::reject::not_found)
+    })
+    .await
+}
+
+pub async fn goal_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    let mut goals: Vec<&shelf::goal::Goal> = shelf.query_goals().collect();
+    goals.sort_by_key(|goal| (goal.start, &goal.key));
+    Ok(warp::reply::json(&goals))
+}
+
+pub async fn goal_create(
+    goal: shelf::goal::Goal,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut state = shelf.lock().await;
+    let key = goal.key.clone();
+    let created = state.shelf.insert_goal(goal).map_err(|err| {
+        warp::reject::custom(model::BadRequest {
+            error: format!("Invalid goal: {}", err),
+        })
+    })?;
+    state.save()?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&model::CreateResponse { key }),
+        if created {
+            warp::http::StatusCode::CREATED
+        } else {
+            warp::http::StatusCode::ACCEPTED
+        },
+    ))
+}
+
+pub async fn goal_progress(
+    key: String,
+    params: model::GoalProgressParams,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let key = decode_key(&key)?;
+    let shelf = &shelf.lock().await.shelf;
+    let goal = shelf.get_goal(&key).ok_or_else(warp::reject::not_found)?;
+    let today = params.date.unwrap_or_else(|| shelf.settings().today());
+    Ok(warp::reply::json(&goal.progress(
+        shelf.all_items(),
+        today,
+        shelf.settings().time_zone,
+    )))
+}
+
+pub async fn settings_get(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    Ok(warp::reply::json(shelf.settings()))
+}
+
+pub async fn settings_put(
+    settings: shelf::settings::Settings,
+    shelf: model::AppStateRef,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut state = shelf.lock().await;
+    state.shelf.set_settings(settings);
+    state.save()?;
+    Ok(warp::reply::json(state.shelf.settings()))
+}
+
+pub async fn schema_fields(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
+    let shelf = &shelf.lock().await.shelf;
+    Ok(warp::reply::json(&shelf.schema().fields))
+}
+
 pub async fn blob_list(shelf: model::AppStateRef) -> Result<impl warp::Reply, Infallible> {
     let shelf = &shelf.lock().await.shelf;
     let mut blobs = HashMap::new();
@@ -0,0 +1,98 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind};
+use shelf::key::title_to_key;
+
+use super::{meta, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+
+pub struct Cubari;
+
+#[async_trait::async_trait]
+impl Importer for Cubari {
+    fn name(&self) -> &'static str {
+        "Cubari"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        url.host_str() == Some("cubari.moe")
+    }
+
+    /// Only oneshots (`/read/...`) are supported.
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        if !url.path().starts_with("/read/") {
+            return Err(ImportError::Unsupported(url.to_string()));
+        }
+        let body = fetcher.fetch(url.as_str()).await?;
+        let document = scraper::Html::parse_document(&body);
+
+        let title = meta(&document, "og:title")?;
+        let title = title.split('|').next().unwrap_or_default().trim();
+        let mut draft = Draft::new(
+            format!("manga-cubari-{}", title_to_key(title)),
+            Kind::Manga,
+            Alternatives::new("English", title),
+        )
+        .with_source(url.as_str())
+        .with_cover(meta(&document, "og:image")?, None)
+        .oneshot();
+        draft.item.tags = vec!["Oneshot".to_owned()];
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cubari;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::Importer;
+    use shelf::common::{DateBool, Kind};
+    use shelf::external::Provider;
+
+    #[tokio::test]
+    async fn test_oneshot() {
+        let fetcher = FixtureFetcher::new().with("cubari.moe", "test.at-the-end-of-4-minutes.html");
+        let url = "https://cubari.moe/read/imgur/oeJ1wKg/1/47/"
+            .parse()
+            .unwrap();
+        let draft = Cubari.import(&url, &fetcher).await.unwrap();
+        let item = &draft.item;
+        assert_eq!(
+            "manga-cubari-at-the-end-of-4-minutes-translated-by-reica",
+            item.key
+        );
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!(
+            Some(&"At the End of 4 Minutes (Translated by reica)".to_owned()),
+            item.name.get_default()
+        );
+        assert_eq!(DateBool::Timestamp(item.added), item.started);
+        assert_eq!(DateBool::Timestamp(item.added), item.completed);
+        assert_eq!(1, item.entries.len());
+        assert_eq!(Some(1), item.entries[0].number);
+        assert_eq!(None, item.entries[0].volume);
+        assert_eq!(item.completed, item.entries[0].completed);
+        assert_eq!(
+            Some("https://cubari.moe/read/imgur/oeJ1wKg/1/47/"),
+            item.extra["external_url"].as_str()
+        );
+        assert_eq!(
+            Some(&"imgur/oeJ1wKg".to_owned()),
+            item.external_ids.get(&Provider::Cubari)
+        );
+        assert_eq!("https://i.imgur.com/VNatRvT.jpg", draft.cover.unwrap().url);
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind};
+use shelf::key::title_to_key;
+
+use super::{parse_error, select, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+use crate::release::{self, text, Source};
+
+const BASE_URL: &str = "https://dynasty-scans.com";
+
+pub struct DynastyScans;
+
+/// Start a draft from the title and author in a page header.
+fn draft(document: &scraper::Html, header: &str, url: &Url) -> Result<Draft, ImportError> {
+    let title = text(select(document, &format!("{} b", header))?);
+    let author = text(select(document, &format!("{} > a", header))?);
+    let mut draft = Draft::new(
+        format!("manga-{}-{}", title_to_key(&author), title_to_key(&title)),
+        Kind::Manga,
+        Alternatives::new("English", title),
+    )
+    .with_source(url.as_str())
+    .with_author(&author);
+    draft.item.tags = vec!["Yuri".to_owned()];
+    Ok(draft)
+}
+
+fn image(document: &scraper::Html, selector: &str) -> Result<String, ImportError> {
+    select(document, selector)?
+        .value()
+        .attr("src")
+        .map(|src| format!("{}{}", BASE_URL, src))
+        .ok_or_else(|| parse_error(format!("{} has no src", selector)))
+}
+
+#[async_trait::async_trait]
+impl Importer for DynastyScans {
+    fn name(&self) -> &'static str {
+        "Dynasty Scans"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        url.host_str() == Some("dynasty-scans.com")
+    }
+
+    /// Series are imported with their chapters; standalone chapters are
+    /// imported as oneshots.
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let mut url = url.clone();
+        url.set_fragment(None);
+        let body = fetcher.fetch(url.as_str()).await?;
+        let document = scraper::Html::parse_document(&body);
+
+        if url.path().starts_with("/series/") {
+            let chapters = release::DynastyScans.parse(&body).map_err(parse_error)?;
+            let cover = image(&document, ".cover img")?;
+            let mut draft = draft(&document, "h2.tag-title", &url)?
+                .with_cover(cover, None)
+                .started();
+            release::append(&mut draft.item, &chapters);
+            Ok(draft)
+        } else if url.path().starts_with("/chapters/") {
+            let cover = image(&document, "#image img")?;
+            let mut draft = draft(&document, "#chapter-title", &url)?
+                .with_cover(cover, None)
+                .oneshot();
+            draft.item.tags.push("Oneshot".to_owned());
+            draft.item.tags.sort();
+            Ok(draft)
+        } else {
+            Err(ImportError::Unsupported(url.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DynastyScans;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::{Draft, Importer};
+    use shelf::common::{Alternatives, DateBool, Kind, Status};
+    use shelf::item::PublicationStatus;
+
+    async fn import(url: &str, fixture: &str) -> Draft {
+        let fetcher = FixtureFetcher::new().with("dynasty-scans.com", fixture);
+        DynastyScans
+            .import(&url.parse().unwrap(), &fetcher)
+            .await
+            .unwrap()
+    }
+
+    fn english(name: &str) -> Option<Alternatives<String>> {
+        Some(Alternatives::new("English", name))
+    }
+
+    #[tokio::test]
+    async fn test_oneshot() {
+        let draft = import(
+            "https://dynasty-scans.com/chapters/beginning_their_new_life_together#1",
+            "test.beginning-their-new-life-together.html",
+        )
+        .await;
+        let item = &draft.item;
+        assert_eq!(
+            "manga-kodama-naoko-beginning-their-new-life-together",
+            item.key
+        );
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!(
+            Some(&"Beginning Their New Life Together".to_owned()),
+            item.name.get_default()
+        );
+        assert_eq!(DateBool::Timestamp(item.added), item.started);
+        assert_eq!(DateBool::Timestamp(item.added), item.completed);
+        assert_eq!(Status::Completed, item.status);
+        assert_eq!(vec!["Oneshot", "Yuri"], item.tags);
+        assert_eq!(1, item.entries.len());
+        assert_eq!(english("Oneshot"), item.entries[0].name);
+        assert_eq!(
+            Some("https://dynasty-scans.com/chapters/beginning_their_new_life_together"),
+            item.extra["external_url"].as_str()
+        );
+        assert_eq!(
+            vec![(
+                shelf::common::Role::Author,
+                "person-kodama-naoko".to_owned()
+            )],
+            item.people
+        );
+        assert_eq!("person-kodama-naoko", draft.people[0].key);
+        assert_eq!(
+            "https://dynasty-scans.com/system/releases/000/031/588/00001.jpeg",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_series() {
+        let url = "https://dynasty-scans.com/series/march_comes_in_like_a_lion";
+        let draft = import(url, "test.march-comes-in-like-a-lion.html").await;
+        let item = &draft.item;
+        assert_eq!("manga-umino-chika-march-comes-in-like-a-lion", item.key);
+        assert_eq!(
+            Some(&"March Comes in Like a Lion".to_owned()),
+            item.name.get_default()
+        );
+        assert_eq!(DateBool::Timestamp(item.added), item.started);
+        assert_eq!(DateBool::False, item.completed);
+        assert_eq!(PublicationStatus::Publishing, item.publication_status);
+        assert_eq!(33, item.entries.len());
+        assert_eq!(Some(1), item.entries[0].number);
+        assert_eq!(Some(1), item.entries[0].volume);
+        assert_eq!(english("Chapter 1: Kiriyama Rei"), item.entries[0].name);
+        assert_eq!(DateBool::False, item.entries[0].completed);
+        assert_eq!(Some(23), item.entries[22].number);
+        assert_eq!(Some(2), item.entries[22].volume);
+        assert_eq!(english("Vol 2 Omake"), item.entries[22].name);
+        assert_eq!(Some(url), item.extra["external_url"].as_str());
+        assert_eq!(
+            "https://dynasty-scans.com/system/tag_contents_covers/000/001/434/medium/3gatsu01.jpg?1359562488",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_series_without_volumes() {
+        let draft = import(
+            "https://dynasty-scans.com/series/can_a_guy_like_me_be_a_maid",
+            "test.can-a-guy-like-me-be-a-maid.html",
+        )
+        .await;
+        let item = &draft.item;
+        assert_eq!("manga-koshou-can-a-guy-like-me-be-a-maid", item.key);
+        assert_eq!(
+            Some(&"Can a Guy Like Me Be a Maid?".to_owned()),
+            item.name.get_default()
+        );
+        assert_eq!(15, item.entries.len());
+        assert_eq!(Some(1), item.entries[0].number);
+        assert_eq!(None, item.entries[0].volume);
+        assert_eq!(english("Chapter 0"), item.entries[0].name);
+        assert_eq!(
+            "https://dynasty-scans.com/system/tag_contents_covers/000/015/043/medium/[Hachimitsu_Scans]_Bokunare_c01_00.jpg?1604022009",
+            draft.cover.unwrap().url
+        );
+    }
+}
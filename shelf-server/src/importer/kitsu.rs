@@ -0,0 +1,344 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, DateBool, Kind, Status};
+use shelf::item::{Entry, PublicationStatus};
+use shelf::key::title_to_key;
+
+use super::{parse_error, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+
+const BASE_URL: &str =
+    "https://kitsu.io/api/edge/anime?fields[categories]=slug%2Ctitle&include=episodes&filter[slug]=";
+
+pub struct Kitsu;
+
+#[derive(serde_derive::Deserialize)]
+struct Response {
+    #[serde(default)]
+    data: Vec<Resource>,
+    #[serde(default)]
+    included: Vec<Resource>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct Resource {
+    #[serde(rename = "type")]
+    typ: String,
+    attributes: Attributes,
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attributes {
+    #[serde(default)]
+    titles: serde_json::Map<String, serde_json::Value>,
+    canonical_title: Option<String>,
+    subtype: Option<String>,
+    synopsis: Option<String>,
+    poster_image: Option<PosterImage>,
+    number: Option<u32>,
+    season_number: Option<u32>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct PosterImage {
+    original: Option<String>,
+}
+
+/// The anime slug of a Kitsu URL.
+fn extract_id(url: &Url) -> Option<&str> {
+    match url.host_str() {
+        Some("kitsu.io") | Some("kitsu.app") => {}
+        _ => return None,
+    }
+    url.path()
+        .strip_prefix("/anime/")
+        .filter(|slug| !slug.is_empty())
+}
+
+fn lang_code_to_name(code: &str) -> String {
+    match code.to_lowercase().as_str() {
+        "en" | "en_us" => "English".to_owned(),
+        "en_jp" => "Japanese (Romaji)".to_owned(),
+        "ja" | "ja_jp" => "Japanese".to_owned(),
+        _ => code.to_owned(),
+    }
+}
+
+/// Merge titles by language, making the canonical title the default.
+fn extract_titles(
+    titles: &serde_json::Map<String, serde_json::Value>,
+    canonical_title: Option<&str>,
+) -> Option<Alternatives<String>> {
+    // Several codes map to the same language; later titles win
+    let mut merged: Vec<(String, String)> = Vec::new();
+    for (code, title) in titles.iter() {
+        let title = match title.as_str() {
+            Some(title) => title.to_owned(),
+            None => continue,
+        };
+        let lang = lang_code_to_name(code);
+        match merged.iter_mut().find(|(existing, _)| *existing == lang) {
+            Some(existing) => existing.1 = title,
+            None => merged.push((lang, title)),
+        }
+    }
+    let default = merged
+        .iter()
+        .find(|(_, title)| Some(title.as_str()) == canonical_title)
+        .or_else(|| merged.first())?
+        .0
+        .clone();
+    Some(Alternatives {
+        default,
+        alternatives: merged.into_iter().collect(),
+    })
+}
+
+fn entry(episode: &Attributes) -> Entry {
+    let name =
+        extract_titles(&episode.titles, episode.canonical_title.as_deref()).unwrap_or_else(|| {
+            let number = episode.number.map(|n| n.to_string()).unwrap_or_default();
+            Alternatives::new(
+                "English",
+                match episode.season_number {
+                    Some(season) => format!("Season {} Episode {}", season, number),
+                    None => format!("Episode {}", number),
+                },
+            )
+        });
+    Entry {
+        name: Some(name),
+        number: episode.number,
+        volume: episode.season_number,
+        completed: DateBool::False,
+        aired: DateBool::False,
+        rating: None,
+        length: None,
+        note: None,
+        extra: Default::default(),
+    }
+}
+
+#[async_trait::async_trait]
+impl Importer for Kitsu {
+    fn name(&self) -> &'static str {
+        "Kitsu"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        extract_id(url).is_some()
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let id = extract_id(url).ok_or_else(|| ImportError::Unsupported(url.to_string()))?;
+        let body = fetcher.fetch(&format!("{}{}", BASE_URL, id)).await?;
+        let response: Response =
+            serde_json::from_str(&body).map_err(|err| parse_error(err.to_string()))?;
+        let anime = match response.data.as_slice() {
+            [] => return Err(parse_error(format!("Anime not found: {}", id))),
+            [anime] => anime,
+            _ => return Err(parse_error(format!("Multiple results found: {}", id))),
+        };
+        if anime.typ != "anime" {
+            return Err(parse_error(format!("Unknown type: {}", anime.typ)));
+        }
+        let attributes = &anime.attributes;
+        let kind = match attributes.subtype.as_deref() {
+            Some("TV") => Kind::TV,
+            Some("movie") => Kind::Film,
+            Some("special") | Some("OVA") => Kind::OVA,
+            Some("ONA") => Kind::ONA,
+            subtype => return Err(parse_error(format!("Unknown subtype: {:?}", subtype))),
+        };
+        let canonical_title = attributes
+            .canonical_title
+            .as_deref()
+            .ok_or_else(|| parse_error("No canonical title"))?;
+        let name = extract_titles(&attributes.titles, Some(canonical_title))
+            .unwrap_or_else(|| Alternatives::new("English", canonical_title));
+
+        let mut entries: Vec<Entry> = response
+            .included
+            .iter()
+            .filter(|resource| resource.typ == "episodes")
+            .map(|resource| entry(&resource.attributes))
+            .collect();
+        entries.sort_by_key(|entry| (entry.volume, entry.number));
+
+        let key = format!(
+            "{}-{}",
+            format!("{:?}", kind).to_lowercase(),
+            title_to_key(canonical_title)
+        );
+        let mut draft = Draft::new(key, kind, name).with_source(url.as_str());
+        if let Some(cover) = attributes
+            .poster_image
+            .as_ref()
+            .and_then(|image| image.original.clone())
+        {
+            draft = draft.with_cover(cover, None);
+        }
+        draft.item.entries = entries;
+        draft.item.status = Status::InProgress;
+        draft.item.publication_status = PublicationStatus::Complete;
+        draft.item.synopsis = attributes.synopsis.clone().unwrap_or_default();
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_id, Kitsu};
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::{Draft, Importer};
+    use shelf::common::Kind;
+
+    async fn import(url: &str, fixture: &str) -> Draft {
+        let fetcher = FixtureFetcher::new().with("kitsu.io", fixture);
+        let draft = Kitsu.import(&url.parse().unwrap(), &fetcher).await.unwrap();
+        assert_eq!(Some(url), draft.item.extra["external_url"].as_str());
+        draft
+    }
+
+    fn entry_name(draft: &Draft, i: usize) -> &str {
+        draft.item.entries[i]
+            .name
+            .as_ref()
+            .and_then(|name| name.get_default())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_extract_id() {
+        let id = |url: &str| extract_id(&url.parse().unwrap()).map(|id| id.to_owned());
+        assert_eq!(None, id("http://google.com"));
+        assert_eq!(None, id("https://kitsu.io/nime/jitsu-wa-watashi-wa"));
+        assert_eq!(
+            Some("jitsu-wa-watashi-wa".to_owned()),
+            id("https://kitsu.io/anime/jitsu-wa-watashi-wa")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tv() {
+        let draft = import(
+            "https://kitsu.io/anime/jitsu-wa-watashi-wa",
+            "test.jitsu-wa-watashi-wa.json",
+        )
+        .await;
+        let item = &draft.item;
+        assert_eq!("tv-jitsu-wa-watashi-wa", item.key);
+        assert_eq!(Kind::TV, item.kind);
+        assert_eq!("Japanese (Romaji)", item.name.default);
+        assert_eq!(
+            Some(&"Jitsu wa Watashi wa".to_owned()),
+            item.name.get_default()
+        );
+        // en and en_us are merged, keeping the later title
+        assert_eq!(
+            Some(&"Actually, I am...".to_owned()),
+            item.name.alternatives.get("English")
+        );
+        assert_eq!(13, item.entries.len());
+        assert_eq!("I'll Confess!", entry_name(&draft, 0));
+        assert_eq!(
+            "https://media.kitsu.io/anime/poster_images/10350/original.jpg?1597697462",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blank_episode_titles() {
+        let draft = import(
+            "https://kitsu.io/anime/jitsu-wa-watashi-wa",
+            "test.rikeikoi.json",
+        )
+        .await;
+        assert_eq!(
+            "tv-rikei-ga-koi-ni-ochita-no-de-shoumei-shitemita",
+            draft.item.key
+        );
+        assert_eq!("Japanese (Romaji)", draft.item.name.default);
+        assert_eq!(12, draft.item.entries.len());
+        assert_eq!("Season 1 Episode 1", entry_name(&draft, 0));
+        assert_eq!(
+            "https://media.kitsu.io/anime/poster_images/42297/original.jpg?1597691461",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_film() {
+        let draft = import(
+            "https://kitsu.io/anime/hanasaku-iroha-home-sweet-home",
+            "test.hanasaku-iroha-home-sweet-home.json",
+        )
+        .await;
+        assert_eq!("film-hanasaku-iroha-home-sweet-home", draft.item.key);
+        assert_eq!(Kind::Film, draft.item.kind);
+        assert_eq!(
+            Some(&"Hanasaku Iroha: Home Sweet Home".to_owned()),
+            draft.item.name.get_default()
+        );
+        assert_eq!(1, draft.item.entries.len());
+        assert_eq!("Episode 1", entry_name(&draft, 0));
+    }
+
+    #[tokio::test]
+    async fn test_special() {
+        let draft = import(
+            "https://kitsu.io/anime/kobayashi-san-chi-no-maid-dragon-ova",
+            "test.kobayashi-san-chi-no-maid-dragon-ova.json",
+        )
+        .await;
+        assert_eq!(
+            "ova-kobayashi-san-chi-no-maid-dragon-valentine-soshite-onsen-amari-kitai-shinaide-kudasai",
+            draft.item.key
+        );
+        assert_eq!(Kind::OVA, draft.item.kind);
+        assert_eq!(1, draft.item.entries.len());
+        assert_eq!("Season 1 Episode 1", entry_name(&draft, 0));
+        assert_eq!(
+            "https://media.kitsu.io/anime/poster_images/13861/original.jpg?1506380050",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ova() {
+        let draft = import(
+            "https://kitsu.io/anime/tokyo-marble-chocolate",
+            "test.tokyo-marble-chocolate.json",
+        )
+        .await;
+        assert_eq!("ova-tokyo-marble-chocolate", draft.item.key);
+        assert_eq!("English", draft.item.name.default);
+        assert_eq!(2, draft.item.entries.len());
+        assert_eq!("Episode 1", entry_name(&draft, 0));
+    }
+
+    #[tokio::test]
+    async fn test_ona() {
+        let draft = import("https://kitsu.io/anime/vlad-love", "test.vladlove.json").await;
+        assert_eq!("ona-vladlove", draft.item.key);
+        assert_eq!(Kind::ONA, draft.item.kind);
+        assert_eq!("Japanese (Romaji)", draft.item.name.default);
+        assert_eq!(Some(&"VladLove".to_owned()), draft.item.name.get_default());
+        assert_eq!(12, draft.item.entries.len());
+        assert_eq!("Season 1 Episode 1", entry_name(&draft, 0));
+    }
+}
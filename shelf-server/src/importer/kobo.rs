@@ -0,0 +1,126 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, DateBool, Kind, Status};
+use shelf::external::Provider;
+use shelf::item::Entry;
+use shelf::key::title_to_key;
+
+use super::{extra, parse_error, select, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+use crate::release::text;
+
+pub struct Kobo;
+
+/// Find the ISBN in the "eBook Details" list.
+fn isbn(metadata: &str) -> Option<String> {
+    let start = metadata.find("ISBN: ")? + "ISBN: ".len();
+    let isbn: String = metadata[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    if isbn.is_empty() {
+        None
+    } else {
+        Some(isbn)
+    }
+}
+
+#[async_trait::async_trait]
+impl Importer for Kobo {
+    fn name(&self) -> &'static str {
+        "Kobo"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(url.host_str(), Some("www.kobo.com") | Some("kobo.com"))
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let body = fetcher.fetch(url.as_str()).await?;
+        let document = scraper::Html::parse_document(&body);
+
+        let title = text(select(&document, ".title.product-field")?);
+        let isbn = isbn(&text(select(&document, ".bookitem-secondary-metadata")?));
+        let mut cover = select(&document, "img.cover-image")?
+            .value()
+            .attr("src")
+            .ok_or_else(|| parse_error("Cover has no src"))?
+            .to_owned();
+        if cover.starts_with("//") {
+            cover = format!("https:{}", cover);
+        }
+
+        let mut draft = Draft::new(
+            format!("novel-{}", title_to_key(&title)),
+            Kind::Novel,
+            Alternatives::new("English", title.clone()),
+        )
+        .with_cover(cover, None);
+        draft.item.extra = extra(&[
+            ("external_url", Some(url.as_str())),
+            ("isbn", isbn.as_deref()),
+        ]);
+        if let Some(isbn) = isbn.and_then(|isbn| Provider::Isbn.canonicalize(&isbn)) {
+            draft.item.external_ids.insert(Provider::Isbn, isbn);
+        }
+        draft.item.entries = vec![Entry {
+            name: Some(Alternatives::new("English", title)),
+            number: Some(1),
+            volume: None,
+            completed: DateBool::False,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        }];
+        draft.item.status = Status::InProgress;
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kobo;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::Importer;
+    use shelf::common::Kind;
+    use shelf::external::Provider;
+
+    #[tokio::test]
+    async fn test_book() {
+        let fetcher = FixtureFetcher::new().with("kobo.com", "test.orsinian-tales.html");
+        let url = "https://www.kobo.com/us/en/ebook/orsinian-tales";
+        let draft = Kobo.import(&url.parse().unwrap(), &fetcher).await.unwrap();
+        let item = &draft.item;
+        assert_eq!("novel-orsinian-tales", item.key);
+        assert_eq!(Kind::Novel, item.kind);
+        assert_eq!(Some(&"Orsinian Tales".to_owned()), item.name.get_default());
+        assert_eq!(Some(url), item.extra["external_url"].as_str());
+        assert_eq!(Some("9781598534962"), item.extra["isbn"].as_str());
+        assert_eq!(
+            Some(&"9781598534962".to_owned()),
+            item.external_ids.get(&Provider::Isbn)
+        );
+        assert_eq!(
+            "https://kbimages1-a.akamaihd.net/d410419c-63cb-4df2-9d9c-85e38eb556b4/353/569/90/False/orsinian-tales.jpg",
+            draft.cover.unwrap().url
+        );
+        assert_eq!(1, item.entries.len());
+        assert_eq!(Some(1), item.entries[0].number);
+        assert_eq!(None, item.entries[0].volume);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use std::collections::HashMap;
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind};
+use shelf::external::Provider;
+use shelf::item::PublicationStatus;
+use shelf::key::title_to_key;
+
+use super::{extra, parse_error, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+
+pub struct MangaDex;
+
+#[derive(serde_derive::Deserialize)]
+struct Response {
+    data: Manga,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct Manga {
+    attributes: MangaAttributes,
+    #[serde(default)]
+    relationships: Vec<Relationship>,
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MangaAttributes {
+    title: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    alt_titles: Vec<HashMap<String, String>>,
+    #[serde(default)]
+    description: HashMap<String, String>,
+    status: Option<String>,
+    publication_demographic: Option<String>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct Tag {
+    attributes: TagAttributes,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct TagAttributes {
+    #[serde(default)]
+    name: HashMap<String, String>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct Relationship {
+    #[serde(rename = "type")]
+    typ: String,
+    attributes: Option<RelationshipAttributes>,
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RelationshipAttributes {
+    file_name: Option<String>,
+    name: Option<String>,
+}
+
+/// The language name for a MangaDex language code, or None for
+/// languages we don't keep titles in.
+fn language(code: &str) -> Option<&str> {
+    Some(match code {
+        "en" => "English",
+        "ja" => "Japanese",
+        "ja-ro" => "Japanese (Romaji)",
+        "zh" => "Chinese",
+        "zh-hk" => "Chinese (Traditional)",
+        "zh-ro" => "Chinese (Pinyin)",
+        "es" | "es-la" | "fr" | "he" | "id" | "ne" | "ru" | "vi" => return None,
+        code => code,
+    })
+}
+
+/// Map a MangaDex tag onto our tags.
+fn tag(name: &str) -> Option<&str> {
+    match name {
+        "Anthology" | "Comedy" | "Drama" | "Fantasy" | "Oneshot" | "Romance" | "Slice of Life" => {
+            Some(name)
+        }
+        "Girls' Love" => Some("Yuri"),
+        "School Life" => Some("School"),
+        _ => None,
+    }
+}
+
+#[async_trait::async_trait]
+impl Importer for MangaDex {
+    fn name(&self) -> &'static str {
+        "MangaDex"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        url.host_str() == Some("mangadex.org")
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let uuid = match Provider::parse_url(url.as_str()) {
+            Some((Provider::MangaDex, uuid)) => uuid,
+            _ => return Err(ImportError::Unsupported(url.to_string())),
+        };
+        let body = fetcher
+            .fetch(&format!(
+                "https://api.mangadex.org/manga/{}?includes[]=author&includes[]=cover_art",
+                uuid
+            ))
+            .await?;
+        let response: Response =
+            serde_json::from_str(&body).map_err(|err| parse_error(err.to_string()))?;
+        let attributes = &response.data.attributes;
+
+        let title = attributes
+            .title
+            .values()
+            .next()
+            .and_then(|title| title.as_str())
+            .ok_or_else(|| parse_error("No title"))?;
+        let mut name = Alternatives::new("English", title);
+        // Titles in a language we already have are numbered
+        let mut counter = 1;
+        for alt_title in attributes.alt_titles.iter() {
+            for (code, value) in alt_title.iter() {
+                let mut lang = match language(code) {
+                    Some(lang) => lang.to_owned(),
+                    None => continue,
+                };
+                if name.alternatives.contains_key(&lang) {
+                    lang = format!("{} {}", lang, counter);
+                    counter += 1;
+                }
+                name.alternatives.insert(lang, value.clone());
+            }
+        }
+
+        let mut cover = None;
+        let mut author = None;
+        for relationship in response.data.relationships.iter() {
+            let related = match &relationship.attributes {
+                Some(related) => related,
+                None => continue,
+            };
+            match relationship.typ.as_str() {
+                "cover_art" => cover = related.file_name.clone(),
+                "author" => author = related.name.clone(),
+                _ => {}
+            }
+        }
+        let cover = cover.ok_or_else(|| parse_error("Could not find cover art"))?;
+
+        let key = match &author {
+            Some(author) => format!("manga-{}-{}", title_to_key(author), title_to_key(title)),
+            None => format!("manga-{}", title_to_key(title)),
+        };
+        let mut draft = Draft::new(key, Kind::Manga, name)
+            .with_cover(
+                format!("https://uploads.mangadex.org/covers/{}/{}", uuid, cover),
+                None,
+            )
+            .started();
+        if let Some(author) = &author {
+            draft = draft.with_author(author);
+        }
+        let item = &mut draft.item;
+        item.extra = extra(&[("mangadex_url", Some(url.as_str()))]);
+        item.external_ids.insert(Provider::MangaDex, uuid);
+        item.publication_status = if attributes.status.as_deref() == Some("completed") {
+            PublicationStatus::Complete
+        } else {
+            PublicationStatus::Publishing
+        };
+        item.tags = attributes
+            .tags
+            .iter()
+            .filter_map(|t| t.attributes.name.get("en").and_then(|name| tag(name)))
+            .map(|t| t.to_owned())
+            .collect();
+        if matches!(&attributes.publication_demographic, Some(d) if d.eq_ignore_ascii_case("josei"))
+        {
+            item.tags.push("Josei".to_owned());
+        }
+        item.tags.sort();
+        if let Some(description) = attributes.description.get("en") {
+            item.synopsis = description.clone();
+        }
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MangaDex;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::{Draft, Importer};
+    use shelf::common::{DateBool, Kind};
+
+    async fn import(url: &str) -> Draft {
+        let fetcher = FixtureFetcher::new()
+            .with(
+                "8280d386-8195-40da-8cbb-e8763ba3a93a",
+                "test.dora-yome.json",
+            )
+            .with(
+                "3f1453fb-9dac-4aca-a2ea-69613856c952",
+                "test.tamen-de-gushi.json",
+            );
+        let draft = MangaDex
+            .import(&url.parse().unwrap(), &fetcher)
+            .await
+            .unwrap();
+        assert_eq!(Some(url), draft.item.extra["mangadex_url"].as_str());
+        assert_eq!(DateBool::Timestamp(draft.item.added), draft.item.started);
+        assert!(draft.item.entries.is_empty());
+        draft
+    }
+
+    #[tokio::test]
+    async fn test_dora_yome() {
+        let draft =
+            import("https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a/dora-yome")
+                .await;
+        let item = &draft.item;
+        assert_eq!("manga-korai-ayumu-dora-yome", item.key);
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!("English", item.name.default);
+        let name = |lang: &str| item.name.alternatives.get(lang).map(|s| s.as_str());
+        assert_eq!(Some("Dora Yome"), name("English"));
+        assert_eq!(Some("ドラよめ"), name("Japanese"));
+        assert_eq!(Some("DoreYome"), name("English 1"));
+        assert_eq!(Some("Dragon Wife"), name("English 2"));
+        assert_eq!(
+            "A dragon's hormones are not a trifling thing. Siegfried rolled a 20 for a chance to defeat the dragon (who was hunting for a mate) and accidentally hit the dragon with {Charm}, next thing you know they are married.",
+            item.synopsis
+        );
+        assert_eq!(vec!["Comedy", "Fantasy", "Oneshot", "Romance"], item.tags);
+        assert_eq!("person-korai-ayumu", draft.people[0].key);
+        assert_eq!(
+            "https://uploads.mangadex.org/covers/8280d386-8195-40da-8cbb-e8763ba3a93a/3ea14915-4f5a-499a-a94f-1e3078cc1a2c.jpg",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tamen_de_gushi() {
+        let draft = import(
+            "https://mangadex.org/title/3f1453fb-9dac-4aca-a2ea-69613856c952/tamen-de-gushi",
+        )
+        .await;
+        let item = &draft.item;
+        assert_eq!("manga-tan-jiu-tanjiu-tamen-de-gushi", item.key);
+        assert_eq!(Some(&"Tamen de Gushi".to_owned()), item.name.get_default());
+        assert_eq!(
+            Some(&"SQ: Begin W/Your Name!".to_owned()),
+            item.name.alternatives.get("English 1")
+        );
+        assert_eq!(
+            "The funny romantic story of how Qiu Tong and Sun Jing met and fell in love.\n\nAlso contains insert art of the characters by the author.\n\n---\n\n- [Original Manhua <AC.QQ>](https://ac.qq.com/Comic/comicInfo/id/630157)",
+            item.synopsis
+        );
+        assert_eq!(
+            vec![
+                "Comedy",
+                "Drama",
+                "Romance",
+                "School",
+                "Slice of Life",
+                "Yuri"
+            ],
+            item.tags
+        );
+        assert_eq!(
+            "https://uploads.mangadex.org/covers/3f1453fb-9dac-4aca-a2ea-69613856c952/d8c446bf-6433-49f9-bd21-3b6c85669721.jpg",
+            draft.cover.unwrap().url
+        );
+    }
+}
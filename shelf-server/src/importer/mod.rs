@@ -0,0 +1,274 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Importing items from the pages of external sites. These are ports of
+//! the importers in the web frontend (`static/v2/src/import/`), and are
+//! tested against the same saved pages.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, DateBool, Person, Role};
+use shelf::external::Provider;
+use shelf::item::{Entry, Item};
+use shelf::key::title_to_key;
+
+use crate::fetch::{FetchError, Fetcher};
+
+mod cubari;
+mod dynastyscans;
+mod kitsu;
+mod kobo;
+mod mangadex;
+mod nitter;
+mod sundaywebry;
+mod webtoons;
+
+/// Where to download the cover of an imported item from.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct CoverDraft {
+    /// The blob key to upload the cover as.
+    pub key: String,
+    pub description: String,
+    pub url: String,
+    /// Some sites refuse to serve images without a referrer.
+    pub referrer: Option<String>,
+}
+
+/// An item built from an external page, to be reviewed before it is
+/// added to the shelf.
+#[derive(Clone, Debug, serde_derive::Serialize)]
+pub struct Draft {
+    pub item: Item,
+    /// People referenced by the item.
+    pub people: Vec<Person>,
+    pub cover: Option<CoverDraft>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// No importer handles the URL.
+    Unsupported(String),
+    Fetch(FetchError),
+    /// The page did not look like we expected.
+    Parse(String),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImportError::Unsupported(url) => write!(f, "Cannot import {}", url),
+            ImportError::Fetch(err) => write!(f, "{}", err),
+            ImportError::Parse(err) => write!(f, "Could not parse page: {}", err),
+        }
+    }
+}
+
+impl From<FetchError> for ImportError {
+    fn from(err: FetchError) -> ImportError {
+        ImportError::Fetch(err)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Importer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether this importer handles the URL.
+    fn matches(&self, url: &Url) -> bool;
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError>;
+}
+
+/// All supported importers.
+pub fn importers() -> Vec<Box<dyn Importer>> {
+    vec![
+        Box::new(cubari::Cubari),
+        Box::new(dynastyscans::DynastyScans),
+        Box::new(kitsu::Kitsu),
+        Box::new(kobo::Kobo),
+        Box::new(mangadex::MangaDex),
+        Box::new(nitter::Nitter),
+        Box::new(sundaywebry::SundayWebry),
+        Box::new(webtoons::Webtoons),
+    ]
+}
+
+/// Whether some importer handles the URL.
+pub fn is_supported(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(url) => importers().iter().any(|importer| importer.matches(&url)),
+        Err(_) => false,
+    }
+}
+
+/// Import a URL with the first importer that handles it.
+pub async fn import(url: &str, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+    let parsed = Url::parse(url).map_err(|_| ImportError::Unsupported(url.to_owned()))?;
+    let importers = importers();
+    let importer = importers
+        .iter()
+        .find(|importer| importer.matches(&parsed))
+        .ok_or_else(|| ImportError::Unsupported(url.to_owned()))?;
+    log::info!(
+        target: crate::LOG_NAME,
+        "Importing {} with {}",
+        url,
+        importer.name()
+    );
+    importer.import(&parsed, fetcher).await
+}
+
+impl Draft {
+    /// Start a draft with the fields every importer sets.
+    fn new(key: String, kind: shelf::common::Kind, name: Alternatives<String>) -> Draft {
+        Draft {
+            item: Item {
+                key,
+                kind,
+                name,
+                ..Default::default()
+            },
+            people: Vec::new(),
+            cover: None,
+        }
+    }
+
+    /// Record the page the item was imported from, and the external ID
+    /// it corresponds to if any.
+    fn with_source(mut self, url: &str) -> Draft {
+        self.item.extra = extra(&[("external_url", Some(url))]);
+        if let Some((provider, id)) = Provider::parse_url(url) {
+            self.item.external_ids.insert(provider, id);
+        }
+        self
+    }
+
+    fn with_author(mut self, name: &str) -> Draft {
+        let key = format!("person-{}", title_to_key(name));
+        self.item.people.push((Role::Author, key.clone()));
+        self.people.push(Person {
+            key,
+            name: Alternatives::new("English", name),
+            external_ids: Default::default(),
+        });
+        self
+    }
+
+    fn with_cover<S: Into<String>>(mut self, url: S, referrer: Option<&str>) -> Draft {
+        self.cover = Some(CoverDraft {
+            key: format!("blob-{}-cover", self.item.key),
+            description: "Cover".to_owned(),
+            url: url.into(),
+            referrer: referrer.map(|r| r.to_owned()),
+        });
+        self
+    }
+
+    /// Mark the item as started when it was added.
+    fn started(mut self) -> Draft {
+        self.item.status = shelf::common::Status::InProgress;
+        self.item.started = DateBool::Timestamp(self.item.added);
+        self
+    }
+
+    /// Mark the item as a completed oneshot: a single entry, read when
+    /// it was added.
+    fn oneshot(mut self) -> Draft {
+        let added = DateBool::Timestamp(self.item.added);
+        self.item.entries = vec![Entry {
+            name: Some(Alternatives::new("English", "Oneshot")),
+            number: Some(1),
+            volume: None,
+            completed: added,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        }];
+        self.item.status = shelf::common::Status::Completed;
+        self.item.started = added;
+        self.item.completed = added;
+        self.item.publication_status = shelf::item::PublicationStatus::Complete;
+        self
+    }
+}
+
+/// Build the `extra` mapping of an item, skipping missing values.
+fn extra(fields: &[(&str, Option<&str>)]) -> serde_yaml::Value {
+    let mut mapping = serde_yaml::Mapping::new();
+    for (key, value) in fields {
+        if let Some(value) = value {
+            mapping.insert((*key).into(), (*value).into());
+        }
+    }
+    serde_yaml::Value::Mapping(mapping)
+}
+
+fn parse_error<S: Into<String>>(error: S) -> ImportError {
+    ImportError::Parse(error.into())
+}
+
+/// The first element matching a selector.
+fn select<'a>(
+    document: &'a scraper::Html,
+    selector: &str,
+) -> Result<scraper::ElementRef<'a>, ImportError> {
+    document
+        .select(&crate::release::selector(selector))
+        .next()
+        .ok_or_else(|| parse_error(format!("Could not find {}", selector)))
+}
+
+/// The content of an OpenGraph `<meta property=...>` tag.
+fn meta(document: &scraper::Html, property: &str) -> Result<String, ImportError> {
+    document
+        .select(&crate::release::selector("meta"))
+        .find(|el| el.value().attr("property") == Some(property))
+        .and_then(|el| el.value().attr("content"))
+        .map(|content| content.trim().to_owned())
+        .ok_or_else(|| parse_error(format!("Could not find property {}", property)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, ImportError};
+    use crate::fetch::FixtureFetcher;
+
+    #[tokio::test]
+    async fn test_unsupported() {
+        let fetcher = FixtureFetcher::new();
+        for url in &["https://example.com/manga/1", "not a url"] {
+            match import(url, &fetcher).await {
+                Err(ImportError::Unsupported(_)) => {}
+                other => panic!("{:?}", other.map(|draft| draft.item.key)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let fetcher = FixtureFetcher::new().with("cubari.moe", "test.at-the-end-of-4-minutes.html");
+        let draft = import("https://cubari.moe/read/imgur/oeJ1wKg/1/47/", &fetcher)
+            .await
+            .unwrap();
+        assert_eq!(
+            "manga-cubari-at-the-end-of-4-minutes-translated-by-reica",
+            draft.item.key
+        );
+        match import("https://mangadex.org/title/x", &fetcher).await {
+            Err(ImportError::Fetch(_)) => {}
+            other => panic!("{:?}", other.map(|draft| draft.item.key)),
+        }
+    }
+}
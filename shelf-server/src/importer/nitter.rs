@@ -0,0 +1,130 @@
+// Copyright 2022 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind};
+use shelf::key::title_to_key;
+
+use super::{meta, parse_error, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+
+/// Imports tweets as oneshots, fetching them from a Nitter instance
+/// since Twitter itself requires JavaScript.
+pub struct Nitter;
+
+#[async_trait::async_trait]
+impl Importer for Nitter {
+    fn name(&self) -> &'static str {
+        "Nitter"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(
+            url.host_str(),
+            Some("twitter.com")
+                | Some("twitter.lidavidm.me")
+                | Some("nitter.net")
+                | Some("unofficialbird.com")
+                | Some("farside.link")
+        )
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let path = if url.host_str() == Some("farside.link") {
+            url.path().trim_start_matches("/nitter")
+        } else {
+            url.path()
+        };
+        let author = path
+            .split('/')
+            .nth(1)
+            .filter(|author| !author.is_empty())
+            .ok_or_else(|| ImportError::Unsupported(url.to_string()))?;
+        let body = fetcher
+            .fetch(&format!("https://farside.link/nitter{}", path))
+            .await?;
+        let document = scraper::Html::parse_document(&body);
+
+        let title = meta(&document, "og:description")?;
+        if title.is_empty() {
+            return Err(parse_error("Tweet has no text"));
+        }
+        // TODO: it'd be nice to associate accounts with authors
+        let mut draft = Draft::new(
+            format!(
+                "manga-twitter-{}-{}",
+                title_to_key(author),
+                title_to_key(&title)
+            ),
+            Kind::Manga,
+            Alternatives::new("Japanese", format!("{} (@{})", title, author)),
+        )
+        .with_source(&format!("https://twitter.com{}", path))
+        .with_cover(meta(&document, "og:image")?, None)
+        .oneshot();
+        draft.item.tags = vec!["Oneshot".to_owned()];
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Nitter;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::Importer;
+    use shelf::common::{Alternatives, DateBool, Kind};
+
+    #[tokio::test]
+    async fn test_oneshot() {
+        let fetcher = FixtureFetcher::new().with(
+            "farside.link/nitter/yuruyunaZNK/status/1567460472689131521",
+            "test.zanka.html",
+        );
+        let url = "https://unofficialbird.com/yuruyunaZNK/status/1567460472689131521#m";
+        let draft = Nitter
+            .import(&url.parse().unwrap(), &fetcher)
+            .await
+            .unwrap();
+        let item = &draft.item;
+        assert_eq!(
+            "manga-twitter-yuruyunaznk-chi-zukashigari-wu-na-meido-sanga-keai-kute-shifang-naio-niangyang-no-baihe",
+            item.key
+        );
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!("Japanese", item.name.default);
+        assert_eq!(
+            Some(
+                &"恥ずかしがり屋なメイドさんが可愛くて仕方ないお嬢様の百合(*´-`) (@yuruyunaZNK)"
+                    .to_owned()
+            ),
+            item.name.get_default()
+        );
+        assert_eq!(DateBool::Timestamp(item.added), item.started);
+        assert_eq!(item.started, item.completed);
+        assert_eq!(1, item.entries.len());
+        assert_eq!(
+            Some(Alternatives::new("English", "Oneshot")),
+            item.entries[0].name
+        );
+        assert_eq!(item.completed, item.entries[0].completed);
+        assert_eq!(
+            Some("https://twitter.com/yuruyunaZNK/status/1567460472689131521"),
+            item.extra["external_url"].as_str()
+        );
+        assert_eq!(
+            "https://unofficialbird.com/pic/media%2FFcC8-FJagAIIae3.jpg",
+            draft.cover.unwrap().url
+        );
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2022 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind};
+use shelf::key::title_to_key;
+
+use super::{meta, parse_error, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+
+/// Imports series from Sunday Webry and other sites built on the same
+/// platform (Shonen Jump+, Comic Days, ...).
+pub struct SundayWebry;
+
+const SUNDAY_WEBRY: &str = "www.sunday-webry.com";
+
+#[async_trait::async_trait]
+impl Importer for SundayWebry {
+    fn name(&self) -> &'static str {
+        "Sunday Webry"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(
+            url.host_str(),
+            Some("comic-days.com")
+                | Some("comic-trail.com")
+                | Some("magcomi.com")
+                | Some("shonenjumpplus.com")
+                | Some("tonarinoyj.jp")
+                | Some(SUNDAY_WEBRY)
+        )
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let body = fetcher.fetch(url.as_str()).await?;
+        let document = scraper::Html::parse_document(&body);
+
+        let host = url.host_str().unwrap_or_default();
+        let raw_title = meta(&document, "og:title")?;
+        let raw_title = raw_title.split(" | ").next().unwrap_or_default().trim();
+        // Sunday Webry titles are "<series> / <title> - <author>"
+        let (site, title_and_author) = if host == SUNDAY_WEBRY {
+            let (_, title_and_author) = raw_title
+                .split_once(" / ")
+                .ok_or_else(|| parse_error(format!("Unexpected title {:?}", raw_title)))?;
+            ("sundaywebry".to_owned(), title_and_author)
+        } else {
+            let site: String = host
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            (site, raw_title)
+        };
+        let split = title_and_author
+            .rfind(" - ")
+            .ok_or_else(|| parse_error(format!("No author in title {:?}", title_and_author)))?;
+        let title = &title_and_author[..split];
+        let author = &title_and_author[split + " - ".len()..];
+
+        let mut draft = Draft::new(
+            format!("manga-{}-{}", site, title_to_key(title_and_author)),
+            Kind::Manga,
+            Alternatives::new("Japanese", format!("{}（{}）", title, author)),
+        )
+        .with_source(url.as_str())
+        .with_cover(meta(&document, "og:image")?, None)
+        .started();
+        for author in author.split('/') {
+            draft = draft.with_author(author.trim());
+        }
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SundayWebry;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::{Draft, Importer};
+    use shelf::common::{DateBool, Kind};
+
+    async fn import(url: &str, fixture: &str) -> Draft {
+        let fetcher = FixtureFetcher::new().with("/episode/", fixture);
+        let draft = SundayWebry
+            .import(&url.parse().unwrap(), &fetcher)
+            .await
+            .unwrap();
+        let item = &draft.item;
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!("Japanese", item.name.default);
+        assert_eq!(DateBool::Timestamp(item.added), item.started);
+        assert_eq!(DateBool::False, item.completed);
+        assert!(item.entries.is_empty());
+        assert_eq!(Some(url), item.extra["external_url"].as_str());
+        draft
+    }
+
+    #[tokio::test]
+    async fn test_sunday_webry() {
+        let draft = import(
+            "https://www.sunday-webry.com/episode/3269754496589975683",
+            "test.キミの手には.html",
+        )
+        .await;
+        assert_eq!(
+            "manga-sundaywebry-kimi-no-shou-niha-xiaoxingzhuling",
+            draft.item.key
+        );
+        assert_eq!(
+            Some(&"キミの手には（小形朱嶺）".to_owned()),
+            draft.item.name.get_default()
+        );
+        assert_eq!(1, draft.people.len());
+        assert_eq!(
+            "https://cdn-img.www.sunday-webry.com/public/series-thumbnail/3269754496589963096-7acf2c99cf3f635a0d95464c4a9aeba4?1636339792",
+            draft.cover.unwrap().url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shonen_jump_plus() {
+        let draft = import(
+            "https://shonenjumpplus.com/episode/3270296674426084193",
+            "test.ハイカロリーアパート.html",
+        )
+        .await;
+        assert_eq!(
+            "manga-shonenjumppluscom-haikarori-apa-to-niao-tomato-yanben-kuuyou",
+            draft.item.key
+        );
+        assert_eq!(
+            Some(&"ハイカロリーアパート（鳥トマト/岩本くうよう）".to_owned()),
+            draft.item.name.get_default()
+        );
+        // Co-authors are separated by slashes
+        let people: Vec<&str> = draft.people.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(vec!["person-niao-tomato", "person-yanben-kuuyou"], people);
+        assert_eq!(
+            "https://cdn-ak-img.shonenjumpplus.com/public/series-thumbnail/3270296674426084181-77ef118e004644cad8609ee3f4fcfa15?1659222305",
+            draft.cover.unwrap().url
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use reqwest::Url;
+use shelf::common::{Alternatives, Kind, Status};
+use shelf::key::title_to_key;
+
+use super::{select, Draft, ImportError, Importer};
+use crate::fetch::Fetcher;
+use crate::release::text;
+
+pub struct Webtoons;
+
+/// The image in a `background: url(...)` style.
+fn background_image(style: &str) -> Option<&str> {
+    let start = style.find("url(")? + "url(".len();
+    let end = start + style[start..].find(')')?;
+    Some(style[start..end].trim_matches(|c| c == '"' || c == '\''))
+}
+
+#[async_trait::async_trait]
+impl Importer for Webtoons {
+    fn name(&self) -> &'static str {
+        "Webtoons"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        matches!(
+            url.host_str(),
+            Some("www.webtoons.com") | Some("webtoons.com")
+        )
+    }
+
+    async fn import(&self, url: &Url, fetcher: &dyn Fetcher) -> Result<Draft, ImportError> {
+        let body = fetcher.fetch(url.as_str()).await?;
+        let document = scraper::Html::parse_document(&body);
+
+        let title = text(select(&document, "h1.subj")?);
+        let mut draft = Draft::new(
+            format!("manga-{}", title_to_key(&title)),
+            Kind::Manga,
+            Alternatives::new("English", title),
+        )
+        .with_source(url.as_str());
+        // Webtoons doesn't serve images without a referrer
+        if let Some(cover) = select(&document, ".detail_body.banner")?
+            .value()
+            .attr("style")
+            .and_then(background_image)
+        {
+            draft = draft.with_cover(cover, Some("https://www.webtoons.com"));
+        }
+        draft.item.status = Status::InProgress;
+        Ok(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Webtoons;
+    use crate::fetch::FixtureFetcher;
+    use crate::importer::Importer;
+    use shelf::common::Kind;
+    use shelf::external::Provider;
+
+    #[tokio::test]
+    async fn test_series() {
+        let fetcher = FixtureFetcher::new().with("title_no=1263", "test.dating-with-a-tail.html");
+        let url = "https://www.webtoons.com/en/romance/dating-with-a-tail/list?title_no=1263";
+        let draft = Webtoons
+            .import(&url.parse().unwrap(), &fetcher)
+            .await
+            .unwrap();
+        let item = &draft.item;
+        assert_eq!("manga-dating-with-a-tail", item.key);
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!(
+            Some(&"Dating with a Tail".to_owned()),
+            item.name.get_default()
+        );
+        assert!(item.entries.is_empty());
+        assert_eq!(Some(url), item.extra["external_url"].as_str());
+        assert_eq!(
+            Some(&"1263".to_owned()),
+            item.external_ids.get(&Provider::Webtoons)
+        );
+        let cover = draft.cover.unwrap();
+        assert_eq!(
+            "https://webtoon-phinf.pstatic.net/20201112_7/1605118411429xSRw5_JPEG/910_EC8DB8EB84A4EC9DBC_ipad.jpg?type=a306",
+            cover.url
+        );
+        assert_eq!(Some("https://www.webtoons.com".to_owned()), cover.referrer);
+    }
+}
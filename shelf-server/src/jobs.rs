@@ -0,0 +1,697 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Background jobs that fetch from external sites for many items, like
+//! fetching covers or importing a list of URLs.
+//!
+//! Jobs run one at a time. Each job is split into tasks (one per item
+//! or URL); results are committed to the shelf as tasks finish, saving
+//! in batches. Job state is written to disk after every task, and jobs
+//! interrupted by a restart are run again.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use shelf::common::Blob;
+use shelf::item::{Cover, Item};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::fetch::Fetcher;
+use crate::importer::CoverDraft;
+use crate::model::{AppState, AppStateRef};
+
+/// How many changed items to commit to the shelf at once.
+const SAVE_BATCH: usize = 10;
+
+/// What a job should do.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "type")]
+pub enum JobRequest {
+    /// Fetch a cover for items without one, from the page they were
+    /// imported from. Defaults to all such items.
+    Covers {
+        #[serde(default)]
+        keys: Option<Vec<String>>,
+    },
+    /// Import pages as new items.
+    Import { urls: Vec<String> },
+    /// Check ongoing serials for new chapters and add them as entries.
+    /// Defaults to all tracked serials.
+    Releases {
+        #[serde(default)]
+        key: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    /// The job stopped early, e.g. because the shelf could not be saved.
+    Failed,
+}
+
+/// The outcome of one task of a job.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct TaskResult {
+    /// The item key or URL the task was for.
+    pub target: String,
+    /// The item that was added or changed, if any.
+    pub item: Option<String>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+impl TaskResult {
+    fn changed(target: &str, item: &str, message: Option<String>) -> TaskResult {
+        TaskResult {
+            target: target.to_owned(),
+            item: Some(item.to_owned()),
+            message,
+            error: None,
+        }
+    }
+
+    fn unchanged<S: Into<String>>(target: &str, message: S) -> TaskResult {
+        TaskResult {
+            target: target.to_owned(),
+            item: None,
+            message: Some(message.into()),
+            error: None,
+        }
+    }
+
+    fn failed<S: Into<String>>(target: &str, error: S) -> TaskResult {
+        TaskResult {
+            target: target.to_owned(),
+            item: None,
+            message: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub request: JobRequest,
+    pub state: JobState,
+    pub submitted: chrono::DateTime<chrono::FixedOffset>,
+    pub finished: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// The number of tasks, known once the job starts.
+    pub total: usize,
+    pub results: Vec<TaskResult>,
+    pub error: Option<String>,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, JobState::Completed | JobState::Failed)
+    }
+}
+
+/// The jobs known to the server, and the queue of jobs to run.
+pub struct JobQueue {
+    jobs: std::sync::Mutex<BTreeMap<u64, Job>>,
+    /// Where job state is persisted, if anywhere.
+    directory: Option<PathBuf>,
+    queue: mpsc::UnboundedSender<u64>,
+    /// Every change to a job is sent here.
+    events: broadcast::Sender<Job>,
+}
+
+impl JobQueue {
+    /// Load jobs persisted in the directory, queueing any that did not
+    /// finish. Returns the receiving end of the queue for `run`.
+    pub fn new(directory: Option<PathBuf>) -> (JobQueue, mpsc::UnboundedReceiver<u64>) {
+        let (queue, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(64);
+        let mut jobs = BTreeMap::new();
+        if let Some(directory) = &directory {
+            for job in load(directory) {
+                jobs.insert(job.id, job);
+            }
+        }
+        let queue = JobQueue {
+            jobs: std::sync::Mutex::new(jobs),
+            directory,
+            queue,
+            events,
+        };
+        let interrupted: Vec<u64> = queue
+            .list()
+            .into_iter()
+            .filter(|job| !job.is_finished())
+            .map(|job| job.id)
+            .collect();
+        for id in interrupted {
+            log::info!(target: crate::LOG_NAME, "Requeueing interrupted job {}", id);
+            queue.update(id, |job| job.state = JobState::Queued);
+            let _ = queue.queue.send(id);
+        }
+        (queue, receiver)
+    }
+
+    pub fn submit(&self, request: JobRequest) -> Job {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let id = jobs.keys().next_back().map(|id| id + 1).unwrap_or(1);
+            let job = Job {
+                id,
+                request,
+                state: JobState::Queued,
+                submitted: chrono::Local::now().into(),
+                finished: None,
+                total: 0,
+                results: Vec::new(),
+                error: None,
+            };
+            jobs.insert(id, job.clone());
+            job
+        };
+        self.persist(&job);
+        let _ = self.queue.send(job.id);
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// All jobs, oldest first.
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Receive every change to a job.
+    pub fn subscribe(&self) -> broadcast::Receiver<Job> {
+        self.events.subscribe()
+    }
+
+    /// Follow a job: its current state, then every change to it. The
+    /// stream ends right after the job finishes, or at once if it
+    /// already has.
+    pub fn watch(&self, id: u64) -> Option<impl futures::Stream<Item = Job>> {
+        use futures::StreamExt;
+
+        // Subscribe first so no update is missed between the two
+        let updates = self.subscribe().into_stream().filter_map(move |update| {
+            futures::future::ready(match update {
+                Ok(job) if job.id == id => Some(job),
+                // Lagged receivers just wait for the next update
+                _ => None,
+            })
+        });
+        let job = self.get(id)?;
+        Some(futures::stream::unfold(
+            (Some(job), Some(Box::pin(updates))),
+            |(current, updates)| async move {
+                let mut updates = updates?;
+                let job = match current {
+                    Some(job) => job,
+                    None => updates.next().await?,
+                };
+                let updates = if job.is_finished() {
+                    None
+                } else {
+                    Some(updates)
+                };
+                Some((job, (None, updates)))
+            },
+        ))
+    }
+
+    fn update<F: FnOnce(&mut Job)>(&self, id: u64, f: F) {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            match jobs.get_mut(&id) {
+                Some(job) => {
+                    f(job);
+                    job.clone()
+                }
+                None => return,
+            }
+        };
+        self.persist(&job);
+        // There may be nobody listening
+        let _ = self.events.send(job);
+    }
+
+    fn persist(&self, job: &Job) {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let result = std::fs::create_dir_all(directory)
+            .map_err(|err| err.to_string())
+            .and_then(|_| serde_json::to_vec_pretty(job).map_err(|err| err.to_string()))
+            .and_then(|contents| {
+                std::fs::write(path(directory, job.id), contents).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            log::error!(target: crate::LOG_NAME, "Could not save job {}: {}", job.id, err);
+        }
+    }
+}
+
+fn path(directory: &std::path::Path, id: u64) -> PathBuf {
+    directory.join(format!("job-{}.json", id))
+}
+
+fn load(directory: &std::path::Path) -> Vec<Job> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        // Nothing was saved yet
+        Err(_) => return Vec::new(),
+    };
+    let mut jobs = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        match std::fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_slice(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(job) => jobs.push(job),
+            Err(err) => log::warn!(
+                target: crate::LOG_NAME,
+                "Could not load job {}: {}",
+                path.to_string_lossy(),
+                err
+            ),
+        }
+    }
+    jobs
+}
+
+/// Run queued jobs one at a time, forever.
+pub async fn run(
+    state: AppStateRef,
+    queue: Arc<JobQueue>,
+    mut receiver: mpsc::UnboundedReceiver<u64>,
+    fetcher: Arc<dyn Fetcher>,
+) {
+    while let Some(id) = receiver.recv().await {
+        process(id, &state, &queue, fetcher.as_ref()).await;
+    }
+}
+
+/// The page an item can be imported from again, to find its cover.
+fn source_url(item: &Item) -> Option<String> {
+    ["external_url", "mangadex_url"]
+        .iter()
+        .filter_map(|field| item.extra.get(*field).and_then(|url| url.as_str()))
+        .map(|url| url.to_owned())
+        .chain(
+            item.external_ids
+                .iter()
+                .filter_map(|(provider, id)| provider.url(id)),
+        )
+        .find(|url| crate::importer::is_supported(url))
+}
+
+/// The items or URLs a job works on.
+fn targets(request: &JobRequest, shelf: &shelf::Shelf) -> Vec<String> {
+    match request {
+        JobRequest::Covers { keys: Some(keys) } => keys.clone(),
+        JobRequest::Covers { keys: None } => shelf
+            .all_items()
+            .iter()
+            .filter(|item| item.covers.is_empty() && source_url(item).is_some())
+            .map(|item| item.key.clone())
+            .collect(),
+        JobRequest::Import { urls } => urls.clone(),
+        JobRequest::Releases { key: Some(key) } => vec![key.clone()],
+        JobRequest::Releases { key: None } => shelf
+            .all_items()
+            .iter()
+            .filter(|item| crate::release::is_tracked(item))
+            .map(|item| item.key.clone())
+            .collect(),
+    }
+}
+
+fn save(state: &mut AppState) -> Result<(), String> {
+    state
+        .saver
+        .save(&mut state.shelf)
+        .map(|_| ())
+        .map_err(|err| format!("Error while saving: {}", err))
+}
+
+/// Run a job to completion, updating its state as tasks finish.
+pub async fn process(id: u64, state: &AppStateRef, queue: &JobQueue, fetcher: &dyn Fetcher) {
+    let request = match queue.get(id) {
+        Some(job) if job.state == JobState::Queued => job.request,
+        _ => return,
+    };
+    let targets = targets(&request, &state.lock().await.shelf);
+    log::info!(
+        target: crate::LOG_NAME,
+        "Running job {} ({} tasks)",
+        id,
+        targets.len()
+    );
+    queue.update(id, |job| {
+        job.state = JobState::Running;
+        job.total = targets.len();
+        job.results.clear();
+    });
+
+    let mut unsaved = 0;
+    let mut error = None;
+    for target in targets.iter() {
+        let result = match &request {
+            JobRequest::Covers { .. } => fetch_cover(target, state, fetcher).await,
+            JobRequest::Import { .. } => import(target, state, fetcher).await,
+            JobRequest::Releases { .. } => check_releases(target, state, fetcher).await,
+        };
+        if result.item.is_some() {
+            unsaved += 1;
+        }
+        queue.update(id, |job| job.results.push(result));
+        if unsaved >= SAVE_BATCH {
+            if let Err(err) = save(&mut *state.lock().await) {
+                error = Some(err);
+                break;
+            }
+            unsaved = 0;
+        }
+    }
+    if error.is_none() && unsaved > 0 {
+        error = save(&mut *state.lock().await).err();
+    }
+
+    queue.update(id, |job| {
+        job.state = if error.is_some() {
+            JobState::Failed
+        } else {
+            JobState::Completed
+        };
+        job.error = error;
+        job.finished = Some(chrono::Local::now().into());
+    });
+}
+
+/// Store a downloaded cover as a blob and add it to the item.
+fn store_cover(
+    state: &mut AppState,
+    item: &mut Item,
+    cover: &CoverDraft,
+    download: crate::fetch::Download,
+) -> Result<(), String> {
+    let path = state
+        .saver
+        .insert_blob(&cover.key)
+        .map_err(|err| format!("Could not save blob: {}", err))?;
+    std::fs::write(&path, &download.bytes)
+        .map_err(|err| format!("Could not save blob: {}", err))?;
+    state
+        .shelf
+        .insert_blob(Blob::new_with_mime(cover.key.clone(), download.mime_type))
+        .map_err(|err| format!("Could not insert blob: {}", err))?;
+    item.covers.push(Cover {
+        key: cover.key.clone(),
+        description: cover.description.clone(),
+    });
+    Ok(())
+}
+
+async fn fetch_cover(key: &str, state: &AppStateRef, fetcher: &dyn Fetcher) -> TaskResult {
+    let url = {
+        let state = state.lock().await;
+        match state.shelf.get_item(key) {
+            None => return TaskResult::failed(key, "No such item"),
+            Some(item) if !item.covers.is_empty() => {
+                return TaskResult::unchanged(key, "Item already has a cover")
+            }
+            Some(item) => match source_url(item) {
+                Some(url) => url,
+                None => return TaskResult::failed(key, "No page to fetch the cover from"),
+            },
+        }
+    };
+    let cover = match crate::importer::import(&url, fetcher).await {
+        Ok(draft) => match draft.cover {
+            Some(cover) => cover,
+            None => return TaskResult::failed(key, format!("No cover found on {}", url)),
+        },
+        Err(err) => return TaskResult::failed(key, err.to_string()),
+    };
+    let cover = CoverDraft {
+        key: format!("blob-{}-cover", key),
+        ..cover
+    };
+    let download = match fetcher
+        .download(&cover.url, cover.referrer.as_deref())
+        .await
+    {
+        Ok(download) => download,
+        Err(err) => return TaskResult::failed(key, err.to_string()),
+    };
+
+    let mut state = state.lock().await;
+    let mut item = match state.shelf.get_item(key) {
+        Some(item) => item.clone(),
+        // Removed while we were fetching
+        None => return TaskResult::failed(key, "No such item"),
+    };
+    let result = store_cover(&mut state, &mut item, &cover, download).and_then(|_| {
+        state
+            .shelf
+            .replace_item(item)
+            .map_err(|err| err.to_string())
+    });
+    match result {
+        Ok(()) => TaskResult::changed(key, key, None),
+        Err(err) => TaskResult::failed(key, err),
+    }
+}
+
+async fn import(url: &str, state: &AppStateRef, fetcher: &dyn Fetcher) -> TaskResult {
+    let draft = match crate::importer::import(url, fetcher).await {
+        Ok(draft) => draft,
+        Err(err) => return TaskResult::failed(url, err.to_string()),
+    };
+    let key = draft.item.key.clone();
+    if state.lock().await.shelf.get_item(&key).is_some() {
+        return TaskResult::unchanged(url, format!("Item {} already exists", key));
+    }
+    let mut message = None;
+    let download = match &draft.cover {
+        Some(cover) => match fetcher
+            .download(&cover.url, cover.referrer.as_deref())
+            .await
+        {
+            Ok(download) => Some((cover, download)),
+            Err(err) => {
+                // Still import the item
+                message = Some(err.to_string());
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut state = state.lock().await;
+    if state.shelf.get_item(&key).is_some() {
+        return TaskResult::unchanged(url, format!("Item {} already exists", key));
+    }
+    let mut item = draft.item;
+    if let Some((cover, download)) = download {
+        if let Err(err) = store_cover(&mut state, &mut item, cover, download) {
+            message = Some(err);
+        }
+    }
+    for person in draft.people {
+        if !state.shelf.query_people().any(|p| p.key == person.key) {
+            state.shelf.insert_person(person);
+        }
+    }
+    match state.shelf.insert_item(item) {
+        Ok(()) => TaskResult::changed(url, &key, message),
+        Err(err) => TaskResult::failed(url, err.to_string()),
+    }
+}
+
+async fn check_releases(key: &str, state: &AppStateRef, fetcher: &dyn Fetcher) -> TaskResult {
+    let item = match state.lock().await.shelf.get_item(key) {
+        Some(item) => item.clone(),
+        None => return TaskResult::failed(key, "No such item"),
+    };
+    let report = match crate::release::check(&item, &crate::release::sources(), fetcher).await {
+        Some(report) => report,
+        None => return TaskResult::unchanged(key, "No source lists chapters of this item"),
+    };
+    if let Some(err) = report.error {
+        return TaskResult::failed(key, err);
+    }
+    if report.new.is_empty() {
+        return TaskResult::unchanged(key, "No new chapters");
+    }
+
+    let mut state = state.lock().await;
+    let mut item = match state.shelf.get_item(key) {
+        Some(item) => item.clone(),
+        None => return TaskResult::failed(key, "No such item"),
+    };
+    let added = crate::release::append(&mut item, &report.new);
+    match state.shelf.replace_item(item) {
+        Ok(()) => TaskResult::changed(key, key, Some(format!("{} new chapters", added))),
+        Err(err) => TaskResult::failed(key, err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{process, JobQueue, JobRequest, JobState};
+    use crate::fetch::FixtureFetcher;
+    use crate::model::{AppState, AppStateRef};
+    use shelf::common::{Alternatives, Kind};
+    use shelf::item::Item;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn state(directory: &std::path::Path) -> AppStateRef {
+        let (jobs, _) = JobQueue::new(None);
+        Arc::new(Mutex::new(AppState {
+            shelf: shelf::Shelf::new(),
+            saver: shelf::save::DirectoryShelf::new(directory).unwrap(),
+            fetcher: Arc::new(FixtureFetcher::new()),
+            jobs: Arc::new(jobs),
+        }))
+    }
+
+    fn fetcher() -> FixtureFetcher {
+        FixtureFetcher::new()
+            .with("cubari.moe", "test.at-the-end-of-4-minutes.html")
+            // Any file will do as a cover
+            .with("i.imgur.com", "test.dora-yome.cover.json")
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let library = tempfile::tempdir().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let state = state(library.path());
+        let (queue, _) = JobQueue::new(Some(directory.path().to_owned()));
+        let job = queue.submit(JobRequest::Import {
+            urls: vec![
+                "https://cubari.moe/read/imgur/oeJ1wKg/1/47/".to_owned(),
+                "https://example.com/".to_owned(),
+                "https://cubari.moe/read/imgur/oeJ1wKg/1/47/".to_owned(),
+            ],
+        });
+        process(job.id, &state, &queue, &fetcher()).await;
+
+        let job = queue.get(job.id).unwrap();
+        assert_eq!(JobState::Completed, job.state);
+        assert_eq!(3, job.total);
+        let key = "manga-cubari-at-the-end-of-4-minutes-translated-by-reica";
+        assert_eq!(Some(key.to_owned()), job.results[0].item);
+        assert!(job.results[1].error.is_some());
+        assert_eq!(None, job.results[2].item);
+
+        let state = state.lock().await;
+        let item = state.shelf.get_item(key).unwrap();
+        assert_eq!(1, item.covers.len());
+        assert!(state.shelf.get_blob(&item.covers[0].key).is_some());
+        assert!(state.saver.get_blob(&item.covers[0].key).exists());
+        assert!(!state.shelf.is_dirty(key));
+
+        // Finished jobs are loaded but not run again
+        let (queue, mut receiver) = JobQueue::new(Some(directory.path().to_owned()));
+        assert_eq!(JobState::Completed, queue.get(job.id).unwrap().state);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_covers() {
+        let library = tempfile::tempdir().unwrap();
+        let state = state(library.path());
+        let mut item = Item {
+            key: "manga-test".into(),
+            kind: Kind::Manga,
+            name: Alternatives::new("English", "Test"),
+            ..Default::default()
+        };
+        item.external_ids.insert(
+            shelf::external::Provider::Cubari,
+            "imgur/oeJ1wKg".to_owned(),
+        );
+        state.lock().await.shelf.insert_item(item).unwrap();
+        let (queue, _) = JobQueue::new(None);
+
+        let job = queue.submit(JobRequest::Covers { keys: None });
+        process(job.id, &state, &queue, &fetcher()).await;
+        let job = queue.get(job.id).unwrap();
+        assert_eq!(1, job.total);
+        assert_eq!(Some("manga-test".to_owned()), job.results[0].item);
+        let covers = state
+            .lock()
+            .await
+            .shelf
+            .get_item("manga-test")
+            .unwrap()
+            .covers
+            .clone();
+        assert_eq!("blob-manga-test-cover", covers[0].key);
+
+        // Items with covers are skipped
+        let job = queue.submit(JobRequest::Covers { keys: None });
+        process(job.id, &state, &queue, &fetcher()).await;
+        assert_eq!(0, queue.get(job.id).unwrap().total);
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        use futures::StreamExt;
+
+        let (queue, _) = JobQueue::new(None);
+        assert!(queue.watch(1).is_none());
+        let job = queue.submit(JobRequest::Releases { key: None });
+        let other = queue.submit(JobRequest::Releases { key: None });
+        let events = queue.watch(job.id).unwrap();
+        queue.update(job.id, |job| job.state = JobState::Running);
+        queue.update(other.id, |job| job.state = JobState::Running);
+        queue.update(job.id, |job| job.state = JobState::Completed);
+        // Ends without waiting for another update
+        let states: Vec<JobState> = events.map(|job| job.state).collect().await;
+        assert_eq!(
+            vec![JobState::Queued, JobState::Running, JobState::Completed],
+            states
+        );
+
+        // A finished job gives just its final state
+        let events = queue.watch(job.id).unwrap();
+        assert_eq!(1, events.collect::<Vec<_>>().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_requeue() {
+        let directory = tempfile::tempdir().unwrap();
+        let id = {
+            let (queue, _) = JobQueue::new(Some(directory.path().to_owned()));
+            queue.submit(JobRequest::Releases { key: None }).id
+        };
+        let (queue, mut receiver) = JobQueue::new(Some(directory.path().to_owned()));
+        assert_eq!(Ok(id), receiver.try_recv());
+        assert_eq!(JobState::Queued, queue.get(id).unwrap().state);
+        assert_eq!(id + 1, queue.submit(JobRequest::Releases { key: None }).id);
+    }
+}
@@ -17,8 +17,12 @@ use tokio::sync::Mutex;
 
 use warp::Filter;
 
+mod fetch;
 mod handlers;
+mod importer;
+mod jobs;
 mod model;
+mod release;
 mod routes;
 
 const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
@@ -54,7 +58,38 @@ async fn main() {
         "Loaded shelf with {} items",
         shelf.all_items().len()
     );
-    let shelf_ref = Arc::new(Mutex::new(model::AppState { shelf, saver }));
+    let migrated = shelf.migrate_external_ids();
+    if migrated > 0 {
+        log::info!(
+            target: LOG_NAME,
+            "Migrated external IDs for {} items",
+            migrated
+        );
+        saver.save(&mut shelf).expect("Could not save shelf");
+    }
+    // Job state is kept outside the library, since it isn't part of it
+    let jobs_root = app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "jobs")
+        .expect("Could not find jobs directory!");
+    let (jobs, queue) = jobs::JobQueue::new(Some(jobs_root));
+    let jobs = Arc::new(jobs);
+    let fetcher: Arc<dyn fetch::Fetcher> = Arc::new(fetch::HttpFetcher::new());
+    let shelf_ref = Arc::new(Mutex::new(model::AppState {
+        shelf,
+        saver,
+        fetcher: fetcher.clone(),
+        jobs: jobs.clone(),
+    }));
+    // Jobs make many requests, so go easy on the sites
+    tokio::spawn(jobs::run(
+        shelf_ref.clone(),
+        jobs,
+        queue,
+        Arc::new(fetch::Throttled::new(
+            fetcher,
+            std::time::Duration::from_secs(2),
+            3,
+        )),
+    ));
 
     let api = routes::api(shelf_ref).with(warp::log(LOG_NAME));
     let static_files = warp::path("static").and(warp::fs::dir("./static"));
@@ -19,6 +19,10 @@ use tokio::sync::Mutex;
 pub struct AppState {
     pub shelf: shelf::Shelf,
     pub saver: shelf::save::DirectoryShelf,
+    /// Used to fetch pages from external sites (e.g. to check for new
+    /// chapters).
+    pub fetcher: Arc<dyn crate::fetch::Fetcher>,
+    pub jobs: Arc<crate::jobs::JobQueue>,
 }
 
 impl AppState {
@@ -45,6 +49,122 @@ pub struct ProxyParams {
     pub cookies: Option<HashMap<String, String>>,
 }
 
+/// The query parameters for /lookup.
+#[derive(serde_derive::Deserialize)]
+pub struct LookupParams {
+    pub url: String,
+}
+
+/// The query parameters for /stats.
+#[derive(serde_derive::Deserialize)]
+pub struct StatsParams {
+    pub year: Option<i32>,
+}
+
+/// The body for POST /item/{key}/rename.
+#[derive(serde_derive::Deserialize)]
+pub struct RenameRequest {
+    /// The new key.
+    pub key: String,
+}
+
+/// The body for POST /list/{key}/item.
+#[derive(serde_derive::Deserialize)]
+pub struct ListInsertRequest {
+    pub item: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Where to insert the item; defaults to the end.
+    #[serde(default)]
+    pub index: Option<usize>,
+}
+
+/// The body for POST /list/{key}/move.
+#[derive(serde_derive::Deserialize)]
+pub struct ListMoveRequest {
+    pub item: String,
+    pub index: usize,
+}
+
+/// The query parameters for /suggestions. Weights not given use the
+/// defaults.
+#[derive(serde_derive::Deserialize)]
+pub struct SuggestParams {
+    pub limit: Option<usize>,
+    pub kind: Option<shelf::common::Kind>,
+    pub tag: Option<String>,
+    pub priority: Option<f64>,
+    pub series: Option<f64>,
+    pub tag_affinity: Option<f64>,
+    pub person_affinity: Option<f64>,
+    pub length: Option<f64>,
+    pub age: Option<f64>,
+}
+
+/// The query parameters for /activity.
+#[derive(serde_derive::Deserialize)]
+pub struct ActivityParams {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// The query parameters for /release.
+#[derive(serde_derive::Deserialize)]
+pub struct ReleaseParams {
+    /// Check only this item, instead of all ongoing serials.
+    pub key: Option<String>,
+}
+
+/// The query parameters for /import.
+#[derive(serde_derive::Deserialize)]
+pub struct ImportParams {
+    /// The page to import.
+    pub url: String,
+}
+
+/// The query parameters for /import/{format}.
+#[derive(serde_derive::Deserialize)]
+pub struct ImportFileParams {
+    /// Only return the plan, without changing the shelf.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The query parameters for /export.
+#[derive(serde_derive::Deserialize)]
+pub struct ExportParams {
+    /// `csv` or `jsonl`.
+    pub format: String,
+    /// Comma-separated column names; see `shelf::export::table::Column`.
+    pub columns: Option<String>,
+    pub kind: Option<shelf::common::Kind>,
+    pub status: Option<shelf::common::Status>,
+    pub tag: Option<String>,
+}
+
+/// The query parameters for /airing/ical.
+#[derive(serde_derive::Deserialize)]
+pub struct AiringCalendarParams {
+    /// How many weeks ahead to include.
+    pub weeks: Option<u32>,
+}
+
+#[derive(serde_derive::Serialize)]
+pub struct ActivityPage<'a> {
+    /// The total number of events.
+    pub total: usize,
+    pub offset: usize,
+    pub events: &'a [shelf::activity::Activity<'a>],
+}
+
+/// The query parameters for /goal/{key}/progress.
+#[derive(serde_derive::Deserialize)]
+pub struct GoalProgressParams {
+    /// Compute progress as of this date (YYYY-MM-DD) instead of today.
+    pub date: Option<chrono::NaiveDate>,
+}
+
 #[derive(Debug)]
 pub struct ReqwestError {
     pub error: String,
@@ -0,0 +1,566 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Checking ongoing serials for chapters that have not been added as
+//! entries yet.
+
+use shelf::common::{Alternatives, DateBool, Kind, Status};
+use shelf::external::Provider;
+use shelf::item::{Entry, Item, PublicationStatus};
+
+use crate::fetch::Fetcher;
+
+/// A chapter listed by a source.
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct Chapter {
+    /// The chapter number, if it is a whole number.
+    pub number: Option<u32>,
+    pub volume: Option<u32>,
+    pub name: String,
+    pub url: Option<String>,
+    pub published: Option<chrono::NaiveDate>,
+}
+
+/// A site that lists the chapters of a series.
+pub trait Source: Send + Sync {
+    /// The provider whose external IDs this source understands.
+    fn provider(&self) -> Provider;
+
+    /// The URL of the chapter list for an external ID, or None if the
+    /// ID does not refer to a series.
+    fn chapters_url(&self, id: &str) -> Option<String>;
+
+    /// Parse the chapter list, in reading order.
+    fn parse(&self, body: &str) -> Result<Vec<Chapter>, String>;
+
+    /// The URL of the next page of the chapter list, if the list is
+    /// split into pages and `body` is not the last one.
+    fn next_page(&self, _id: &str, _body: &str) -> Option<String> {
+        None
+    }
+
+    /// Parse a chapter list fetched as several pages.
+    fn parse_pages(&self, pages: &[String]) -> Result<Vec<Chapter>, String> {
+        let mut chapters = Vec::new();
+        for page in pages {
+            chapters.extend(self.parse(page)?);
+        }
+        Ok(chapters)
+    }
+}
+
+/// The most pages fetched for one chapter list.
+const MAX_PAGES: usize = 20;
+
+/// All supported sources.
+pub fn sources() -> Vec<Box<dyn Source>> {
+    vec![
+        Box::new(MangaDex),
+        Box::new(DynastyScans),
+        Box::new(Webtoons),
+    ]
+}
+
+pub(crate) fn selector(s: &str) -> scraper::Selector {
+    scraper::Selector::parse(s).expect("Invalid selector")
+}
+
+pub(crate) fn text(element: scraper::ElementRef) -> String {
+    element.text().collect::<String>().trim().to_owned()
+}
+
+pub struct MangaDex;
+
+#[derive(serde_derive::Deserialize)]
+struct MangaDexFeed {
+    data: Vec<MangaDexChapter>,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    total: usize,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct MangaDexChapter {
+    id: String,
+    attributes: MangaDexChapterAttributes,
+}
+
+#[derive(serde_derive::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MangaDexChapterAttributes {
+    volume: Option<String>,
+    chapter: Option<String>,
+    title: Option<String>,
+    publish_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl Source for MangaDex {
+    fn provider(&self) -> Provider {
+        Provider::MangaDex
+    }
+
+    fn chapters_url(&self, id: &str) -> Option<String> {
+        Some(MangaDex::page_url(id, 0))
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<Chapter>, String> {
+        self.parse_pages(&[body.to_owned()])
+    }
+
+    fn next_page(&self, id: &str, body: &str) -> Option<String> {
+        let feed: MangaDexFeed = serde_json::from_str(body).ok()?;
+        let next = feed.offset + feed.data.len();
+        if feed.data.is_empty() || next >= feed.total {
+            None
+        } else {
+            Some(MangaDex::page_url(id, next))
+        }
+    }
+
+    fn parse_pages(&self, pages: &[String]) -> Result<Vec<Chapter>, String> {
+        let mut chapters: Vec<Chapter> = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for page in pages {
+            let feed: MangaDexFeed = serde_json::from_str(page).map_err(|err| err.to_string())?;
+            for chapter in feed.data {
+                let (id, attributes) = (chapter.id, chapter.attributes);
+                // Several groups may translate the same chapter, but
+                // chapters without a number (oneshots) are all distinct
+                let key = attributes.chapter.clone().unwrap_or_else(|| id.clone());
+                if !seen.insert(key) {
+                    continue;
+                }
+                let title = attributes.title.filter(|title| !title.is_empty());
+                let name = match (&attributes.chapter, title) {
+                    (Some(number), Some(title)) => format!("Chapter {}: {}", number, title),
+                    (Some(number), None) => format!("Chapter {}", number),
+                    (None, Some(title)) => title,
+                    (None, None) => "Oneshot".to_owned(),
+                };
+                chapters.push(Chapter {
+                    number: attributes.chapter.and_then(|number| number.parse().ok()),
+                    volume: attributes.volume.and_then(|volume| volume.parse().ok()),
+                    name,
+                    url: Some(format!("https://mangadex.org/chapter/{}", id)),
+                    published: attributes.publish_at.map(|t| t.naive_utc().date()),
+                });
+            }
+        }
+        Ok(chapters)
+    }
+}
+
+impl MangaDex {
+    /// The API allows at most 500 chapters per request.
+    const PAGE_SIZE: usize = 500;
+
+    fn page_url(id: &str, offset: usize) -> String {
+        format!(
+            "https://api.mangadex.org/manga/{}/feed?offset={}&limit={}&translatedLanguage[]=en&order[chapter]=asc",
+            id,
+            offset,
+            MangaDex::PAGE_SIZE
+        )
+    }
+}
+
+pub struct DynastyScans;
+
+impl Source for DynastyScans {
+    fn provider(&self) -> Provider {
+        Provider::DynastyScans
+    }
+
+    fn chapters_url(&self, id: &str) -> Option<String> {
+        if id.starts_with("series/") {
+            Provider::DynastyScans.url(id)
+        } else {
+            None
+        }
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<Chapter>, String> {
+        let document = scraper::Html::parse_document(body);
+        let link = selector("a.name");
+        let released = selector("small");
+        let mut chapters = Vec::new();
+        let mut volume = None;
+        // Chapters are numbered in order, like the importer does
+        for element in document.select(&selector(".chapter-list > dt, .chapter-list > dd")) {
+            if element.value().name() == "dt" {
+                volume = text(element)
+                    .strip_prefix("Volume ")
+                    .and_then(|volume| volume.trim().parse().ok());
+                continue;
+            }
+            let link = match element.select(&link).next() {
+                Some(link) => link,
+                None => continue,
+            };
+            let published = element.select(&released).next().and_then(|small| {
+                let date = text(small);
+                let date = date.trim_start_matches("released").trim();
+                chrono::NaiveDate::parse_from_str(date, "%b %e '%y").ok()
+            });
+            chapters.push(Chapter {
+                number: Some(chapters.len() as u32 + 1),
+                volume,
+                name: text(link),
+                url: link
+                    .value()
+                    .attr("href")
+                    .map(|href| format!("https://dynasty-scans.com{}", href)),
+                published,
+            });
+        }
+        if chapters.is_empty() {
+            return Err("No chapters found".to_owned());
+        }
+        Ok(chapters)
+    }
+}
+
+pub struct Webtoons;
+
+impl Source for Webtoons {
+    fn provider(&self) -> Provider {
+        Provider::Webtoons
+    }
+
+    fn chapters_url(&self, id: &str) -> Option<String> {
+        Provider::Webtoons.url(id)
+    }
+
+    /// Only the first page of the episode list is parsed, which holds
+    /// the latest episodes.
+    fn parse(&self, body: &str) -> Result<Vec<Chapter>, String> {
+        let document = scraper::Html::parse_document(body);
+        let name = selector(".subj span");
+        let date = selector(".date");
+        let link = selector("a");
+        let mut chapters = Vec::new();
+        for element in document.select(&selector("#_listUl > li")) {
+            let number = element
+                .value()
+                .attr("data-episode-no")
+                .and_then(|number| number.parse().ok());
+            let name = element.select(&name).next().map(text).unwrap_or_default();
+            chapters.push(Chapter {
+                number,
+                volume: None,
+                name,
+                url: element
+                    .select(&link)
+                    .next()
+                    .and_then(|link| link.value().attr("href"))
+                    .map(|href| href.to_owned()),
+                published: element.select(&date).next().and_then(|date| {
+                    chrono::NaiveDate::parse_from_str(&text(date), "%b %d, %Y").ok()
+                }),
+            });
+        }
+        if chapters.is_empty() {
+            return Err("No episodes found".to_owned());
+        }
+        // The list is newest first
+        chapters.reverse();
+        Ok(chapters)
+    }
+}
+
+/// The result of checking one item.
+#[derive(Debug, serde_derive::Serialize)]
+pub struct ReleaseReport {
+    pub item: String,
+    pub provider: Provider,
+    /// The number of chapters listed by the source.
+    pub available: usize,
+    /// Chapters not yet added as entries.
+    pub new: Vec<Chapter>,
+    /// Whether the new chapters were added to the item.
+    pub appended: bool,
+    pub error: Option<String>,
+}
+
+/// Whether an item should be checked for new chapters.
+pub fn is_tracked(item: &Item) -> bool {
+    item.kind == Kind::Manga
+        && item.publication_status == PublicationStatus::Publishing
+        && item.status != Status::Dropped
+}
+
+/// Find the source, external ID and chapter list URL for an item.
+fn find_source<'a, 'b>(
+    item: &'b Item,
+    sources: &'a [Box<dyn Source>],
+) -> Option<(&'a dyn Source, &'b str, String)> {
+    sources.iter().find_map(|source| {
+        let id = item.external_ids.get(&source.provider())?;
+        source
+            .chapters_url(id)
+            .map(|url| (source.as_ref(), id.as_str(), url))
+    })
+}
+
+/// Fetch every page of a chapter list and parse it.
+async fn fetch_chapters(
+    source: &dyn Source,
+    id: &str,
+    url: String,
+    fetcher: &dyn Fetcher,
+) -> Result<Vec<Chapter>, String> {
+    let mut pages = Vec::new();
+    let mut next = Some(url);
+    while let Some(url) = next {
+        if pages.len() == MAX_PAGES {
+            return Err(format!("Chapter list is longer than {} pages", MAX_PAGES));
+        }
+        let body = fetcher.fetch(&url).await.map_err(|err| err.to_string())?;
+        next = source.next_page(id, &body);
+        pages.push(body);
+    }
+    source.parse_pages(&pages)
+}
+
+fn is_known(item: &Item, chapter: &Chapter) -> bool {
+    item.entries.iter().any(|entry| match chapter.number {
+        Some(number) => entry.number == Some(number),
+        None => entry.name.as_ref().and_then(|name| name.get_default()) == Some(&chapter.name),
+    })
+}
+
+/// The chapters that are not entries of the item yet.
+pub fn new_chapters(item: &Item, chapters: &[Chapter]) -> Vec<Chapter> {
+    chapters
+        .iter()
+        .filter(|chapter| !is_known(item, chapter))
+        .cloned()
+        .collect()
+}
+
+/// Add chapters not yet in the item as unread entries. Returns the
+/// number of entries added.
+pub fn append(item: &mut Item, chapters: &[Chapter]) -> usize {
+    let chapters = new_chapters(item, chapters);
+    for chapter in chapters.iter() {
+        item.entries.push(Entry {
+            name: Some(Alternatives::new("English", chapter.name.clone())),
+            number: chapter.number,
+            volume: chapter.volume,
+            completed: DateBool::False,
+            aired: chapter.published.map(DateBool::Date).unwrap_or_default(),
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        });
+    }
+    chapters.len()
+}
+
+/// Fetch the chapter list of an item and compare it to the item's
+/// entries. Returns None if no source supports the item.
+pub async fn check(
+    item: &Item,
+    sources: &[Box<dyn Source>],
+    fetcher: &dyn Fetcher,
+) -> Option<ReleaseReport> {
+    let (source, id, url) = find_source(item, sources)?;
+    let mut report = ReleaseReport {
+        item: item.key.clone(),
+        provider: source.provider(),
+        available: 0,
+        new: Vec::new(),
+        appended: false,
+        error: None,
+    };
+    match fetch_chapters(source, id, url, fetcher).await {
+        Ok(chapters) => {
+            report.available = chapters.len();
+            report.new = new_chapters(item, &chapters);
+        }
+        Err(err) => {
+            log::warn!(
+                target: crate::LOG_NAME,
+                "Could not check releases of {}: {}",
+                item.key,
+                err
+            );
+            report.error = Some(err);
+        }
+    }
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append, check, sources};
+    use crate::fetch::FixtureFetcher;
+    use shelf::common::{Alternatives, DateBool, Kind};
+    use shelf::external::Provider;
+    use shelf::item::{Entry, Item};
+
+    fn entry(number: u32) -> Entry {
+        Entry {
+            name: None,
+            number: Some(number),
+            volume: None,
+            completed: DateBool::True,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        }
+    }
+
+    fn item(provider: Provider, id: &str, read: u32) -> Item {
+        let mut item = Item {
+            key: "manga-test".into(),
+            kind: Kind::Manga,
+            name: Alternatives::new("English", "Test"),
+            entries: (1..=read).map(entry).collect(),
+            ..Default::default()
+        };
+        item.external_ids.insert(provider, id.into());
+        item
+    }
+
+    fn fetcher() -> FixtureFetcher {
+        FixtureFetcher::new()
+            .with(
+                "dynasty-scans.com/series/march_comes_in_like_a_lion",
+                "test.march-comes-in-like-a-lion.html",
+            )
+            .with("titleNo=1263", "test.dating-with-a-tail.html")
+            .with(
+                "3f1453fb-9dac-4aca-a2ea-69613856c952/feed",
+                "test.tamen-de-gushi.feed.json",
+            )
+            .with(
+                "a1c3e5f7-0000-4000-8000-000000000000/feed?offset=0&",
+                "test.mangadex-paged.feed-1.json",
+            )
+            .with(
+                "a1c3e5f7-0000-4000-8000-000000000000/feed?offset=2&",
+                "test.mangadex-paged.feed-2.json",
+            )
+    }
+
+    #[tokio::test]
+    async fn test_dynasty_scans() {
+        let item = item(
+            Provider::DynastyScans,
+            "series/march_comes_in_like_a_lion",
+            30,
+        );
+        let report = check(&item, &sources(), &fetcher()).await.unwrap();
+        assert_eq!(None, report.error);
+        assert_eq!(33, report.available);
+        let numbers: Vec<Option<u32>> = report.new.iter().map(|c| c.number).collect();
+        assert_eq!(vec![Some(31), Some(32), Some(33)], numbers);
+
+        let report = check(&item, &sources(), &FixtureFetcher::new())
+            .await
+            .unwrap();
+        assert!(report.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dynasty_scans_chapters() {
+        let item = item(
+            Provider::DynastyScans,
+            "series/march_comes_in_like_a_lion",
+            0,
+        );
+        let report = check(&item, &sources(), &fetcher()).await.unwrap();
+        let first = &report.new[0];
+        assert_eq!("Chapter 1: Kiriyama Rei", first.name);
+        assert_eq!(Some(1), first.volume);
+        assert_eq!(
+            Some(chrono::NaiveDate::from_ymd_opt(2008, 11, 7).unwrap()),
+            first.published
+        );
+        assert_eq!(
+            Some("https://dynasty-scans.com/chapters/march_comes_in_like_a_lion_ch01"),
+            first.url.as_deref()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webtoons() {
+        let item = item(Provider::Webtoons, "1263", 2);
+        let report = check(&item, &sources(), &fetcher()).await.unwrap();
+        assert_eq!(None, report.error);
+        assert_eq!(3, report.available);
+        assert_eq!(1, report.new.len());
+        assert_eq!(Some(3), report.new[0].number);
+        assert_eq!("Episode 3", report.new[0].name);
+        assert_eq!(
+            Some(chrono::NaiveDate::from_ymd_opt(2020, 11, 12).unwrap()),
+            report.new[0].published
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mangadex_append() {
+        let mut item = item(
+            Provider::MangaDex,
+            "3f1453fb-9dac-4aca-a2ea-69613856c952",
+            1,
+        );
+        let report = check(&item, &sources(), &fetcher()).await.unwrap();
+        assert_eq!(None, report.error);
+        // The duplicate translation of chapter 2 is skipped
+        assert_eq!(4, report.available);
+        let names: Vec<&str> = report.new.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            vec!["Chapter 2", "Chapter 2.5: Extra: Insert Art", "Chapter 3"],
+            names
+        );
+        assert_eq!(None, report.new[1].number);
+
+        assert_eq!(3, append(&mut item, &report.new));
+        assert_eq!(4, item.entries.len());
+        assert_eq!(DateBool::False, item.entries[3].completed);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2018, 4, 1).unwrap()),
+            item.entries[3].aired
+        );
+        // Appending again adds nothing, including unnumbered chapters
+        assert_eq!(0, append(&mut item, &report.new));
+    }
+
+    #[tokio::test]
+    async fn test_mangadex_pages() {
+        let item = item(
+            Provider::MangaDex,
+            "a1c3e5f7-0000-4000-8000-000000000000",
+            1,
+        );
+        let report = check(&item, &sources(), &fetcher()).await.unwrap();
+        assert_eq!(None, report.error);
+        // Chapter 2 is translated on both pages; each oneshot is kept
+        assert_eq!(4, report.available);
+        let names: Vec<&str> = report.new.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(vec!["Chapter 2", "Side Story", "Oneshot"], names);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported() {
+        let item = item(Provider::Cubari, "imgur/oeJ1wKg", 0);
+        assert!(check(&item, &sources(), &fetcher()).await.is_none());
+    }
+}
@@ -23,6 +23,12 @@ pub fn api(
         .boxed()
         .or(item_post(shelf.clone()))
         .boxed()
+        .or(item_delete(shelf.clone()))
+        .boxed()
+        .or(item_rename(shelf.clone()))
+        .boxed()
+        .or(lookup(shelf.clone()))
+        .boxed()
         .or(person_list(shelf.clone()))
         .boxed()
         .or(person_create(shelf.clone()))
@@ -33,6 +39,64 @@ pub fn api(
         .boxed()
         .or(tag_list(shelf.clone()))
         .boxed()
+        .or(schema_fields(shelf.clone()))
+        .boxed()
+        .or(settings_get(shelf.clone()))
+        .boxed()
+        .or(settings_put(shelf.clone()))
+        .boxed()
+        .or(stats(shelf.clone()))
+        .boxed()
+        .or(stats_time(shelf.clone()))
+        .boxed()
+        .or(list_list(shelf.clone()))
+        .boxed()
+        .or(list_get(shelf.clone()))
+        .boxed()
+        .or(list_create(shelf.clone()))
+        .boxed()
+        .or(list_delete(shelf.clone()))
+        .boxed()
+        .or(list_insert_item(shelf.clone()))
+        .boxed()
+        .or(list_move_item(shelf.clone()))
+        .boxed()
+        .or(list_remove_item(shelf.clone()))
+        .boxed()
+        .or(goal_list(shelf.clone()))
+        .boxed()
+        .or(suggestions(shelf.clone()))
+        .boxed()
+        .or(activity(shelf.clone()))
+        .boxed()
+        .or(activity_atom(shelf.clone()))
+        .boxed()
+        .or(airing(shelf.clone()))
+        .boxed()
+        .or(airing_ical(shelf.clone()))
+        .boxed()
+        .or(release_check(shelf.clone()))
+        .boxed()
+        .or(release_append(shelf.clone()))
+        .boxed()
+        .or(import(shelf.clone()))
+        .boxed()
+        .or(import_file(shelf.clone()))
+        .boxed()
+        .or(export(shelf.clone()))
+        .boxed()
+        .or(job_list(shelf.clone()))
+        .boxed()
+        .or(job_submit(shelf.clone()))
+        .boxed()
+        .or(job_get(shelf.clone()))
+        .boxed()
+        .or(job_events(shelf.clone()))
+        .boxed()
+        .or(goal_create(shelf.clone()))
+        .boxed()
+        .or(goal_progress(shelf.clone()))
+        .boxed()
         .or(blob_list(shelf.clone()))
         .boxed()
         .or(blob_create(shelf.clone()))
@@ -79,6 +143,35 @@ pub fn item_post(
         .and_then(handlers::item_post)
 }
 
+pub fn item_delete(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / String)
+        .and(warp::delete())
+        .and(with_shelf(shelf))
+        .and_then(handlers::item_delete)
+}
+
+pub fn item_rename(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("item" / String / "rename")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(16 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::item_rename)
+}
+
+pub fn lookup(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("lookup")
+        .and(warp::get())
+        .and(warp::query::<model::LookupParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::lookup)
+}
+
 pub fn person_list(
     shelf: model::AppStateRef,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -126,6 +219,285 @@ pub fn tag_list(
         .and_then(handlers::tag_list)
 }
 
+pub fn schema_fields(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("schema" / "fields")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::schema_fields)
+}
+
+pub fn settings_get(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("settings")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::settings_get)
+}
+
+pub fn settings_put(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("settings")
+        .and(warp::put())
+        .and(warp::body::content_length_limit(16 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::settings_put)
+}
+
+pub fn stats(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("stats")
+        .and(warp::get())
+        .and(warp::query::<model::StatsParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::stats)
+}
+
+pub fn stats_time(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("stats" / "time")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::stats_time)
+}
+
+pub fn suggestions(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("suggestions")
+        .and(warp::get())
+        .and(warp::query::<model::SuggestParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::suggestions)
+}
+
+pub fn activity(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("activity")
+        .and(warp::get())
+        .and(warp::query::<model::ActivityParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::activity)
+}
+
+pub fn activity_atom(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("activity" / "atom")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::activity_atom)
+}
+
+pub fn airing(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("airing")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::airing)
+}
+
+pub fn airing_ical(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("airing" / "ical")
+        .and(warp::get())
+        .and(warp::query::<model::AiringCalendarParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::airing_ical)
+}
+
+pub fn release_check(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("release")
+        .and(warp::get())
+        .and(warp::query::<model::ReleaseParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::release_check)
+}
+
+pub fn release_append(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("release")
+        .and(warp::post())
+        .and(warp::query::<model::ReleaseParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::release_append)
+}
+
+pub fn import(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("import")
+        .and(warp::post())
+        .and(warp::query::<model::ImportParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::import)
+}
+
+pub fn import_file(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("import" / String)
+        .and(warp::post())
+        .and(warp::query::<model::ImportFileParams>())
+        // Allow uploads up to 50 MiB
+        .and(warp::body::content_length_limit(50 * 1024 * 1024).and(warp::body::bytes()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::import_file)
+}
+
+pub fn export(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("export")
+        .and(warp::get())
+        .and(warp::query::<model::ExportParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::export)
+}
+
+pub fn job_list(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::job_list)
+}
+
+pub fn job_submit(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::job_submit)
+}
+
+pub fn job_get(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs" / u64)
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::job_get)
+}
+
+pub fn job_events(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs" / u64 / "events")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::job_events)
+}
+
+pub fn list_list(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_list)
+}
+
+pub fn list_get(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / String)
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_get)
+}
+
+pub fn list_create(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list")
+        .and(warp::put())
+        .and(warp::body::content_length_limit(256 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_create)
+}
+
+pub fn list_delete(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / String)
+        .and(warp::delete())
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_delete)
+}
+
+pub fn list_insert_item(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / String / "item")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(16 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_insert_item)
+}
+
+pub fn list_move_item(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / String / "move")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(16 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_move_item)
+}
+
+pub fn list_remove_item(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("list" / String / "item" / String)
+        .and(warp::delete())
+        .and(with_shelf(shelf))
+        .and_then(handlers::list_remove_item)
+}
+
+pub fn goal_list(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("goal")
+        .and(warp::get())
+        .and(with_shelf(shelf))
+        .and_then(handlers::goal_list)
+}
+
+pub fn goal_create(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("goal")
+        .and(warp::put())
+        .and(warp::body::content_length_limit(16 * 1024).and(warp::body::json()))
+        .and(with_shelf(shelf))
+        .and_then(handlers::goal_create)
+}
+
+pub fn goal_progress(
+    shelf: model::AppStateRef,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("goal" / String / "progress")
+        .and(warp::get())
+        .and(warp::query::<model::GoalProgressParams>())
+        .and(with_shelf(shelf))
+        .and_then(handlers::goal_progress)
+}
+
 pub fn blob_list(
     shelf: model::AppStateRef,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -0,0 +1,91 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Back up the whole shelf to a single archive file, or restore one.
+
+const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
+    name: "shelf",
+    author: "lidavidm",
+};
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = clap::App::new("archive")
+        .about("Back up the shelf to an archive, or restore one")
+        .subcommand(
+            clap::SubCommand::with_name("create")
+                .about("Write the whole shelf to an archive")
+                .arg(
+                    clap::Arg::with_name("FILE")
+                        .required(true)
+                        .help("The archive to write"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about("Check an archive and add its contents to a shelf")
+                .arg(
+                    clap::Arg::with_name("directory")
+                        .long("directory")
+                        .takes_value(true)
+                        .help("Restore into this directory instead of the user's shelf"),
+                )
+                .arg(
+                    clap::Arg::with_name("FILE")
+                        .required(true)
+                        .help("The archive to restore"),
+                ),
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .get_matches();
+
+    let (command, args) = matches.subcommand();
+    let args = args.unwrap();
+    let file = args.value_of("FILE").unwrap();
+    let library_root = match args.value_of("directory") {
+        Some(directory) => {
+            std::fs::create_dir_all(directory)?;
+            std::path::PathBuf::from(directory)
+        }
+        None => app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "shelf")?,
+    };
+    let mut shelf = shelf::Shelf::new();
+    eprintln!("Opening shelf: {}", library_root.to_string_lossy());
+    let saver = shelf::save::DirectoryShelf::new(&library_root)?;
+    saver.load(&mut shelf)?;
+
+    if command == "create" {
+        let manifest = shelf::archive::export(&shelf, &saver, std::fs::File::create(file)?)?;
+        println!("Archived {} files to {}", manifest.files.len(), file);
+        return Ok(());
+    }
+
+    let (manifest, archive) = shelf::archive::read(std::fs::File::open(file)?)?;
+    eprintln!(
+        "Archive created {} with {} files",
+        manifest.created,
+        manifest.files.len()
+    );
+    let report = archive.restore(&mut shelf, &saver)?;
+    for conflict in report.conflicts.iter() {
+        println!("! {}", conflict);
+    }
+    saver.save(&mut shelf)?;
+    println!(
+        "Restored {} new, {} unchanged, {} conflicts",
+        report.added,
+        report.unchanged,
+        report.conflicts.len()
+    );
+    Ok(())
+}
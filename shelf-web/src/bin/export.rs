@@ -14,6 +14,8 @@
 
 use std::collections::HashMap;
 
+use shelf::export::table::{self, Column};
+
 const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
     name: "shelf",
     author: "lidavidm",
@@ -97,22 +99,51 @@ fn format_kind(args: std::collections::HashMap<String, tera::Value>) -> tera::Re
     Ok(tera::Value::String(kind))
 }
 
-pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let library_root = app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "shelf")?;
-    let mut shelf = shelf::Shelf::new();
+fn format_rating(
+    args: std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let rating = match args.get("rating") {
+        Some(tera::Value::Null) | None => return Ok(tera::Value::String("-".to_owned())),
+        Some(rating) => rating,
+    };
+    let rating: shelf::rating::Rating = serde_json::from_value(rating.clone())
+        .map_err(|e| make_err(format!("format_rating: invalid rating: {}", e)))?;
+    let rating = match args.get("scale") {
+        Some(scale) => {
+            let scale: shelf::rating::Scale = serde_json::from_value(scale.clone())
+                .map_err(|e| make_err(format!("format_rating: invalid scale: {}", e)))?;
+            // Only convert when no precision is lost
+            rating.convert_exact(scale).unwrap_or(rating)
+        }
+        None => rating,
+    };
+    Ok(tera::Value::String(rating.to_string()))
+}
 
-    // Load the shelf, but discard the saver, since it can't be moved
-    // across threads
-    {
-        eprintln!("Opening shelf: {}", library_root.to_string_lossy());
-        let saver = shelf::save::DirectoryShelf::new(&library_root)?;
-        saver.load(&mut shelf)?;
-    }
+/// Make the format_date function, which shows timestamps in the
+/// library's time zone.
+fn format_date(settings: shelf::settings::Settings) -> tera::GlobalFn {
+    Box::new(move |args| {
+        let date = match args.get("date") {
+            Some(date) => date,
+            None => return Ok(tera::Value::String(String::new())),
+        };
+        let date: shelf::common::DateBool = serde_json::from_value(date.clone())
+            .map_err(|e| make_err(format!("format_date: invalid date: {}", e)))?;
+        Ok(tera::Value::String(
+            date.with_time_zone(settings.time_zone).to_string(),
+        ))
+    })
+}
 
+/// Render the bookshelf page of books and other written works.
+fn markdown(shelf: &shelf::Shelf) -> Result<String, Box<dyn std::error::Error>> {
     let mut templates = tera::compile_templates!("templates/**/*");
     templates.register_filter("count_completed", count_completed);
     templates.register_function("lookup", Box::new(lookup));
     templates.register_function("format_kind", Box::new(format_kind));
+    templates.register_function("format_rating", Box::new(format_rating));
+    templates.register_function("format_date", format_date(*shelf.settings()));
 
     let mut value = tera::Context::new();
     let mut items: Vec<shelf::item::Item> = shelf
@@ -146,7 +177,134 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     value.insert("items", &items);
     value.insert("people", &people);
-    println!("{}", templates.render("bookshelf.md", &value)?);
+    Ok(templates.render("bookshelf.md", &value)?)
+}
+
+/// Parse the values of a repeated flag, e.g. kinds or statuses by name.
+fn values<T: serde::de::DeserializeOwned>(
+    matches: &clap::ArgMatches,
+    name: &str,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(|value| {
+            serde_json::from_value(serde_json::Value::String(value.to_owned()))
+                .map_err(|_| format!("Invalid --{} {:?}", name, value).into())
+        })
+        .collect()
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = clap::App::new("export")
+        .about("Export the shelf")
+        .arg(
+            clap::Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["markdown", "mal", "goodreads", "csv", "jsonl"])
+                .default_value("markdown")
+                .help(
+                    "markdown: the bookshelf page; mal: MyAnimeList XML; \
+                     goodreads: Goodreads library CSV; csv: CSV of --columns; \
+                     jsonl: JSON Lines of --columns",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("columns")
+                .long("columns")
+                .takes_value(true)
+                .help(
+                    "Comma-separated columns for csv and jsonl, e.g. \
+                     key,name,name.Japanese,people.Author,series,completed,extra.mal_id",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("kind")
+                .long("kind")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only export items of this kind (csv and jsonl; may be repeated)"),
+        )
+        .arg(
+            clap::Arg::with_name("status")
+                .long("status")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only export items with this status (csv and jsonl; may be repeated)"),
+        )
+        .arg(
+            clap::Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only export items with this tag (csv and jsonl; may be repeated)"),
+        )
+        .arg(
+            clap::Arg::with_name("list")
+                .long("list")
+                .takes_value(true)
+                .possible_values(&["anime", "manga"])
+                .default_value("anime")
+                .help("Which MyAnimeList list to export"),
+        )
+        .get_matches();
+
+    let library_root = app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "shelf")?;
+    let mut shelf = shelf::Shelf::new();
+
+    // Load the shelf, but discard the saver, since it can't be moved
+    // across threads
+    {
+        eprintln!("Opening shelf: {}", library_root.to_string_lossy());
+        let saver = shelf::save::DirectoryShelf::new(&library_root)?;
+        saver.load(&mut shelf)?;
+    }
+
+    let (out, report) = match matches.value_of("format") {
+        Some("mal") => {
+            let list = match matches.value_of("list") {
+                Some("manga") => shelf::import::mal::List::Manga,
+                _ => shelf::import::mal::List::Anime,
+            };
+            shelf::export::mal::export(&shelf, list)
+        }
+        Some("goodreads") => shelf::export::goodreads::export(&shelf)?,
+        Some(format @ "csv") | Some(format @ "jsonl") => {
+            let columns = match matches.value_of("columns") {
+                Some(columns) => Column::parse_list(columns)?,
+                None => Column::defaults(),
+            };
+            let filter = shelf::filter::ItemFilter {
+                kinds: values(&matches, "kind")?,
+                statuses: values(&matches, "status")?,
+                tags: values(&matches, "tag")?,
+                ..Default::default()
+            };
+            if format == "csv" {
+                table::csv(&shelf, &columns, &filter)?
+            } else {
+                table::json_lines(&shelf, &columns, &filter)
+            }
+        }
+        _ => {
+            println!("{}", markdown(&shelf)?);
+            return Ok(());
+        }
+    };
+    for (key, reason) in report.skipped.iter() {
+        eprintln!("Skipped {}: {}", key, reason);
+    }
+    eprintln!(
+        "Exported {} items, skipped {}",
+        report.exported,
+        report.skipped.len()
+    );
+    print!("{}", out);
 
     Ok(())
 }
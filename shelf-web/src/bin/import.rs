@@ -0,0 +1,156 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import a list exported from another site into the shelf.
+
+use shelf::import::spreadsheet::{self, Mapping};
+use shelf::import::{calibre, diff, kobo, Action, Format};
+
+const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
+    name: "shelf",
+    author: "lidavidm",
+};
+
+/// Show a scalar field value, or None for lists and maps.
+fn show<T: serde::Serialize>(value: &T) -> Option<String> {
+    match serde_json::to_value(value).ok()? {
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => None,
+        value => Some(value.to_string()),
+    }
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = clap::App::new("import")
+        .about("Import a list exported from another site")
+        .arg(
+            clap::Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&[
+                    "mal",
+                    "goodreads",
+                    "letterboxd",
+                    "imdb",
+                    "anilist",
+                    "kitsu",
+                    "calibre",
+                    "kobo",
+                    "csv",
+                ])
+                .required(true)
+                .help(
+                    "The format of the file (mal: MyAnimeList XML export; \
+                     goodreads: Goodreads library CSV export; \
+                     letterboxd: Letterboxd export ZIP; imdb: IMDb ratings CSV export; \
+                     anilist: AniList list JSON; kitsu: Kitsu library JSON; \
+                     calibre: Calibre library directory; \
+                     kobo: KoboReader.sqlite from a Kobo e-reader; \
+                     csv: any CSV or TSV file, read with --mapping)",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("mapping")
+                .long("mapping")
+                .takes_value(true)
+                .required_if("format", "csv")
+                .help("A YAML file mapping the columns of a CSV or TSV file to item fields"),
+        )
+        .arg(
+            clap::Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Show what would change, field by field, without saving"),
+        )
+        .arg(
+            clap::Arg::with_name("FILE")
+                .required(true)
+                .help("The exported file, Calibre library directory, or Kobo database"),
+        )
+        .get_matches();
+    let format = matches.value_of("format").unwrap();
+    let file = matches.value_of("FILE").unwrap();
+
+    let library_root = app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "shelf")?;
+    let mut shelf = shelf::Shelf::new();
+    eprintln!("Opening shelf: {}", library_root.to_string_lossy());
+    let saver = shelf::save::DirectoryShelf::new(&library_root)?;
+    saver.load(&mut shelf)?;
+
+    let plan = match format {
+        "calibre" => calibre::plan(&shelf, std::path::Path::new(file))?,
+        "kobo" => kobo::plan(&shelf, std::path::Path::new(file))?,
+        "csv" => {
+            let mapping = std::fs::read_to_string(matches.value_of("mapping").unwrap())?;
+            spreadsheet::plan(&shelf, &Mapping::parse(&mapping)?, &std::fs::read(file)?)?
+        }
+        _ => {
+            let format: Format = format.parse()?;
+            format.plan(&shelf, &std::fs::read(file)?)?
+        }
+    };
+    let dry_run = matches.is_present("dry-run");
+    for warning in plan.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    for change in plan.changes.iter() {
+        match change.action {
+            Action::Insert => println!("+ {}", change.item.key),
+            Action::Update => println!("~ {} ({})", change.item.key, change.fields.join(", ")),
+            Action::Unchanged => {}
+        }
+        if dry_run && change.action == Action::Update {
+            if let Some(before) = shelf.get_item(&change.item.key) {
+                for (field, old, new) in diff(before, &change.item) {
+                    match (show(&old), show(&new)) {
+                        (Some(old), Some(new)) => println!("    {}: {} -> {}", field, old, new),
+                        _ => println!("    {}: (changed)", field),
+                    }
+                }
+            }
+        }
+    }
+    for person in plan.people.iter() {
+        println!("+ {}", person.key);
+    }
+    for series in plan.series.iter() {
+        println!("+ {}", series.key);
+    }
+    for file in plan.blobs.iter() {
+        println!("+ {} ({})", file.blob.key, file.source.to_string_lossy());
+    }
+    for title in plan.unmatched.iter() {
+        println!("? {}", title);
+    }
+    println!(
+        "{} new, {} updated, {} unchanged, {} unmatched",
+        plan.count(Action::Insert),
+        plan.count(Action::Update),
+        plan.count(Action::Unchanged),
+        plan.unmatched.len()
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    plan.write_blobs(&saver)?;
+    let report = plan.apply(&mut shelf);
+    for error in report.errors.iter() {
+        eprintln!("Error: {}", error);
+    }
+    saver.save(&mut shelf)?;
+    println!(
+        "Imported {} new and {} updated items, {} new people, {} new series and {} new blobs",
+        report.inserted, report.updated, report.people, report.series, report.blobs
+    );
+    Ok(())
+}
@@ -75,6 +75,47 @@ fn count_completed(
     Ok(tera::Value::Number(count.into()))
 }
 
+fn make_err(s: impl Into<String>) -> tera::Error {
+    tera::Error::from_kind(tera::ErrorKind::Msg(s.into()))
+}
+
+fn format_rating(
+    args: std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let rating = match args.get("rating") {
+        Some(tera::Value::Null) | None => return Ok(tera::Value::String("-".to_owned())),
+        Some(rating) => rating,
+    };
+    let rating: shelf::rating::Rating = serde_json::from_value(rating.clone())
+        .map_err(|e| make_err(format!("format_rating: invalid rating: {}", e)))?;
+    let rating = match args.get("scale") {
+        Some(scale) => {
+            let scale: shelf::rating::Scale = serde_json::from_value(scale.clone())
+                .map_err(|e| make_err(format!("format_rating: invalid scale: {}", e)))?;
+            // Only convert when no precision is lost
+            rating.convert_exact(scale).unwrap_or(rating)
+        }
+        None => rating,
+    };
+    Ok(tera::Value::String(rating.to_string()))
+}
+
+/// Make the format_date function, which shows timestamps in the
+/// library's time zone.
+fn format_date(settings: shelf::settings::Settings) -> tera::GlobalFn {
+    Box::new(move |args| {
+        let date = match args.get("date") {
+            Some(date) => date,
+            None => return Ok(tera::Value::String(String::new())),
+        };
+        let date: shelf::common::DateBool = serde_json::from_value(date.clone())
+            .map_err(|e| make_err(format!("format_date: invalid date: {}", e)))?;
+        Ok(tera::Value::String(
+            date.with_time_zone(settings.time_zone).to_string(),
+        ))
+    })
+}
+
 fn home(shelf: Arc<RwLock<shelf::Shelf>>) -> WithTemplate {
     let mut value = tera::Context::new();
     value.insert("items", shelf.read().unwrap().all_items());
@@ -141,6 +182,80 @@ fn item(params: ItemOptions, shelf: Arc<RwLock<shelf::Shelf>>) -> WithTemplate {
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct YearInReviewOptions {
+    year: i32,
+}
+
+fn year_in_review(params: YearInReviewOptions, shelf: Arc<RwLock<shelf::Shelf>>) -> WithTemplate {
+    let mut value = tera::Context::new();
+    let shelf = shelf.read().unwrap();
+    let stats = shelf.stats(Some(params.year));
+    let tz = shelf.settings().time_zone;
+
+    // Items finished this year, best-rated first
+    let mut completed: Vec<&shelf::item::Item> = shelf
+        .all_items()
+        .iter()
+        .filter(|item| item.completed.local_year(tz) == Some(params.year))
+        .collect();
+    completed.sort_by(|a, b| {
+        let a = a.rating.as_ref().map(|r| r.fraction()).unwrap_or(-1.0);
+        let b = b.rating.as_ref().map(|r| r.fraction()).unwrap_or(-1.0);
+        b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut people = std::collections::HashMap::new();
+    for person in shelf.query_people() {
+        people.insert(
+            person.key.clone(),
+            person
+                .name
+                .alternatives
+                .get(&person.name.default)
+                .cloned()
+                .unwrap_or_else(|| person.key.clone()),
+        );
+    }
+
+    let mut months: Vec<(String, usize, usize)> = stats
+        .items_completed_by_month
+        .keys()
+        .chain(stats.entries_completed_by_month.keys())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .map(|month| {
+            (
+                month.clone(),
+                stats
+                    .items_completed_by_month
+                    .get(month)
+                    .cloned()
+                    .unwrap_or(0),
+                stats
+                    .entries_completed_by_month
+                    .get(month)
+                    .cloned()
+                    .unwrap_or(0),
+            )
+        })
+        .collect();
+    months.sort();
+    let entries: usize = months.iter().map(|(_, _, entries)| entries).sum();
+
+    value.insert("year", &params.year);
+    value.insert("months", &months);
+    value.insert("entries", &entries);
+    value.insert("stats", &stats);
+    value.insert("completed", &completed);
+    value.insert("people", &people);
+    value.insert("hours", &format!("{:.1}", stats.time.total.hours()));
+    WithTemplate {
+        name: "year-in-review.html",
+        value,
+    }
+}
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let library_root = app_dirs::app_dir(app_dirs::AppDataType::UserConfig, &APP_INFO, "shelf")?;
     // let path = library_root.clone();
@@ -155,6 +270,7 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         saver.load(&mut shelf)?;
     }
 
+    let settings = *shelf.settings();
     let shelf_ref = Arc::new(RwLock::new(shelf));
     let shelf_ref = warp::any().map(move || shelf_ref.clone());
 
@@ -163,6 +279,14 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         .write()
         .unwrap()
         .register_filter("count_completed", count_completed);
+    templates
+        .write()
+        .unwrap()
+        .register_function("format_rating", Box::new(format_rating));
+    templates
+        .write()
+        .unwrap()
+        .register_function("format_date", format_date(settings));
     let templates = warp::any().map(move || templates.clone());
 
     let root = warp::path::end();
@@ -185,7 +309,17 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(item)
         .and(templates.clone())
         .map(render);
-    let views = view_home.or(view_in_progress).or(view_item);
+    let view_year_in_review = warp::get2()
+        .and(warp::path("year-in-review").and(warp::path::end()))
+        .and(warp::query::<YearInReviewOptions>())
+        .and(shelf_ref.clone())
+        .map(year_in_review)
+        .and(templates.clone())
+        .map(render);
+    let views = view_home
+        .or(view_in_progress)
+        .or(view_item)
+        .or(view_year_in_review);
 
     let assets = warp::path("static").and(warp::fs::dir("static"));
 
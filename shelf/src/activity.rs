@@ -0,0 +1,405 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! A chronological feed of consumption events, derived from the
+//! started/completed dates of items and entries.
+
+use std::cmp::Reverse;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeZone};
+
+use crate::common::{DateBool, Kind};
+use crate::item::{Entry, Item};
+use crate::rating::Rating;
+use crate::settings::Zone;
+use crate::shelf::Shelf;
+
+/// Something that happened to an item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Event {
+    Started,
+    Completed,
+    /// An entry was completed (index into `Item::entries`).
+    Entry(usize),
+}
+
+/// A free-text note about starting or completing an item. Notes about
+/// entries are kept on `Entry::note`, so they stay with their entry
+/// when entries are added, removed or reordered.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DiaryNote {
+    pub event: Event,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Activity<'a> {
+    pub item: &'a str,
+    pub kind: Kind,
+    pub event: Event,
+    pub date: DateBool,
+    pub entry: Option<&'a Entry>,
+    pub rating: Option<&'a Rating>,
+    pub note: Option<&'a str>,
+    /// A one-line description, e.g. "Watched episode 4 of Foo, rated 8/10".
+    pub summary: String,
+}
+
+fn is_video(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::TV | Kind::Film | Kind::OVA | Kind::ONA | Kind::Music | Kind::Play | Kind::Musical
+    )
+}
+
+fn describe_entry(kind: Kind, entry: &Entry) -> String {
+    let unit = if is_video(kind) { "episode" } else { "chapter" };
+    let mut parts = Vec::new();
+    if let Some(volume) = entry.volume {
+        parts.push(format!("volume {}", volume));
+    }
+    if let Some(number) = entry.number {
+        parts.push(format!("{} {}", unit, number));
+    }
+    let name = entry.name.as_ref().and_then(|name| name.get_default());
+    match (parts.is_empty(), name) {
+        (true, Some(name)) => format!("\"{}\"", name),
+        (true, None) => "an entry".to_owned(),
+        (false, Some(name)) => format!("{} (\"{}\")", parts.join(" "), name),
+        (false, None) => parts.join(" "),
+    }
+}
+
+fn summarize(item: &Item, event: Event, rating: Option<&Rating>) -> String {
+    let name = item
+        .name
+        .get_default()
+        .map(|s| s.as_str())
+        .unwrap_or(&item.key);
+    let mut summary = match event {
+        Event::Started => format!("Started {}", name),
+        Event::Completed => format!("Completed {}", name),
+        // A film's entries are viewings
+        Event::Entry(0) if item.kind == Kind::Film => format!("Watched {}", name),
+        Event::Entry(_) if item.kind == Kind::Film => format!("Rewatched {}", name),
+        Event::Entry(index) => {
+            let verb = match item.kind {
+                kind if is_video(kind) => "Watched",
+                Kind::VisualNovel => "Played",
+                _ => "Read",
+            };
+            format!(
+                "{} {} of {}",
+                verb,
+                describe_entry(item.kind, &item.entries[index]),
+                name
+            )
+        }
+    };
+    if let Some(rating) = rating {
+        summary.push_str(&format!(", rated {}", rating));
+    }
+    summary
+}
+
+/// List the dated events of an item, in no particular order.
+///
+/// Timestamps are converted to `tz`, if given.
+pub fn item_activity(item: &Item, tz: Option<Zone>) -> Vec<Activity<'_>> {
+    let note = |event: Event| match event {
+        Event::Entry(index) => item.entries[index].note.as_deref(),
+        _ => item
+            .diary
+            .iter()
+            .find(|note| note.event == event)
+            .map(|note| note.text.as_str()),
+    };
+    let mut events = vec![
+        (Event::Started, item.started, None, None),
+        (Event::Completed, item.completed, None, item.rating.as_ref()),
+    ];
+    for (index, entry) in item.entries.iter().enumerate() {
+        events.push((
+            Event::Entry(index),
+            entry.completed,
+            Some(entry),
+            entry.rating.as_ref(),
+        ));
+    }
+
+    events
+        .into_iter()
+        .filter(|(_, date, _, _)| date.is_dated())
+        .map(|(event, date, entry, rating)| Activity {
+            item: &item.key,
+            kind: item.kind,
+            event,
+            date: date.with_time_zone(tz),
+            entry,
+            rating,
+            note: note(event),
+            summary: summarize(item, event, rating),
+        })
+        .collect()
+}
+
+/// List the dated events of all items, most recent first.
+///
+/// Events dated only as "completed" (without a date) are left out.
+/// Imprecise dates sort after the precise dates they cover, e.g. "May
+/// 2020" is more recent than "2020-05-31".
+pub fn activity<'a, I: IntoIterator<Item = &'a Item>>(
+    items: I,
+    tz: Option<Zone>,
+) -> Vec<Activity<'a>> {
+    let mut events: Vec<Activity> = items
+        .into_iter()
+        .flat_map(|item| item_activity(item, tz))
+        .collect();
+    let event_order = |event: Event| match event {
+        Event::Started => 0,
+        Event::Entry(index) => index + 1,
+        Event::Completed => usize::MAX,
+    };
+    events.sort_by_key(|a| (Reverse(a.date), a.item, Reverse(event_order(a.event))));
+    events
+}
+
+impl Shelf {
+    pub fn activity(&self) -> Vec<Activity<'_>> {
+        activity(self.all_items(), self.settings().time_zone)
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// When something happened, using the start of the period for
+/// imprecise dates.
+fn instant(date: &DateBool) -> Option<DateTime<FixedOffset>> {
+    match date {
+        DateBool::Timestamp(t) => Some(*t),
+        _ => {
+            let (start, _) = date.range()?;
+            Some(FixedOffset::east_opt(0)?.from_utc_datetime(&start.and_hms_opt(0, 0, 0)?))
+        }
+    }
+}
+
+/// An RFC 3339 timestamp for a date, using the start of the period for
+/// imprecise dates.
+fn rfc3339(date: &DateBool) -> Option<String> {
+    instant(date).map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+/// An ID for an event that doesn't change when entries are added,
+/// removed or reordered.
+fn event_id(activity: &Activity) -> String {
+    match (activity.event, activity.entry) {
+        (Event::Started, _) => "started".to_owned(),
+        (Event::Completed, _) => "completed".to_owned(),
+        (Event::Entry(index), None) => format!("entry-{}", index),
+        (Event::Entry(_), Some(entry)) => {
+            let mut parts = vec!["entry".to_owned()];
+            if let Some(volume) = entry.volume {
+                parts.push(format!("v{}", volume));
+            }
+            if let Some(number) = entry.number {
+                parts.push(number.to_string());
+            }
+            parts.extend(rfc3339(&entry.completed));
+            parts.join("-")
+        }
+    }
+}
+
+/// Render events as an Atom feed.
+///
+/// `id` should be a stable URI identifying the feed.
+pub fn atom_feed(title: &str, id: &str, events: &[Activity]) -> String {
+    let updated = events
+        .iter()
+        .filter_map(|a| instant(&a.date))
+        .max()
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_owned());
+
+    let mut feed = String::new();
+    feed.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <title>{}</title>\n", escape_xml(title)));
+    feed.push_str(&format!("  <id>{}</id>\n", escape_xml(id)));
+    feed.push_str(&format!("  <updated>{}</updated>\n", updated));
+    feed.push_str("  <author><name>shelf</name></author>\n");
+    for event in events {
+        let updated = match rfc3339(&event.date) {
+            Some(updated) => updated,
+            None => continue,
+        };
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_xml(&event.summary)
+        ));
+        feed.push_str(&format!(
+            "    <id>{}</id>\n",
+            escape_xml(&format!(
+                "{}/{}/{}",
+                id.trim_end_matches('/'),
+                event.item,
+                event_id(event)
+            ))
+        ));
+        feed.push_str(&format!("    <updated>{}</updated>\n", updated));
+        // Atom requires content or an alternate link on every entry
+        feed.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            escape_xml(event.note.unwrap_or(&event.summary))
+        ));
+        feed.push_str("  </entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{activity, atom_feed, summarize, DiaryNote, Event};
+    use crate::common::{Alternatives, DateBool, Kind};
+    use crate::item::{Entry, Item};
+    use crate::rating::{Rating, Scale};
+    use chrono::{DateTime, NaiveDate};
+
+    fn entry(number: u32, completed: DateBool) -> Entry {
+        Entry {
+            name: None,
+            number: Some(number),
+            volume: None,
+            completed,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: serde_yaml::Value::Null,
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        let mut tv = Item {
+            key: "tv-foo".into(),
+            kind: Kind::TV,
+            name: Alternatives::new("English", "Foo"),
+            started: DateBool::Date(NaiveDate::from_ymd(2020, 5, 1)),
+            entries: vec![
+                entry(1, DateBool::Date(NaiveDate::from_ymd(2020, 5, 1))),
+                entry(
+                    2,
+                    DateBool::Timestamp(
+                        DateTime::parse_from_rfc3339("2020-05-03T20:00:00+09:00").unwrap(),
+                    ),
+                ),
+                entry(3, DateBool::True),
+            ],
+            diary: vec![DiaryNote {
+                event: Event::Started,
+                text: "Recommended by a friend".into(),
+            }],
+            ..Default::default()
+        };
+        tv.entries[1].rating = Some(Rating::new(8, Scale::Ten));
+        tv.entries[1].note = Some("Great <episode> & music".into());
+        let novel = Item {
+            key: "novel-bar".into(),
+            kind: Kind::Novel,
+            name: Alternatives::new("English", "Bar"),
+            completed: DateBool::YearMonth(2020, 5),
+            ..Default::default()
+        };
+        vec![tv, novel]
+    }
+
+    #[test]
+    fn test_activity_order() {
+        let items = items();
+        let events = activity(&items, None);
+        let order: Vec<(&str, Event)> = events.iter().map(|a| (a.item, a.event)).collect();
+        assert_eq!(
+            vec![
+                ("novel-bar", Event::Completed),
+                ("tv-foo", Event::Entry(1)),
+                ("tv-foo", Event::Entry(0)),
+                ("tv-foo", Event::Started),
+            ],
+            order
+        );
+        assert_eq!("Watched episode 2 of Foo, rated 8/10", events[1].summary);
+        assert_eq!(Some("Great <episode> & music"), events[1].note);
+        assert_eq!("Completed Bar", events[0].summary);
+        assert_eq!(Some("Recommended by a friend"), events[3].note);
+
+        // Notes stay with their entry when entries are reordered
+        let mut items = items;
+        items[0].entries.swap(0, 1);
+        let events = activity(&items, None);
+        assert_eq!(Event::Entry(0), events[1].event);
+        assert_eq!(Some(2), events[1].entry.and_then(|entry| entry.number));
+        assert_eq!(Some("Great <episode> & music"), events[1].note);
+    }
+
+    #[test]
+    fn test_film_viewings() {
+        let film = Item {
+            key: "film-baz".into(),
+            kind: Kind::Film,
+            name: Alternatives::new("English", "Baz"),
+            entries: vec![entry(1, DateBool::True), entry(2, DateBool::True)],
+            ..Default::default()
+        };
+        assert_eq!("Watched Baz", summarize(&film, Event::Entry(0), None));
+        assert_eq!("Rewatched Baz", summarize(&film, Event::Entry(1), None));
+    }
+
+    #[test]
+    fn test_atom_feed() {
+        let items = items();
+        let feed = atom_feed("Activity", "urn:shelf:activity", &activity(&items, None));
+        assert!(feed.contains("<updated>2020-05-01T00:00:00Z</updated>"));
+        assert!(feed.contains("<updated>2020-05-03T20:00:00+09:00</updated>"));
+        // The latest event, not the first listed (a month that started earlier)
+        assert!(feed.contains("</id>\n  <updated>2020-05-03T20:00:00+09:00</updated>"));
+        assert!(feed.contains("<id>urn:shelf:activity/tv-foo/entry-1-2020-05-01T00:00:00Z</id>"));
+        assert!(feed.contains("Great &lt;episode&gt; &amp; music"));
+        assert_eq!(4, feed.matches("<entry>").count());
+        assert_eq!(4, feed.matches("<content type=\"text\">").count());
+        assert!(feed.contains("<content type=\"text\">Completed Bar</content>"));
+
+        // Entry IDs don't depend on where the entry is
+        let mut items = items;
+        items[0].entries.reverse();
+        let reordered = atom_feed("Activity", "urn:shelf:activity", &activity(&items, None));
+        assert_eq!(feed, reordered);
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Airing schedules for TV series: which episodes have aired, which
+//! are coming up, and how far behind the viewer is.
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+
+use crate::common::{DateBool, Status};
+use crate::item::{Entry, Item, PublicationStatus};
+use crate::shelf::Shelf;
+
+/// The length assumed for episodes without a known length.
+const DEFAULT_EPISODE_MINUTES: u32 = 30;
+
+/// A scheduled episode.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Episode<'a> {
+    pub item: &'a str,
+    pub name: &'a str,
+    pub number: u32,
+    pub airs: DateTime<FixedOffset>,
+    pub minutes: u32,
+}
+
+/// How far through an airing series the viewer is.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Airing<'a> {
+    pub item: &'a str,
+    pub name: &'a str,
+    /// The number of episodes aired so far.
+    pub aired: u32,
+    /// The number of episodes watched.
+    pub watched: u32,
+    /// Episodes aired but not watched.
+    pub behind: u32,
+    /// The number of episodes planned, if known.
+    pub episodes: Option<u32>,
+    pub next: Option<Episode<'a>>,
+}
+
+fn name(item: &Item) -> &str {
+    item.name
+        .get_default()
+        .map(|s| s.as_str())
+        .unwrap_or(&item.key)
+}
+
+/// The entry for an episode (numbered from 1), matching by number or
+/// else by position.
+fn entry(item: &Item, number: u32) -> Option<&Entry> {
+    item.entries
+        .iter()
+        .enumerate()
+        .find(|(index, entry)| entry.number.unwrap_or(*index as u32 + 1) == number)
+        .map(|(_, entry)| entry)
+}
+
+/// The last episode number to consider, if the series has an end.
+fn last_episode(item: &Item) -> Option<u32> {
+    let broadcast = item.broadcast.as_ref()?;
+    broadcast.episodes.or_else(|| {
+        if item.publication_status == PublicationStatus::Complete {
+            Some(item.entries.len() as u32)
+        } else {
+            None
+        }
+    })
+}
+
+/// When an episode (numbered from 1) airs.
+///
+/// The entry's own air date takes precedence over the broadcast
+/// schedule. Air dates without a time are taken to be at midnight in
+/// the broadcaster's time zone.
+pub fn air_time(item: &Item, number: u32) -> Option<DateTime<FixedOffset>> {
+    let broadcast = item.broadcast.as_ref()?;
+    if matches!(last_episode(item), Some(last) if number > last) {
+        return None;
+    }
+    match entry(item, number).map(|entry| &entry.aired) {
+        Some(DateBool::Timestamp(t)) => Some(*t),
+        Some(aired) if aired.is_dated() => {
+            let offset = broadcast
+                .premiere
+                .map(|premiere| *premiere.offset())
+                .or_else(|| FixedOffset::east_opt(0))?;
+            let (start, _) = aired.range()?;
+            offset
+                .from_local_datetime(&start.and_hms_opt(0, 0, 0)?)
+                .single()
+        }
+        _ => broadcast.scheduled(number),
+    }
+}
+
+fn episode(item: &Item, number: u32, airs: DateTime<FixedOffset>) -> Episode<'_> {
+    let minutes = entry(item, number)
+        .and_then(|entry| entry.length)
+        .or(item.length)
+        .and_then(|length| length.minutes)
+        .unwrap_or(DEFAULT_EPISODE_MINUTES);
+    Episode {
+        item: &item.key,
+        name: name(item),
+        number,
+        airs,
+        minutes,
+    }
+}
+
+/// Whether an item is still being followed.
+fn following(item: &Item) -> bool {
+    item.broadcast.is_some() && item.status != Status::Completed && item.status != Status::Dropped
+}
+
+/// Compute the airing status of an item, if it has a broadcast
+/// schedule.
+pub fn airing(item: &Item, now: DateTime<FixedOffset>) -> Option<Airing<'_>> {
+    item.broadcast.as_ref()?;
+    let mut aired = 0;
+    let mut next = None;
+    let mut number = 1;
+    while let Some(airs) = air_time(item, number) {
+        if airs > now {
+            next = Some(episode(item, number, airs));
+            break;
+        }
+        aired = number;
+        number += 1;
+    }
+    let watched = item
+        .entries
+        .iter()
+        .filter(|entry| entry.completed != DateBool::False)
+        .count() as u32;
+    Some(Airing {
+        item: &item.key,
+        name: name(item),
+        aired,
+        watched,
+        behind: aired.saturating_sub(watched),
+        episodes: last_episode(item),
+        next,
+    })
+}
+
+/// List the followed series with episodes aired but not yet watched,
+/// furthest behind first.
+pub fn behind_schedule<'a, I: IntoIterator<Item = &'a Item>>(
+    items: I,
+    now: DateTime<FixedOffset>,
+) -> Vec<Airing<'a>> {
+    let mut result: Vec<Airing> = items
+        .into_iter()
+        .filter(|item| following(item))
+        .filter_map(|item| airing(item, now))
+        .filter(|airing| airing.behind > 0)
+        .collect();
+    result.sort_by(|a, b| b.behind.cmp(&a.behind).then(a.item.cmp(b.item)));
+    result
+}
+
+/// List the episodes of followed series airing in `[from, until)`, in
+/// order.
+pub fn upcoming<'a, I: IntoIterator<Item = &'a Item>>(
+    items: I,
+    from: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+) -> Vec<Episode<'a>> {
+    let mut result = Vec::new();
+    for item in items.into_iter().filter(|item| following(item)) {
+        let mut number = 1;
+        while let Some(airs) = air_time(item, number) {
+            if airs >= until {
+                break;
+            }
+            if airs >= from {
+                result.push(episode(item, number, airs));
+            }
+            number += 1;
+        }
+    }
+    result.sort_by(|a, b| a.airs.cmp(&b.airs).then(a.item.cmp(b.item)));
+    result
+}
+
+impl Shelf {
+    pub fn behind_schedule(&self, now: DateTime<FixedOffset>) -> Vec<Airing<'_>> {
+        behind_schedule(self.all_items(), now)
+    }
+
+    pub fn upcoming_episodes(
+        &self,
+        from: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+    ) -> Vec<Episode<'_>> {
+        upcoming(self.all_items(), from, until)
+    }
+}
+
+/// Escape a TEXT value (RFC 5545 section 3.3.11).
+fn escape_text(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            ';' => result.push_str("\\;"),
+            ',' => result.push_str("\\,"),
+            '\n' => result.push_str("\\n"),
+            '\r' => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Append a content line, folded at 75 octets (RFC 5545 section 3.1).
+fn push_line(calendar: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            calendar.push_str("\r\n ");
+            width = 1;
+        }
+        calendar.push(c);
+        width += c.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+fn utc(t: &DateTime<FixedOffset>) -> String {
+    t.naive_utc().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Render episodes as an iCalendar feed.
+///
+/// `stamp` is used as the DTSTAMP of each event, normally the current
+/// time.
+pub fn ical_feed(name: &str, episodes: &[Episode], stamp: DateTime<FixedOffset>) -> String {
+    let mut calendar = String::new();
+    push_line(&mut calendar, "BEGIN:VCALENDAR");
+    push_line(&mut calendar, "VERSION:2.0");
+    push_line(&mut calendar, "PRODID:-//shelf//airing//EN");
+    push_line(&mut calendar, "CALSCALE:GREGORIAN");
+    push_line(
+        &mut calendar,
+        &format!("X-WR-CALNAME:{}", escape_text(name)),
+    );
+    for episode in episodes {
+        let end = episode.airs + chrono::Duration::minutes(i64::from(episode.minutes));
+        push_line(&mut calendar, "BEGIN:VEVENT");
+        push_line(
+            &mut calendar,
+            &format!(
+                "UID:{}",
+                escape_text(&format!("{}-{}@shelf", episode.item, episode.number))
+            ),
+        );
+        push_line(&mut calendar, &format!("DTSTAMP:{}", utc(&stamp)));
+        push_line(&mut calendar, &format!("DTSTART:{}", utc(&episode.airs)));
+        push_line(&mut calendar, &format!("DTEND:{}", utc(&end)));
+        push_line(
+            &mut calendar,
+            &format!(
+                "SUMMARY:{}",
+                escape_text(&format!("{} episode {}", episode.name, episode.number))
+            ),
+        );
+        push_line(&mut calendar, "END:VEVENT");
+    }
+    push_line(&mut calendar, "END:VCALENDAR");
+    calendar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{airing, behind_schedule, ical_feed, upcoming};
+    use crate::common::{Alternatives, DateBool, Kind, Season, Status};
+    use crate::item::{Broadcast, Entry, Item};
+    use chrono::{DateTime, NaiveDate, NaiveTime, Weekday};
+
+    fn time(s: &str) -> DateTime<chrono::FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn entry(number: u32, completed: DateBool, aired: DateBool) -> Entry {
+        Entry {
+            name: None,
+            number: Some(number),
+            volume: None,
+            completed,
+            aired,
+            rating: None,
+            length: None,
+            note: None,
+            extra: serde_yaml::Value::Null,
+        }
+    }
+
+    fn item() -> Item {
+        Item {
+            key: "tv-foo".into(),
+            kind: Kind::TV,
+            name: Alternatives::new("English", "Foo, the Series"),
+            status: Status::InProgress,
+            broadcast: Some(Broadcast {
+                year: 2020,
+                season: Season::Spring,
+                premiere: Some(time("2020-04-03T23:30:00+09:00")),
+                episodes: Some(12),
+            }),
+            entries: vec![
+                entry(
+                    1,
+                    DateBool::Date(NaiveDate::from_ymd(2020, 4, 4)),
+                    DateBool::False,
+                ),
+                // Aired a week late
+                entry(
+                    3,
+                    DateBool::False,
+                    DateBool::Date(NaiveDate::from_ymd(2020, 4, 24)),
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let broadcast = item().broadcast.unwrap();
+        assert_eq!(Some(Weekday::Fri), broadcast.weekday());
+        assert_eq!(Some(NaiveTime::from_hms(23, 30, 0)), broadcast.time());
+        assert_eq!(
+            Some(time("2020-04-10T23:30:00+09:00")),
+            broadcast.scheduled(2)
+        );
+        assert_eq!(None, broadcast.scheduled(0));
+        assert_eq!(None, broadcast.scheduled(13));
+    }
+
+    #[test]
+    fn test_airing() {
+        let item = item();
+        let status = airing(&item, time("2020-04-20T12:00:00+09:00")).unwrap();
+        assert_eq!(2, status.aired);
+        assert_eq!(1, status.watched);
+        assert_eq!(1, status.behind);
+        assert_eq!(Some(12), status.episodes);
+        let next = status.next.unwrap();
+        assert_eq!(3, next.number);
+        assert_eq!(time("2020-04-24T00:00:00+09:00"), next.airs);
+
+        let items = vec![item];
+        assert_eq!(
+            1,
+            behind_schedule(&items, time("2020-04-20T12:00:00+09:00")).len()
+        );
+        assert!(behind_schedule(&items, time("2020-04-05T12:00:00+09:00")).is_empty());
+        assert_eq!(
+            11,
+            airing(&items[0], time("2021-01-01T00:00:00Z"))
+                .unwrap()
+                .behind
+        );
+    }
+
+    #[test]
+    fn test_upcoming_ical() {
+        let items = vec![item()];
+        let episodes = upcoming(
+            &items,
+            time("2020-04-20T00:00:00+09:00"),
+            time("2020-05-04T00:00:00+09:00"),
+        );
+        let numbers: Vec<u32> = episodes.iter().map(|e| e.number).collect();
+        assert_eq!(vec![3, 4, 5], numbers);
+
+        let feed = ical_feed("Airing", &episodes, time("2020-04-20T00:00:00Z"));
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert_eq!(3, feed.matches("BEGIN:VEVENT").count());
+        assert!(feed.contains("UID:tv-foo-4@shelf\r\n"));
+        assert!(feed.contains("DTSTART:20200424T143000Z\r\n"));
+        assert!(feed.contains("DTEND:20200424T150000Z\r\n"));
+        assert!(feed.contains("SUMMARY:Foo\\, the Series episode 4\r\n"));
+    }
+}
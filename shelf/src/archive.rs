@@ -0,0 +1,577 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Whole-library archives, for handing a shelf to someone without its
+//! git history.
+//!
+//! An archive is a ZIP file laid out like a shelf directory (entity
+//! YAML files, `settings.yaml`, `schema.yaml`, and `blobs/` with its
+//! index), plus a `manifest.yaml` listing the SHA-256 checksum of every
+//! other file. Restoring checks the checksums first, then adds what the
+//! shelf is missing; entities that already exist with different
+//! contents are kept and reported as conflicts.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::common::{Blob, Person};
+use crate::goal::Goal;
+use crate::item::Item;
+use crate::list::List;
+use crate::save::{DirectoryShelf, SaveError};
+use crate::schema::Schema;
+use crate::series::Series;
+use crate::settings::Settings;
+use crate::shelf::Shelf;
+
+/// The version of the archive layout. Archives from later versions
+/// are refused.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.yaml";
+const SETTINGS_PATH: &str = "settings.yaml";
+const SCHEMA_PATH: &str = "schema.yaml";
+const BLOBS_INDEX: &str = "blobs/index.yaml";
+const BLOBS_PREFIX: &str = "blobs/";
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(String),
+    /// The archive is not a ZIP file, or a file in it is malformed.
+    Format(String),
+    /// A file is missing, unlisted, or doesn't match its checksum.
+    Checksum(String),
+    /// The archive was made with a later layout version.
+    Version(u32),
+    Save(SaveError),
+}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(err: std::io::Error) -> Self {
+        ArchiveError::Io(err.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for ArchiveError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ArchiveError::Format(err.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for ArchiveError {
+    fn from(err: serde_yaml::Error) -> Self {
+        ArchiveError::Format(err.to_string())
+    }
+}
+
+impl From<SaveError> for ArchiveError {
+    fn from(err: SaveError) -> Self {
+        ArchiveError::Save(err)
+    }
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Io(err) => write!(f, "Could not read or write archive: {}", err),
+            ArchiveError::Format(err) => write!(f, "Invalid archive: {}", err),
+            ArchiveError::Checksum(path) => write!(f, "Archive file {} is corrupt", path),
+            ArchiveError::Version(version) => write!(
+                f,
+                "Archive version {} is newer than this version of shelf ({})",
+                version, ARCHIVE_VERSION
+            ),
+            ArchiveError::Save(err) => write!(f, "Could not save shelf: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created: chrono::DateTime<chrono::FixedOffset>,
+    /// Hex SHA-256 checksums of the other files, by path.
+    pub files: BTreeMap<String, String>,
+}
+
+fn checksum(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Writes files into a ZIP, recording their checksums.
+struct Writer<W: Write + Seek> {
+    zip: zip::ZipWriter<W>,
+    files: BTreeMap<String, String>,
+}
+
+impl<W: Write + Seek> Writer<W> {
+    fn file(&mut self, path: &str, contents: &[u8]) -> Result<(), ArchiveError> {
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        self.zip.start_file(path, options)?;
+        self.zip.write_all(contents)?;
+        self.files.insert(path.to_owned(), checksum(contents));
+        Ok(())
+    }
+
+    fn yaml<T: serde::Serialize>(&mut self, path: &str, value: &T) -> Result<(), ArchiveError> {
+        self.file(path, serde_yaml::to_string(value)?.as_bytes())
+    }
+}
+
+/// Write the whole shelf as it is in memory, including unsaved
+/// changes, to an archive. Blob contents are read from `saver`.
+pub fn export<W: Write + Seek>(
+    shelf: &Shelf,
+    saver: &DirectoryShelf,
+    writer: W,
+) -> Result<Manifest, ArchiveError> {
+    let mut writer = Writer {
+        zip: zip::ZipWriter::new(writer),
+        files: BTreeMap::new(),
+    };
+    writer.yaml(SETTINGS_PATH, shelf.settings())?;
+    writer.yaml(SCHEMA_PATH, shelf.schema())?;
+
+    let mut people: Vec<&Person> = shelf.query_people().collect();
+    people.sort_by(|a, b| a.key.cmp(&b.key));
+    for person in people {
+        writer.yaml(&format!("person--{}.yaml", person.key), person)?;
+    }
+    let mut series: Vec<&Series> = shelf.query_series().collect();
+    series.sort_by(|a, b| a.key.cmp(&b.key));
+    for series in series {
+        writer.yaml(&format!("series--{}.yaml", series.key), series)?;
+    }
+    let mut items: Vec<&Item> = shelf.all_items().iter().collect();
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+    for item in items {
+        writer.yaml(&format!("item--{}.yaml", item.key), item)?;
+    }
+    let mut lists: Vec<&List> = shelf.query_lists().collect();
+    lists.sort_by(|a, b| a.key.cmp(&b.key));
+    for list in lists {
+        writer.yaml(&format!("list--{}.yaml", list.key), list)?;
+    }
+    let mut goals: Vec<&Goal> = shelf.query_goals().collect();
+    goals.sort_by(|a, b| a.key.cmp(&b.key));
+    for goal in goals {
+        writer.yaml(&format!("goal--{}.yaml", goal.key), goal)?;
+    }
+
+    let mut blobs: Vec<&Blob> = shelf.query_blobs().collect();
+    blobs.sort_by(|a, b| a.key.cmp(&b.key));
+    writer.yaml(BLOBS_INDEX, &blobs)?;
+    for blob in blobs {
+        let contents = std::fs::read(saver.get_blob(&blob.key))
+            .map_err(|_| SaveError::MissingBlob(blob.key.clone()))?;
+        writer.file(&format!("{}{}", BLOBS_PREFIX, blob.key), &contents)?;
+    }
+
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        created: chrono::Local::now().into(),
+        files: writer.files,
+    };
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    writer.zip.start_file(MANIFEST_PATH, options)?;
+    writer
+        .zip
+        .write_all(serde_yaml::to_string(&manifest)?.as_bytes())?;
+    writer.zip.finish()?;
+    Ok(manifest)
+}
+
+/// The contents of an archive whose checksums have been verified.
+#[derive(Clone, Debug, Default)]
+pub struct Archive {
+    pub settings: Option<Settings>,
+    pub schema: Option<Schema>,
+    pub people: Vec<Person>,
+    pub series: Vec<Series>,
+    pub items: Vec<Item>,
+    pub lists: Vec<List>,
+    pub goals: Vec<Goal>,
+    pub blobs: Vec<(Blob, Vec<u8>)>,
+}
+
+/// Read an archive, checking its version and checksums.
+pub fn read<R: Read + Seek>(reader: R) -> Result<(Manifest, Archive), ArchiveError> {
+    let mut zip = zip::ZipArchive::new(reader)?;
+    let manifest: Manifest = {
+        let file = zip
+            .by_name(MANIFEST_PATH)
+            .map_err(|_| ArchiveError::Format("no manifest".to_owned()))?;
+        serde_yaml::from_reader(file)?
+    };
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(ArchiveError::Version(manifest.version));
+    }
+
+    let mut files = BTreeMap::new();
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        let path = file.name().to_owned();
+        if path == MANIFEST_PATH || file.is_dir() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        if manifest.files.get(&path) != Some(&checksum(&contents)) {
+            return Err(ArchiveError::Checksum(path));
+        }
+        files.insert(path, contents);
+    }
+    if let Some(missing) = manifest
+        .files
+        .keys()
+        .find(|path| !files.contains_key(*path))
+    {
+        return Err(ArchiveError::Checksum(missing.clone()));
+    }
+
+    let mut archive = Archive::default();
+    let mut blobs: Vec<Blob> = Vec::new();
+    for (path, contents) in files.iter() {
+        match path.as_str() {
+            SETTINGS_PATH => archive.settings = Some(serde_yaml::from_slice(contents)?),
+            SCHEMA_PATH => archive.schema = Some(serde_yaml::from_slice(contents)?),
+            BLOBS_INDEX => blobs = serde_yaml::from_slice(contents)?,
+            path if path.starts_with(BLOBS_PREFIX) => {}
+            path if path.starts_with("person--") => {
+                archive.people.push(serde_yaml::from_slice(contents)?)
+            }
+            path if path.starts_with("series--") => {
+                archive.series.push(serde_yaml::from_slice(contents)?)
+            }
+            path if path.starts_with("item--") => {
+                archive.items.push(serde_yaml::from_slice(contents)?)
+            }
+            path if path.starts_with("list--") => {
+                archive.lists.push(serde_yaml::from_slice(contents)?)
+            }
+            path if path.starts_with("goal--") => {
+                archive.goals.push(serde_yaml::from_slice(contents)?)
+            }
+            path => return Err(ArchiveError::Format(format!("unknown file {}", path))),
+        }
+    }
+    for blob in blobs {
+        let contents = files
+            .remove(&format!("{}{}", BLOBS_PREFIX, blob.key))
+            .ok_or_else(|| ArchiveError::Format(format!("no contents for {}", blob.key)))?;
+        archive.blobs.push((blob, contents));
+    }
+    Ok((manifest, archive))
+}
+
+/// What restoring an archive did.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RestoreReport {
+    /// The number of items, people, series, lists, goals and blobs
+    /// added.
+    pub added: usize,
+    /// The number already on the shelf with the same contents.
+    pub unchanged: usize,
+    /// Entities that differ from the shelf's (which are kept) or could
+    /// not be added, and why.
+    pub conflicts: Vec<String>,
+}
+
+impl RestoreReport {
+    /// Compare an entity with the shelf's, returning whether to add it.
+    fn check<T: PartialEq>(&mut self, key: &str, existing: Option<&T>, new: &T) -> bool {
+        match existing {
+            None => true,
+            Some(existing) if existing == new => {
+                self.unchanged += 1;
+                false
+            }
+            Some(_) => {
+                self.conflict(key, "differs from the shelf");
+                false
+            }
+        }
+    }
+
+    fn conflict<S: std::fmt::Display>(&mut self, key: &str, reason: S) {
+        self.conflicts.push(format!("{}: {}", key, reason));
+    }
+
+    fn added<E: std::fmt::Display>(&mut self, key: &str, result: Result<bool, E>) {
+        match result {
+            Ok(_) => self.added += 1,
+            Err(err) => self.conflict(key, err),
+        }
+    }
+}
+
+impl Archive {
+    /// Add the archive's contents to a shelf, writing blob files into
+    /// `saver`'s directory. The shelf's own settings and schema are
+    /// kept unless they are the defaults. The shelf still has to be
+    /// saved afterwards.
+    pub fn restore(
+        self,
+        shelf: &mut Shelf,
+        saver: &DirectoryShelf,
+    ) -> Result<RestoreReport, ArchiveError> {
+        let mut report = RestoreReport::default();
+        if let Some(settings) = self.settings {
+            if *shelf.settings() == Settings::default() {
+                shelf.set_settings(settings);
+            } else if *shelf.settings() != settings {
+                report.conflict("settings", "differs from the shelf");
+            }
+        }
+        if let Some(schema) = self.schema {
+            if *shelf.schema() == Schema::default() {
+                if let Err(err) = shelf.set_schema(schema) {
+                    report.conflict("schema", err);
+                }
+            } else if *shelf.schema() != schema {
+                report.conflict("schema", "differs from the shelf");
+            }
+        }
+
+        for person in self.people {
+            if report.check(&person.key, shelf.get_person(&person.key), &person) {
+                shelf.insert_person(person);
+                report.added += 1;
+            }
+        }
+        for series in self.series {
+            if report.check(&series.key, shelf.get_series(&series.key), &series) {
+                shelf.insert_series(series);
+                report.added += 1;
+            }
+        }
+        for (blob, contents) in self.blobs {
+            // Blob keys become file names
+            if ["/", "\\", ".."].iter().any(|bad| blob.key.contains(bad)) {
+                report.conflict(&blob.key, "invalid blob key");
+                continue;
+            }
+            if shelf.get_blob(&blob.key).is_some() {
+                match std::fs::read(saver.get_blob(&blob.key)) {
+                    Ok(existing) => {
+                        report.check(&blob.key, Some(&existing), &contents);
+                    }
+                    Err(_) => report.conflict(&blob.key, "file is missing from the shelf"),
+                }
+                continue;
+            }
+            let key = blob.key.clone();
+            match shelf.insert_blob(blob) {
+                Ok(_) => {
+                    std::fs::write(saver.insert_blob(&key)?, &contents)?;
+                    report.added += 1;
+                }
+                Err(err) => report.conflict(&key, err),
+            }
+        }
+        for item in self.items {
+            if report.check(&item.key, shelf.get_item(&item.key), &item) {
+                let key = item.key.clone();
+                report.added(&key, shelf.insert_item(item).map(|_| true));
+            }
+        }
+        for list in self.lists {
+            if report.check(&list.key, shelf.get_list(&list.key), &list) {
+                let key = list.key.clone();
+                report.added(&key, shelf.insert_list(list));
+            }
+        }
+        for goal in self.goals {
+            if report.check(&goal.key, shelf.get_goal(&goal.key), &goal) {
+                let key = goal.key.clone();
+                report.added(&key, shelf.insert_goal(goal));
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use tempfile::Builder;
+
+    use super::{export, read, ArchiveError, RestoreReport};
+    use crate::common::{Alternatives, Blob, Person, Role};
+    use crate::item::{Cover, Item};
+    use crate::list::{List, ListEntry};
+    use crate::save::DirectoryShelf;
+    use crate::series::Series;
+    use crate::Shelf;
+
+    fn shelf(saver: &DirectoryShelf) -> Shelf {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-a".into(),
+            name: Alternatives::new("English", "A"),
+            external_ids: Default::default(),
+        });
+        shelf.insert_series(Series {
+            key: "series-s".into(),
+            name: Alternatives::new("English", "S"),
+            people: Vec::new(),
+            external_ids: Default::default(),
+        });
+        std::fs::write(saver.insert_blob("blob-cover").unwrap(), b"cover").unwrap();
+        shelf
+            .insert_blob(Blob::new_with_mime(
+                "blob-cover".into(),
+                "image/jpeg".into(),
+            ))
+            .unwrap();
+        shelf
+            .insert_item(Item {
+                key: "novel-b".into(),
+                people: vec![(Role::Author, "person-a".into())],
+                series: Some(("series-s".into(), Some("1".into()))),
+                covers: vec![Cover {
+                    key: "blob-cover".into(),
+                    description: "Cover".into(),
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        let mut list = List::new("list-l", "L");
+        list.items.push(ListEntry::new("novel-b"));
+        shelf.insert_list(list).unwrap();
+        shelf
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source_dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let source = DirectoryShelf::new(source_dir.path()).unwrap();
+        let original = shelf(&source);
+        let mut out = Cursor::new(Vec::new());
+        let manifest = export(&original, &source, &mut out).unwrap();
+        assert!(manifest.files.contains_key("item--novel-b.yaml"));
+        assert!(manifest.files.contains_key("blobs/blob-cover"));
+
+        // Into a fresh shelf
+        let target_dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let target = DirectoryShelf::new(target_dir.path()).unwrap();
+        let mut restored = Shelf::new();
+        let (_, archive) = read(Cursor::new(out.get_ref())).unwrap();
+        let report = archive.restore(&mut restored, &target).unwrap();
+        assert_eq!(
+            RestoreReport {
+                added: 5,
+                unchanged: 0,
+                conflicts: Vec::new(),
+            },
+            report
+        );
+        assert_eq!(original.get_item("novel-b"), restored.get_item("novel-b"));
+        assert_eq!(
+            b"cover".to_vec(),
+            std::fs::read(target.get_blob("blob-cover")).unwrap()
+        );
+        target.save(&mut restored).unwrap();
+        let mut loaded = Shelf::new();
+        target.load(&mut loaded).unwrap();
+        assert_eq!(original.get_list("list-l"), loaded.get_list("list-l"));
+
+        // Merging into a shelf that changed since
+        let mut item = loaded.get_item("novel-b").unwrap().clone();
+        item.comments = "Changed".into();
+        loaded.replace_item(item).unwrap();
+        let (_, archive) = read(Cursor::new(out.get_ref())).unwrap();
+        let report = archive.restore(&mut loaded, &target).unwrap();
+        assert_eq!(0, report.added);
+        assert_eq!(4, report.unchanged);
+        assert_eq!(
+            vec!["novel-b: differs from the shelf".to_owned()],
+            report.conflicts
+        );
+        assert_eq!("Changed", loaded.get_item("novel-b").unwrap().comments);
+    }
+
+    #[test]
+    fn test_restore_blobs() {
+        let source_dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let source = DirectoryShelf::new(source_dir.path()).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        export(&shelf(&source), &source, &mut out).unwrap();
+
+        let target_dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let target = DirectoryShelf::new(target_dir.path()).unwrap();
+        let mut restored = Shelf::new();
+        let (_, mut archive) = read(Cursor::new(out.get_ref())).unwrap();
+        for key in ["blob-../escape", "blob-a/b", "blob-a\\b", "cover"].iter() {
+            archive.blobs.push((
+                Blob::new_with_mime(key.to_string(), "image/jpeg".into()),
+                b"x".to_vec(),
+            ));
+        }
+        let report = archive.restore(&mut restored, &target).unwrap();
+        assert_eq!(5, report.added);
+        assert_eq!(
+            vec![
+                "blob-../escape: invalid blob key".to_owned(),
+                "blob-a/b: invalid blob key".to_owned(),
+                "blob-a\\b: invalid blob key".to_owned(),
+                "cover: InvalidKey(\"cover\")".to_owned(),
+            ],
+            report.conflicts
+        );
+        assert!(!target_dir.path().join("escape").exists());
+        assert!(!target.get_blob("cover").exists());
+        assert!(restored.get_blob("cover").is_none());
+
+        // The shelf knows the blob, but its file is gone
+        std::fs::remove_file(target.get_blob("blob-cover")).unwrap();
+        let (_, archive) = read(Cursor::new(out.get_ref())).unwrap();
+        let report = archive.restore(&mut restored, &target).unwrap();
+        assert_eq!(
+            vec!["blob-cover: file is missing from the shelf".to_owned()],
+            report.conflicts
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut out = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut out);
+            let options = zip::write::FileOptions::default();
+            zip.start_file("manifest.yaml", options).unwrap();
+            zip.write_all(
+                b"version: 1\ncreated: 2020-01-01T00:00:00+00:00\nfiles:\n  settings.yaml: abc\n",
+            )
+            .unwrap();
+            zip.start_file("settings.yaml", options).unwrap();
+            zip.write_all(b"{}").unwrap();
+            zip.finish().unwrap();
+        }
+        match read(Cursor::new(out.get_ref())) {
+            Err(ArchiveError::Checksum(path)) => assert_eq!("settings.yaml", path),
+            other => panic!("Expected a checksum error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -17,8 +17,12 @@ use std::fmt;
 
 use chrono;
 use serde::de;
+
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::external::ExternalIds;
+use crate::settings::Zone;
+
 /// A reusable container for multiple named values for the same key.
 ///
 /// For instance, for multi-lingual titles, this can be used to store
@@ -43,6 +47,11 @@ impl<T: PartialEq> Alternatives<T> {
             alternatives,
         }
     }
+
+    /// The value for the default key, if present.
+    pub fn get_default(&self) -> Option<&T> {
+        self.alternatives.get(&self.default)
+    }
 }
 
 /// The role for a person associated with a work.
@@ -61,10 +70,12 @@ pub type PersonIdx = String;
 pub struct Person {
     pub key: PersonIdx,
     pub name: Alternatives<String>,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
 }
 
 /// The read/watch status of an item.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum Status {
     Completed,
     InProgress,
@@ -74,7 +85,7 @@ pub enum Status {
 }
 
 /// The type of a work.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum Kind {
     Unknown,
     Manga,
@@ -112,16 +123,75 @@ impl Blob {
     }
 }
 
+/// A season of the year, following the anime convention: Winter is
+/// January through March, Spring is April through June, and so on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Season {
+    Winter,
+    Spring,
+    Summer,
+    Fall,
+}
+
+impl Season {
+    pub fn all() -> &'static [Season] {
+        &[Season::Winter, Season::Spring, Season::Summer, Season::Fall]
+    }
+
+    /// The first month (1-12) of the season.
+    pub fn start_month(self) -> u32 {
+        match self {
+            Season::Winter => 1,
+            Season::Spring => 4,
+            Season::Summer => 7,
+            Season::Fall => 10,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Season::Winter => "Winter",
+            Season::Spring => "Spring",
+            Season::Summer => "Summer",
+            Season::Fall => "Fall",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Season> {
+        Season::all()
+            .iter()
+            .find(|season| season.name().eq_ignore_ascii_case(s))
+            .copied()
+    }
+}
+
 /// A hybrid date or Boolean, used to import data from sites that
 /// offer only a Boolean completed flag instead of a completion date,
 /// or a less granular completion date.
+///
+/// Dates of different precision are ordered by the end of the period
+/// they cover, so that e.g. "May 2020" sorts after any day in May 2020;
+/// periods ending on the same day sort shortest first. Timestamps are
+/// ordered by the instant they refer to, and sort within the UTC day
+/// they fall in, before a date for that whole day. `False` sorts
+/// before `True`, which sorts before any date.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DateBool {
     False,
     True,
     Timestamp(chrono::DateTime<chrono::FixedOffset>),
     Date(chrono::naive::NaiveDate),
+    /// Serialized as "YYYY-MM-00".
     YearMonth(u32, u32),
+    /// Serialized as "YYYY-00-00".
+    Year(u32),
+    /// Serialized as e.g. "2020-spring".
+    Season(u32, Season),
+    /// A quarter (1-4) of a year, serialized as e.g. "2020-Q2".
+    Quarter(u32, u32),
+    /// Sometime within a range of years (inclusive), serialized as
+    /// "~2015" or "~2014/2016".
+    Approximate(u32, u32),
 }
 
 impl Default for DateBool {
@@ -130,6 +200,191 @@ impl Default for DateBool {
     }
 }
 
+/// The same moment with the offset fixed, as timestamps are stored.
+pub fn fixed_offset<Tz: chrono::TimeZone>(
+    time: chrono::DateTime<Tz>,
+) -> chrono::DateTime<chrono::FixedOffset> {
+    use chrono::Offset;
+    let offset = time.offset().fix();
+    time.with_timezone(&offset)
+}
+
+/// The first and last days of a span of months.
+fn month_span(year: u32, first: u32, last: u32) -> Option<(chrono::NaiveDate, chrono::NaiveDate)> {
+    let start = chrono::NaiveDate::from_ymd_opt(year as i32, first, 1)?;
+    let next = if last == 12 {
+        chrono::NaiveDate::from_ymd_opt(year as i32 + 1, 1, 1)?
+    } else {
+        chrono::NaiveDate::from_ymd_opt(year as i32, last + 1, 1)?
+    };
+    Some((start, next.pred_opt()?))
+}
+
+impl DateBool {
+    /// The year of the date, if known.
+    pub fn year(&self) -> Option<i32> {
+        use chrono::Datelike;
+        match self {
+            DateBool::False | DateBool::True => None,
+            DateBool::Timestamp(t) => Some(t.year()),
+            DateBool::Date(d) => Some(d.year()),
+            DateBool::YearMonth(y, _)
+            | DateBool::Year(y)
+            | DateBool::Season(y, _)
+            | DateBool::Quarter(y, _) => Some(*y as i32),
+            DateBool::Approximate(start, end) if start == end => Some(*start as i32),
+            DateBool::Approximate(_, _) => None,
+        }
+    }
+
+    /// The year and month of the date, if known.
+    pub fn year_month(&self) -> Option<(i32, u32)> {
+        use chrono::Datelike;
+        match self {
+            DateBool::Timestamp(t) => Some((t.year(), t.month())),
+            DateBool::Date(d) => Some((d.year(), d.month())),
+            DateBool::YearMonth(y, m) => Some((*y as i32, *m)),
+            _ => None,
+        }
+    }
+
+    /// The calendar date, if known to the day.
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        match self {
+            DateBool::Timestamp(t) => Some(t.naive_local().date()),
+            DateBool::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// The first and last days (inclusive) the date could refer to.
+    pub fn range(&self) -> Option<(chrono::NaiveDate, chrono::NaiveDate)> {
+        match self {
+            DateBool::False | DateBool::True => None,
+            DateBool::Timestamp(_) | DateBool::Date(_) => self.date().map(|d| (d, d)),
+            DateBool::YearMonth(y, m) => month_span(*y, *m, *m),
+            DateBool::Year(y) => month_span(*y, 1, 12),
+            DateBool::Season(y, season) => {
+                month_span(*y, season.start_month(), season.start_month() + 2)
+            }
+            DateBool::Quarter(y, q) => {
+                // Quarters built in code rather than parsed may be out of range
+                let last = q.checked_mul(3).filter(|m| (3..=12).contains(m))?;
+                month_span(*y, last - 2, last)
+            }
+            DateBool::Approximate(start, end) => {
+                let (first, _) = month_span(*start, 1, 1)?;
+                let (_, last) = month_span(*end, 12, 12)?;
+                Some((first, last))
+            }
+        }
+    }
+
+    /// Convert a timestamp to the given time zone, using the offset in
+    /// effect at that moment, so that `date()`, `year()`, etc. give the
+    /// calendar day in that zone. Other dates are unchanged, as is
+    /// everything when `tz` is `None`.
+    pub fn with_time_zone(self, tz: Option<Zone>) -> DateBool {
+        match (self, tz) {
+            (DateBool::Timestamp(t), Some(tz)) => DateBool::Timestamp(tz.convert(&t)),
+            _ => self,
+        }
+    }
+
+    /// The calendar date in the given time zone, if known to the day.
+    pub fn local_date(&self, tz: Option<Zone>) -> Option<chrono::NaiveDate> {
+        self.with_time_zone(tz).date()
+    }
+
+    /// The year in the given time zone, if known.
+    pub fn local_year(&self, tz: Option<Zone>) -> Option<i32> {
+        self.with_time_zone(tz).year()
+    }
+
+    /// Whether the date is known at all (even imprecisely).
+    pub fn is_dated(&self) -> bool {
+        self.range().is_some()
+    }
+
+    pub fn is_approximate(&self) -> bool {
+        matches!(self, DateBool::Approximate(_, _))
+    }
+
+    fn order_key(&self) -> impl Ord {
+        use chrono::Timelike;
+        use std::cmp::Reverse;
+        let (class, variant) = match self {
+            DateBool::False => (0, 0),
+            DateBool::True => (1, 0),
+            DateBool::Timestamp(_) => (2, 0),
+            DateBool::Date(_) => (2, 1),
+            DateBool::YearMonth(_, _) => (2, 2),
+            DateBool::Season(_, _) => (2, 3),
+            DateBool::Quarter(_, _) => (2, 4),
+            DateBool::Year(_) => (2, 5),
+            DateBool::Approximate(_, _) => (2, 6),
+        };
+        let span = match self {
+            // Placed by the instant, on the UTC day it falls in
+            DateBool::Timestamp(t) => {
+                let utc = t.naive_utc();
+                Some((
+                    utc.date(),
+                    (utc.num_seconds_from_midnight(), utc.nanosecond()),
+                    Reverse(utc.date()),
+                ))
+            }
+            _ => self
+                .range()
+                .map(|(start, end)| (end, (u32::MAX, u32::MAX), Reverse(start))),
+        };
+        // Dates out of range have no span, so fall back to their fields
+        let fields = match self {
+            DateBool::YearMonth(y, n) | DateBool::Quarter(y, n) | DateBool::Approximate(y, n) => {
+                (*y, *n)
+            }
+            DateBool::Season(y, season) => (*y, season.start_month()),
+            DateBool::Year(y) => (*y, 0),
+            _ => (0, 0),
+        };
+        (class, span, variant, fields)
+    }
+}
+
+impl PartialOrd for DateBool {
+    fn partial_cmp(&self, other: &DateBool) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DateBool {
+    fn cmp(&self, other: &DateBool) -> std::cmp::Ordering {
+        self.order_key().cmp(&other.order_key())
+    }
+}
+
+/// A human-readable rendering, e.g. "2020-05-03", "May 2020", "Spring
+/// 2020", or "c. 2014–2016". `False` renders as an empty string.
+impl fmt::Display for DateBool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateBool::False => Ok(()),
+            DateBool::True => write!(f, "Yes"),
+            DateBool::Timestamp(t) => write!(f, "{}", t.format("%Y-%m-%d %H:%M")),
+            DateBool::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            DateBool::YearMonth(y, m) => match month_span(*y, *m, *m) {
+                Some((start, _)) => write!(f, "{}", start.format("%B %Y")),
+                None => write!(f, "{}-{:02}", y, m),
+            },
+            DateBool::Year(y) => write!(f, "{}", y),
+            DateBool::Season(y, season) => write!(f, "{} {}", season.name(), y),
+            DateBool::Quarter(y, q) => write!(f, "Q{} {}", q, y),
+            DateBool::Approximate(start, end) if start == end => write!(f, "c. {}", start),
+            DateBool::Approximate(start, end) => write!(f, "c. {}–{}", start, end),
+        }
+    }
+}
+
 impl Serialize for DateBool {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -143,10 +398,66 @@ impl Serialize for DateBool {
             DateBool::YearMonth(year, month) => {
                 serializer.serialize_str(&format!("{}-{:02}-00", year, month))
             }
+            DateBool::Year(year) => serializer.serialize_str(&format!("{}-00-00", year)),
+            DateBool::Season(year, season) => {
+                serializer.serialize_str(&format!("{}-{}", year, season.name().to_lowercase()))
+            }
+            DateBool::Quarter(year, quarter) => {
+                serializer.serialize_str(&format!("{}-Q{}", year, quarter))
+            }
+            DateBool::Approximate(start, end) if start == end => {
+                serializer.serialize_str(&format!("~{}", start))
+            }
+            DateBool::Approximate(start, end) => {
+                serializer.serialize_str(&format!("~{}/{}", start, end))
+            }
         }
     }
 }
 
+/// Parse the partial-precision formats: "YYYY-MM-00", "YYYY-00-00",
+/// "YYYY-spring" (or "Spring YYYY"), "YYYY-Q2", "~YYYY", and
+/// "~YYYY/YYYY".
+fn parse_partial_date(v: &str) -> Option<DateBool> {
+    let year = |s: &str| s.parse::<u32>().ok().filter(|y| *y <= 9999);
+
+    if let Some(range) = v.strip_prefix('~') {
+        let mut parts = range.splitn(2, '/');
+        let start = year(parts.next()?)?;
+        let end = match parts.next() {
+            Some(end) => year(end)?,
+            None => start,
+        };
+        return if start <= end {
+            Some(DateBool::Approximate(start, end))
+        } else {
+            None
+        };
+    }
+
+    if let Some((season, y)) = v.split_once(' ') {
+        return Some(DateBool::Season(year(y)?, Season::parse(season)?));
+    }
+
+    let parts: Vec<&str> = v.split('-').collect();
+    match parts.as_slice() {
+        [y, m, "00"] => {
+            let (y, m) = (year(y)?, m.parse::<u32>().ok()?);
+            match m {
+                0 => Some(DateBool::Year(y)),
+                1..=12 => Some(DateBool::YearMonth(y, m)),
+                _ => None,
+            }
+        }
+        [y, q] if q.starts_with('Q') => {
+            let q = q[1..].parse::<u32>().ok().filter(|q| (1..=4).contains(q))?;
+            Some(DateBool::Quarter(year(y)?, q))
+        }
+        [y, season] => Some(DateBool::Season(year(y)?, Season::parse(season)?)),
+        _ => None,
+    }
+}
+
 impl<'de> Deserialize<'de> for DateBool {
     fn deserialize<D>(deserializer: D) -> Result<DateBool, D::Error>
     where
@@ -183,18 +494,9 @@ impl<'de> Deserialize<'de> for DateBool {
                     Ok(DateBool::Date(ts))
                 } else if let Ok(ts) = chrono::naive::NaiveDate::parse_from_str(v, "%Y-%m-%d") {
                     Ok(DateBool::Date(ts))
+                } else if let Some(date) = parse_partial_date(v) {
+                    Ok(date)
                 } else {
-                    let parts: Vec<&str> = v.split('-').collect();
-                    if parts.len() == 3 {
-                        let year = parts[0].parse::<u32>();
-                        let month = parts[1].parse::<u32>();
-                        let day = parts[2].parse::<u32>();
-                        if let (Ok(y), Ok(m), Ok(d)) = (year, month, day) {
-                            if y <= 9999 && m >= 1 && m <= 12 && d == 0 {
-                                return Ok(DateBool::YearMonth(y, m));
-                            }
-                        }
-                    }
                     Err(E::custom(format!("unrecognized date(time) format: {}", v)))
                 }
             }
@@ -206,7 +508,7 @@ impl<'de> Deserialize<'de> for DateBool {
 
 #[cfg(test)]
 mod tests {
-    use super::DateBool;
+    use super::{DateBool, Season};
     use chrono::{DateTime, NaiveDate};
     use serde_test::{assert_de_tokens, assert_tokens, Token};
 
@@ -241,4 +543,177 @@ mod tests {
             &[Token::String("2018-01-00")],
         );
     }
+
+    #[test]
+    fn test_datebool_partial_ser_de() {
+        assert_ser_de(&DateBool::Year(2015), &[Token::String("2015-00-00")]);
+        assert_ser_de(
+            &DateBool::Season(2020, Season::Spring),
+            &[Token::String("2020-spring")],
+        );
+        assert_de_tokens(
+            &DateBool::Season(2020, Season::Fall),
+            &[Token::String("Fall 2020")],
+        );
+        assert_ser_de(&DateBool::Quarter(2020, 2), &[Token::String("2020-Q2")]);
+        assert_ser_de(
+            &DateBool::Approximate(2015, 2015),
+            &[Token::String("~2015")],
+        );
+        assert_ser_de(
+            &DateBool::Approximate(2014, 2016),
+            &[Token::String("~2014/2016")],
+        );
+
+        let bad: Result<DateBool, _> = serde_yaml::from_str("\"2020-Q5\"");
+        assert!(bad.is_err());
+        let bad: Result<DateBool, _> = serde_yaml::from_str("\"2020-Q0\"");
+        assert!(bad.is_err());
+        let bad: Result<DateBool, _> = serde_yaml::from_str("\"~2016/2014\"");
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn test_datebool_order() {
+        let timestamp =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-05-31T12:00:00+09:00").unwrap());
+        let mut dates = vec![
+            DateBool::Approximate(2019, 2020),
+            DateBool::Year(2020),
+            DateBool::Quarter(2020, 2),
+            DateBool::YearMonth(2020, 5),
+            DateBool::Date(NaiveDate::from_ymd(2020, 5, 31)),
+            timestamp,
+            DateBool::Season(2020, Season::Spring),
+            DateBool::Date(NaiveDate::from_ymd(2020, 5, 1)),
+            DateBool::Year(2019),
+            DateBool::True,
+            DateBool::False,
+        ];
+        dates.sort();
+        assert_eq!(
+            vec![
+                DateBool::False,
+                DateBool::True,
+                DateBool::Year(2019),
+                DateBool::Date(NaiveDate::from_ymd(2020, 5, 1)),
+                timestamp,
+                DateBool::Date(NaiveDate::from_ymd(2020, 5, 31)),
+                DateBool::YearMonth(2020, 5),
+                DateBool::Season(2020, Season::Spring),
+                DateBool::Quarter(2020, 2),
+                DateBool::Year(2020),
+                DateBool::Approximate(2019, 2020),
+            ],
+            dates
+        );
+        assert!(DateBool::Year(2020) != DateBool::Approximate(2020, 2020));
+        assert!(DateBool::Year(2020) < DateBool::Approximate(2020, 2020));
+
+        use std::cmp::Ordering;
+
+        // Timestamps are ordered by when they happened
+        let tokyo =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-05-03T01:00:00+09:00").unwrap());
+        let utc =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-05-02T20:00:00Z").unwrap());
+        assert!(tokyo < utc);
+        let same =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-05-02T16:00:00Z").unwrap());
+        assert_eq!(tokyo, same);
+        assert_eq!(Ordering::Equal, tokyo.cmp(&same));
+
+        // Quarters and months out of range are still told apart
+        for (a, b) in [
+            (DateBool::Quarter(2020, 0), DateBool::Quarter(2020, 5)),
+            (DateBool::YearMonth(2020, 0), DateBool::YearMonth(2020, 13)),
+        ]
+        .iter()
+        {
+            assert_ne!(a, b);
+            assert_eq!(Ordering::Less, a.cmp(b));
+        }
+    }
+
+    #[test]
+    fn test_datebool_range_display() {
+        assert_eq!(
+            Some((
+                NaiveDate::from_ymd(2020, 10, 1),
+                NaiveDate::from_ymd(2020, 12, 31)
+            )),
+            DateBool::Season(2020, Season::Fall).range()
+        );
+        assert_eq!(
+            Some((
+                NaiveDate::from_ymd(2020, 2, 1),
+                NaiveDate::from_ymd(2020, 2, 29)
+            )),
+            DateBool::YearMonth(2020, 2).range()
+        );
+        assert_eq!(
+            Some((
+                NaiveDate::from_ymd(2020, 10, 1),
+                NaiveDate::from_ymd(2020, 12, 31)
+            )),
+            DateBool::Quarter(2020, 4).range()
+        );
+        assert_eq!(None, DateBool::Quarter(2020, 0).range());
+        assert_eq!(None, DateBool::Quarter(2020, 5).range());
+        assert_eq!(None, DateBool::Quarter(2020, u32::MAX).range());
+        assert_eq!(None, DateBool::Approximate(2014, 2016).year());
+        assert_eq!(Some(2015), DateBool::Approximate(2015, 2015).year());
+        assert_eq!("May 2020", DateBool::YearMonth(2020, 5).to_string());
+        assert_eq!("Q3 2020", DateBool::Quarter(2020, 3).to_string());
+        assert_eq!(
+            "c. 2014–2016",
+            DateBool::Approximate(2014, 2016).to_string()
+        );
+        assert_eq!("", DateBool::False.to_string());
+    }
+
+    #[test]
+    fn test_datebool_time_zone() {
+        use chrono_tz::Tz;
+
+        // 23:30 on New Year's Eve in New York is already New Year's Day
+        // in UTC
+        let date =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2019-12-31T23:30:00-05:00").unwrap());
+        assert_eq!(
+            Some(NaiveDate::from_ymd(2019, 12, 31)),
+            date.local_date(None)
+        );
+        assert_eq!(Some(2019), date.local_year(None));
+        let utc = Some(Tz::UTC.into());
+        assert_eq!(Some(NaiveDate::from_ymd(2020, 1, 1)), date.local_date(utc));
+        assert_eq!(Some(2020), date.local_year(utc));
+        assert_eq!(Some((2020, 1)), date.with_time_zone(utc).year_month());
+
+        // Daylight saving time: 03:30 UTC is the previous evening in New
+        // York in both winter (-05:00) and summer (-04:00)
+        let new_york = Some(Tz::America__New_York.into());
+        let winter =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-01-15T03:30:00Z").unwrap());
+        assert_eq!(
+            Some(NaiveDate::from_ymd(2020, 1, 14)),
+            winter.local_date(new_york)
+        );
+        let summer =
+            DateBool::Timestamp(DateTime::parse_from_rfc3339("2020-07-15T03:30:00Z").unwrap());
+        assert_eq!(
+            Some(NaiveDate::from_ymd(2020, 7, 14)),
+            summer.local_date(new_york)
+        );
+        assert_eq!(
+            "2020-07-14T23:30:00-04:00",
+            match summer.with_time_zone(new_york) {
+                DateBool::Timestamp(t) => t.to_rfc3339(),
+                _ => unreachable!(),
+            }
+        );
+
+        let date = DateBool::YearMonth(2019, 12);
+        assert_eq!(date, date.with_time_zone(utc));
+    }
 }
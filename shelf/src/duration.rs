@@ -0,0 +1,260 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Length metadata and time-spent totals.
+
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+use crate::common::{DateBool, Kind};
+use crate::item::Item;
+use crate::settings::Zone;
+use crate::shelf::Shelf;
+
+/// How long a work or entry is.
+///
+/// `minutes` covers both runtime (for video) and playtime (for visual
+/// novels). Any combination of measures may be given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Length {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<u32>,
+}
+
+impl Length {
+    pub fn minutes(minutes: u32) -> Length {
+        Length {
+            minutes: Some(minutes),
+            ..Default::default()
+        }
+    }
+
+    pub fn pages(pages: u32) -> Length {
+        Length {
+            pages: Some(pages),
+            ..Default::default()
+        }
+    }
+}
+
+/// Totals of what has been consumed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TimeSpent {
+    pub minutes: u64,
+    pub pages: u64,
+    pub words: u64,
+    /// The number of completed entries (or items without entries)
+    /// counted.
+    pub completions: u64,
+    /// How many of those completions had no length information.
+    pub unknown_length: u64,
+}
+
+impl TimeSpent {
+    pub fn hours(&self) -> f64 {
+        self.minutes as f64 / 60.0
+    }
+
+    pub fn add_length(&mut self, length: Option<Length>) {
+        self.completions += 1;
+        match length {
+            Some(length) => {
+                self.minutes += u64::from(length.minutes.unwrap_or(0));
+                self.pages += u64::from(length.pages.unwrap_or(0));
+                self.words += u64::from(length.words.unwrap_or(0));
+            }
+            None => self.unknown_length += 1,
+        }
+    }
+}
+
+impl AddAssign for TimeSpent {
+    fn add_assign(&mut self, other: TimeSpent) {
+        self.minutes += other.minutes;
+        self.pages += other.pages;
+        self.words += other.words;
+        self.completions += other.completions;
+        self.unknown_length += other.unknown_length;
+    }
+}
+
+/// A completed unit of a work, along with its length if known.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Consumption {
+    pub completed: DateBool,
+    pub length: Option<Length>,
+}
+
+/// List what was consumed of an item.
+///
+/// Each completed entry counts once, using its own length or else the
+/// item's length. An item without entries counts once if completed,
+/// using the item's length. Timestamps are converted to `tz`, if given.
+pub fn consumed(item: &Item, tz: Option<Zone>) -> Vec<Consumption> {
+    if item.entries.is_empty() {
+        if item.completed == DateBool::False {
+            return Vec::new();
+        }
+        return vec![Consumption {
+            completed: item.completed.with_time_zone(tz),
+            length: item.length,
+        }];
+    }
+    item.entries
+        .iter()
+        .filter(|entry| entry.completed != DateBool::False)
+        .map(|entry| Consumption {
+            completed: entry.completed.with_time_zone(tz),
+            length: entry.length.or(item.length),
+        })
+        .collect()
+}
+
+/// Time spent, broken down in various ways.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TimeStats {
+    pub total: TimeSpent,
+    pub by_kind: BTreeMap<Kind, TimeSpent>,
+    /// Completions without a date are not counted here.
+    pub by_year: BTreeMap<i32, TimeSpent>,
+    pub by_tag: BTreeMap<String, TimeSpent>,
+}
+
+impl TimeStats {
+    pub fn add_item(&mut self, item: &Item, tz: Option<Zone>) {
+        for consumption in consumed(item, tz) {
+            self.add(item, &consumption);
+        }
+    }
+
+    /// Add a single consumption of the given item.
+    pub fn add(&mut self, item: &Item, consumption: &Consumption) {
+        let mut spent = TimeSpent::default();
+        spent.add_length(consumption.length);
+
+        self.total += spent;
+        *self.by_kind.entry(item.kind).or_default() += spent;
+        if let Some(year) = consumption.completed.year() {
+            *self.by_year.entry(year).or_default() += spent;
+        }
+        for tag in item.tags.iter() {
+            *self.by_tag.entry(tag.clone()).or_default() += spent;
+        }
+    }
+}
+
+pub fn time_stats<'a, I: IntoIterator<Item = &'a Item>>(items: I, tz: Option<Zone>) -> TimeStats {
+    let mut stats = TimeStats::default();
+    for item in items {
+        stats.add_item(item, tz);
+    }
+    stats
+}
+
+impl Shelf {
+    pub fn time_stats(&self) -> TimeStats {
+        time_stats(self.all_items(), self.settings().time_zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{consumed, time_stats, Length};
+    use crate::common::{DateBool, Kind};
+    use crate::item::{Entry, Item};
+    use chrono::NaiveDate;
+
+    fn entry(number: u32, completed: DateBool, length: Option<Length>) -> Entry {
+        Entry {
+            name: None,
+            number: Some(number),
+            volume: None,
+            completed,
+            aired: DateBool::False,
+            rating: None,
+            length,
+            note: None,
+            extra: serde_yaml::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_consumed() {
+        let mut item = Item {
+            kind: Kind::TV,
+            length: Some(Length::minutes(24)),
+            ..Default::default()
+        };
+        assert!(consumed(&item, None).is_empty());
+        item.completed = DateBool::True;
+        assert_eq!(1, consumed(&item, None).len());
+
+        item.entries = vec![
+            entry(1, DateBool::True, None),
+            entry(2, DateBool::YearMonth(2020, 5), Some(Length::minutes(48))),
+            entry(3, DateBool::False, None),
+        ];
+        let result = consumed(&item, None);
+        assert_eq!(2, result.len());
+        assert_eq!(Some(Length::minutes(24)), result[0].length);
+        assert_eq!(Some(Length::minutes(48)), result[1].length);
+    }
+
+    #[test]
+    fn test_time_stats() {
+        let tv = Item {
+            kind: Kind::TV,
+            tags: vec!["Yuri".into()],
+            length: Some(Length::minutes(24)),
+            entries: vec![
+                entry(1, DateBool::Date(NaiveDate::from_ymd(2019, 12, 31)), None),
+                entry(2, DateBool::Date(NaiveDate::from_ymd(2020, 1, 1)), None),
+                entry(3, DateBool::True, None),
+            ],
+            ..Default::default()
+        };
+        let novel = Item {
+            kind: Kind::Novel,
+            tags: vec!["Yuri".into()],
+            completed: DateBool::YearMonth(2020, 2),
+            ..Default::default()
+        };
+        let manga = Item {
+            kind: Kind::Manga,
+            entries: vec![entry(
+                1,
+                DateBool::YearMonth(2020, 3),
+                Some(Length::pages(30)),
+            )],
+            ..Default::default()
+        };
+
+        let stats = time_stats(vec![&tv, &novel, &manga], None);
+        assert_eq!(72, stats.total.minutes);
+        assert_eq!(30, stats.total.pages);
+        assert_eq!(5, stats.total.completions);
+        assert_eq!(1, stats.total.unknown_length);
+        assert_eq!(72, stats.by_kind[&Kind::TV].minutes);
+        assert_eq!(24, stats.by_year[&2019].minutes);
+        assert_eq!(24, stats.by_year[&2020].minutes);
+        assert_eq!(30, stats.by_year[&2020].pages);
+        assert_eq!(3, stats.by_year[&2020].completions);
+        assert_eq!(4, stats.by_tag["Yuri"].completions);
+        assert!((stats.total.hours() - 1.2).abs() < 1e-9);
+    }
+}
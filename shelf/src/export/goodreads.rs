@@ -0,0 +1,185 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Export books as a Goodreads library CSV, in the same columns
+//! Goodreads exports.
+//!
+//! Goodreads' importer matches books by ISBN or by title and author, so
+//! books without IDs are still exported.
+
+use super::ExportReport;
+use crate::common::{DateBool, Role};
+use crate::external::Provider;
+use crate::import::goodreads::{is_book, shelf_of, stars, Row};
+use crate::item::Item;
+use crate::shelf::Shelf;
+
+fn author_name(shelf: &Shelf, key: &str) -> String {
+    shelf
+        .get_person(key)
+        .and_then(|person| person.name.get_default().cloned())
+        .unwrap_or_else(|| key.to_owned())
+}
+
+fn row(shelf: &Shelf, item: &Item) -> Row {
+    let tz = shelf.settings().time_zone;
+    let date = |date: DateBool| {
+        date.with_time_zone(tz)
+            .date()
+            .map(|date| date.format("%Y/%m/%d").to_string())
+            .unwrap_or_default()
+    };
+    let mut authors = item
+        .people
+        .iter()
+        .filter(|(role, _)| *role == Role::Author)
+        .map(|(_, key)| author_name(shelf, key));
+    let author = authors.next().unwrap_or_default();
+    let additional_authors = authors.collect::<Vec<_>>().join(", ");
+    let isbn = item
+        .external_ids
+        .get(&Provider::Isbn)
+        .cloned()
+        .unwrap_or_default();
+    let (isbn, isbn13) = if isbn.len() == 13 {
+        (String::new(), isbn)
+    } else {
+        (isbn, String::new())
+    };
+    let exclusive_shelf = shelf_of(item.status).to_owned();
+    let bookshelves = std::iter::once(exclusive_shelf.clone())
+        .chain(item.tags.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Row {
+        book_id: item
+            .external_ids
+            .get(&Provider::Goodreads)
+            .and_then(|id| id.strip_prefix("book/"))
+            .unwrap_or_default()
+            .to_owned(),
+        title: item.name.get_default().cloned().unwrap_or_default(),
+        author,
+        additional_authors,
+        isbn,
+        isbn13,
+        rating: item.rating.as_ref().map(stars).unwrap_or(0).to_string(),
+        date_read: date(item.completed),
+        date_added: tz
+            .map_or(item.added, |tz| tz.convert(&item.added))
+            .format("%Y/%m/%d")
+            .to_string(),
+        bookshelves,
+        exclusive_shelf,
+        review: item.comments.clone(),
+    }
+}
+
+/// Export the shelf's books. Items of other kinds are ignored.
+pub fn export(shelf: &Shelf) -> Result<(String, ExportReport), csv::Error> {
+    let mut report = ExportReport::default();
+    let mut items: Vec<&Item> = shelf
+        .all_items()
+        .iter()
+        .filter(|item| is_book(item.kind))
+        .collect();
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for item in items {
+        if item
+            .name
+            .get_default()
+            .filter(|name| !name.is_empty())
+            .is_none()
+        {
+            report.skip(&item.key, "No title");
+            continue;
+        }
+        writer.serialize(row(shelf, item))?;
+        report.exported += 1;
+    }
+    let out = writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(std::io::Error::from(err.error().kind())))?;
+    Ok((String::from_utf8_lossy(&out).into_owned(), report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::export;
+    use crate::common::{Alternatives, DateBool, Kind, Person, Role, Status};
+    use crate::external::Provider;
+    use crate::import::goodreads::plan;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    #[test]
+    fn test_export() {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-a".into(),
+            name: Alternatives::new("English", "Author, Jr."),
+            external_ids: Default::default(),
+        });
+        let mut book = Item {
+            key: "novel-a".into(),
+            kind: Kind::Novel,
+            name: Alternatives::new("English", "A \"Book\""),
+            status: Status::Completed,
+            started: DateBool::True,
+            completed: DateBool::Date(chrono::NaiveDate::from_ymd_opt(2020, 3, 15).unwrap()),
+            rating: Some(Rating::new(7, Scale::FiveStar)),
+            tags: vec!["fantasy".into()],
+            people: vec![(Role::Author, "person-a".into())],
+            comments: "Good,\nreally.".into(),
+            ..Default::default()
+        };
+        book.external_ids
+            .insert(Provider::Isbn, "9780553383041".into());
+        book.external_ids
+            .insert(Provider::Goodreads, "book/13642".into());
+        shelf.insert_item(book).unwrap();
+        shelf
+            .insert_item(Item {
+                key: "collection-b".into(),
+                kind: Kind::Collection,
+                ..Default::default()
+            })
+            .unwrap();
+        shelf
+            .insert_item(Item {
+                key: "tv-c".into(),
+                kind: Kind::TV,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let (csv, report) = export(&shelf).unwrap();
+        assert_eq!(1, report.exported);
+        assert_eq!(
+            vec![("collection-b".to_owned(), "No title".to_owned())],
+            report.skipped
+        );
+        assert!(csv.starts_with("Book Id,Title,Author,"));
+
+        // Reading it back changes nothing
+        let plan = plan(&shelf, csv.as_bytes()).unwrap();
+        assert_eq!(1, plan.count(Action::Unchanged));
+        assert!(plan.people.is_empty());
+        assert!(plan.warnings.is_empty());
+    }
+}
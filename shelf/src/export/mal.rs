@@ -0,0 +1,347 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Export MyAnimeList anime and manga list XML, as read by MAL's own
+//! importer and tools compatible with it.
+//!
+//! Only items with a MAL ID can be exported, since MAL matches titles
+//! by ID alone.
+
+use std::fmt::Write;
+
+use super::ExportReport;
+use crate::common::{DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::import::mal::List;
+use crate::item::{Item, Priority};
+use crate::rating::Scale;
+use crate::shelf::Shelf;
+
+/// The MAL list an item belongs on, if any.
+pub fn list_of(kind: Kind) -> Option<List> {
+    match kind {
+        Kind::TV | Kind::OVA | Kind::ONA | Kind::Film => Some(List::Anime),
+        Kind::Manga | Kind::Novel => Some(List::Manga),
+        _ => None,
+    }
+}
+
+/// Format a date the way MAL does: unknown parts are zero, and
+/// `0000-00-00` means no date.
+fn date(date: DateBool, tz: Option<crate::settings::Zone>) -> String {
+    let date = date.with_time_zone(tz);
+    if let Some(date) = date.date() {
+        date.format("%Y-%m-%d").to_string()
+    } else if let Some((year, month)) = date.year_month() {
+        format!("{:04}-{:02}-00", year, month)
+    } else if let Some(year) = date.year() {
+        format!("{:04}-00-00", year)
+    } else {
+        "0000-00-00".to_owned()
+    }
+}
+
+fn status(status: Status, list: List) -> &'static str {
+    match (status, list) {
+        (Status::InProgress, List::Anime) => "Watching",
+        (Status::InProgress, List::Manga) => "Reading",
+        (Status::Completed, _) => "Completed",
+        (Status::OnHold, _) => "On-Hold",
+        (Status::Dropped, _) => "Dropped",
+        (Status::Planned, List::Anime) => "Plan to Watch",
+        (Status::Planned, List::Manga) => "Plan to Read",
+    }
+}
+
+fn cdata(text: &str) -> String {
+    // "]]>" can't appear in CDATA, so split it across two sections
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+fn series_type(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Film => "Movie",
+        Kind::OVA => "OVA",
+        Kind::ONA => "ONA",
+        Kind::Novel => "Novel",
+        Kind::Manga => "Manga",
+        _ => "TV",
+    }
+}
+
+/// Write one title. The `write!`s can't fail, since they write to a
+/// `String`.
+fn write_item(
+    out: &mut String,
+    item: &Item,
+    id: &str,
+    list: List,
+    tz: Option<crate::settings::Zone>,
+) {
+    let completed = item
+        .entries
+        .iter()
+        .filter(|entry| entry.completed != DateBool::False)
+        .count();
+    // MAL uses 0 for an unknown total, which is the case while a work
+    // is still being published
+    let total = match item.publication_status {
+        crate::item::PublicationStatus::Complete => item.entries.len(),
+        crate::item::PublicationStatus::Publishing => 0,
+    };
+    let score = item
+        .rating
+        .as_ref()
+        .map(|rating| rating.convert(Scale::Ten).score)
+        .unwrap_or(0);
+    let priority = match item.priority {
+        Some(Priority::High) => "HIGH",
+        Some(Priority::Medium) => "MEDIUM",
+        Some(Priority::Low) | None => "LOW",
+    };
+    let title = item.name.get_default().cloned().unwrap_or_default();
+
+    let _ = writeln!(out, "  <{}>", list.tag());
+    match list {
+        List::Anime => {
+            let _ = writeln!(out, "    <series_animedb_id>{}</series_animedb_id>", id);
+            let _ = writeln!(out, "    <series_title>{}</series_title>", cdata(&title));
+            let _ = writeln!(
+                out,
+                "    <series_type>{}</series_type>",
+                series_type(item.kind)
+            );
+            let _ = writeln!(out, "    <series_episodes>{}</series_episodes>", total);
+            let _ = writeln!(out, "    <my_id>0</my_id>");
+            let _ = writeln!(
+                out,
+                "    <my_watched_episodes>{}</my_watched_episodes>",
+                completed
+            );
+        }
+        List::Manga => {
+            let volumes = |completed_only: bool| {
+                item.entries
+                    .iter()
+                    .filter(|entry| !completed_only || entry.completed != DateBool::False)
+                    .filter_map(|entry| entry.volume)
+                    .max()
+                    .unwrap_or(0)
+            };
+            let total_volumes = if total > 0 { volumes(false) } else { 0 };
+            let _ = writeln!(out, "    <manga_mangadb_id>{}</manga_mangadb_id>", id);
+            let _ = writeln!(out, "    <manga_title>{}</manga_title>", cdata(&title));
+            let _ = writeln!(out, "    <manga_volumes>{}</manga_volumes>", total_volumes);
+            let _ = writeln!(out, "    <manga_chapters>{}</manga_chapters>", total);
+            let _ = writeln!(out, "    <my_id>0</my_id>");
+            let _ = writeln!(
+                out,
+                "    <my_read_volumes>{}</my_read_volumes>",
+                volumes(true)
+            );
+            let _ = writeln!(
+                out,
+                "    <my_read_chapters>{}</my_read_chapters>",
+                completed
+            );
+        }
+    }
+    let _ = writeln!(
+        out,
+        "    <my_start_date>{}</my_start_date>",
+        date(item.started, tz)
+    );
+    let _ = writeln!(
+        out,
+        "    <my_finish_date>{}</my_finish_date>",
+        date(item.completed, tz)
+    );
+    let _ = writeln!(out, "    <my_score>{}</my_score>", score);
+    let _ = writeln!(
+        out,
+        "    <my_status>{}</my_status>",
+        status(item.status, list)
+    );
+    let _ = writeln!(
+        out,
+        "    <my_comments>{}</my_comments>",
+        cdata(&item.comments)
+    );
+    let _ = writeln!(out, "    <my_priority>{}</my_priority>", priority);
+    let _ = writeln!(
+        out,
+        "    <my_tags>{}</my_tags>",
+        cdata(&item.tags.join(", "))
+    );
+    // Tell MAL to overwrite its copy of the entry
+    let _ = writeln!(out, "    <update_on_import>1</update_on_import>");
+    let _ = writeln!(out, "  </{}>", list.tag());
+}
+
+/// Export the items that belong on a MAL list. Items of other kinds are
+/// ignored; items without a MAL ID for the list are skipped and
+/// reported.
+pub fn export(shelf: &Shelf, list: List) -> (String, ExportReport) {
+    let tz = shelf.settings().time_zone;
+    let mut report = ExportReport::default();
+    let mut items: Vec<&Item> = shelf
+        .all_items()
+        .iter()
+        .filter(|item| list_of(item.kind) == Some(list))
+        .collect();
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut body = String::new();
+    for item in items {
+        let prefix = format!("{}/", list.tag());
+        match item.external_ids.get(&Provider::MyAnimeList) {
+            Some(id) if id.starts_with(&prefix) => {
+                write_item(&mut body, item, &id[prefix.len()..], list, tz);
+                report.exported += 1;
+            }
+            Some(id) => report.skip(&item.key, format!("MAL ID {} is not on the list", id)),
+            None => report.skip(&item.key, "No MAL ID"),
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8" ?>"#);
+    let _ = writeln!(out, "<myanimelist>");
+    let _ = writeln!(out, "  <myinfo>");
+    let _ = writeln!(
+        out,
+        "    <user_export_type>{}</user_export_type>",
+        match list {
+            List::Anime => 1,
+            List::Manga => 2,
+        }
+    );
+    let _ = writeln!(out, "  </myinfo>");
+    out.push_str(&body);
+    let _ = writeln!(out, "</myanimelist>");
+    (out, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date, export};
+    use crate::common::{Alternatives, DateBool, Kind, Status};
+    use crate::external::Provider;
+    use crate::import::mal::{parse, List};
+    use crate::item::{Entry, Item, PublicationStatus};
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    fn entry(volume: u32, completed: DateBool) -> Entry {
+        Entry {
+            name: None,
+            number: None,
+            volume: Some(volume),
+            completed,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_date() {
+        let tz = Some(chrono_tz::Tz::Asia__Tokyo.into());
+        assert_eq!("0000-00-00", date(DateBool::False, tz));
+        assert_eq!("0000-00-00", date(DateBool::True, tz));
+        assert_eq!("2020-03-00", date(DateBool::YearMonth(2020, 3), tz));
+        assert_eq!("2020-00-00", date(DateBool::Quarter(2020, 2), tz));
+        assert_eq!("0000-00-00", date(DateBool::Approximate(2019, 2020), tz));
+        // Timestamps are dated in the library's time zone
+        let ts = chrono::DateTime::parse_from_rfc3339("2020-03-01T20:00:00-05:00").unwrap();
+        assert_eq!("2020-03-02", date(DateBool::Timestamp(ts), tz));
+        assert_eq!("2020-03-01", date(DateBool::Timestamp(ts), None));
+    }
+
+    #[test]
+    fn test_export() {
+        let mut shelf = Shelf::new();
+        let mut manga = Item {
+            key: "manga-a".into(),
+            kind: Kind::Manga,
+            name: Alternatives::new("Romaji", "A & <B>"),
+            status: Status::Completed,
+            rating: Some(Rating::new(80, Scale::Hundred)),
+            started: DateBool::YearMonth(2020, 1),
+            completed: DateBool::True,
+            publication_status: PublicationStatus::Complete,
+            entries: vec![
+                entry(1, DateBool::True),
+                entry(1, DateBool::True),
+                entry(2, DateBool::False),
+            ],
+            ..Default::default()
+        };
+        manga
+            .external_ids
+            .insert(Provider::MyAnimeList, "manga/42".into());
+        shelf.insert_item(manga).unwrap();
+        let mut wrong_list = Item {
+            key: "novel-b".into(),
+            kind: Kind::Novel,
+            ..Default::default()
+        };
+        wrong_list
+            .external_ids
+            .insert(Provider::MyAnimeList, "anime/1".into());
+        shelf.insert_item(wrong_list).unwrap();
+        shelf
+            .insert_item(Item {
+                key: "manga-c".into(),
+                ..Default::default()
+            })
+            .unwrap();
+        shelf
+            .insert_item(Item {
+                key: "tv-d".into(),
+                kind: Kind::TV,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let (xml, report) = export(&shelf, List::Manga);
+        assert_eq!(1, report.exported);
+        assert_eq!(
+            vec![
+                ("manga-c".to_owned(), "No MAL ID".to_owned()),
+                (
+                    "novel-b".to_owned(),
+                    "MAL ID anime/1 is not on the list".to_owned()
+                ),
+            ],
+            report.skipped
+        );
+        assert!(xml.contains("<manga_volumes>2</manga_volumes>"));
+        assert!(xml.contains("<my_read_volumes>1</my_read_volumes>"));
+
+        // MAL can read it back
+        let records = parse(&xml).unwrap();
+        assert_eq!(1, records.len());
+        let record = &records[0];
+        assert_eq!("manga/42", record.external_id());
+        assert_eq!("A & <B>", record.title);
+        assert_eq!((3, 2), (record.total, record.progress));
+        assert_eq!(8, record.score);
+        assert_eq!(Status::Completed, record.status);
+        assert_eq!(DateBool::YearMonth(2020, 1), record.started);
+        assert_eq!(DateBool::False, record.finished);
+    }
+}
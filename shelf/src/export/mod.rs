@@ -0,0 +1,33 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Exporting the shelf to formats other sites and applications read.
+
+pub mod goodreads;
+pub mod mal;
+pub mod table;
+
+/// What was left out of an export.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ExportReport {
+    pub exported: usize,
+    /// Items that could not be exported, and why.
+    pub skipped: Vec<(String, String)>,
+}
+
+impl ExportReport {
+    fn skip<S: Into<String>>(&mut self, key: &str, reason: S) {
+        self.skipped.push((key.to_owned(), reason.into()));
+    }
+}
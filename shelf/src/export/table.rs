@@ -0,0 +1,464 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Export items as a table of chosen columns, either CSV or JSON Lines
+//! (one object per item, keyed by column name).
+//!
+//! Columns are named like `name`, `name.Japanese`, `people.Author` or
+//! `extra.mal_id`; see `Column`. In CSV, lists are joined with "; "
+//! and missing values are empty.
+
+use super::ExportReport;
+use crate::common::{DateBool, Role};
+use crate::external::Provider;
+use crate::filter::ItemFilter;
+use crate::item::Item;
+use crate::shelf::Shelf;
+
+/// The columns exported when none are chosen.
+pub const DEFAULT_COLUMNS: &[&str] = &[
+    "key",
+    "kind",
+    "name",
+    "status",
+    "rating",
+    "people",
+    "series",
+    "entries_completed",
+    "entries",
+    "started",
+    "completed",
+];
+
+/// A value derived from an item.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Key,
+    Kind,
+    /// The name in the default language.
+    Name,
+    /// `name.<Language>`: the name in another language.
+    AlternativeName(String),
+    Status,
+    Rating,
+    Tags,
+    /// `people` or `people.<Role>`: names of the item's people.
+    People(Option<Role>),
+    /// The series name.
+    Series,
+    /// The item's index or entry name within its series.
+    SeriesIndex,
+    /// The number of entries.
+    Entries,
+    /// The number of completed entries.
+    EntriesCompleted,
+    Started,
+    Completed,
+    Added,
+    Synopsis,
+    Comments,
+    /// `external.<Provider>`: an external ID.
+    External(Provider),
+    /// `extra.<path>`: a value in `Item::extra`, with nested fields
+    /// separated by dots.
+    Extra(Vec<String>),
+}
+
+/// Parse a type from its name, as it is written in YAML.
+fn variant<T: serde::de::DeserializeOwned>(name: &str) -> Option<T> {
+    serde_yaml::from_value(serde_yaml::Value::String(name.to_owned())).ok()
+}
+
+/// The name a value of a type is written with.
+fn variant_name<T: serde::Serialize>(value: &T) -> String {
+    match serde_yaml::to_value(value) {
+        Ok(serde_yaml::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+impl std::str::FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Column, String> {
+        let unknown = || format!("Unknown column {:?}", s);
+        let (field, arg) = match s.split_once('.') {
+            Some((field, arg)) => (field, Some(arg)),
+            None => (s, None),
+        };
+        Ok(match (field, arg) {
+            ("key", None) => Column::Key,
+            ("kind", None) => Column::Kind,
+            ("name", None) => Column::Name,
+            ("name", Some(language)) => Column::AlternativeName(language.to_owned()),
+            ("status", None) => Column::Status,
+            ("rating", None) => Column::Rating,
+            ("tags", None) => Column::Tags,
+            ("people", None) => Column::People(None),
+            ("people", Some(role)) => Column::People(Some(variant(role).ok_or_else(unknown)?)),
+            ("series", None) => Column::Series,
+            ("series_index", None) => Column::SeriesIndex,
+            ("entries", None) => Column::Entries,
+            ("entries_completed", None) => Column::EntriesCompleted,
+            ("started", None) => Column::Started,
+            ("completed", None) => Column::Completed,
+            ("added", None) => Column::Added,
+            ("synopsis", None) => Column::Synopsis,
+            ("comments", None) => Column::Comments,
+            ("external", Some(provider)) => {
+                Column::External(variant(provider).ok_or_else(unknown)?)
+            }
+            ("extra", Some(path)) if !path.split('.').any(str::is_empty) => {
+                Column::Extra(path.split('.').map(str::to_owned).collect())
+            }
+            _ => return Err(unknown()),
+        })
+    }
+}
+
+impl Column {
+    /// Parse a comma-separated list of columns.
+    pub fn parse_list(columns: &str) -> Result<Vec<Column>, String> {
+        columns
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    pub fn defaults() -> Vec<Column> {
+        DEFAULT_COLUMNS
+            .iter()
+            .map(|column| column.parse().unwrap())
+            .collect()
+    }
+
+    /// The column's name, as it is parsed.
+    pub fn name(&self) -> String {
+        match self {
+            Column::Key => "key".to_owned(),
+            Column::Kind => "kind".to_owned(),
+            Column::Name => "name".to_owned(),
+            Column::AlternativeName(language) => format!("name.{}", language),
+            Column::Status => "status".to_owned(),
+            Column::Rating => "rating".to_owned(),
+            Column::Tags => "tags".to_owned(),
+            Column::People(None) => "people".to_owned(),
+            Column::People(Some(role)) => format!("people.{}", variant_name(role)),
+            Column::Series => "series".to_owned(),
+            Column::SeriesIndex => "series_index".to_owned(),
+            Column::Entries => "entries".to_owned(),
+            Column::EntriesCompleted => "entries_completed".to_owned(),
+            Column::Started => "started".to_owned(),
+            Column::Completed => "completed".to_owned(),
+            Column::Added => "added".to_owned(),
+            Column::Synopsis => "synopsis".to_owned(),
+            Column::Comments => "comments".to_owned(),
+            Column::External(provider) => format!("external.{}", variant_name(provider)),
+            Column::Extra(path) => format!("extra.{}", path.join(".")),
+        }
+    }
+
+    /// The column's value for an item, or null if it has none.
+    pub fn value(&self, shelf: &Shelf, item: &Item) -> serde_json::Value {
+        use serde_json::Value;
+
+        let tz = shelf.settings().time_zone;
+        let string = |value: Option<&String>| match value {
+            Some(value) if !value.is_empty() => Value::String(value.clone()),
+            _ => Value::Null,
+        };
+        let date = |date: DateBool| match date {
+            DateBool::False => Value::Null,
+            date => Value::String(date.with_time_zone(tz).to_string()),
+        };
+        match self {
+            Column::Key => Value::String(item.key.clone()),
+            Column::Kind => serde_json::to_value(item.kind).unwrap_or(Value::Null),
+            Column::Name => string(item.name.get_default()),
+            Column::AlternativeName(language) => string(item.name.alternatives.get(language)),
+            Column::Status => serde_json::to_value(item.status).unwrap_or(Value::Null),
+            Column::Rating => match &item.rating {
+                Some(rating) => Value::String(rating.to_string()),
+                None => Value::Null,
+            },
+            Column::Tags => Value::Array(item.tags.iter().cloned().map(Value::String).collect()),
+            Column::People(role) => Value::Array(
+                item.people
+                    .iter()
+                    .filter(|(other, _)| role.map(|role| role == *other).unwrap_or(true))
+                    .map(|(_, key)| {
+                        Value::String(
+                            shelf
+                                .get_person(key)
+                                .and_then(|person| person.name.get_default().cloned())
+                                .unwrap_or_else(|| key.clone()),
+                        )
+                    })
+                    .collect(),
+            ),
+            Column::Series => match &item.series {
+                Some((key, _)) => Value::String(
+                    shelf
+                        .get_series(key)
+                        .and_then(|series| series.name.get_default().cloned())
+                        .unwrap_or_else(|| key.clone()),
+                ),
+                None => Value::Null,
+            },
+            Column::SeriesIndex => {
+                string(item.series.as_ref().and_then(|(_, index)| index.as_ref()))
+            }
+            Column::Entries => item.entries.len().into(),
+            Column::EntriesCompleted => item
+                .entries
+                .iter()
+                .filter(|entry| entry.completed != DateBool::False)
+                .count()
+                .into(),
+            Column::Started => date(item.started),
+            Column::Completed => date(item.completed),
+            Column::Added => Value::String(
+                tz.map_or(item.added, |tz| tz.convert(&item.added))
+                    .to_rfc3339(),
+            ),
+            Column::Synopsis => string(Some(&item.synopsis)),
+            Column::Comments => string(Some(&item.comments)),
+            Column::External(provider) => string(item.external_ids.get(provider)),
+            Column::Extra(path) => path
+                .iter()
+                .try_fold(&item.extra, |value, field| value.get(field.as_str()))
+                .and_then(|value| serde_json::to_value(value).ok())
+                .unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// Render a value as a CSV cell.
+fn cell(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(value) => value,
+        serde_json::Value::Array(values) => {
+            values.into_iter().map(cell).collect::<Vec<_>>().join("; ")
+        }
+        value => value.to_string(),
+    }
+}
+
+/// The items matching a filter, by key.
+fn items<'a>(shelf: &'a Shelf, filter: &ItemFilter) -> Vec<&'a Item> {
+    let mut items: Vec<&Item> = shelf
+        .all_items()
+        .iter()
+        .filter(|item| filter.matches(item))
+        .collect();
+    items.sort_by(|a, b| a.key.cmp(&b.key));
+    items
+}
+
+/// Export the items matching a filter as CSV, with a header row.
+pub fn csv(
+    shelf: &Shelf,
+    columns: &[Column],
+    filter: &ItemFilter,
+) -> Result<(String, ExportReport), csv::Error> {
+    let mut report = ExportReport::default();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(columns.iter().map(Column::name))?;
+    for item in items(shelf, filter) {
+        writer.write_record(columns.iter().map(|column| cell(column.value(shelf, item))))?;
+        report.exported += 1;
+    }
+    let out = writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(std::io::Error::from(err.error().kind())))?;
+    Ok((String::from_utf8_lossy(&out).into_owned(), report))
+}
+
+/// Export the items matching a filter as JSON Lines.
+pub fn json_lines(
+    shelf: &Shelf,
+    columns: &[Column],
+    filter: &ItemFilter,
+) -> (String, ExportReport) {
+    let mut report = ExportReport::default();
+    let mut out = String::new();
+    for item in items(shelf, filter) {
+        let object: serde_json::Map<String, serde_json::Value> = columns
+            .iter()
+            .map(|column| (column.name(), column.value(shelf, item)))
+            .collect();
+        out.push_str(&serde_json::Value::Object(object).to_string());
+        out.push('\n');
+        report.exported += 1;
+    }
+    (out, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv, json_lines, Column};
+    use crate::common::{Alternatives, DateBool, Kind, Person, Role, Status};
+    use crate::external::Provider;
+    use crate::filter::ItemFilter;
+    use crate::item::{Entry, Item};
+    use crate::rating::{Rating, Scale};
+    use crate::series::Series;
+    use crate::Shelf;
+
+    fn shelf() -> Shelf {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-a".into(),
+            name: Alternatives::new("English", "Author, Jr."),
+            external_ids: Default::default(),
+        });
+        shelf.insert_series(Series {
+            key: "series-s".into(),
+            name: Alternatives::new("English", "The Series"),
+            people: Vec::new(),
+            external_ids: Default::default(),
+        });
+        let mut name = Alternatives::new("English", "Frieren");
+        name.alternatives
+            .insert("Japanese".into(), "葬送のフリーレン".into());
+        let mut manga = Item {
+            key: "manga-frieren".into(),
+            kind: Kind::Manga,
+            name,
+            status: Status::InProgress,
+            rating: Some(Rating::new(9, Scale::FiveStar)),
+            tags: vec!["fantasy".into(), "slice of life".into()],
+            people: vec![(Role::Author, "person-a".into())],
+            series: Some(("series-s".into(), Some("1".into()))),
+            started: DateBool::Date(chrono::NaiveDate::from_ymd_opt(2021, 1, 2).unwrap()),
+            extra: serde_yaml::from_str("mal_id: 126287\nnested:\n  value: x").unwrap(),
+            ..Default::default()
+        };
+        for number in 1..=3 {
+            manga.entries.push(Entry {
+                name: None,
+                number: Some(number),
+                volume: None,
+                completed: if number < 3 {
+                    DateBool::True
+                } else {
+                    DateBool::False
+                },
+                aired: DateBool::False,
+                rating: None,
+                length: None,
+                note: None,
+                extra: Default::default(),
+            });
+        }
+        manga
+            .external_ids
+            .insert(Provider::MyAnimeList, "manga/126287".into());
+        shelf.insert_item(manga).unwrap();
+        shelf
+            .insert_item(Item {
+                key: "tv-b".into(),
+                kind: Kind::TV,
+                name: Alternatives::new("English", "B"),
+                ..Default::default()
+            })
+            .unwrap();
+        shelf
+    }
+
+    #[test]
+    fn test_parse() {
+        let columns =
+            Column::parse_list("key, name.Japanese,people.Author,external.Isbn,extra.a.b").unwrap();
+        assert_eq!(
+            vec![
+                Column::Key,
+                Column::AlternativeName("Japanese".into()),
+                Column::People(Some(Role::Author)),
+                Column::External(Provider::Isbn),
+                Column::Extra(vec!["a".into(), "b".into()]),
+            ],
+            columns
+        );
+        assert_eq!(
+            vec![
+                "key",
+                "name.Japanese",
+                "people.Author",
+                "external.Isbn",
+                "extra.a.b"
+            ],
+            columns.iter().map(Column::name).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Err("Unknown column \"people.Cook\"".to_owned()),
+            Column::parse_list("people.Cook")
+        );
+        assert!(Column::parse_list("extra").is_err());
+        assert!(Column::parse_list("extra.a..b").is_err());
+        assert_eq!(11, Column::defaults().len());
+    }
+
+    #[test]
+    fn test_csv() {
+        let shelf = shelf();
+        let (out, report) = csv(&shelf, &Column::defaults(), &ItemFilter::default()).unwrap();
+        assert_eq!(2, report.exported);
+        assert_eq!(
+            "key,kind,name,status,rating,people,series,entries_completed,entries,started,completed\n\
+             manga-frieren,Manga,Frieren,InProgress,4.5/5,\"Author, Jr.\",The Series,2,3,2021-01-02,\n\
+             tv-b,TV,B,Planned,,,,0,0,,\n",
+            out
+        );
+
+        let filter = ItemFilter {
+            tags: vec!["fantasy".into()],
+            ..Default::default()
+        };
+        let columns = Column::parse_list("name.Japanese,tags,extra.nested.value").unwrap();
+        let (out, _) = csv(&shelf, &columns, &filter).unwrap();
+        assert_eq!(
+            "name.Japanese,tags,extra.nested.value\n葬送のフリーレン,fantasy; slice of life,x\n",
+            out
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let shelf = shelf();
+        let columns =
+            Column::parse_list("key,tags,series_index,external.MyAnimeList,extra.mal_id").unwrap();
+        let (out, report) = json_lines(&shelf, &columns, &ItemFilter::default());
+        assert_eq!(2, report.exported);
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            serde_json::json!({
+                "key": "manga-frieren",
+                "tags": ["fantasy", "slice of life"],
+                "series_index": "1",
+                "external.MyAnimeList": "manga/126287",
+                "extra.mal_id": 126287,
+            }),
+            lines[0]
+        );
+        assert_eq!(serde_json::Value::Null, lines[1]["extra.mal_id"]);
+        assert_eq!(serde_json::json!([]), lines[1]["tags"]);
+    }
+}
@@ -0,0 +1,569 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Identifiers of works and people on external sites.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_yaml;
+
+use crate::common::Kind;
+
+/// A site or catalog that assigns identifiers to works or people.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Provider {
+    MyAnimeList,
+    MangaDex,
+    Kitsu,
+    AniList,
+    DynastyScans,
+    Webtoons,
+    Cubari,
+    Isbn,
+    Imdb,
+    Vndb,
+    Goodreads,
+    /// The UUID of a book in a local Calibre library.
+    Calibre,
+}
+
+/// External identifiers of an entity, in canonical form.
+pub type ExternalIds = BTreeMap<Provider, String>;
+
+impl Provider {
+    pub fn all() -> &'static [Provider] {
+        &[
+            Provider::MyAnimeList,
+            Provider::MangaDex,
+            Provider::Kitsu,
+            Provider::AniList,
+            Provider::DynastyScans,
+            Provider::Webtoons,
+            Provider::Cubari,
+            Provider::Isbn,
+            Provider::Imdb,
+            Provider::Vndb,
+            Provider::Goodreads,
+            Provider::Calibre,
+        ]
+    }
+
+    /// Parse a URL into a provider and canonical ID.
+    pub fn parse_url(url: &str) -> Option<(Provider, String)> {
+        let url = Url::parse(url)?;
+        let segments = &url.segments;
+        let segment = |i: usize| segments.get(i).map(|s| s.as_str());
+        match url.host.as_str() {
+            "myanimelist.net" => match (segment(0), segment(1)) {
+                (Some(typ @ "anime"), Some(id)) | (Some(typ @ "manga"), Some(id))
+                    if is_numeric(id) =>
+                {
+                    Some((Provider::MyAnimeList, format!("{}/{}", typ, id)))
+                }
+                _ => None,
+            },
+            "mangadex.org" => match (segment(0), segment(1)) {
+                (Some("title"), Some(id)) | (Some("manga"), Some(id)) => {
+                    Some((Provider::MangaDex, id.to_lowercase()))
+                }
+                _ => None,
+            },
+            "kitsu.io" | "kitsu.app" => match (segment(0), segment(1)) {
+                (Some(typ @ "anime"), Some(id)) | (Some(typ @ "manga"), Some(id))
+                    if is_numeric(id) =>
+                {
+                    Some((Provider::Kitsu, format!("{}/{}", typ, id)))
+                }
+                _ => None,
+            },
+            "anilist.co" => match (segment(0), segment(1)) {
+                (Some(typ @ "anime"), Some(id)) | (Some(typ @ "manga"), Some(id))
+                    if is_numeric(id) =>
+                {
+                    Some((Provider::AniList, format!("{}/{}", typ, id)))
+                }
+                _ => None,
+            },
+            "dynasty-scans.com" => match (segment(0), segment(1)) {
+                (Some(typ @ "series"), Some(id))
+                | (Some(typ @ "chapters"), Some(id))
+                | (Some(typ @ "anthologies"), Some(id))
+                | (Some(typ @ "doujins"), Some(id)) => {
+                    Some((Provider::DynastyScans, format!("{}/{}", typ, id)))
+                }
+                _ => None,
+            },
+            "webtoons.com" => url
+                .query_param("title_no")
+                .or_else(|| url.query_param("titleNo"))
+                .filter(|id| is_numeric(id))
+                .map(|id| (Provider::Webtoons, id.to_owned())),
+            "cubari.moe" => match (segment(0), segment(1), segment(2)) {
+                (Some("read"), Some(source), Some(slug)) => {
+                    Some((Provider::Cubari, format!("{}/{}", source, slug)))
+                }
+                _ => None,
+            },
+            "imdb.com" => match (segment(0), segment(1)) {
+                (Some("title"), Some(id)) | (Some("name"), Some(id)) => Provider::Imdb
+                    .canonicalize(id)
+                    .map(|id| (Provider::Imdb, id)),
+                _ => None,
+            },
+            "vndb.org" => segment(0)
+                .and_then(|id| Provider::Vndb.canonicalize(id))
+                .map(|id| (Provider::Vndb, id)),
+            // Book IDs are followed by a slug, e.g. 2767052-the-hunger-games
+            "goodreads.com" => match (segment(0), segment(1), segment(2)) {
+                (Some(typ @ "book"), Some("show"), Some(id))
+                | (Some(typ @ "author"), Some("show"), Some(id)) => {
+                    let id: String = id.chars().take_while(|c| c.is_ascii_digit()).collect();
+                    if id.is_empty() {
+                        None
+                    } else {
+                        Some((Provider::Goodreads, format!("{}/{}", typ, id)))
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Normalize an ID given without a URL, or return None if it is
+    /// not a valid ID for this provider.
+    pub fn canonicalize(self, id: &str) -> Option<String> {
+        let id = id.trim();
+        if id.is_empty() {
+            return None;
+        }
+        match self {
+            Provider::Isbn => {
+                let digits: String = id
+                    .chars()
+                    .filter(|c| *c != '-' && !c.is_whitespace())
+                    .collect::<String>()
+                    .to_uppercase();
+                isbn13(&digits)
+            }
+            Provider::Imdb => {
+                let id = id.to_lowercase();
+                if (id.starts_with("tt") || id.starts_with("nm")) && is_numeric(&id[2..]) {
+                    Some(id)
+                } else {
+                    None
+                }
+            }
+            Provider::Vndb => {
+                let id = id.to_lowercase();
+                let mut chars = id.chars();
+                match chars.next() {
+                    Some('v') | Some('s') | Some('p') | Some('c') if is_numeric(chars.as_str()) => {
+                        Some(id)
+                    }
+                    _ => None,
+                }
+            }
+            Provider::Webtoons if !is_numeric(id) => None,
+            // Slugs can change, so only the numeric ID is canonical
+            Provider::Kitsu => match id.split_once('/') {
+                Some((typ @ "anime", number)) | Some((typ @ "manga", number))
+                    if is_numeric(number) =>
+                {
+                    Some(format!("{}/{}", typ, number))
+                }
+                _ => None,
+            },
+            _ => {
+                if id.contains(char::is_whitespace) {
+                    None
+                } else {
+                    Some(id.to_owned())
+                }
+            }
+        }
+    }
+
+    /// Build a URL pointing to the given canonical ID.
+    pub fn url(self, id: &str) -> Option<String> {
+        match self {
+            Provider::MyAnimeList => Some(format!("https://myanimelist.net/{}", id)),
+            Provider::MangaDex => Some(format!("https://mangadex.org/title/{}", id)),
+            Provider::Kitsu => Some(format!("https://kitsu.io/{}", id)),
+            Provider::AniList => Some(format!("https://anilist.co/{}", id)),
+            Provider::DynastyScans => Some(format!("https://dynasty-scans.com/{}", id)),
+            Provider::Webtoons => Some(format!(
+                "https://www.webtoons.com/episodeList?titleNo={}",
+                id
+            )),
+            Provider::Cubari => Some(format!("https://cubari.moe/read/{}/", id)),
+            Provider::Isbn | Provider::Calibre => None,
+            Provider::Imdb => {
+                if id.starts_with("nm") {
+                    Some(format!("https://www.imdb.com/name/{}/", id))
+                } else {
+                    Some(format!("https://www.imdb.com/title/{}/", id))
+                }
+            }
+            Provider::Vndb => Some(format!("https://vndb.org/{}", id)),
+            Provider::Goodreads => id
+                .split_once('/')
+                .map(|(typ, id)| format!("https://www.goodreads.com/{}/show/{}", typ, id)),
+        }
+    }
+}
+
+/// Collect the external IDs stored in the legacy `extra` keys
+/// (`mal_id`, `mangadex_url`, `external_url` and `isbn`).
+pub fn ids_from_extra(kind: Kind, extra: &serde_yaml::Value) -> Vec<(Provider, String)> {
+    let mut result = Vec::new();
+    let get = |key: &str| extra.get(key);
+
+    if let Some(mal_id) = get("mal_id") {
+        let mal_id = match mal_id {
+            serde_yaml::Value::Number(n) => n.as_u64().map(|n| n.to_string()),
+            serde_yaml::Value::String(s) if is_numeric(s) => Some(s.clone()),
+            _ => None,
+        };
+        let typ = match kind {
+            Kind::TV | Kind::Film | Kind::OVA | Kind::ONA | Kind::Music => Some("anime"),
+            Kind::Manga | Kind::Novel | Kind::ShortStory => Some("manga"),
+            // MyAnimeList doesn't list anything else
+            _ => None,
+        };
+        if let (Some(mal_id), Some(typ)) = (mal_id, typ) {
+            result.push((Provider::MyAnimeList, format!("{}/{}", typ, mal_id)));
+        }
+    }
+    for key in &["mangadex_url", "external_url"] {
+        if let Some(serde_yaml::Value::String(url)) = get(key) {
+            if let Some(parsed) = Provider::parse_url(url) {
+                result.push(parsed);
+            }
+        }
+    }
+    if let Some(serde_yaml::Value::String(isbn)) = get("isbn") {
+        if let Some(isbn) = Provider::Isbn.canonicalize(isbn) {
+            result.push((Provider::Isbn, isbn));
+        }
+    }
+    result
+}
+
+/// A reverse index from external IDs to entity keys.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalIndex {
+    index: HashMap<(Provider, String), String>,
+}
+
+/// The canonical form of an ID, for IDs saved before it was enforced
+/// (e.g. an ISBN-10).
+fn normalize(provider: Provider, id: &str) -> String {
+    provider.canonicalize(id).unwrap_or_else(|| id.to_owned())
+}
+
+impl ExternalIndex {
+    pub fn get(&self, provider: Provider, id: &str) -> Option<&str> {
+        self.index
+            .get(&(provider, normalize(provider, id)))
+            .map(|key| key.as_str())
+    }
+
+    /// Find an ID in `ids` that is already claimed by an entity other
+    /// than `key`.
+    pub fn find_conflict<'a>(
+        &self,
+        key: &str,
+        ids: &'a ExternalIds,
+    ) -> Option<(Provider, &'a str)> {
+        ids.iter()
+            .find(|(provider, id)| match self.get(**provider, id) {
+                Some(existing) => existing != key,
+                None => false,
+            })
+            .map(|(provider, id)| (*provider, id.as_str()))
+    }
+
+    pub fn insert(&mut self, key: &str, ids: &ExternalIds) {
+        for (provider, id) in ids.iter() {
+            self.index
+                .insert((*provider, normalize(*provider, id)), key.to_owned());
+        }
+    }
+
+    pub fn remove(&mut self, key: &str, ids: &ExternalIds) {
+        for (provider, id) in ids.iter() {
+            let entry = (*provider, normalize(*provider, id));
+            if self.index.get(&entry).map(|k| k == key).unwrap_or(false) {
+                self.index.remove(&entry);
+            }
+        }
+    }
+}
+
+/// Validate an ISBN-10 or ISBN-13 (digits only, with a check digit
+/// that may be X for ISBN-10) and convert it to ISBN-13.
+fn isbn13(digits: &str) -> Option<String> {
+    // Lengths below are in bytes
+    if !digits.is_ascii() {
+        return None;
+    }
+    let value = |c: char| c.to_digit(10);
+    let check13 = |first: &str| {
+        let sum: u32 = first
+            .chars()
+            .filter_map(value)
+            .enumerate()
+            .map(|(i, d)| if i % 2 == 0 { d } else { d * 3 })
+            .sum();
+        (10 - sum % 10) % 10
+    };
+    match digits.len() {
+        10 => {
+            let (first, check) = digits.split_at(9);
+            if !is_numeric(first) {
+                return None;
+            }
+            let sum: u32 = first
+                .chars()
+                .filter_map(value)
+                .enumerate()
+                .map(|(i, d)| d * (10 - i as u32))
+                .sum();
+            let expected = (11 - sum % 11) % 11;
+            let actual = match check {
+                "X" => 10,
+                check => value(check.chars().next()?)?,
+            };
+            if expected != actual {
+                return None;
+            }
+            let first = format!("978{}", first);
+            let check = check13(&first);
+            Some(format!("{}{}", first, check))
+        }
+        13 if is_numeric(digits) => {
+            let (first, check) = digits.split_at(12);
+            if check13(first).to_string() == check {
+                Some(digits.to_owned())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_numeric(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Just enough of a URL parser to extract IDs.
+struct Url {
+    host: String,
+    segments: Vec<String>,
+    query: Vec<(String, String)>,
+}
+
+impl Url {
+    fn parse(url: &str) -> Option<Url> {
+        let url = url.trim();
+        let rest = if let Some(rest) = url.strip_prefix("https://") {
+            rest
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            rest
+        } else {
+            url
+        };
+        let rest = rest.split('#').next().unwrap_or("");
+        let mut parts = rest.splitn(2, '?');
+        let path = parts.next().unwrap_or("");
+        let query = parts.next().unwrap_or("");
+
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        let host = segments.next()?.to_lowercase();
+        let host = host.split(':').next().unwrap_or("");
+        let host = host
+            .trim_start_matches("www.")
+            .trim_start_matches("m.")
+            .to_owned();
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Url {
+            host,
+            segments: segments.map(|s| s.to_owned()).collect(),
+            query: query
+                .split('&')
+                .filter_map(|pair| {
+                    let mut kv = pair.splitn(2, '=');
+                    match (kv.next(), kv.next()) {
+                        (Some(k), Some(v)) => Some((k.to_owned(), v.to_owned())),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ids_from_extra, Provider};
+    use crate::common::Kind;
+
+    fn parse(url: &str) -> Option<(Provider, String)> {
+        Provider::parse_url(url)
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            Some((Provider::MyAnimeList, "manga/1234".into())),
+            parse("https://myanimelist.net/manga/1234/Some_Title")
+        );
+        assert_eq!(
+            Some((Provider::MyAnimeList, "anime/5".into())),
+            parse("http://www.myanimelist.net/anime/5")
+        );
+        assert_eq!(None, parse("https://myanimelist.net/people/5"));
+        assert_eq!(
+            Some((
+                Provider::MangaDex,
+                "8280d386-8195-40da-8cbb-e8763ba3a93a".into()
+            )),
+            parse("https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a/dora-yome")
+        );
+        assert_eq!(
+            Some((Provider::Kitsu, "anime/12345".into())),
+            parse("https://kitsu.io/anime/12345")
+        );
+        // Slugs aren't canonical, and can't be resolved offline
+        assert_eq!(None, parse("https://kitsu.io/anime/jitsu-wa-watashi-wa"));
+        assert_eq!(
+            Some((Provider::AniList, "anime/21".into())),
+            parse("https://anilist.co/anime/21/ONE-PIECE/")
+        );
+        assert_eq!(
+            Some((
+                Provider::DynastyScans,
+                "chapters/beginning_their_new_life_together".into()
+            )),
+            parse("https://dynasty-scans.com/chapters/beginning_their_new_life_together#1")
+        );
+        assert_eq!(
+            Some((Provider::Webtoons, "1234".into())),
+            parse("https://www.webtoons.com/en/romance/foo/list?title_no=1234&page=2")
+        );
+        assert_eq!(
+            Some((Provider::Cubari, "imgur/oeJ1wKg".into())),
+            parse("https://cubari.moe/read/imgur/oeJ1wKg/1/47/")
+        );
+        assert_eq!(
+            Some((Provider::Imdb, "tt0111161".into())),
+            parse("https://www.imdb.com/title/tt0111161/?ref_=fn_al_tt_1")
+        );
+        assert_eq!(
+            Some((Provider::Vndb, "v17".into())),
+            parse("https://vndb.org/v17")
+        );
+        assert_eq!(
+            Some((Provider::Goodreads, "book/2767052".into())),
+            parse("https://www.goodreads.com/book/show/2767052-the-hunger-games")
+        );
+        assert_eq!(None, parse("https://www.goodreads.com/book/show/"));
+        assert_eq!(None, parse("https://example.com/title/1"));
+        assert_eq!(None, parse("not a url"));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(
+            Some("9780060934347".to_owned()),
+            Provider::Isbn.canonicalize("978-0-06-093434-7")
+        );
+        // ISBN-10s become ISBN-13s
+        assert_eq!(
+            Some("9780804429573".to_owned()),
+            Provider::Isbn.canonicalize("0-8044-2957-x")
+        );
+        assert_eq!(
+            Provider::Isbn.canonicalize("0060934344"),
+            Provider::Isbn.canonicalize("9780060934347")
+        );
+        assert_eq!(None, Provider::Isbn.canonicalize("12345"));
+        // Bad check digits
+        assert_eq!(None, Provider::Isbn.canonicalize("0-8044-2957-1"));
+        assert_eq!(None, Provider::Isbn.canonicalize("9780060934348"));
+        // Non-ASCII, with the same byte length as an ISBN
+        assert_eq!(None, Provider::Isbn.canonicalize("12345678é"));
+        assert_eq!(None, Provider::Isbn.canonicalize("é12345678901"));
+        assert_eq!(
+            Some("anime/42".to_owned()),
+            Provider::Kitsu.canonicalize("anime/42")
+        );
+        assert_eq!(None, Provider::Kitsu.canonicalize("anime/vlad-love"));
+        assert_eq!(None, Provider::Imdb.canonicalize("0111161"));
+        assert_eq!(None, Provider::Webtoons.canonicalize("abc"));
+    }
+
+    #[test]
+    fn test_url_roundtrip() {
+        for url in &[
+            "https://myanimelist.net/manga/1234",
+            "https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a",
+            "https://kitsu.io/anime/42",
+            "https://dynasty-scans.com/series/march_comes_in_like_a_lion",
+            "https://cubari.moe/read/gist/abcdef/",
+            "https://www.webtoons.com/episodeList?titleNo=1234",
+            "https://www.imdb.com/name/nm0000123/",
+            "https://www.goodreads.com/author/show/153394",
+        ] {
+            let (provider, id) = parse(url).unwrap();
+            assert_eq!(Some(url.to_string()), provider.url(&id));
+        }
+    }
+
+    #[test]
+    fn test_ids_from_extra() {
+        let extra = serde_yaml::from_str(
+            "{mal_id: 42, mangadex_url: 'https://mangadex.org/title/abc', isbn: '0-8044-2957-X'}",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                (Provider::MyAnimeList, "manga/42".to_owned()),
+                (Provider::MangaDex, "abc".to_owned()),
+                (Provider::Isbn, "9780804429573".to_owned()),
+            ],
+            ids_from_extra(Kind::Manga, &extra)
+        );
+        assert_eq!(
+            vec![(Provider::MyAnimeList, "anime/42".to_owned())],
+            ids_from_extra(Kind::Film, &serde_yaml::from_str("{mal_id: 42}").unwrap())
+        );
+        assert!(
+            ids_from_extra(Kind::Play, &serde_yaml::from_str("{mal_id: 42}").unwrap()).is_empty()
+        );
+        assert!(ids_from_extra(Kind::Manga, &serde_yaml::Value::Null).is_empty());
+    }
+}
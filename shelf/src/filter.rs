@@ -0,0 +1,111 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+use crate::common::{Kind, Status};
+use crate::item::Item;
+
+/// A simple query over items.
+///
+/// Each non-empty criterion must match. Within `kinds` and `statuses`,
+/// any value may match; within `tags` and `people`, all values must
+/// match.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ItemFilter {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<Kind>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statuses: Vec<Status>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Person keys.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub people: Vec<String>,
+    /// A series key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+}
+
+impl ItemFilter {
+    pub fn matches(&self, item: &Item) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&item.kind) {
+            return false;
+        }
+        if !self.statuses.is_empty() && !self.statuses.contains(&item.status) {
+            return false;
+        }
+        if !self.tags.iter().all(|tag| item.tags.contains(tag)) {
+            return false;
+        }
+        if !self
+            .people
+            .iter()
+            .all(|person| item.people.iter().any(|(_, p)| p == person))
+        {
+            return false;
+        }
+        if let Some(series) = &self.series {
+            match &item.series {
+                Some((key, _)) if key == series => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ItemFilter;
+    use crate::common::{Kind, Role, Status};
+    use crate::item::Item;
+
+    #[test]
+    fn test_filter_matches() {
+        let item = Item {
+            kind: Kind::Novel,
+            status: Status::Completed,
+            tags: vec!["Yuri".into(), "Fantasy".into()],
+            people: vec![(Role::Author, "person-foo".into())],
+            ..Default::default()
+        };
+        assert!(ItemFilter::default().matches(&item));
+
+        let mut filter = ItemFilter {
+            kinds: vec![Kind::Manga, Kind::Novel],
+            tags: vec!["Yuri".into()],
+            ..Default::default()
+        };
+        assert!(filter.matches(&item));
+        filter.tags.push("Horror".into());
+        assert!(!filter.matches(&item));
+
+        let filter = ItemFilter {
+            statuses: vec![Status::Planned],
+            ..Default::default()
+        };
+        assert!(!filter.matches(&item));
+
+        let filter = ItemFilter {
+            people: vec!["person-foo".into()],
+            ..Default::default()
+        };
+        assert!(filter.matches(&item));
+
+        let filter = ItemFilter {
+            series: Some("series-foo".into()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&item));
+    }
+}
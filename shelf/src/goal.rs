@@ -0,0 +1,286 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Reading/watching goals, e.g. "read 50 books in 2020".
+
+use chrono::NaiveDate;
+
+use crate::common::DateBool;
+use crate::duration::consumed;
+use crate::filter::ItemFilter;
+use crate::item::Item;
+use crate::schema::FieldError;
+use crate::settings::Zone;
+
+/// What a goal counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Metric {
+    /// Completed items.
+    Items,
+    /// Completed entries (chapters, episodes, ...).
+    Entries,
+    Pages,
+    Hours,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Goal {
+    pub key: String,
+    pub name: String,
+    pub target: u32,
+    pub metric: Metric,
+    /// Which items count towards the goal.
+    #[serde(default)]
+    pub filter: ItemFilter,
+    pub start: NaiveDate,
+    /// The last day of the goal (inclusive).
+    pub end: NaiveDate,
+}
+
+/// How far along a goal is.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Progress {
+    pub key: String,
+    pub current: f64,
+    pub target: u32,
+    /// `current / target`.
+    pub fraction: f64,
+    /// How much progress is expected by now, if progress were even
+    /// across the goal's date range.
+    pub expected: f64,
+    /// What `current` will be at the end at the current rate.
+    pub projected: f64,
+    pub days_elapsed: i64,
+    pub days_left: i64,
+    /// How much is needed per remaining day to meet the target.
+    pub needed_per_day: f64,
+    pub complete: bool,
+    /// Whether `current` is at least `expected`.
+    pub on_pace: bool,
+}
+
+impl Goal {
+    pub fn validate(&self) -> Result<(), FieldError> {
+        if self.key.is_empty() {
+            Err(FieldError::new("key", "goal key must not be empty"))
+        } else if self.end < self.start {
+            Err(FieldError::new(
+                "end",
+                format!("goal ends ({}) before it starts ({})", self.end, self.start),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether a completion date falls within the goal.
+    ///
+    /// Imprecise dates (e.g. a month) count if the period they cover
+    /// overlaps the goal's date range, except approximate dates, which
+    /// must fall entirely within it.
+    pub fn contains(&self, date: &DateBool) -> bool {
+        match date.range() {
+            Some((start, end)) if date.is_approximate() => self.start <= start && end <= self.end,
+            Some((start, end)) => start <= self.end && self.start <= end,
+            None => false,
+        }
+    }
+
+    /// The amount an item contributes towards this goal.
+    ///
+    /// Timestamps are converted to `tz` (if given) before checking
+    /// whether they fall within the goal.
+    pub fn count(&self, item: &Item, tz: Option<Zone>) -> f64 {
+        let contains = |date: &DateBool| self.contains(&date.with_time_zone(tz));
+        if !self.filter.matches(item) {
+            return 0.0;
+        }
+        match self.metric {
+            Metric::Items => {
+                if contains(&item.completed) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Metric::Entries => item
+                .entries
+                .iter()
+                .filter(|entry| contains(&entry.completed))
+                .count() as f64,
+            Metric::Pages | Metric::Hours => consumed(item, tz)
+                .iter()
+                .filter(|c| self.contains(&c.completed))
+                .filter_map(|c| c.length)
+                .map(|length| match self.metric {
+                    Metric::Pages => f64::from(length.pages.unwrap_or(0)),
+                    _ => f64::from(length.minutes.unwrap_or(0)) / 60.0,
+                })
+                .sum(),
+        }
+    }
+
+    pub fn progress<'a, I: IntoIterator<Item = &'a Item>>(
+        &self,
+        items: I,
+        today: NaiveDate,
+        tz: Option<Zone>,
+    ) -> Progress {
+        let current: f64 = items.into_iter().map(|item| self.count(item, tz)).sum();
+        let target = f64::from(self.target);
+
+        let total_days = (self.end - self.start).num_days() + 1;
+        let days_elapsed = ((today - self.start).num_days() + 1).clamp(0, total_days);
+        let days_left = total_days - days_elapsed;
+        let elapsed = days_elapsed as f64 / total_days as f64;
+
+        Progress {
+            key: self.key.clone(),
+            current,
+            target: self.target,
+            fraction: if self.target > 0 {
+                current / target
+            } else {
+                1.0
+            },
+            expected: target * elapsed,
+            projected: if days_elapsed > 0 {
+                current / elapsed
+            } else {
+                0.0
+            },
+            days_elapsed,
+            days_left,
+            needed_per_day: if days_left > 0 {
+                (target - current).max(0.0) / days_left as f64
+            } else {
+                0.0
+            },
+            complete: current >= target,
+            on_pace: current >= target * elapsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Goal, Metric};
+    use crate::common::{DateBool, Kind};
+    use crate::duration::Length;
+    use crate::filter::ItemFilter;
+    use crate::item::{Entry, Item};
+    use chrono::NaiveDate;
+
+    fn goal(metric: Metric) -> Goal {
+        Goal {
+            key: "goal-2020".into(),
+            name: "2020 Challenge".into(),
+            target: 10,
+            metric,
+            filter: ItemFilter::default(),
+            start: NaiveDate::from_ymd(2020, 1, 1),
+            end: NaiveDate::from_ymd(2020, 12, 31),
+        }
+    }
+
+    fn entry(completed: DateBool) -> Entry {
+        Entry {
+            name: None,
+            number: None,
+            volume: None,
+            completed,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: serde_yaml::Value::Null,
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                kind: Kind::Novel,
+                completed: DateBool::YearMonth(2020, 3),
+                length: Some(Length::pages(300)),
+                ..Default::default()
+            },
+            Item {
+                kind: Kind::Novel,
+                completed: DateBool::Date(NaiveDate::from_ymd(2019, 12, 31)),
+                length: Some(Length::pages(200)),
+                ..Default::default()
+            },
+            Item {
+                kind: Kind::TV,
+                length: Some(Length::minutes(30)),
+                entries: vec![
+                    entry(DateBool::Date(NaiveDate::from_ymd(2020, 2, 1))),
+                    entry(DateBool::Date(NaiveDate::from_ymd(2020, 2, 2))),
+                    entry(DateBool::True),
+                ],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_goal_count() {
+        let items = items();
+        let count = |goal: &Goal| -> f64 { items.iter().map(|i| goal.count(i, None)).sum() };
+        assert_eq!(1.0, count(&goal(Metric::Items)));
+        assert_eq!(2.0, count(&goal(Metric::Entries)));
+        assert_eq!(300.0, count(&goal(Metric::Pages)));
+        assert_eq!(1.0, count(&goal(Metric::Hours)));
+
+        let mut goal = goal(Metric::Items);
+        goal.filter.kinds = vec![Kind::TV];
+        assert_eq!(0.0, count(&goal));
+    }
+
+    #[test]
+    fn test_goal_progress() {
+        let items = items();
+        let mut goal = goal(Metric::Entries);
+        goal.target = 4;
+        // Halfway through a leap year
+        let progress = goal.progress(&items, NaiveDate::from_ymd(2020, 7, 1), None);
+        assert_eq!(2.0, progress.current);
+        assert_eq!(0.5, progress.fraction);
+        assert_eq!(183, progress.days_elapsed);
+        assert_eq!(183, progress.days_left);
+        assert_eq!(2.0, progress.expected);
+        assert!(progress.on_pace);
+        assert!(!progress.complete);
+        assert!((progress.projected - 4.0).abs() < 1e-9);
+
+        let progress = goal.progress(&items, NaiveDate::from_ymd(2019, 6, 1), None);
+        assert_eq!(0, progress.days_elapsed);
+        assert_eq!(0.0, progress.expected);
+
+        let progress = goal.progress(&items, NaiveDate::from_ymd(2021, 6, 1), None);
+        assert_eq!(0, progress.days_left);
+        assert!(!progress.on_pace);
+    }
+
+    #[test]
+    fn test_goal_validate() {
+        let mut goal = goal(Metric::Items);
+        assert!(goal.validate().is_ok());
+        goal.end = NaiveDate::from_ymd(2019, 1, 1);
+        assert_eq!("end", goal.validate().unwrap_err().field);
+        goal.key = "".into();
+        assert_eq!("key", goal.validate().unwrap_err().field);
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import AniList library exports (JSON).
+//!
+//! These are `MediaListCollection`s as returned by AniList's GraphQL API,
+//! either bare or wrapped in the API's `{"data": ...}` response; a file
+//! may also hold an array of them, e.g. one for anime and one for manga.
+//! Scores are in the user's score format, read from
+//! `user.mediaListOptions.scoreFormat` if present and otherwise assumed
+//! to be `POINT_100`.
+
+use std::collections::HashMap;
+
+use super::library::{self, LibraryEntry};
+use super::{ImportError, ImportPlan};
+use crate::common::{DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Collection {
+    #[serde(default)]
+    user: Option<User>,
+    lists: Vec<List>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    #[serde(default)]
+    media_list_options: Option<MediaListOptions>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaListOptions {
+    #[serde(default)]
+    score_format: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct List {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    is_custom_list: Option<bool>,
+    entries: Vec<ListEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListEntry {
+    status: String,
+    #[serde(default)]
+    score: Option<f64>,
+    #[serde(default)]
+    progress: Option<u32>,
+    #[serde(default)]
+    repeat: Option<u32>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    started_at: Option<FuzzyDate>,
+    #[serde(default)]
+    completed_at: Option<FuzzyDate>,
+    media: Media,
+}
+
+/// A date with any of its parts possibly unknown.
+#[derive(Deserialize)]
+struct FuzzyDate {
+    year: Option<u32>,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Media {
+    id: u32,
+    #[serde(default)]
+    id_mal: Option<u32>,
+    /// ANIME or MANGA.
+    #[serde(rename = "type")]
+    media_type: String,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    episodes: Option<u32>,
+    #[serde(default)]
+    chapters: Option<u32>,
+    title: Title,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    #[serde(default)]
+    romaji: Option<String>,
+    #[serde(default)]
+    english: Option<String>,
+    #[serde(default)]
+    native: Option<String>,
+}
+
+impl FuzzyDate {
+    fn to_date_bool(&self) -> DateBool {
+        match (self.year, self.month, self.day) {
+            (Some(year), Some(month), Some(day)) => {
+                match chrono::NaiveDate::from_ymd_opt(year as i32, month, day) {
+                    Some(date) => DateBool::Date(date),
+                    None => DateBool::YearMonth(year, month),
+                }
+            }
+            (Some(year), Some(month), None) => DateBool::YearMonth(year, month),
+            (Some(year), None, _) => DateBool::Year(year),
+            _ => DateBool::False,
+        }
+    }
+}
+
+fn date(date: &Option<FuzzyDate>) -> DateBool {
+    date.as_ref()
+        .map(FuzzyDate::to_date_bool)
+        .unwrap_or(DateBool::False)
+}
+
+fn status(status: &str) -> Result<Status, ImportError> {
+    match status {
+        "CURRENT" | "REPEATING" => Ok(Status::InProgress),
+        "COMPLETED" => Ok(Status::Completed),
+        "PAUSED" => Ok(Status::OnHold),
+        "DROPPED" => Ok(Status::Dropped),
+        "PLANNING" => Ok(Status::Planned),
+        _ => Err(ImportError::Parse(format!("Unknown status {:?}", status))),
+    }
+}
+
+fn kind(media: &Media) -> Kind {
+    match (media.media_type.as_str(), media.format.as_deref()) {
+        (_, Some("MOVIE")) => Kind::Film,
+        (_, Some("OVA")) | (_, Some("SPECIAL")) => Kind::OVA,
+        (_, Some("ONA")) => Kind::ONA,
+        (_, Some("MUSIC")) => Kind::Music,
+        (_, Some("NOVEL")) => Kind::Novel,
+        ("MANGA", _) => Kind::Manga,
+        _ => Kind::TV,
+    }
+}
+
+/// Convert a score in one of AniList's score formats.
+fn rating(score: f64, format: &str) -> Result<Option<Rating>, ImportError> {
+    if score <= 0.0 {
+        return Ok(None);
+    }
+    let (score, scale) = match format {
+        "POINT_100" => (score, Scale::Hundred),
+        "POINT_10_DECIMAL" => (score * 10.0, Scale::Hundred),
+        "POINT_10" => (score, Scale::Ten),
+        "POINT_5" => (score * 2.0, Scale::FiveStar),
+        // Smileys
+        "POINT_3" => (score * 100.0 / 3.0, Scale::Hundred),
+        _ => {
+            return Err(ImportError::Parse(format!(
+                "Unknown score format {:?}",
+                format
+            )))
+        }
+    };
+    let score = score.round() as u32;
+    if score > scale.steps() {
+        return Err(ImportError::Parse(format!(
+            "Score {} is out of range for {:?}",
+            score, format
+        )));
+    }
+    Ok(Some(Rating::new(score, scale)))
+}
+
+fn to_entry(entry: &ListEntry, score_format: &str) -> Result<LibraryEntry, ImportError> {
+    let media = &entry.media;
+    let typ = media.media_type.to_lowercase();
+    let mut ids = vec![(Provider::AniList, format!("{}/{}", typ, media.id))];
+    if let Some(id) = media.id_mal {
+        ids.push((Provider::MyAnimeList, format!("{}/{}", typ, id)));
+    }
+    let titles = [
+        ("Japanese (Romaji)", &media.title.romaji),
+        ("English", &media.title.english),
+        ("Japanese", &media.title.native),
+    ]
+    .iter()
+    .filter_map(|(lang, title)| {
+        title
+            .as_ref()
+            .filter(|title| !title.trim().is_empty())
+            .map(|title| (lang.to_string(), title.trim().to_owned()))
+    })
+    .collect();
+    let total = media.episodes.or(media.chapters).unwrap_or(0);
+    let repeats = entry.repeat.unwrap_or(0);
+    let progress = entry.progress.unwrap_or(0);
+    Ok(LibraryEntry {
+        ids,
+        kind: kind(media),
+        titles,
+        total,
+        // While repeating, progress counts the current rewatch, but the
+        // whole series was completed before
+        progress: if entry.status == "REPEATING" {
+            progress.max(total)
+        } else {
+            progress
+        },
+        status: status(&entry.status)?,
+        rating: rating(entry.score.unwrap_or(0.0), score_format)?,
+        started: date(&entry.started_at),
+        completed: date(&entry.completed_at),
+        repeats,
+        tags: Vec::new(),
+        notes: entry.notes.clone().unwrap_or_default(),
+    })
+}
+
+/// Find the collections in a parsed export.
+fn collections(value: serde_json::Value) -> Result<Vec<Collection>, ImportError> {
+    match value {
+        serde_json::Value::Array(values) => {
+            let mut result = Vec::new();
+            for value in values {
+                result.extend(collections(value)?);
+            }
+            Ok(result)
+        }
+        serde_json::Value::Object(mut object) => {
+            if let Some(data) = object.remove("data") {
+                if let Some(collection) = data.get("MediaListCollection") {
+                    return collections(collection.clone());
+                }
+            }
+            serde_json::from_value(serde_json::Value::Object(object))
+                .map(|collection| vec![collection])
+                .map_err(|err| ImportError::Parse(format!("Not an AniList export: {}", err)))
+        }
+        _ => Err(ImportError::Parse("Not an AniList export".to_owned())),
+    }
+}
+
+/// Parse an AniList export. Titles on custom lists also appear on their
+/// status list, so they are merged, and custom lists become tags.
+pub fn parse(json: &[u8]) -> Result<Vec<LibraryEntry>, ImportError> {
+    let value: serde_json::Value =
+        serde_json::from_slice(json).map_err(|err| ImportError::Parse(err.to_string()))?;
+    let mut library: Vec<LibraryEntry> = Vec::new();
+    let mut index: HashMap<(String, u32), usize> = HashMap::new();
+    for collection in collections(value)? {
+        let score_format = collection
+            .user
+            .as_ref()
+            .and_then(|user| user.media_list_options.as_ref())
+            .and_then(|options| options.score_format.clone())
+            .unwrap_or_else(|| "POINT_100".to_owned());
+        for list in collection.lists.iter() {
+            let custom_list = list
+                .name
+                .as_ref()
+                .filter(|_| list.is_custom_list.unwrap_or(false));
+            for entry in list.entries.iter() {
+                let id = (entry.media.media_type.clone(), entry.media.id);
+                let idx = match index.get(&id) {
+                    Some(idx) => *idx,
+                    None => {
+                        library.push(to_entry(entry, &score_format)?);
+                        index.insert(id, library.len() - 1);
+                        library.len() - 1
+                    }
+                };
+                if let Some(name) = custom_list {
+                    if !library[idx].tags.contains(name) {
+                        library[idx].tags.push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok(library)
+}
+
+/// Plan importing an AniList export into the shelf.
+pub fn plan(shelf: &Shelf, json: &[u8]) -> Result<ImportPlan, ImportError> {
+    Ok(library::plan(shelf, parse(json)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, plan};
+    use crate::common::{Alternatives, DateBool, Kind, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const EXPORT: &str = r#"{"data": {"MediaListCollection": {
+        "user": {"mediaListOptions": {"scoreFormat": "POINT_10_DECIMAL"}},
+        "lists": [
+            {"name": "Completed", "isCustomList": false, "entries": [
+                {"status": "COMPLETED", "score": 8.5, "progress": 26, "repeat": 2,
+                 "notes": null,
+                 "startedAt": {"year": 2019, "month": 4, "day": null},
+                 "completedAt": {"year": 2019, "month": 6, "day": 2},
+                 "media": {"id": 1, "idMal": 1, "type": "ANIME", "format": "TV",
+                           "episodes": 26,
+                           "title": {"romaji": "Cowboy Bebop", "english": "Cowboy Bebop",
+                                     "native": "カウボーイビバップ"}}}
+            ]},
+            {"name": "Watching", "isCustomList": false, "entries": [
+                {"status": "CURRENT", "score": 0, "progress": 2,
+                 "startedAt": {"year": null, "month": null, "day": null},
+                 "media": {"id": 21, "type": "ANIME", "format": "TV", "episodes": null,
+                           "title": {"romaji": "One Piece", "english": "ONE PIECE",
+                                     "native": null}}}
+            ]},
+            {"name": "Favourites", "isCustomList": true, "entries": [
+                {"status": "COMPLETED", "score": 8.5, "progress": 26,
+                 "media": {"id": 1, "idMal": 1, "type": "ANIME", "format": "TV",
+                           "episodes": 26,
+                           "title": {"romaji": "Cowboy Bebop"}}}
+            ]}
+        ]
+    }}}"#;
+
+    #[test]
+    fn test_parse() {
+        let library = parse(EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, library.len());
+        let bebop = &library[0];
+        assert_eq!(
+            vec![
+                (Provider::AniList, "anime/1".to_owned()),
+                (Provider::MyAnimeList, "anime/1".to_owned())
+            ],
+            bebop.ids
+        );
+        assert_eq!(Some(Rating::new(85, Scale::Hundred)), bebop.rating);
+        assert_eq!(DateBool::YearMonth(2019, 4), bebop.started);
+        assert_eq!(2, bebop.repeats);
+        assert_eq!(vec!["Favourites"], bebop.tags);
+        assert_eq!(3, bebop.titles.len());
+
+        assert!(parse(b"{}").is_err());
+        assert!(parse(b"[1]").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut shelf = Shelf::new();
+        let mut name = Alternatives::new("English", "One Piece");
+        name.alternatives
+            .insert("Japanese".into(), "ワンピース".into());
+        shelf
+            .insert_item(Item {
+                key: "tv-one-piece".into(),
+                kind: Kind::TV,
+                name,
+                ..Default::default()
+            })
+            .unwrap();
+        // Same title, but read rather than watched
+        shelf
+            .insert_item(Item {
+                key: "manga-cowboy-bebop".into(),
+                kind: Kind::Manga,
+                name: Alternatives::new("English", "Cowboy Bebop"),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let plan = plan(&shelf, EXPORT.as_bytes()).unwrap();
+        let bebop = &plan.changes[0];
+        assert_eq!(Action::Insert, bebop.action);
+        assert_eq!("tv-cowboy-bebop", bebop.item.key);
+        assert_eq!(Status::Completed, bebop.item.status);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2019, 6, 2).unwrap()),
+            bebop.item.completed
+        );
+        assert_eq!(26, bebop.item.entries.len());
+        assert!(bebop
+            .item
+            .entries
+            .iter()
+            .all(|entry| entry.completed == DateBool::True));
+        assert_eq!(Some(2), bebop.item.extra["repeats"].as_u64());
+        assert_eq!("Japanese (Romaji)", bebop.item.name.default);
+
+        // Matched by title, case aside
+        let one_piece = &plan.changes[1];
+        assert_eq!(Action::Update, one_piece.action);
+        assert_eq!("tv-one-piece", one_piece.item.key);
+        assert_eq!(Status::InProgress, one_piece.item.status);
+        assert_eq!(DateBool::True, one_piece.item.started);
+        assert_eq!(None, one_piece.item.rating);
+        assert_eq!(2, one_piece.item.entries.len());
+        assert_eq!(
+            Some(&"anime/21".to_owned()),
+            one_piece.item.external_ids.get(&Provider::AniList)
+        );
+
+        plan.apply(&mut shelf);
+        let again = super::plan(&shelf, EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, again.count(Action::Unchanged));
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import books from a Calibre library directory, reading its
+//! `metadata.db` database and the `cover.jpg` of each book.
+//!
+//! Books are matched to items by Calibre UUID, then ISBN or Goodreads
+//! ID, then key, so importing the same library again only picks up
+//! what changed in it. Calibre doesn't track reading, so new books are
+//! planned.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::{ImportError, ImportPlan};
+use crate::common::{Alternatives, Blob, Kind, Role};
+use crate::external::Provider;
+use crate::item::{Cover, Item, PublicationStatus};
+use crate::key::title_to_key;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+/// A book with the metadata Calibre keeps in separate tables.
+#[derive(Clone, Debug, Default)]
+struct Book {
+    id: i64,
+    title: String,
+    /// The book's directory, relative to the library.
+    path: String,
+    series_index: f64,
+    has_cover: bool,
+    uuid: String,
+    /// When the book was added to the library.
+    timestamp: String,
+    authors: Vec<String>,
+    series: Option<String>,
+    tags: Vec<String>,
+    /// 1 to 10 (half stars), or 0 if unrated.
+    rating: u32,
+    /// Identifier type (`isbn`, `goodreads`, `amazon`, ...) and value.
+    identifiers: Vec<(String, String)>,
+    /// HTML.
+    comments: String,
+}
+
+impl Book {
+    fn kind(&self) -> Kind {
+        let non_fiction = self
+            .tags
+            .iter()
+            .any(|tag| matches!(tag.to_lowercase().as_str(), "non-fiction" | "nonfiction"));
+        if non_fiction {
+            Kind::NonFiction
+        } else {
+            Kind::Novel
+        }
+    }
+
+    fn external_ids(&self) -> Vec<(Provider, String)> {
+        let mut ids = Vec::new();
+        if let Some(uuid) = Provider::Calibre.canonicalize(&self.uuid) {
+            ids.push((Provider::Calibre, uuid));
+        }
+        for (typ, value) in self.identifiers.iter() {
+            match typ.as_str() {
+                "isbn" => {
+                    if let Some(isbn) = Provider::Isbn.canonicalize(value) {
+                        ids.push((Provider::Isbn, isbn));
+                    }
+                }
+                "goodreads" => {
+                    if let Some(id) = Provider::Goodreads.canonicalize(value) {
+                        ids.push((Provider::Goodreads, format!("book/{}", id)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        ids
+    }
+
+    fn added(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        let timestamp = self.timestamp.trim();
+        chrono::DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%:z")
+            .or_else(|_| chrono::DateTime::parse_from_rfc3339(timestamp))
+            .ok()
+    }
+}
+
+/// Reduce Calibre's HTML comments to plain text, keeping paragraphs.
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag = None;
+    for c in html.chars() {
+        match (&mut tag, c) {
+            (None, '<') => tag = Some(String::new()),
+            (None, c) => text.push(c),
+            (Some(name), '>') => {
+                let name = name.trim_start_matches('/').to_lowercase();
+                if matches!(
+                    name.split_whitespace().next(),
+                    Some("p") | Some("div") | Some("br") | Some("br/")
+                ) && !text.ends_with('\n')
+                {
+                    text.push('\n');
+                }
+                tag = None;
+            }
+            (Some(name), c) => name.push(c),
+        }
+    }
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Read every book in a Calibre database.
+fn read_books(db: &Connection) -> rusqlite::Result<Vec<Book>> {
+    let mut books = db
+        .prepare(
+            "SELECT id, title, path, series_index, has_cover, uuid, timestamp
+             FROM books ORDER BY id",
+        )?
+        .query_map(params![], |row| {
+            Ok(Book {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                path: row.get(2)?,
+                series_index: row.get::<_, Option<f64>>(3)?.unwrap_or(1.0),
+                has_cover: row.get::<_, Option<bool>>(4)?.unwrap_or(false),
+                uuid: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+                timestamp: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                ..Default::default()
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut authors = db.prepare(
+        "SELECT authors.name FROM books_authors_link
+         JOIN authors ON authors.id = books_authors_link.author
+         WHERE books_authors_link.book = ?1 ORDER BY books_authors_link.id",
+    )?;
+    let mut series = db.prepare(
+        "SELECT series.name FROM books_series_link
+         JOIN series ON series.id = books_series_link.series
+         WHERE books_series_link.book = ?1",
+    )?;
+    let mut tags = db.prepare(
+        "SELECT tags.name FROM books_tags_link
+         JOIN tags ON tags.id = books_tags_link.tag
+         WHERE books_tags_link.book = ?1 ORDER BY tags.name",
+    )?;
+    let mut ratings = db.prepare(
+        "SELECT ratings.rating FROM books_ratings_link
+         JOIN ratings ON ratings.id = books_ratings_link.rating
+         WHERE books_ratings_link.book = ?1",
+    )?;
+    let mut identifiers =
+        db.prepare("SELECT type, val FROM identifiers WHERE book = ?1 ORDER BY id")?;
+    let mut comments = db.prepare("SELECT text FROM comments WHERE book = ?1")?;
+
+    for book in books.iter_mut() {
+        book.authors = authors
+            .query_map(params![book.id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        book.series = series
+            .query_map(params![book.id], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        book.tags = tags
+            .query_map(params![book.id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        book.rating = ratings
+            .query_map(params![book.id], |row| row.get::<_, Option<u32>>(0))?
+            .next()
+            .transpose()?
+            .flatten()
+            .unwrap_or(0);
+        book.identifiers = identifiers
+            .query_map(params![book.id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        book.comments = comments
+            .query_map(params![book.id], |row| row.get::<_, Option<String>>(0))?
+            .next()
+            .transpose()?
+            .flatten()
+            .unwrap_or_default();
+    }
+    Ok(books)
+}
+
+/// Apply a Calibre book to an item. Calibre is taken as the source of
+/// truth for the rating, while everything else is only filled in.
+fn update(shelf: &Shelf, plan: &mut ImportPlan, item: &mut Item, book: &Book) {
+    for (provider, id) in book.external_ids() {
+        item.external_ids.entry(provider).or_insert(id);
+    }
+    if !item.people.iter().any(|(role, _)| *role == Role::Author) {
+        for author in book.authors.iter() {
+            let person = plan.person(shelf, author);
+            item.people.push((Role::Author, person));
+        }
+    }
+    if item.series.is_none() {
+        if let Some(series) = &book.series {
+            let series = plan.series(shelf, series);
+            item.series = Some((series, Some(book.series_index.to_string())));
+        }
+    }
+    // Keep a rating on another scale that converts to the same score
+    if (1..=10).contains(&book.rating)
+        && item
+            .rating
+            .as_ref()
+            .map(|rating| rating.convert(Scale::FiveStar).score)
+            != Some(book.rating)
+    {
+        item.rating = Some(Rating::new(book.rating, Scale::FiveStar));
+    }
+    for tag in book.tags.iter() {
+        if !item.tags.contains(tag) {
+            item.tags.push(tag.clone());
+        }
+    }
+    if item.synopsis.is_empty() {
+        item.synopsis = strip_html(&book.comments);
+    }
+}
+
+/// Plan importing a Calibre library into the shelf. Covers are planned
+/// as blobs, whose files must be written before the plan is applied.
+pub fn plan(shelf: &Shelf, library: &Path) -> Result<ImportPlan, ImportError> {
+    let db = Connection::open_with_flags(
+        library.join("metadata.db"),
+        OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .map_err(|err| ImportError::Parse(err.to_string()))?;
+    let books = read_books(&db).map_err(|err| ImportError::Parse(err.to_string()))?;
+
+    let mut plan = ImportPlan::default();
+    for book in books {
+        if book.title.trim().is_empty() {
+            plan.warnings
+                .push(format!("Skipping book {} without a title", book.id));
+            continue;
+        }
+        let kind = book.kind();
+        let key = format!(
+            "{}-{}",
+            format!("{:?}", kind).to_lowercase(),
+            title_to_key(&book.title)
+        );
+        let existing = book
+            .external_ids()
+            .iter()
+            .find_map(|(provider, id)| shelf.find_item_by_external_id(*provider, id))
+            .or_else(|| {
+                shelf
+                    .get_item(&key)
+                    .filter(|item| !item.external_ids.contains_key(&Provider::Calibre))
+            });
+        if existing
+            .map(|item| plan.contains(&item.key))
+            .unwrap_or(false)
+        {
+            plan.warnings.push(format!(
+                "Skipping {:?}: its item was already matched",
+                book.title
+            ));
+            continue;
+        }
+        let mut item = match existing {
+            Some(existing) => existing.clone(),
+            None => {
+                let key = if shelf.get_item(&key).is_some() || plan.contains(&key) {
+                    format!("{}-{}", key, book.id)
+                } else {
+                    key
+                };
+                let mut item = Item {
+                    key,
+                    kind,
+                    name: Alternatives::new("English", book.title.trim()),
+                    publication_status: PublicationStatus::Complete,
+                    ..Default::default()
+                };
+                if let Some(added) = book.added() {
+                    item.added = added;
+                }
+                item
+            }
+        };
+        update(shelf, &mut plan, &mut item, &book);
+        let cover = library.join(&book.path).join("cover.jpg");
+        if item.covers.is_empty() && book.has_cover && cover.is_file() {
+            let key = format!("blob-{}-cover", item.key);
+            if shelf.get_blob(&key).is_none() {
+                plan.blob(
+                    Blob::new_with_mime(key.clone(), "image/jpeg".to_owned()),
+                    cover,
+                );
+            }
+            item.covers.push(Cover {
+                key,
+                description: "Cover".to_owned(),
+            });
+        }
+        plan.push(existing, item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rusqlite::Connection;
+    use tempfile::Builder;
+
+    use super::plan;
+    use crate::common::{Kind, Role};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::save::DirectoryShelf;
+    use crate::Shelf;
+
+    /// The parts of Calibre's schema the importer reads.
+    const SCHEMA: &str = "
+        CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, path TEXT,
+            series_index REAL, has_cover BOOL, uuid TEXT, timestamp TIMESTAMP);
+        CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+        CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series INTEGER);
+        CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+        CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+        CREATE TABLE books_ratings_link (id INTEGER PRIMARY KEY, book INTEGER, rating INTEGER);
+        CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER, type TEXT, val TEXT);
+        CREATE TABLE comments (id INTEGER PRIMARY KEY, book INTEGER, text TEXT);
+
+        INSERT INTO books VALUES
+            (1, 'A Wizard of Earthsea', 'Ursula K. Le Guin/A Wizard of Earthsea (1)',
+             1.0, 1, '7d3a0e1c-6c1b-4e0b-9c39-0e3f2f6b8a01', '2020-01-05 12:00:00.000000+00:00'),
+            (2, 'The Tombs of Atuan', 'Ursula K. Le Guin/The Tombs of Atuan (2)',
+             2.5, 0, '0f3c1a52-1d7e-4b55-8f0e-5d7f7e0b6a02', '2020-01-06 12:00:00+00:00'),
+            (3, 'Gödel, Escher, Bach', 'Douglas R. Hofstadter/Godel, Escher, Bach (3)',
+             1.0, 0, 'c3b9f0a4-2b6e-4a1f-9f51-3a9e8d2c7b03', '2020-01-07 12:00:00+00:00');
+        INSERT INTO authors VALUES (1, 'Ursula K. Le Guin'), (2, 'Douglas R. Hofstadter');
+        INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 2, 1), (3, 3, 2);
+        INSERT INTO series VALUES (1, 'Earthsea Cycle');
+        INSERT INTO books_series_link VALUES (1, 1, 1), (2, 2, 1);
+        INSERT INTO tags VALUES (1, 'Fantasy'), (2, 'Non-Fiction');
+        INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 2, 1), (3, 3, 2);
+        INSERT INTO ratings VALUES (1, 9), (2, 0);
+        INSERT INTO books_ratings_link VALUES (1, 1, 1), (2, 2, 2);
+        INSERT INTO identifiers VALUES
+            (1, 1, 'isbn', '9780547773742'), (2, 1, 'goodreads', '13642'),
+            (3, 3, 'isbn', '0-465-02656-7');
+        INSERT INTO comments VALUES
+            (1, 1, '<div><p>Ged was the greatest sorcerer in Earthsea.</p><p>A &amp; B</p></div>');
+    ";
+
+    fn library(dir: &Path) {
+        let db = Connection::open(dir.join("metadata.db")).unwrap();
+        db.execute_batch(SCHEMA).unwrap();
+        let book = dir.join("Ursula K. Le Guin/A Wizard of Earthsea (1)");
+        std::fs::create_dir_all(&book).unwrap();
+        std::fs::write(book.join("cover.jpg"), b"\xff\xd8\xff").unwrap();
+    }
+
+    #[test]
+    fn test_plan() {
+        let dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        library(dir.path());
+        let mut shelf = Shelf::new();
+        shelf
+            .insert_item(Item {
+                key: "nonfiction-geb".into(),
+                kind: Kind::NonFiction,
+                external_ids: vec![(Provider::Isbn, "0465026567".to_owned())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let plan = plan(&shelf, dir.path()).unwrap();
+        assert!(plan.warnings.is_empty());
+        assert_eq!(2, plan.people.len());
+        assert_eq!(1, plan.series.len());
+        assert_eq!("series-earthsea-cycle", plan.series[0].key);
+        assert_eq!(1, plan.blobs.len());
+
+        let earthsea = &plan.changes[0];
+        assert_eq!(Action::Insert, earthsea.action);
+        assert_eq!("novel-a-wizard-of-earthsea", earthsea.item.key);
+        assert_eq!(
+            Some(("series-earthsea-cycle".to_owned(), Some("1".to_owned()))),
+            earthsea.item.series
+        );
+        assert_eq!(
+            vec![(Role::Author, "person-ursula-k-le-guin".to_owned())],
+            earthsea.item.people
+        );
+        assert_eq!(vec!["Fantasy".to_owned()], earthsea.item.tags);
+        assert_eq!(Some(Rating::new(9, Scale::FiveStar)), earthsea.item.rating);
+        assert_eq!(
+            "Ged was the greatest sorcerer in Earthsea.\n\nA & B",
+            earthsea.item.synopsis
+        );
+        assert_eq!(
+            Some(&"book/13642".to_owned()),
+            earthsea.item.external_ids.get(&Provider::Goodreads)
+        );
+        assert_eq!(
+            "blob-novel-a-wizard-of-earthsea-cover",
+            earthsea.item.covers[0].key
+        );
+        assert_eq!(
+            "2020-01-05T12:00:00+00:00",
+            earthsea.item.added.to_rfc3339()
+        );
+
+        let atuan = &plan.changes[1].item;
+        assert_eq!(
+            Some(("series-earthsea-cycle".to_owned(), Some("2.5".to_owned()))),
+            atuan.series
+        );
+        assert_eq!(None, atuan.rating);
+        assert!(atuan.covers.is_empty());
+
+        let geb = &plan.changes[2];
+        assert_eq!(Action::Update, geb.action);
+        assert_eq!("nonfiction-geb", geb.item.key);
+        assert!(geb.item.external_ids.contains_key(&Provider::Calibre));
+
+        let saver_dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let saver = DirectoryShelf::new(saver_dir.path()).unwrap();
+        plan.write_blobs(&saver).unwrap();
+        assert_eq!(
+            b"\xff\xd8\xff".to_vec(),
+            std::fs::read(saver.get_blob("blob-novel-a-wizard-of-earthsea-cover")).unwrap()
+        );
+        let report = plan.apply(&mut shelf);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!((2, 1, 1), (report.people, report.series, report.blobs));
+
+        let again = super::plan(&shelf, dir.path()).unwrap();
+        assert_eq!(3, again.count(Action::Unchanged));
+        assert!(again.people.is_empty());
+        assert!(again.series.is_empty());
+        assert!(again.blobs.is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import Goodreads library exports (CSV).
+//!
+//! Books are matched to items by Goodreads ID, then ISBN, then key.
+//! Goodreads only knows that something is a book, so new items are
+//! novels unless a shelf says otherwise.
+
+use super::{ImportError, ImportPlan};
+use crate::common::{Alternatives, DateBool, Kind, Role, Status};
+use crate::external::Provider;
+use crate::item::Item;
+use crate::key::title_to_key;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+/// One row of a Goodreads export. Goodreads exports more columns than
+/// these, and reads files with only some of them.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Row {
+    #[serde(rename = "Book Id")]
+    pub book_id: String,
+    #[serde(rename = "Title")]
+    pub title: String,
+    #[serde(rename = "Author")]
+    pub author: String,
+    /// Comma-separated.
+    #[serde(rename = "Additional Authors")]
+    pub additional_authors: String,
+    #[serde(rename = "ISBN")]
+    pub isbn: String,
+    #[serde(rename = "ISBN13")]
+    pub isbn13: String,
+    /// 1 to 5 stars, or 0 if unrated.
+    #[serde(rename = "My Rating")]
+    pub rating: String,
+    /// YYYY/MM/DD
+    #[serde(rename = "Date Read")]
+    pub date_read: String,
+    #[serde(rename = "Date Added")]
+    pub date_added: String,
+    /// Comma-separated, including the exclusive shelf.
+    #[serde(rename = "Bookshelves")]
+    pub bookshelves: String,
+    /// read, currently-reading, to-read, or a custom exclusive shelf.
+    #[serde(rename = "Exclusive Shelf")]
+    pub exclusive_shelf: String,
+    #[serde(rename = "My Review")]
+    pub review: String,
+}
+
+/// The kinds of item that are exported to Goodreads.
+pub fn is_book(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Novel | Kind::NonFiction | Kind::Collection | Kind::ShortStory
+    )
+}
+
+pub fn status(shelf: &str) -> Status {
+    match shelf {
+        "read" => Status::Completed,
+        "currently-reading" => Status::InProgress,
+        "did-not-finish" | "dnf" | "abandoned" => Status::Dropped,
+        "on-hold" | "paused" => Status::OnHold,
+        _ => Status::Planned,
+    }
+}
+
+/// The exclusive shelf for a status. Goodreads only has the first
+/// three by default; the others are custom shelves.
+pub fn shelf_of(status: Status) -> &'static str {
+    match status {
+        Status::Completed => "read",
+        Status::InProgress => "currently-reading",
+        Status::Planned => "to-read",
+        Status::Dropped => "did-not-finish",
+        Status::OnHold => "on-hold",
+    }
+}
+
+fn kind(shelves: &[String]) -> Kind {
+    for shelf in shelves {
+        match shelf.as_str() {
+            "non-fiction" | "nonfiction" => return Kind::NonFiction,
+            "short-stories" | "short-story" => return Kind::ShortStory,
+            "anthologies" | "anthology" | "collections" => return Kind::Collection,
+            _ => {}
+        }
+    }
+    Kind::Novel
+}
+
+/// Goodreads writes ISBNs as `="0439023483"` so spreadsheets keep the
+/// leading zeroes.
+fn isbn(value: &str) -> Option<String> {
+    let value = value.trim_start_matches('=').trim_matches('"');
+    Provider::Isbn.canonicalize(value)
+}
+
+fn date(value: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(value.trim(), "%Y/%m/%d").ok()
+}
+
+/// The whole stars Goodreads would show for a rating.
+pub fn stars(rating: &Rating) -> u32 {
+    // Half stars round up
+    rating.convert(Scale::FiveStar).score.div_ceil(2)
+}
+
+impl Row {
+    fn shelves(&self) -> Vec<String> {
+        self.bookshelves
+            .split(',')
+            .map(|shelf| shelf.trim().to_owned())
+            .filter(|shelf| !shelf.is_empty() && *shelf != self.exclusive_shelf)
+            .collect()
+    }
+
+    fn authors(&self) -> Vec<&str> {
+        std::iter::once(self.author.as_str())
+            .chain(self.additional_authors.split(','))
+            .map(|author| author.trim())
+            .filter(|author| !author.is_empty())
+            .collect()
+    }
+
+    fn external_ids(&self) -> Vec<(Provider, String)> {
+        let mut ids = Vec::new();
+        if let Some(id) = Provider::Goodreads.canonicalize(&self.book_id) {
+            ids.push((Provider::Goodreads, format!("book/{}", id)));
+        }
+        // Prefer the ISBN-13
+        if let Some(isbn) = isbn(&self.isbn13).or_else(|| isbn(&self.isbn)) {
+            ids.push((Provider::Isbn, isbn));
+        }
+        ids
+    }
+}
+
+/// Apply a Goodreads row to an item. Goodreads is taken as the source
+/// of truth for status and rating, while dates are only filled in.
+fn update(item: &mut Item, row: &Row) -> Result<(), ImportError> {
+    let status = status(&row.exclusive_shelf);
+    item.status = status;
+    let stars: u32 = match row.rating.trim() {
+        "" => 0,
+        rating => rating
+            .parse()
+            .ok()
+            .filter(|stars| *stars <= 5)
+            .ok_or_else(|| ImportError::Parse(format!("Invalid rating {:?}", rating)))?,
+    };
+    // Keep a finer-grained rating that rounds to the same stars
+    if stars > 0 && item.rating.as_ref().map(self::stars) != Some(stars) {
+        item.rating = Some(Rating::new(stars * 2, Scale::FiveStar));
+    }
+    if item.started == DateBool::False && status != Status::Planned {
+        item.started = DateBool::True;
+    }
+    if item.completed == DateBool::False && status == Status::Completed {
+        item.completed = date(&row.date_read)
+            .map(DateBool::Date)
+            .unwrap_or(DateBool::True);
+    }
+    for shelf in row.shelves() {
+        if !item.tags.contains(&shelf) {
+            item.tags.push(shelf);
+        }
+    }
+    if item.comments.is_empty() {
+        item.comments = row.review.clone();
+    }
+    for (provider, id) in row.external_ids() {
+        item.external_ids.entry(provider).or_insert(id);
+    }
+    Ok(())
+}
+
+/// Plan importing a Goodreads export into the shelf.
+pub fn plan(shelf: &Shelf, csv: &[u8]) -> Result<ImportPlan, ImportError> {
+    let settings = shelf.settings();
+    let mut plan = ImportPlan::default();
+    let mut reader = csv::Reader::from_reader(csv);
+    for (line, row) in reader.deserialize::<Row>().enumerate() {
+        let row = row.map_err(|err| ImportError::Parse(err.to_string()))?;
+        if row.title.trim().is_empty() {
+            plan.warnings
+                .push(format!("Skipping row {} without a title", line + 1));
+            continue;
+        }
+        let ids = row.external_ids();
+        let key = format!(
+            "{}-{}",
+            format!("{:?}", kind(&row.shelves())).to_lowercase(),
+            title_to_key(&row.title)
+        );
+        let existing = ids
+            .iter()
+            .find_map(|(provider, id)| shelf.find_item_by_external_id(*provider, id))
+            .or_else(|| {
+                shelf
+                    .get_item(&key)
+                    .filter(|item| !item.external_ids.contains_key(&Provider::Goodreads))
+            });
+        if existing
+            .map(|item| plan.contains(&item.key))
+            .unwrap_or(false)
+        {
+            plan.warnings.push(format!(
+                "Skipping {:?}: its item was already matched",
+                row.title
+            ));
+            continue;
+        }
+        let mut item = match existing {
+            Some(existing) => existing.clone(),
+            None => {
+                let key = if shelf.get_item(&key).is_some() || plan.contains(&key) {
+                    format!("{}-{}", key, row.book_id.trim())
+                } else {
+                    key
+                };
+                let mut item = Item {
+                    key,
+                    kind: kind(&row.shelves()),
+                    name: Alternatives::new("English", row.title.trim()),
+                    ..Default::default()
+                };
+                if let Some(added) = date(&row.date_added).and_then(|date| settings.midnight(date))
+                {
+                    item.added = added;
+                }
+                for author in row.authors() {
+                    let person = plan.person(shelf, author);
+                    item.people.push((Role::Author, person));
+                }
+                item
+            }
+        };
+        update(&mut item, &row)?;
+        plan.push(existing, item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::plan;
+    use crate::common::{Alternatives, DateBool, Kind, Person, Role, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const EXPORT: &str = "\
+Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,Average Rating,Publisher,Binding,Number of Pages,Year Published,Original Publication Year,Date Read,Date Added,Bookshelves,Bookshelves with positions,Exclusive Shelf,My Review,Spoiler,Private Notes,Read Count,Owned Copies
+13642,A Wizard of Earthsea (Earthsea Cycle #1),Ursula K. Le Guin,\"Le Guin, Ursula K.\",,\"=\"\"0553383043\"\"\",\"=\"\"9780553383041\"\"\",4,4.01,Bantam,Paperback,183,2004,1968,2020/03/15,2020/01/02,\"fantasy, read\",,read,Lovely.,,,1,0
+7235533,The Left Hand of Darkness,Ursula K Le Guin,,,=\"\",=\"\",0,4.09,Ace,Paperback,304,2000,1969,,2021/05/06,\"to-read, sci-fi, non-fiction\",,to-read,,,,0,0
+";
+
+    #[test]
+    fn test_plan_new() {
+        let mut shelf = Shelf::new();
+        let plan = plan(&shelf, EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, plan.count(Action::Insert));
+        // Both spellings of the author are the same person
+        assert_eq!(1, plan.people.len());
+        assert_eq!("person-ursula-k-le-guin", plan.people[0].key);
+
+        let earthsea = &plan.changes[0].item;
+        assert_eq!("novel-a-wizard-of-earthsea-earthsea-cycle-1", earthsea.key);
+        assert_eq!(Kind::Novel, earthsea.kind);
+        assert_eq!(Status::Completed, earthsea.status);
+        assert_eq!(Some(Rating::new(8, Scale::FiveStar)), earthsea.rating);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2020, 3, 15).unwrap()),
+            earthsea.completed
+        );
+        assert_eq!(vec!["fantasy".to_owned()], earthsea.tags);
+        assert_eq!(
+            Some(&"9780553383041".to_owned()),
+            earthsea.external_ids.get(&Provider::Isbn)
+        );
+        assert_eq!(
+            Some(&"book/13642".to_owned()),
+            earthsea.external_ids.get(&Provider::Goodreads)
+        );
+        assert_eq!("2020-01-02T00:00:00+00:00", earthsea.added.to_rfc3339());
+        assert_eq!(
+            vec![(Role::Author, "person-ursula-k-le-guin".to_owned())],
+            earthsea.people
+        );
+
+        let left_hand = &plan.changes[1].item;
+        assert_eq!(Kind::NonFiction, left_hand.kind);
+        assert_eq!(Status::Planned, left_hand.status);
+        assert_eq!(DateBool::False, left_hand.started);
+        assert_eq!(None, left_hand.rating);
+        assert_eq!(None, left_hand.external_ids.get(&Provider::Isbn));
+
+        let report = plan.apply(&mut shelf);
+        assert_eq!((1, 2), (report.people, report.inserted));
+        let again = super::plan(&shelf, EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, again.count(Action::Unchanged));
+        assert!(again.people.is_empty());
+    }
+
+    #[test]
+    fn test_plan_existing() {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-le-guin".into(),
+            name: Alternatives::new("English", "Ursula K. Le Guin"),
+            external_ids: Default::default(),
+        });
+        let mut item = Item {
+            key: "novel-earthsea".into(),
+            kind: Kind::Novel,
+            status: Status::InProgress,
+            started: DateBool::YearMonth(2020, 2),
+            // Rounds to 4 stars
+            rating: Some(Rating::new(75, Scale::Hundred)),
+            ..Default::default()
+        };
+        item.external_ids
+            .insert(Provider::Isbn, "9780553383041".into());
+        shelf.insert_item(item).unwrap();
+
+        let plan = plan(&shelf, EXPORT.as_bytes()).unwrap();
+        let change = &plan.changes[0];
+        assert_eq!(Action::Update, change.action);
+        assert_eq!("novel-earthsea", change.item.key);
+        assert_eq!(DateBool::YearMonth(2020, 2), change.item.started);
+        assert_eq!(Some(Rating::new(75, Scale::Hundred)), change.item.rating);
+        assert!(change.item.people.is_empty());
+        assert_eq!(
+            vec!["status", "completed", "comments", "tags", "external_ids"],
+            {
+                let mut fields = change.fields.clone();
+                fields.sort_by_key(|field| {
+                    ["status", "completed", "comments", "tags", "external_ids"]
+                        .iter()
+                        .position(|f| f == field)
+                });
+                fields
+            }
+        );
+        // The other book's author is matched by name
+        assert!(plan.people.is_empty());
+        assert_eq!(
+            vec![(Role::Author, "person-le-guin".to_owned())],
+            plan.changes[1].item.people
+        );
+    }
+}
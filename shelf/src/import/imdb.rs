@@ -0,0 +1,219 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import IMDb ratings exports (CSV).
+//!
+//! Only films are imported; series and episodes are skipped with a
+//! warning. Rated titles count as watched, though IMDb doesn't record
+//! when.
+
+use super::{ImportError, ImportPlan};
+use crate::common::{Alternatives, DateBool, Kind, Role, Status};
+use crate::duration::Length;
+use crate::external::Provider;
+use crate::item::{Item, PublicationStatus};
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct Row {
+    /// The IMDb ID, e.g. tt0111161.
+    #[serde(rename = "Const")]
+    id: String,
+    /// 1 to 10.
+    #[serde(rename = "Your Rating")]
+    rating: String,
+    /// YYYY-MM-DD
+    #[serde(rename = "Date Rated")]
+    date_rated: String,
+    #[serde(rename = "Title")]
+    title: String,
+    /// `movie`, `tvSeries`, etc., or `Movie`, `TV Series`, etc. in newer
+    /// exports.
+    #[serde(rename = "Title Type")]
+    title_type: String,
+    #[serde(rename = "Runtime (mins)")]
+    runtime: String,
+    #[serde(rename = "Year")]
+    year: String,
+    /// Comma-separated.
+    #[serde(rename = "Directors")]
+    directors: String,
+}
+
+fn is_film(title_type: &str) -> bool {
+    let normalized = title_type.replace(' ', "").to_lowercase();
+    matches!(normalized.as_str(), "movie" | "tvmovie" | "video" | "short")
+}
+
+fn update(item: &mut Item, row: &Row, score: u32) {
+    item.status = Status::Completed;
+    if item.started == DateBool::False {
+        item.started = DateBool::True;
+    }
+    if item.completed == DateBool::False {
+        item.completed = DateBool::True;
+    }
+    if item.rating.as_ref().map(|r| r.convert(Scale::Ten).score) != Some(score) {
+        item.rating = Some(Rating::new(score, Scale::Ten));
+    }
+    if item.length.is_none() {
+        item.length = row.runtime.trim().parse().ok().map(|minutes| Length {
+            minutes: Some(minutes),
+            ..Default::default()
+        });
+    }
+    if let Some(id) = Provider::Imdb.canonicalize(&row.id) {
+        item.external_ids.entry(Provider::Imdb).or_insert(id);
+    }
+}
+
+/// Plan importing an IMDb ratings export into the shelf.
+pub fn plan(shelf: &Shelf, csv: &[u8]) -> Result<ImportPlan, ImportError> {
+    let settings = shelf.settings();
+    let mut plan = ImportPlan::default();
+    let mut reader = csv::Reader::from_reader(csv);
+    for row in reader.deserialize::<Row>() {
+        let row = row.map_err(|err| ImportError::Parse(err.to_string()))?;
+        if !is_film(&row.title_type) {
+            plan.warnings.push(format!(
+                "Skipping {:?}: {} is not a film",
+                row.title, row.title_type
+            ));
+            continue;
+        }
+        let score = row
+            .rating
+            .trim()
+            .parse()
+            .ok()
+            .filter(|score| (1..=10).contains(score))
+            .ok_or_else(|| ImportError::Parse(format!("Invalid rating {:?}", row.rating)))?;
+        let id = Provider::Imdb
+            .canonicalize(&row.id)
+            .ok_or_else(|| ImportError::Parse(format!("Invalid IMDb ID {:?}", row.id)))?;
+        let existing = match shelf.find_item_by_external_id(Provider::Imdb, &id) {
+            Some(item) if plan.contains(&item.key) => {
+                plan.warnings
+                    .push(format!("Skipping {:?}: listed twice", row.title));
+                continue;
+            }
+            Some(item) => Ok(item),
+            None => plan.film(shelf, &row.title, row.year.trim().parse().ok(), Some(&id)),
+        };
+        let mut item = match &existing {
+            Ok(existing) => (*existing).clone(),
+            Err(key) => {
+                let mut item = Item {
+                    key: key.clone(),
+                    kind: Kind::Film,
+                    name: Alternatives::new("English", row.title.trim()),
+                    publication_status: PublicationStatus::Complete,
+                    ..Default::default()
+                };
+                if let Some(added) =
+                    chrono::NaiveDate::parse_from_str(row.date_rated.trim(), "%Y-%m-%d")
+                        .ok()
+                        .and_then(|date| settings.midnight(date))
+                {
+                    item.added = added;
+                }
+                item
+            }
+        };
+        if !item.people.iter().any(|(role, _)| *role == Role::Director) {
+            for director in row.directors.split(',').map(str::trim) {
+                if !director.is_empty() {
+                    let person = plan.person(shelf, director);
+                    item.people.push((Role::Director, person));
+                }
+            }
+        }
+        update(&mut item, &row, score);
+        plan.push(existing.ok(), item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::plan;
+    use crate::common::{Alternatives, DateBool, Kind, Person, Role, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const EXPORT: &str = "\
+Const,Your Rating,Date Rated,Title,URL,Title Type,IMDb Rating,Runtime (mins),Year,Genres,Num Votes,Release Date,Directors
+tt0851578,9,2020-01-05,Paprika,https://www.imdb.com/title/tt0851578/,movie,7.7,90,2006,\"Animation, Sci-Fi\",80000,2006-09-02,Satoshi Kon
+tt0079944,8,2020-02-01,Stalker,https://www.imdb.com/title/tt0079944/,Movie,8.1,162,1979,Drama,130000,1979-05-25,Andrei Tarkovsky
+tt0213338,10,2020-02-02,Cowboy Bebop,https://www.imdb.com/title/tt0213338/,tvSeries,8.9,24,1998,Animation,130000,1998-04-03,
+";
+
+    #[test]
+    fn test_plan() {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-kon".into(),
+            name: Alternatives::new("English", "Satoshi Kon"),
+            external_ids: Default::default(),
+        });
+        shelf
+            .insert_item(Item {
+                key: "film-paprika".into(),
+                kind: Kind::Film,
+                // Rounds to 9/10
+                rating: Some(Rating::new(88, Scale::Hundred)),
+                ..Default::default()
+            })
+            .unwrap();
+        let plan = plan(&shelf, EXPORT.as_bytes()).unwrap();
+        assert_eq!(
+            vec!["Skipping \"Cowboy Bebop\": tvSeries is not a film".to_owned()],
+            plan.warnings
+        );
+
+        let paprika = &plan.changes[0];
+        assert_eq!(Action::Update, paprika.action);
+        assert_eq!(Some(Rating::new(88, Scale::Hundred)), paprika.item.rating);
+        assert_eq!(
+            vec![(Role::Director, "person-kon".to_owned())],
+            paprika.item.people
+        );
+        assert_eq!(
+            Some(&"tt0851578".to_owned()),
+            paprika.item.external_ids.get(&Provider::Imdb)
+        );
+
+        let stalker = &plan.changes[1].item;
+        assert_eq!("film-stalker", stalker.key);
+        assert_eq!(Status::Completed, stalker.status);
+        assert_eq!(DateBool::True, stalker.completed);
+        assert_eq!(Some(Rating::new(8, Scale::Ten)), stalker.rating);
+        assert_eq!(Some(162), stalker.length.and_then(|length| length.minutes));
+        assert_eq!("2020-02-01T00:00:00+00:00", stalker.added.to_rfc3339());
+        assert_eq!(
+            vec![(Role::Director, "person-andrei-tarkovsky".to_owned())],
+            stalker.people
+        );
+
+        plan.apply(&mut shelf);
+        let again = super::plan(&shelf, EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, again.count(Action::Unchanged));
+        assert!(again.people.is_empty());
+    }
+}
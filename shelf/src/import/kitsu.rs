@@ -0,0 +1,346 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import Kitsu library exports (JSON).
+//!
+//! These are `libraryEntries` documents as returned by Kitsu's JSON:API,
+//! with the anime and manga included, e.g. from
+//! `/api/edge/library-entries?filter[userId]=...&include=anime,manga`.
+//! A file may also hold an array of such documents, one per page.
+
+use std::collections::HashMap;
+
+use super::library::{self, LibraryEntry};
+use super::{ImportError, ImportPlan};
+use crate::common::{DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+#[derive(Deserialize)]
+struct Document {
+    data: Vec<Resource>,
+    #[serde(default)]
+    included: Vec<Resource>,
+}
+
+#[derive(Deserialize)]
+struct Resource {
+    id: String,
+    #[serde(rename = "type")]
+    resource_type: String,
+    #[serde(default)]
+    attributes: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    relationships: HashMap<String, Relationship>,
+}
+
+#[derive(Deserialize)]
+struct Relationship {
+    #[serde(default)]
+    data: Option<Identifier>,
+}
+
+#[derive(Deserialize)]
+struct Identifier {
+    id: String,
+    #[serde(rename = "type")]
+    resource_type: String,
+}
+
+impl Resource {
+    fn string(&self, attribute: &str) -> Option<&str> {
+        self.attributes
+            .get(attribute)
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn number(&self, attribute: &str) -> Option<u32> {
+        self.attributes
+            .get(attribute)
+            .and_then(|value| value.as_u64())
+            .map(|value| value as u32)
+    }
+}
+
+/// The same language names as the Kitsu metadata importer uses.
+fn language(code: &str) -> String {
+    match code.to_lowercase().as_str() {
+        "en" | "en_us" => "English".to_owned(),
+        "en_jp" => "Japanese (Romaji)".to_owned(),
+        "ja" | "ja_jp" => "Japanese".to_owned(),
+        _ => code.to_owned(),
+    }
+}
+
+fn status(status: &str) -> Result<Status, ImportError> {
+    match status {
+        "current" => Ok(Status::InProgress),
+        "completed" => Ok(Status::Completed),
+        "on_hold" => Ok(Status::OnHold),
+        "dropped" => Ok(Status::Dropped),
+        "planned" => Ok(Status::Planned),
+        _ => Err(ImportError::Parse(format!("Unknown status {:?}", status))),
+    }
+}
+
+fn kind(media: &Resource) -> Kind {
+    match (media.resource_type.as_str(), media.string("subtype")) {
+        ("anime", Some("movie")) => Kind::Film,
+        ("anime", Some("OVA")) | ("anime", Some("special")) => Kind::OVA,
+        ("anime", Some("ONA")) => Kind::ONA,
+        ("anime", Some("music")) => Kind::Music,
+        ("anime", _) => Kind::TV,
+        (_, Some("novel")) => Kind::Novel,
+        _ => Kind::Manga,
+    }
+}
+
+fn date(value: Option<&str>) -> DateBool {
+    value
+        .and_then(|value| chrono::DateTime::parse_from_rfc3339(value).ok())
+        .map(DateBool::Timestamp)
+        .unwrap_or(DateBool::False)
+}
+
+fn rating(entry: &Resource) -> Result<Option<Rating>, ImportError> {
+    // Ratings are out of 20; older exports only have 0.5 to 5 stars
+    if let Some(rating) = entry.number("ratingTwenty") {
+        if rating > 20 {
+            return Err(ImportError::Parse(format!("Invalid rating {}", rating)));
+        }
+        return Ok(Some(Rating::new(rating * 5, Scale::Hundred)).filter(|_| rating > 0));
+    }
+    match entry.string("rating") {
+        None => Ok(None),
+        Some(stars) => stars
+            .parse::<f32>()
+            .ok()
+            .filter(|stars| *stars >= 0.0 && *stars <= 5.0)
+            .map(|stars| Some(Rating::new((stars * 2.0).round() as u32, Scale::FiveStar)))
+            .ok_or_else(|| ImportError::Parse(format!("Invalid rating {:?}", stars))),
+    }
+}
+
+fn to_entry(entry: &Resource, media: &Resource) -> Result<LibraryEntry, ImportError> {
+    let typ = &media.resource_type;
+    let ids: Vec<_> = Provider::Kitsu
+        .canonicalize(&format!("{}/{}", typ, media.id))
+        .map(|id| (Provider::Kitsu, id))
+        .into_iter()
+        .collect();
+
+    let mut titles: Vec<(String, String)> = Vec::new();
+    if let Some(serde_json::Value::Object(by_code)) = media.attributes.get("titles") {
+        for (code, title) in by_code.iter() {
+            let title = match title.as_str().map(str::trim) {
+                Some(title) if !title.is_empty() => title.to_owned(),
+                _ => continue,
+            };
+            let lang = language(code);
+            if !titles.iter().any(|(existing, _)| *existing == lang) {
+                titles.push((lang, title));
+            }
+        }
+    }
+    // The canonical title is the default
+    if let Some(canonical) = media.string("canonicalTitle") {
+        match titles.iter().position(|(_, title)| title == canonical) {
+            Some(idx) => {
+                let title = titles.remove(idx);
+                titles.insert(0, title);
+            }
+            None => titles.insert(0, ("English".to_owned(), canonical.to_owned())),
+        }
+    }
+
+    let reconsuming = entry
+        .attributes
+        .get("reconsuming")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    let total = media
+        .number("episodeCount")
+        .or_else(|| media.number("chapterCount"))
+        .unwrap_or(0);
+    let progress = entry.number("progress").unwrap_or(0);
+    Ok(LibraryEntry {
+        ids,
+        kind: kind(media),
+        titles,
+        total,
+        // While rewatching, progress counts the current rewatch
+        progress: if reconsuming {
+            progress.max(total)
+        } else {
+            progress
+        },
+        status: status(entry.string("status").unwrap_or_default())?,
+        rating: rating(entry)?,
+        started: date(entry.string("startedAt")),
+        completed: date(entry.string("finishedAt")),
+        repeats: entry.number("reconsumeCount").unwrap_or(0),
+        tags: Vec::new(),
+        notes: entry.string("notes").unwrap_or_default().to_owned(),
+    })
+}
+
+/// Parse a Kitsu export.
+pub fn parse(json: &[u8]) -> Result<Vec<LibraryEntry>, ImportError> {
+    let value: serde_json::Value =
+        serde_json::from_slice(json).map_err(|err| ImportError::Parse(err.to_string()))?;
+    let values = match value {
+        serde_json::Value::Array(values) => values,
+        value => vec![value],
+    };
+    let mut library = Vec::new();
+    for value in values {
+        let document: Document = serde_json::from_value(value)
+            .map_err(|err| ImportError::Parse(format!("Not a Kitsu export: {}", err)))?;
+        let included: HashMap<(&str, &str), &Resource> = document
+            .included
+            .iter()
+            .map(|resource| {
+                (
+                    (resource.resource_type.as_str(), resource.id.as_str()),
+                    resource,
+                )
+            })
+            .collect();
+        for entry in document.data.iter() {
+            if entry.resource_type != "libraryEntries" {
+                continue;
+            }
+            let media = ["media", "anime", "manga"]
+                .iter()
+                .filter_map(|name| entry.relationships.get(*name))
+                .filter_map(|relationship| relationship.data.as_ref())
+                .find_map(|id| included.get(&(id.resource_type.as_str(), id.id.as_str())));
+            match media {
+                Some(media) => library.push(to_entry(entry, media)?),
+                None => {
+                    return Err(ImportError::Parse(format!(
+                        "Library entry {} has no included anime or manga",
+                        entry.id
+                    )))
+                }
+            }
+        }
+    }
+    Ok(library)
+}
+
+/// Plan importing a Kitsu export into the shelf.
+pub fn plan(shelf: &Shelf, json: &[u8]) -> Result<ImportPlan, ImportError> {
+    Ok(library::plan(shelf, parse(json)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, plan};
+    use crate::common::{DateBool, Kind, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const EXPORT: &str = r#"{
+        "data": [
+            {"id": "10", "type": "libraryEntries",
+             "attributes": {"status": "completed", "progress": 12, "reconsuming": false,
+                            "reconsumeCount": 1, "ratingTwenty": 17, "notes": "Great",
+                            "startedAt": "2020-01-01T10:00:00.000Z",
+                            "finishedAt": "2020-02-01T10:00:00.000Z"},
+             "relationships": {"anime": {"data": {"type": "anime", "id": "42"}},
+                               "manga": {"data": null}}},
+            {"id": "11", "type": "libraryEntries",
+             "attributes": {"status": "current", "progress": 3, "ratingTwenty": null},
+             "relationships": {"manga": {"data": {"type": "manga", "id": "7"}}}}
+        ],
+        "included": [
+            {"id": "42", "type": "anime",
+             "attributes": {"slug": "jitsu-wa-watashi-wa", "canonicalTitle": "Jitsu wa Watashi wa",
+                            "titles": {"en": "Actually, I Am…", "en_jp": "Jitsu wa Watashi wa"},
+                            "subtype": "TV", "episodeCount": 13}},
+            {"id": "7", "type": "manga",
+             "attributes": {"slug": "yotsubato", "canonicalTitle": "Yotsuba&!",
+                            "titles": {"en_jp": "Yotsuba&!", "ja_jp": "よつばと!"},
+                            "subtype": "manga", "chapterCount": null}}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse() {
+        let library = parse(EXPORT.as_bytes()).unwrap();
+        assert_eq!(2, library.len());
+        let anime = &library[0];
+        assert_eq!(vec![(Provider::Kitsu, "anime/42".to_owned())], anime.ids);
+        assert_eq!(
+            (
+                "Japanese (Romaji)".to_owned(),
+                "Jitsu wa Watashi wa".to_owned()
+            ),
+            anime.titles[0]
+        );
+        assert_eq!(Some(Rating::new(85, Scale::Hundred)), anime.rating);
+        assert_eq!(1, anime.repeats);
+        assert_eq!(Kind::Manga, library[1].kind);
+        assert_eq!(None, library[1].rating);
+
+        assert!(parse(br#"{"data": [{"id": "1", "type": "libraryEntries"}]}"#).is_err());
+        assert!(parse(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut shelf = Shelf::new();
+        let mut item = Item {
+            key: "tv-jitsu".into(),
+            kind: Kind::TV,
+            ..Default::default()
+        };
+        item.external_ids.insert(Provider::Kitsu, "anime/42".into());
+        shelf.insert_item(item).unwrap();
+
+        let plan = plan(&shelf, EXPORT.as_bytes()).unwrap();
+        let anime = &plan.changes[0];
+        assert_eq!(Action::Update, anime.action);
+        assert_eq!("tv-jitsu", anime.item.key);
+        assert_eq!(Status::Completed, anime.item.status);
+        assert_eq!("Great", anime.item.comments);
+        assert_eq!(13, anime.item.entries.len());
+        assert_eq!(
+            "2020-02-01T10:00:00+00:00",
+            match anime.item.completed {
+                DateBool::Timestamp(ts) => ts.to_rfc3339(),
+                _ => panic!(),
+            }
+        );
+
+        let manga = &plan.changes[1];
+        assert_eq!(Action::Insert, manga.action);
+        assert_eq!("manga-yotsuba", manga.item.key);
+        assert_eq!(3, manga.item.entries.len());
+        assert_eq!(
+            Some(&"Chapter 1".to_owned()),
+            manga.item.entries[0]
+                .name
+                .as_ref()
+                .and_then(|name| name.get_default())
+        );
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Sync reading progress from a Kobo e-reader's `KoboReader.sqlite`
+//! database.
+//!
+//! Only items already on the shelf are updated, matched by ISBN, then
+//! key, then title; other books on the device are reported as
+//! unmatched. Statuses only move forward (planned to reading to
+//! completed), so a book dropped on the shelf but left open on the
+//! device stays dropped. Re-running after a sync only changes what the
+//! device changed.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::goodreads::is_book;
+use super::{ImportError, ImportPlan};
+use crate::common::{DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::item::Item;
+use crate::key::title_to_key;
+use crate::shelf::Shelf;
+
+/// A book on the device.
+#[derive(Clone, Debug, Default)]
+struct Book {
+    title: String,
+    isbn: String,
+    /// 0 if unread, 1 if reading, 2 if finished.
+    read_status: i64,
+    percent_read: u32,
+    /// In seconds.
+    time_spent: u32,
+    /// An ISO 8601 timestamp, in UTC if no offset is given.
+    last_read: String,
+}
+
+impl Book {
+    fn last_read(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let last_read = self.last_read.trim();
+        chrono::DateTime::parse_from_rfc3339(last_read)
+            .map(|time| time.with_timezone(&chrono::Utc))
+            .or_else(|_| {
+                chrono::NaiveDateTime::parse_from_str(last_read, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|time| chrono::DateTime::from_utc(time, chrono::Utc))
+            })
+            .ok()
+    }
+}
+
+/// Read the books (rather than chapters or bookmarks) on the device.
+fn read_books(db: &Connection) -> rusqlite::Result<Vec<Book>> {
+    db.prepare(
+        "SELECT Title, ISBN, ReadStatus, ___PercentRead, TimeSpentReading, DateLastRead
+         FROM content WHERE ContentType = 6 AND BookID IS NULL ORDER BY Title",
+    )?
+    .query_map(params![], |row| {
+        Ok(Book {
+            title: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            isbn: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            read_status: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+            percent_read: row.get::<_, Option<u32>>(3)?.unwrap_or(0),
+            time_spent: row.get::<_, Option<u32>>(4)?.unwrap_or(0),
+            last_read: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+        })
+    })?
+    .collect()
+}
+
+/// Find the item a book on the device refers to.
+fn find<'a>(shelf: &'a Shelf, plan: &ImportPlan, book: &Book) -> Option<&'a Item> {
+    if let Some(item) = Provider::Isbn
+        .canonicalize(&book.isbn)
+        .and_then(|isbn| shelf.find_item_by_external_id(Provider::Isbn, &isbn))
+    {
+        return Some(item);
+    }
+    let title = title_to_key(&book.title);
+    [Kind::Novel, Kind::NonFiction]
+        .iter()
+        .find_map(|kind| {
+            shelf.get_item(&format!(
+                "{}-{}",
+                format!("{:?}", kind).to_lowercase(),
+                title
+            ))
+        })
+        .or_else(|| {
+            shelf.all_items().iter().find(|item| {
+                is_book(item.kind)
+                    && !plan.contains(&item.key)
+                    && item
+                        .name
+                        .alternatives
+                        .values()
+                        .any(|name| title_to_key(name) == title)
+            })
+        })
+}
+
+fn set_extra(item: &mut Item, key: &str, value: serde_yaml::Value) {
+    if item.extra.is_null() {
+        item.extra = serde_yaml::Value::Mapping(Default::default());
+    }
+    if let serde_yaml::Value::Mapping(extra) = &mut item.extra {
+        extra.insert(serde_yaml::Value::String(key.to_owned()), value);
+    }
+}
+
+/// Apply the device's reading state to an item.
+fn update(item: &mut Item, book: &Book, last_read: Option<DateBool>) {
+    match book.read_status {
+        1 => {
+            if matches!(item.status, Status::Planned | Status::OnHold) {
+                item.status = Status::InProgress;
+            }
+        }
+        2 => {
+            item.status = Status::Completed;
+            if item.completed == DateBool::False {
+                item.completed = last_read.unwrap_or(DateBool::True);
+            }
+        }
+        _ => return,
+    }
+    if item.started == DateBool::False {
+        item.started = DateBool::True;
+    }
+    let progress = if book.read_status == 2 {
+        100
+    } else {
+        book.percent_read.min(100)
+    };
+    set_extra(item, "progress", progress.into());
+    if book.time_spent > 0 {
+        set_extra(item, "time_spent", (book.time_spent / 60).into());
+    }
+    if let Some(last_read) = last_read.and_then(|date| serde_yaml::to_value(date).ok()) {
+        set_extra(item, "last_read", last_read);
+    }
+}
+
+/// Plan syncing a Kobo database into the shelf.
+pub fn plan(shelf: &Shelf, database: &Path) -> Result<ImportPlan, ImportError> {
+    let tz = shelf.settings().time_zone;
+    let db = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| ImportError::Parse(err.to_string()))?;
+    let books = read_books(&db).map_err(|err| ImportError::Parse(err.to_string()))?;
+
+    let mut plan = ImportPlan::default();
+    for book in books {
+        if book.read_status == 0 {
+            continue;
+        }
+        let existing = match find(shelf, &plan, &book) {
+            Some(item) if plan.contains(&item.key) => {
+                plan.warnings.push(format!(
+                    "Skipping {:?}: its item was already matched",
+                    book.title
+                ));
+                continue;
+            }
+            Some(item) => item,
+            None => {
+                plan.unmatched.push(book.title.clone());
+                continue;
+            }
+        };
+        let last_read = book
+            .last_read()
+            .map(|time| DateBool::Timestamp(time.into()).with_time_zone(tz));
+        let mut item = existing.clone();
+        update(&mut item, &book, last_read);
+        plan.push(Some(existing), item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+    use tempfile::Builder;
+
+    use super::plan;
+    use crate::common::{Alternatives, DateBool, Kind, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::Shelf;
+
+    /// The parts of the device's schema the importer reads.
+    const SCHEMA: &str = "
+        CREATE TABLE content (ContentID TEXT PRIMARY KEY, ContentType TEXT, BookID TEXT,
+            Title TEXT, ISBN TEXT, ReadStatus INT, ___PercentRead INTEGER,
+            TimeSpentReading INT, DateLastRead TEXT);
+        INSERT INTO content VALUES
+            ('a1', 6, NULL, 'Piranesi', '9781635575637', 1, 42, 3600, '2021-03-01T10:00:00Z'),
+            ('a1!ch1', 9, 'a1', 'Chapter 1', NULL, 2, 100, 0, NULL),
+            ('b2', 6, NULL, 'The Left Hand of Darkness', '', 2, 0, 7260,
+             '2021-02-14T21:30:00.000'),
+            ('c3', 6, NULL, 'Unread Book', '', 0, 0, 0, NULL),
+            ('d4', 6, NULL, 'Not On The Shelf', '', 1, 5, 60, '2021-03-02T08:00:00Z'),
+            ('e5', 6, NULL, 'Abandoned', '', 1, 10, 60, '2021-01-02T08:00:00Z');
+    ";
+
+    fn item(key: &str, title: &str, status: Status) -> Item {
+        Item {
+            key: key.into(),
+            kind: Kind::Novel,
+            name: Alternatives::new("English", title),
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let dir = Builder::new().prefix("shelf-test-").tempdir().unwrap();
+        let database = dir.path().join("KoboReader.sqlite");
+        Connection::open(&database)
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+
+        let mut shelf = Shelf::new();
+        let mut piranesi = item("novel-piranesi-2020", "Piranesi", Status::Planned);
+        piranesi
+            .external_ids
+            .insert(Provider::Isbn, "9781635575637".into());
+        shelf.insert_item(piranesi).unwrap();
+        shelf
+            .insert_item(item(
+                "novel-left-hand",
+                "The Left Hand of Darkness",
+                Status::InProgress,
+            ))
+            .unwrap();
+        shelf
+            .insert_item(item("novel-abandoned", "Abandoned", Status::Dropped))
+            .unwrap();
+
+        let plan = plan(&shelf, &database).unwrap();
+        assert_eq!(vec!["Not On The Shelf".to_owned()], plan.unmatched);
+        assert_eq!(3, plan.changes.len());
+
+        let abandoned = &plan.changes[0].item;
+        assert_eq!(Status::Dropped, abandoned.status);
+
+        let piranesi = &plan.changes[1].item;
+        assert_eq!(Status::InProgress, piranesi.status);
+        assert_eq!(DateBool::True, piranesi.started);
+        assert_eq!(DateBool::False, piranesi.completed);
+        assert_eq!(Some(42), piranesi.extra["progress"].as_u64());
+        assert_eq!(Some(60), piranesi.extra["time_spent"].as_u64());
+        assert_eq!(
+            Some("2021-03-01T10:00:00+00:00"),
+            piranesi.extra["last_read"].as_str()
+        );
+
+        let left_hand = &plan.changes[2].item;
+        assert_eq!(Status::Completed, left_hand.status);
+        assert_eq!("2021-02-14 21:30", left_hand.completed.to_string());
+        assert_eq!(Some(100), left_hand.extra["progress"].as_u64());
+        assert_eq!(Some(121), left_hand.extra["time_spent"].as_u64());
+
+        let report = plan.apply(&mut shelf);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(3, report.updated);
+        let again = super::plan(&shelf, &database).unwrap();
+        assert_eq!(3, again.count(Action::Unchanged));
+    }
+}
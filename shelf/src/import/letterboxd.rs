@@ -0,0 +1,414 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import Letterboxd account exports (a ZIP of CSV files).
+//!
+//! The files only identify films by title and year, which are combined
+//! across files. A film is completed on the date of the first viewing;
+//! when the diary has rewatches, each later viewing becomes an entry so
+//! every viewing is counted once.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use super::{ImportError, ImportPlan};
+use crate::common::{Alternatives, DateBool, Kind, Status};
+use crate::item::{Entry, Item};
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+/// A row of any of the export's files, which share most columns.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct Row {
+    /// When the row was logged, YYYY-MM-DD.
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Year")]
+    year: String,
+    /// 0.5 to 5 stars, in half stars.
+    #[serde(rename = "Rating")]
+    rating: String,
+    /// "Yes" if the film had been seen before.
+    #[serde(rename = "Rewatch")]
+    rewatch: String,
+    /// Comma-separated.
+    #[serde(rename = "Tags")]
+    tags: String,
+    /// Diary only: when the film was watched, YYYY-MM-DD.
+    #[serde(rename = "Watched Date")]
+    watched_date: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Viewing {
+    date: chrono::NaiveDate,
+    rating: Option<Rating>,
+    rewatch: bool,
+}
+
+/// Everything the export says about one film.
+#[derive(Clone, Debug, Default)]
+struct Film {
+    name: String,
+    year: Option<i32>,
+    /// The earliest date it was logged.
+    added: Option<chrono::NaiveDate>,
+    watched: bool,
+    watchlist: bool,
+    rating: Option<Rating>,
+    viewings: Vec<Viewing>,
+    tags: Vec<String>,
+}
+
+fn date(value: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok()
+}
+
+fn rating(value: &str) -> Result<Option<Rating>, ImportError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|stars| *stars > 0.0 && *stars <= 5.0)
+        .map(|stars| Some(Rating::new((stars * 2.0).round() as u32, Scale::FiveStar)))
+        .ok_or_else(|| ImportError::Parse(format!("Invalid rating {:?}", value)))
+}
+
+fn read_csv(
+    archive: &mut zip::ZipArchive<std::io::Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<Vec<Row>>, ImportError> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(ImportError::Parse(format!("{}: {}", name, err))),
+    };
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)
+        .map_err(|err| ImportError::Parse(format!("{}: {}", name, err)))?;
+    csv::Reader::from_reader(contents.as_slice())
+        .deserialize()
+        .collect::<Result<Vec<Row>, _>>()
+        .map(Some)
+        .map_err(|err| ImportError::Parse(format!("{}: {}", name, err)))
+}
+
+/// Read the films out of the export, in the order they first appear.
+fn films(zip: &[u8]) -> Result<Vec<Film>, ImportError> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip))
+        .map_err(|err| ImportError::Parse(err.to_string()))?;
+    let mut films: Vec<Film> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    let mut found = false;
+    for name in &["watched.csv", "diary.csv", "ratings.csv", "watchlist.csv"] {
+        let rows = match read_csv(&mut archive, name)? {
+            Some(rows) => rows,
+            None => continue,
+        };
+        found = true;
+        for row in rows {
+            if row.name.trim().is_empty() {
+                continue;
+            }
+            let id = (row.name.trim().to_owned(), row.year.trim().to_owned());
+            let idx = *index.entry(id).or_insert_with(|| {
+                films.push(Film {
+                    name: row.name.trim().to_owned(),
+                    year: row.year.trim().parse().ok(),
+                    ..Default::default()
+                });
+                films.len() - 1
+            });
+            let film = &mut films[idx];
+            if let Some(logged) = date(&row.date) {
+                film.added = Some(film.added.map_or(logged, |added| added.min(logged)));
+            }
+            match *name {
+                "watched.csv" => film.watched = true,
+                "diary.csv" => {
+                    film.watched = true;
+                    if let Some(watched) = date(&row.watched_date).or_else(|| date(&row.date)) {
+                        film.viewings.push(Viewing {
+                            date: watched,
+                            rating: rating(&row.rating)?,
+                            rewatch: row.rewatch.trim() == "Yes",
+                        });
+                    }
+                    for tag in row.tags.split(',').map(str::trim) {
+                        if !tag.is_empty() && !film.tags.iter().any(|t| t == tag) {
+                            film.tags.push(tag.to_owned());
+                        }
+                    }
+                }
+                "ratings.csv" => {
+                    film.watched = true;
+                    film.rating = rating(&row.rating)?;
+                }
+                _ => film.watchlist = true,
+            }
+        }
+    }
+    if !found {
+        return Err(ImportError::Parse(
+            "Not a Letterboxd export: no watched, diary, ratings or watchlist file".to_owned(),
+        ));
+    }
+    for film in films.iter_mut() {
+        film.viewings.sort_by_key(|viewing| viewing.date);
+        film.viewings.dedup();
+    }
+    Ok(films)
+}
+
+/// Apply what Letterboxd knows about a film. Watching is only ever
+/// added: a film on the watchlist doesn't undo watching it elsewhere.
+fn update(item: &mut Item, film: &Film) {
+    if film.watched {
+        item.status = Status::Completed;
+    } else if film.watchlist && item.status != Status::Completed {
+        item.status = Status::Planned;
+    }
+    // Keep a finer-grained rating that rounds to the same half stars
+    if let Some(rating) = &film.rating {
+        if item
+            .rating
+            .as_ref()
+            .map(|r| r.convert(Scale::FiveStar).score)
+            != Some(rating.score)
+        {
+            item.rating = Some(rating.clone());
+        }
+    }
+    let first = film
+        .viewings
+        .iter()
+        .find(|viewing| !viewing.rewatch)
+        .map(|viewing| DateBool::Date(viewing.date));
+    if film.watched {
+        if item.started == DateBool::False {
+            item.started = first.unwrap_or(DateBool::True);
+        }
+        if item.completed == DateBool::False {
+            item.completed = first.unwrap_or(DateBool::True);
+        }
+    }
+    let rewatched = film.viewings.len() > 1 || film.viewings.iter().any(|v| v.rewatch);
+    if rewatched {
+        for viewing in film.viewings.iter() {
+            let completed = DateBool::Date(viewing.date);
+            // The first viewing is the item's own completion
+            if item.completed == completed
+                || item
+                    .entries
+                    .iter()
+                    .any(|entry| entry.completed == completed)
+            {
+                continue;
+            }
+            item.entries.push(Entry {
+                name: None,
+                number: None,
+                volume: None,
+                completed,
+                aired: DateBool::False,
+                rating: viewing.rating.clone(),
+                length: None,
+                note: None,
+                extra: Default::default(),
+            });
+        }
+        item.entries.sort_by_key(|entry| entry.completed.date());
+    }
+    for tag in film.tags.iter() {
+        if !item.tags.contains(tag) {
+            item.tags.push(tag.clone());
+        }
+    }
+}
+
+/// Plan importing a Letterboxd export into the shelf.
+pub fn plan(shelf: &Shelf, zip: &[u8]) -> Result<ImportPlan, ImportError> {
+    let settings = shelf.settings();
+    let mut plan = ImportPlan::default();
+    for film in films(zip)? {
+        let existing = plan.film(shelf, &film.name, film.year, None);
+        let mut item = match &existing {
+            Ok(existing) => (*existing).clone(),
+            Err(key) => {
+                let mut item = Item {
+                    key: key.clone(),
+                    kind: Kind::Film,
+                    name: Alternatives::new("English", &film.name),
+                    publication_status: crate::item::PublicationStatus::Complete,
+                    ..Default::default()
+                };
+                if let Some(added) = film.added.and_then(|date| settings.midnight(date)) {
+                    item.added = added;
+                }
+                item
+            }
+        };
+        update(&mut item, &film);
+        plan.push(existing.ok(), item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::plan;
+    use crate::common::{DateBool, Kind, Status};
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    fn export(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(*name, Default::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> DateBool {
+        DateBool::Date(chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    const WATCHED: &str = "\
+Date,Name,Year,Letterboxd URI
+2020-01-05,Paprika,2006,https://boxd.it/1a
+2020-02-01,Stalker,1979,https://boxd.it/2b
+";
+    const DIARY: &str = "\
+Date,Name,Year,Letterboxd URI,Rating,Rewatch,Tags,Watched Date
+2020-01-05,Paprika,2006,https://boxd.it/x1,4,,anime,2020-01-04
+2021-06-01,Paprika,2006,https://boxd.it/x2,4.5,Yes,\"anime, favourites\",2021-05-30
+2020-02-01,Stalker,1979,https://boxd.it/x3,,Yes,,2020-02-01
+";
+    const RATINGS: &str = "\
+Date,Name,Year,Letterboxd URI,Rating
+2021-06-01,Paprika,2006,https://boxd.it/1a,4.5
+";
+    const WATCHLIST: &str = "\
+Date,Name,Year,Letterboxd URI
+2019-12-01,Paprika,2006,https://boxd.it/1a
+2020-03-01,Solaris,1972,https://boxd.it/3c
+";
+
+    #[test]
+    fn test_plan_new() {
+        let zip = export(&[
+            ("watched.csv", WATCHED),
+            ("diary.csv", DIARY),
+            ("ratings.csv", RATINGS),
+            ("watchlist.csv", WATCHLIST),
+        ]);
+        let mut shelf = Shelf::new();
+        let plan = plan(&shelf, &zip).unwrap();
+        assert_eq!(3, plan.count(Action::Insert));
+
+        let paprika = &plan.changes[0].item;
+        assert_eq!("film-paprika", paprika.key);
+        assert_eq!(Kind::Film, paprika.kind);
+        assert_eq!(Status::Completed, paprika.status);
+        assert_eq!(Some(Rating::new(9, Scale::FiveStar)), paprika.rating);
+        assert_eq!(date(2020, 1, 4), paprika.completed);
+        assert_eq!("2019-12-01T00:00:00+00:00", paprika.added.to_rfc3339());
+        assert_eq!(
+            vec![date(2021, 5, 30)],
+            paprika
+                .entries
+                .iter()
+                .map(|entry| entry.completed)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(Rating::new(9, Scale::FiveStar)),
+            paprika.entries[0].rating
+        );
+        assert_eq!(vec!["anime", "favourites"], paprika.tags);
+
+        // Seen before the diary began, so the first watch is undated
+        let stalker = &plan.changes[1].item;
+        assert_eq!(DateBool::True, stalker.completed);
+        assert_eq!(1, stalker.entries.len());
+
+        let solaris = &plan.changes[2].item;
+        assert_eq!(Status::Planned, solaris.status);
+        assert_eq!(DateBool::False, solaris.completed);
+
+        plan.apply(&mut shelf);
+        let again = super::plan(&shelf, &zip).unwrap();
+        assert_eq!(3, again.count(Action::Unchanged));
+    }
+
+    #[test]
+    fn test_plan_existing() {
+        let mut shelf = Shelf::new();
+        shelf
+            .insert_item(Item {
+                key: "film-solaris".into(),
+                kind: Kind::Film,
+                status: Status::Completed,
+                completed: date(2010, 1, 1),
+                ..Default::default()
+            })
+            .unwrap();
+        // A different film with the same title
+        shelf
+            .insert_item(Item {
+                key: "film-paprika".into(),
+                kind: Kind::Film,
+                ..Default::default()
+            })
+            .unwrap();
+        shelf
+            .insert_item(Item {
+                key: "film-paprika-2006".into(),
+                kind: Kind::Film,
+                ..Default::default()
+            })
+            .unwrap();
+        let zip = export(&[("watchlist.csv", WATCHLIST), ("watched.csv", WATCHED)]);
+        let plan = plan(&shelf, &zip).unwrap();
+
+        let keys = plan
+            .changes
+            .iter()
+            .map(|change| (change.item.key.as_str(), change.action))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("film-paprika-2006", Action::Update),
+                ("film-stalker", Action::Insert),
+                ("film-solaris", Action::Unchanged),
+            ],
+            keys
+        );
+        assert_eq!(DateBool::True, plan.changes[0].item.completed);
+
+        assert!(super::plan(&shelf, b"not a zip").is_err());
+        assert!(super::plan(&shelf, &export(&[("profile.csv", "")])).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Planning imports of the user libraries kept by anime and manga
+//! trackers (AniList and Kitsu), once each site's export is parsed.
+//!
+//! Titles are matched to items by external ID, then by any of their
+//! titles in any language.
+
+use super::{complete_entries, ImportPlan};
+use crate::common::{Alternatives, DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::item::Item;
+use crate::key::title_to_key;
+use crate::rating::Rating;
+use crate::shelf::Shelf;
+
+/// One title of a user's library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryEntry {
+    /// The site's own ID for the title first, then any IDs it knows of
+    /// on other sites.
+    pub ids: Vec<(Provider, String)>,
+    pub kind: Kind,
+    /// Titles by language, the default first.
+    pub titles: Vec<(String, String)>,
+    /// The number of episodes or chapters, or 0 if unknown.
+    pub total: u32,
+    /// The number of episodes watched or chapters read.
+    pub progress: u32,
+    pub status: Status,
+    pub rating: Option<Rating>,
+    pub started: DateBool,
+    pub completed: DateBool,
+    /// Times rewatched or reread, after the first time.
+    pub repeats: u32,
+    pub tags: Vec<String>,
+    pub notes: String,
+}
+
+impl LibraryEntry {
+    fn unit(&self) -> &'static str {
+        match self.kind {
+            Kind::Manga | Kind::Novel => "Chapter",
+            _ => "Episode",
+        }
+    }
+
+    fn is_title(&self, name: &Alternatives<String>) -> bool {
+        name.alternatives.values().any(|alternative| {
+            let alternative = title_to_key(alternative);
+            !alternative.is_empty()
+                && self
+                    .titles
+                    .iter()
+                    .any(|(_, title)| title_to_key(title) == alternative)
+        })
+    }
+}
+
+/// Whether two kinds are on the same list of a tracker, i.e. are both
+/// watched or both read.
+fn same_list(a: Kind, b: Kind) -> bool {
+    let read = |kind| matches!(kind, Kind::Manga | Kind::Novel);
+    read(a) == read(b)
+}
+
+/// Apply a library entry to an item: the tracker is taken as the
+/// source of truth for status and rating, while dates and entries are
+/// only filled in.
+fn update(item: &mut Item, entry: &LibraryEntry) {
+    for (provider, id) in entry.ids.iter() {
+        item.external_ids
+            .entry(*provider)
+            .or_insert_with(|| id.clone());
+    }
+    item.status = entry.status;
+    if let Some(rating) = &entry.rating {
+        // Keep a rating on another scale that converts to the same score
+        if item.rating.as_ref().map(|r| r.convert(rating.scale).score) != Some(rating.score) {
+            item.rating = Some(rating.clone());
+        }
+    }
+    if item.started == DateBool::False {
+        item.started = match entry.started {
+            DateBool::False if entry.status != Status::Planned => DateBool::True,
+            started => started,
+        };
+    }
+    if item.completed == DateBool::False && (entry.status == Status::Completed || entry.repeats > 0)
+    {
+        item.completed = match entry.completed {
+            DateBool::False => DateBool::True,
+            completed => completed,
+        };
+    }
+    if entry.repeats > 0 {
+        if item.extra.is_null() {
+            item.extra = serde_yaml::Value::Mapping(Default::default());
+        }
+        if let serde_yaml::Value::Mapping(extra) = &mut item.extra {
+            let key = serde_yaml::Value::String("repeats".to_owned());
+            let repeats = extra
+                .get(&key)
+                .and_then(|value| value.as_u64())
+                .unwrap_or(0);
+            if u64::from(entry.repeats) > repeats {
+                extra.insert(key, serde_yaml::Value::Number(entry.repeats.into()));
+            }
+        }
+    }
+    for tag in entry.tags.iter() {
+        if !item.tags.contains(tag) {
+            item.tags.push(tag.clone());
+        }
+    }
+    if item.comments.is_empty() {
+        item.comments = entry.notes.clone();
+    }
+    complete_entries(item, entry.progress, entry.total, entry.unit());
+}
+
+/// Find the item a library entry refers to.
+fn find<'a>(shelf: &'a Shelf, plan: &ImportPlan, entry: &LibraryEntry) -> Option<&'a Item> {
+    if let Some(item) = entry
+        .ids
+        .iter()
+        .find_map(|(provider, id)| shelf.find_item_by_external_id(*provider, id))
+    {
+        return Some(item);
+    }
+    // Only match by title if the item isn't already linked to a
+    // different title on the same site
+    let provider = entry.ids.first().map(|(provider, _)| *provider);
+    shelf.all_items().iter().find(|item| {
+        same_list(item.kind, entry.kind)
+            && provider
+                .filter(|provider| item.external_ids.contains_key(provider))
+                .is_none()
+            && !plan.contains(&item.key)
+            && entry.is_title(&item.name)
+    })
+}
+
+/// Plan importing a parsed library into the shelf.
+pub fn plan(shelf: &Shelf, library: Vec<LibraryEntry>) -> ImportPlan {
+    let mut plan = ImportPlan::default();
+    for entry in library {
+        let (lang, title) = match entry.titles.first() {
+            Some(title) => title.clone(),
+            None => {
+                plan.warnings.push(format!(
+                    "Skipping {} without a title",
+                    entry
+                        .ids
+                        .first()
+                        .map(|(_, id)| id.as_str())
+                        .unwrap_or("an entry")
+                ));
+                continue;
+            }
+        };
+        let existing = find(shelf, &plan, &entry);
+        if existing
+            .map(|item| plan.contains(&item.key))
+            .unwrap_or(false)
+        {
+            plan.warnings.push(format!(
+                "Skipping {:?}: its item was already matched",
+                title
+            ));
+            continue;
+        }
+        let mut item = match existing {
+            Some(existing) => existing.clone(),
+            None => {
+                let key = format!(
+                    "{}-{}",
+                    format!("{:?}", entry.kind).to_lowercase(),
+                    title_to_key(&title)
+                );
+                // Titles that only differ in punctuation map to the same key
+                let key = if shelf.get_item(&key).is_some() || plan.contains(&key) {
+                    let id = entry
+                        .ids
+                        .first()
+                        .and_then(|(_, id)| id.rsplit('/').next())
+                        .unwrap_or_default();
+                    format!("{}-{}", key, title_to_key(id))
+                } else {
+                    key
+                };
+                let mut name = Alternatives::new(lang, title);
+                for (lang, title) in entry.titles.iter().skip(1) {
+                    name.alternatives
+                        .entry(lang.clone())
+                        .or_insert_with(|| title.clone());
+                }
+                Item {
+                    key,
+                    kind: entry.kind,
+                    name,
+                    ..Default::default()
+                }
+            }
+        };
+        update(&mut item, &entry);
+        plan.push(existing, item);
+    }
+    plan
+}
@@ -0,0 +1,493 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import MyAnimeList anime and manga list exports.
+//!
+//! MAL exports one XML file per list, with an `<anime>` or `<manga>`
+//! element per title. Titles are matched to items by their MAL ID, then
+//! by key.
+
+use std::collections::HashMap;
+
+use quick_xml::events::Event;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use super::{complete_entries, ImportError, ImportPlan};
+use crate::common::{Alternatives, DateBool, Kind, Status};
+use crate::external::Provider;
+use crate::item::{Item, Priority};
+use crate::key::title_to_key;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum List {
+    Anime,
+    Manga,
+}
+
+impl List {
+    /// The element name used for titles on the list.
+    pub fn tag(self) -> &'static str {
+        match self {
+            List::Anime => "anime",
+            List::Manga => "manga",
+        }
+    }
+}
+
+/// One title of a MAL list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub list: List,
+    pub id: u32,
+    pub title: String,
+    /// TV, Movie, etc. Only exported for anime.
+    pub series_type: Option<String>,
+    /// The number of episodes or chapters, or 0 if unknown.
+    pub total: u32,
+    /// The number of episodes watched or chapters read.
+    pub progress: u32,
+    pub status: Status,
+    /// 1 to 10, or 0 if unscored.
+    pub score: u32,
+    pub started: DateBool,
+    pub finished: DateBool,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    pub comments: String,
+}
+
+impl Record {
+    pub fn external_id(&self) -> String {
+        format!("{}/{}", self.list.tag(), self.id)
+    }
+
+    pub fn kind(&self) -> Kind {
+        match (self.list, self.series_type.as_deref()) {
+            (List::Manga, Some("Novel")) | (List::Manga, Some("Light Novel")) => Kind::Novel,
+            (List::Manga, _) => Kind::Manga,
+            (List::Anime, Some("Movie")) => Kind::Film,
+            (List::Anime, Some("OVA")) | (List::Anime, Some("Special")) => Kind::OVA,
+            (List::Anime, Some("ONA")) => Kind::ONA,
+            (List::Anime, Some("Music")) => Kind::Music,
+            (List::Anime, _) => Kind::TV,
+        }
+    }
+}
+
+fn parse_status(status: &str) -> Result<Status, ImportError> {
+    // Older exports use numeric statuses
+    match status {
+        "Watching" | "Reading" | "1" => Ok(Status::InProgress),
+        "Completed" | "2" => Ok(Status::Completed),
+        "On-Hold" | "3" => Ok(Status::OnHold),
+        "Dropped" | "4" => Ok(Status::Dropped),
+        "Plan to Watch" | "Plan to Read" | "6" => Ok(Status::Planned),
+        _ => Err(ImportError::Parse(format!("Unknown status {:?}", status))),
+    }
+}
+
+fn parse_date(date: &str) -> DateBool {
+    if date.is_empty() {
+        return DateBool::False;
+    }
+    let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+        date.into_deserializer();
+    DateBool::deserialize(deserializer).unwrap_or(DateBool::False)
+}
+
+fn parse_number(fields: &HashMap<String, String>, field: &str) -> Result<u32, ImportError> {
+    match fields.get(field).map(|value| value.trim()) {
+        None | Some("") => Ok(0),
+        Some(value) => value
+            .parse()
+            .map_err(|_| ImportError::Parse(format!("Invalid {}: {:?}", field, value))),
+    }
+}
+
+fn to_record(list: List, fields: &HashMap<String, String>) -> Result<Record, ImportError> {
+    let (id, title, progress, total) = match list {
+        List::Anime => (
+            "series_animedb_id",
+            "series_title",
+            "my_watched_episodes",
+            "series_episodes",
+        ),
+        List::Manga => (
+            "manga_mangadb_id",
+            "manga_title",
+            "my_read_chapters",
+            "manga_chapters",
+        ),
+    };
+    let get = |field: &str| fields.get(field).map(|value| value.trim()).unwrap_or("");
+    let id = parse_number(fields, id)?;
+    if id == 0 {
+        return Err(ImportError::Parse(format!("{} without an ID", list.tag())));
+    }
+    Ok(Record {
+        list,
+        id,
+        title: get(title).to_owned(),
+        series_type: Some(get("series_type"))
+            .filter(|typ| !typ.is_empty())
+            .map(|typ| typ.to_owned()),
+        total: parse_number(fields, total)?,
+        progress: parse_number(fields, progress)?,
+        status: parse_status(get("my_status"))?,
+        score: parse_number(fields, "my_score")?,
+        started: parse_date(get("my_start_date")),
+        finished: parse_date(get("my_finish_date")),
+        priority: match get("my_priority") {
+            "LOW" => Some(Priority::Low),
+            "MEDIUM" => Some(Priority::Medium),
+            "HIGH" => Some(Priority::High),
+            _ => None,
+        },
+        tags: get("my_tags")
+            .split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_owned())
+            .collect(),
+        comments: get("my_comments").to_owned(),
+    })
+}
+
+/// Parse a MAL list export.
+pub fn parse(xml: &str) -> Result<Vec<Record>, ImportError> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut records = Vec::new();
+    // The list and fields of the title being read
+    let mut current: Option<(List, HashMap<String, String>)> = None;
+    let mut field: Option<String> = None;
+    let error = |reader: &quick_xml::Reader<&[u8]>, err: quick_xml::Error| -> ImportError {
+        ImportError::Parse(format!("At position {}: {}", reader.buffer_position(), err))
+    };
+
+    loop {
+        let text = match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                match name.as_str() {
+                    "anime" => current = Some((List::Anime, HashMap::new())),
+                    "manga" => current = Some((List::Manga, HashMap::new())),
+                    _ if current.is_some() => field = Some(name),
+                    _ => {}
+                }
+                continue;
+            }
+            Ok(Event::Text(e)) => e
+                .unescape()
+                .map_err(|err| error(&reader, err))?
+                .into_owned(),
+            Ok(Event::CData(e)) => String::from_utf8_lossy(&e).into_owned(),
+            Ok(Event::End(e)) => {
+                match e.name().as_ref() {
+                    b"anime" | b"manga" => {
+                        if let Some((list, fields)) = current.take() {
+                            records.push(to_record(list, &fields)?);
+                        }
+                    }
+                    _ => field = None,
+                }
+                continue;
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => continue,
+            Err(err) => return Err(error(&reader, err)),
+        };
+        if let (Some((_, fields)), Some(field)) = (current.as_mut(), field.as_ref()) {
+            fields.entry(field.clone()).or_default().push_str(&text);
+        }
+    }
+    if records.is_empty() && !xml.contains("<myanimelist") {
+        return Err(ImportError::Parse("Not a MyAnimeList export".to_owned()));
+    }
+    Ok(records)
+}
+
+/// Apply a MAL record to an item: MAL is taken as the source of truth
+/// for status and score, while dates and entries are only filled in.
+pub fn update(item: &mut Item, record: &Record) {
+    item.external_ids
+        .insert(Provider::MyAnimeList, record.external_id());
+    item.status = record.status;
+    if record.score > 0 {
+        item.rating = Some(Rating::new(record.score, Scale::Ten));
+    }
+    if item.started == DateBool::False {
+        item.started = match record.started {
+            DateBool::False if record.status != Status::Planned => DateBool::True,
+            started => started,
+        };
+    }
+    if item.completed == DateBool::False && record.status == Status::Completed {
+        item.completed = match record.finished {
+            DateBool::False => DateBool::True,
+            finished => finished,
+        };
+    }
+    if matches!(record.status, Status::Planned | Status::OnHold) && item.priority.is_none() {
+        item.priority = record.priority;
+    }
+    for tag in record.tags.iter() {
+        if !item.tags.contains(tag) {
+            item.tags.push(tag.clone());
+        }
+    }
+    if item.comments.is_empty() {
+        item.comments = record.comments.clone();
+    }
+    let unit = match record.list {
+        List::Anime => "Episode",
+        List::Manga => "Chapter",
+    };
+    complete_entries(item, record.progress, record.total, unit);
+}
+
+/// Plan importing a MAL list export into the shelf.
+pub fn plan(shelf: &Shelf, xml: &str) -> Result<ImportPlan, ImportError> {
+    let mut plan = ImportPlan::default();
+    for record in parse(xml)? {
+        if record.title.is_empty() {
+            plan.warnings
+                .push(format!("Skipping {} without a title", record.external_id()));
+            continue;
+        }
+        let kind = record.kind();
+        let key = format!(
+            "{}-{}",
+            format!("{:?}", kind).to_lowercase(),
+            title_to_key(&record.title)
+        );
+        let existing = shelf
+            .find_item_by_external_id(Provider::MyAnimeList, &record.external_id())
+            .or_else(|| {
+                // Only match by key if the item isn't already linked to a
+                // different title
+                shelf
+                    .get_item(&key)
+                    .filter(|item| !item.external_ids.contains_key(&Provider::MyAnimeList))
+            });
+        let mut item = match existing {
+            Some(existing) => existing.clone(),
+            None => {
+                // Titles that only differ in punctuation map to the same key
+                let key = if shelf.get_item(&key).is_some() || plan.contains(&key) {
+                    format!("{}-{}", key, record.id)
+                } else {
+                    key
+                };
+                Item {
+                    key,
+                    kind,
+                    name: Alternatives::new("Romaji", record.title.clone()),
+                    ..Default::default()
+                }
+            }
+        };
+        if existing.is_some() && plan.contains(&item.key) {
+            plan.warnings.push(format!(
+                "Skipping {}: {} was already matched",
+                record.external_id(),
+                item.key
+            ));
+            continue;
+        }
+        update(&mut item, &record);
+        plan.push(existing, item);
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, plan, List};
+    use crate::common::{DateBool, Kind, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::{Entry, Item};
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const ANIME: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<myanimelist>
+  <myinfo>
+    <user_id>1</user_id>
+    <user_name>test</user_name>
+    <user_export_type>1</user_export_type>
+  </myinfo>
+  <anime>
+    <series_animedb_id>1</series_animedb_id>
+    <series_title><![CDATA[Cowboy Bebop]]></series_title>
+    <series_type>TV</series_type>
+    <series_episodes>26</series_episodes>
+    <my_id>0</my_id>
+    <my_watched_episodes>26</my_watched_episodes>
+    <my_start_date>2019-04-01</my_start_date>
+    <my_finish_date>2019-05-02</my_finish_date>
+    <my_score>9</my_score>
+    <my_status>Completed</my_status>
+    <my_comments><![CDATA[]]></my_comments>
+    <my_priority>LOW</my_priority>
+    <my_tags><![CDATA[space, jazz]]></my_tags>
+  </anime>
+  <anime>
+    <series_animedb_id>5</series_animedb_id>
+    <series_title><![CDATA[Cowboy Bebop: Tengoku no Tobira]]></series_title>
+    <series_type>Movie</series_type>
+    <series_episodes>1</series_episodes>
+    <my_watched_episodes>0</my_watched_episodes>
+    <my_start_date>0000-00-00</my_start_date>
+    <my_finish_date>0000-00-00</my_finish_date>
+    <my_score>0</my_score>
+    <my_status>Plan to Watch</my_status>
+    <my_priority>HIGH</my_priority>
+    <my_tags><![CDATA[]]></my_tags>
+  </anime>
+</myanimelist>
+"#;
+
+    const MANGA: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<myanimelist>
+  <myinfo><user_export_type>2</user_export_type></myinfo>
+  <manga>
+    <manga_mangadb_id>2</manga_mangadb_id>
+    <manga_title><![CDATA[Berserk]]></manga_title>
+    <manga_volumes>0</manga_volumes>
+    <manga_chapters>0</manga_chapters>
+    <my_read_volumes>0</my_read_volumes>
+    <my_read_chapters>3</my_read_chapters>
+    <my_start_date>2020-01-00</my_start_date>
+    <my_finish_date>0000-00-00</my_finish_date>
+    <my_score>8</my_score>
+    <my_status>Reading</my_status>
+    <my_comments><![CDATA[Guts & Griffith]]></my_comments>
+  </manga>
+</myanimelist>
+"#;
+
+    #[test]
+    fn test_parse() {
+        let records = parse(ANIME).unwrap();
+        assert_eq!(2, records.len());
+        let bebop = &records[0];
+        assert_eq!(List::Anime, bebop.list);
+        assert_eq!("anime/1", bebop.external_id());
+        assert_eq!("Cowboy Bebop", bebop.title);
+        assert_eq!(Kind::TV, bebop.kind());
+        assert_eq!((26, 26), (bebop.total, bebop.progress));
+        assert_eq!(Status::Completed, bebop.status);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2019, 4, 1).unwrap()),
+            bebop.started
+        );
+        assert_eq!(vec!["space".to_owned(), "jazz".to_owned()], bebop.tags);
+        assert_eq!(Kind::Film, records[1].kind());
+        assert_eq!(DateBool::False, records[1].started);
+
+        let records = parse(MANGA).unwrap();
+        assert_eq!(Kind::Manga, records[0].kind());
+        assert_eq!(Status::InProgress, records[0].status);
+        // Unknown days are exported as 00
+        assert_eq!(DateBool::YearMonth(2020, 1), records[0].started);
+        assert_eq!("Guts & Griffith", records[0].comments);
+
+        assert!(parse("<html></html>").is_err());
+        assert!(parse("<myanimelist>").is_ok());
+    }
+
+    #[test]
+    fn test_plan_new() {
+        let shelf = Shelf::new();
+        let plan = plan(&shelf, ANIME).unwrap();
+        assert_eq!(2, plan.count(Action::Insert));
+
+        let bebop = &plan.changes[0].item;
+        assert_eq!("tv-cowboy-bebop", bebop.key);
+        assert_eq!(Some(&"Cowboy Bebop".to_owned()), bebop.name.get_default());
+        assert_eq!(Some(Rating::new(9, Scale::Ten)), bebop.rating);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2019, 5, 2).unwrap()),
+            bebop.completed
+        );
+        assert_eq!(26, bebop.entries.len());
+        assert!(bebop.entries.iter().all(|e| e.completed == DateBool::True));
+        assert_eq!(
+            Some(&"anime/1".to_owned()),
+            bebop.external_ids.get(&Provider::MyAnimeList)
+        );
+        // Priority only applies to the backlog
+        assert_eq!(None, bebop.priority);
+
+        let film = &plan.changes[1].item;
+        assert_eq!("film-cowboy-bebop-tengoku-no-tobira", film.key);
+        assert_eq!(Status::Planned, film.status);
+        assert_eq!(DateBool::False, film.started);
+        assert_eq!(None, film.rating);
+        assert_eq!(Some(crate::item::Priority::High), film.priority);
+        assert_eq!(DateBool::False, film.entries[0].completed);
+    }
+
+    #[test]
+    fn test_plan_existing() {
+        let mut shelf = Shelf::new();
+        let mut berserk = Item {
+            key: "manga-berserk-deluxe".into(),
+            kind: Kind::Manga,
+            status: Status::InProgress,
+            started: DateBool::True,
+            ..Default::default()
+        };
+        berserk.entries.push(Entry {
+            name: None,
+            number: Some(1),
+            volume: Some(1),
+            completed: DateBool::YearMonth(2019, 12),
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: Default::default(),
+        });
+        berserk
+            .external_ids
+            .insert(Provider::MyAnimeList, "manga/2".into());
+        shelf.insert_item(berserk).unwrap();
+
+        let plan = plan(&shelf, MANGA).unwrap();
+        let change = &plan.changes[0];
+        assert_eq!(Action::Update, change.action);
+        let item = &change.item;
+        assert_eq!("manga-berserk-deluxe", item.key);
+        assert_eq!(DateBool::True, item.started);
+        assert_eq!(Some(Rating::new(8, Scale::Ten)), item.rating);
+        assert_eq!(3, item.entries.len());
+        assert_eq!(DateBool::YearMonth(2019, 12), item.entries[0].completed);
+        assert_eq!(Some(1), item.entries[0].volume);
+        assert_eq!(DateBool::True, item.entries[2].completed);
+        assert!(change.fields.contains(&"entries".to_owned()));
+        assert!(!change.fields.contains(&"started".to_owned()));
+
+        // Importing again changes nothing
+        let mut shelf = shelf;
+        plan.apply(&mut shelf);
+        let again = super::plan(&shelf, MANGA).unwrap();
+        assert_eq!(1, again.count(Action::Unchanged));
+    }
+}
@@ -0,0 +1,531 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Importing lists exported from other sites and applications.
+//!
+//! Importers don't modify the shelf directly. Instead they build an
+//! `ImportPlan` of items to add and changes to items already on the
+//! shelf (matched by external ID), which can be reviewed before it is
+//! applied.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::common::{Alternatives, Blob, DateBool, Kind, Person};
+use crate::external::Provider;
+use crate::item::{Entry, Item};
+use crate::key::title_to_key;
+use crate::save::{DirectoryShelf, SaveError};
+use crate::series::Series;
+use crate::shelf::Shelf;
+
+pub mod anilist;
+pub mod calibre;
+pub mod goodreads;
+pub mod imdb;
+pub mod kitsu;
+pub mod kobo;
+pub mod letterboxd;
+pub mod library;
+pub mod mal;
+pub mod spreadsheet;
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file could not be parsed.
+    Parse(String),
+    UnknownFormat(String),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Parse(err) => write!(f, "Could not parse file: {}", err),
+            ImportError::UnknownFormat(format) => write!(f, "Unknown import format {:?}", format),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// The formats lists can be imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A MyAnimeList anime or manga list export (XML).
+    MyAnimeList,
+    /// A Goodreads library export (CSV).
+    Goodreads,
+    /// A Letterboxd account export (ZIP).
+    Letterboxd,
+    /// An IMDb ratings export (CSV).
+    Imdb,
+    /// An AniList anime or manga list (JSON).
+    AniList,
+    /// A Kitsu library (JSON).
+    Kitsu,
+}
+
+impl std::str::FromStr for Format {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Format, ImportError> {
+        match s {
+            "mal" => Ok(Format::MyAnimeList),
+            "goodreads" => Ok(Format::Goodreads),
+            "letterboxd" => Ok(Format::Letterboxd),
+            "imdb" => Ok(Format::Imdb),
+            "anilist" => Ok(Format::AniList),
+            "kitsu" => Ok(Format::Kitsu),
+            _ => Err(ImportError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+impl Format {
+    /// Plan the import of an exported file.
+    pub fn plan(self, shelf: &Shelf, contents: &[u8]) -> Result<ImportPlan, ImportError> {
+        match self {
+            Format::MyAnimeList => mal::plan(shelf, &text(contents)?),
+            Format::Goodreads => goodreads::plan(shelf, contents),
+            Format::Letterboxd => letterboxd::plan(shelf, contents),
+            Format::Imdb => imdb::plan(shelf, contents),
+            Format::AniList => anilist::plan(shelf, contents),
+            Format::Kitsu => kitsu::plan(shelf, contents),
+        }
+    }
+}
+
+/// Decode a file as UTF-8.
+fn text(contents: &[u8]) -> Result<String, ImportError> {
+    String::from_utf8(contents.to_vec())
+        .map_err(|err| ImportError::Parse(format!("File is not UTF-8: {}", err)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Action {
+    Insert,
+    Update,
+    Unchanged,
+}
+
+/// What will happen to one item.
+#[derive(Clone, Debug, Serialize)]
+pub struct Change {
+    pub action: Action,
+    /// The item as it will be after the import.
+    pub item: Item,
+    /// For updates, the fields that will change.
+    pub fields: Vec<String>,
+}
+
+/// A file to copy into the shelf as a blob.
+#[derive(Clone, Debug, Serialize)]
+pub struct BlobFile {
+    pub blob: Blob,
+    pub source: PathBuf,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ImportPlan {
+    /// People to add, before the items that reference them.
+    pub people: Vec<Person>,
+    /// Series to add, before the items in them.
+    pub series: Vec<Series>,
+    /// Blobs to add, before the items that reference them. Their files
+    /// must be written with `write_blobs` before the plan is applied.
+    pub blobs: Vec<BlobFile>,
+    pub changes: Vec<Change>,
+    /// Records that were skipped, and why.
+    pub warnings: Vec<String>,
+    /// Records that match no item, for importers that only update
+    /// items already on the shelf.
+    pub unmatched: Vec<String>,
+}
+
+/// The outcome of applying a plan.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ImportReport {
+    /// The number of people added.
+    pub people: usize,
+    /// The number of series added.
+    pub series: usize,
+    /// The number of blobs added.
+    pub blobs: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Items that could not be added or updated.
+    pub errors: Vec<String>,
+}
+
+/// Mark the first `progress` entries completed, adding entries named
+/// after `unit` (e.g. "Episode 3") up to `total` as needed. Existing
+/// completion dates are kept.
+fn complete_entries(item: &mut Item, progress: u32, total: u32, unit: &str) {
+    for number in 1..=progress.max(total) {
+        let completed = if number <= progress {
+            DateBool::True
+        } else {
+            DateBool::False
+        };
+        match item
+            .entries
+            .iter_mut()
+            .find(|entry| entry.number == Some(number))
+        {
+            Some(entry) => {
+                if completed != DateBool::False && entry.completed == DateBool::False {
+                    entry.completed = completed;
+                }
+            }
+            None => item.entries.push(Entry {
+                name: Some(Alternatives::new("English", format!("{} {}", unit, number))),
+                number: Some(number),
+                volume: None,
+                completed,
+                aired: DateBool::False,
+                rating: None,
+                length: None,
+                note: None,
+                extra: Default::default(),
+            }),
+        }
+    }
+}
+
+/// The top-level fields that differ between two versions of an item,
+/// with their old and new values.
+pub fn diff(before: &Item, after: &Item) -> Vec<(String, serde_yaml::Value, serde_yaml::Value)> {
+    let (before, after) = match (serde_yaml::to_value(before), serde_yaml::to_value(after)) {
+        (Ok(serde_yaml::Value::Mapping(before)), Ok(serde_yaml::Value::Mapping(after))) => {
+            (before, after)
+        }
+        _ => return Vec::new(),
+    };
+    after
+        .into_iter()
+        .filter_map(|(field, value)| {
+            let old = before
+                .get(&field)
+                .cloned()
+                .unwrap_or(serde_yaml::Value::Null);
+            match field {
+                serde_yaml::Value::String(field) if old != value => Some((field, old, value)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn changed_fields(before: &Item, after: &Item) -> Vec<String> {
+    diff(before, after)
+        .into_iter()
+        .map(|(field, _, _)| field)
+        .collect()
+}
+
+impl ImportPlan {
+    /// Plan to add an item, or to replace the existing item it was
+    /// matched with.
+    pub fn push(&mut self, existing: Option<&Item>, item: Item) {
+        let (action, fields) = match existing {
+            None => (Action::Insert, Vec::new()),
+            Some(existing) => {
+                let fields = changed_fields(existing, &item);
+                if fields.is_empty() {
+                    (Action::Unchanged, fields)
+                } else {
+                    (Action::Update, fields)
+                }
+            }
+        };
+        self.changes.push(Change {
+            action,
+            item,
+            fields,
+        });
+    }
+
+    /// The key of the person with the given name, adding them if they
+    /// are not on the shelf. Names are compared by their key form, so
+    /// differences in case, spacing and accents are ignored.
+    pub fn person(&mut self, shelf: &Shelf, name: &str) -> String {
+        let normalized = title_to_key(name);
+        let same_name = |person: &&Person| {
+            person
+                .name
+                .get_default()
+                .map(|name| title_to_key(name) == normalized)
+                .unwrap_or(false)
+        };
+        if let Some(person) = shelf
+            .query_people()
+            .find(same_name)
+            .or_else(|| self.people.iter().find(same_name))
+        {
+            return person.key.clone();
+        }
+        let taken = |key: &str| {
+            shelf.query_people().any(|person| person.key == key)
+                || self.people.iter().any(|person| person.key == key)
+        };
+        let mut key = format!("person-{}", normalized);
+        let mut suffix = 2;
+        while taken(&key) {
+            key = format!("person-{}-{}", normalized, suffix);
+            suffix += 1;
+        }
+        self.people.push(Person {
+            key: key.clone(),
+            name: Alternatives::new("English", name.trim()),
+            external_ids: Default::default(),
+        });
+        key
+    }
+
+    /// The key of the series with the given name, adding it if it is
+    /// not on the shelf. Names are compared like `person`'s.
+    pub fn series(&mut self, shelf: &Shelf, name: &str) -> String {
+        let normalized = title_to_key(name);
+        let same_name = |series: &&Series| {
+            series
+                .name
+                .get_default()
+                .map(|name| title_to_key(name) == normalized)
+                .unwrap_or(false)
+        };
+        if let Some(series) = shelf
+            .query_series()
+            .find(same_name)
+            .or_else(|| self.series.iter().find(same_name))
+        {
+            return series.key.clone();
+        }
+        let taken = |key: &str| {
+            shelf.query_series().any(|series| series.key == key)
+                || self.series.iter().any(|series| series.key == key)
+        };
+        let mut key = format!("series-{}", normalized);
+        let mut suffix = 2;
+        while taken(&key) {
+            key = format!("series-{}-{}", normalized, suffix);
+            suffix += 1;
+        }
+        self.series.push(Series {
+            key: key.clone(),
+            name: Alternatives::new("English", name.trim()),
+            people: Vec::new(),
+            external_ids: Default::default(),
+        });
+        key
+    }
+
+    /// Plan to copy a file into the shelf as a blob.
+    pub fn blob(&mut self, blob: Blob, source: PathBuf) {
+        self.blobs.push(BlobFile { blob, source });
+    }
+
+    /// Copy the files of the planned blobs into the shelf directory.
+    pub fn write_blobs(&self, saver: &DirectoryShelf) -> Result<(), SaveError> {
+        for file in self.blobs.iter() {
+            let path = saver.insert_blob(&file.blob.key)?;
+            std::fs::copy(&file.source, path)?;
+        }
+        Ok(())
+    }
+
+    /// Find the film a title refers to, or else pick the key for a new
+    /// one. Hand-entered films have no year, so `film-{title}` matches
+    /// as well as `film-{title}-{year}`, the key new films get when the
+    /// plain one is taken. Films the plan already touches, or with a
+    /// different IMDb ID, don't match.
+    pub fn film<'a>(
+        &self,
+        shelf: &'a Shelf,
+        title: &str,
+        year: Option<i32>,
+        imdb: Option<&str>,
+    ) -> Result<&'a Item, String> {
+        let same_film = |item: &Item| match (imdb, item.external_ids.get(&Provider::Imdb)) {
+            (Some(id), Some(other)) => id == other,
+            _ => item.kind == Kind::Film,
+        };
+        let key = format!("film-{}", title_to_key(title));
+        let with_year = year.map(|year| format!("{}-{}", key, year));
+        let candidates = with_year.iter().chain(std::iter::once(&key));
+        let mut free = None;
+        for candidate in candidates {
+            match shelf.get_item(candidate) {
+                Some(item) if same_film(item) && !self.contains(candidate) => return Ok(item),
+                None if !self.contains(candidate) => free = Some(candidate.clone()),
+                _ => {}
+            }
+        }
+        // Prefer the plain key, which is tried last
+        if let Some(free) = free {
+            return Err(free);
+        }
+        let mut suffix = 2;
+        loop {
+            let candidate = format!("{}-{}", key, suffix);
+            if shelf.get_item(&candidate).is_none() && !self.contains(&candidate) {
+                return Err(candidate);
+            }
+            suffix += 1;
+        }
+    }
+
+    /// Whether the plan already touches an item with this key.
+    pub fn contains(&self, key: &str) -> bool {
+        self.changes.iter().any(|change| change.item.key == key)
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.action == action)
+            .count()
+    }
+
+    /// Make the planned changes. Items that fail validation are skipped
+    /// and reported.
+    pub fn apply(self, shelf: &mut Shelf) -> ImportReport {
+        let mut report = ImportReport::default();
+        for person in self.people {
+            if shelf.insert_person(person) {
+                report.people += 1;
+            }
+        }
+        for series in self.series {
+            if shelf.insert_series(series) {
+                report.series += 1;
+            }
+        }
+        for file in self.blobs {
+            let key = file.blob.key.clone();
+            match shelf.insert_blob(file.blob) {
+                Ok(true) => report.blobs += 1,
+                Ok(false) => {}
+                Err(err) => report.errors.push(format!("{}: {}", key, err)),
+            }
+        }
+        let mut seen = HashSet::new();
+        for change in self.changes {
+            let key = change.item.key.clone();
+            if !seen.insert(key.clone()) {
+                report.errors.push(format!("{}: imported twice", key));
+                continue;
+            }
+            let result = match change.action {
+                Action::Insert if shelf.get_item(&key).is_some() => {
+                    report.errors.push(format!("{}: already exists", key));
+                    continue;
+                }
+                Action::Insert => shelf.insert_item(change.item),
+                Action::Update => shelf.replace_item(change.item),
+                Action::Unchanged => {
+                    report.unchanged += 1;
+                    continue;
+                }
+            };
+            match result {
+                Ok(()) if change.action == Action::Insert => report.inserted += 1,
+                Ok(()) => report.updated += 1,
+                Err(err) => report.errors.push(format!("{}: {}", key, err)),
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, ImportPlan, ImportReport};
+    use crate::common::{Alternatives, Person, Status};
+    use crate::item::Item;
+    use crate::Shelf;
+
+    #[test]
+    fn test_plan() {
+        let mut shelf = Shelf::new();
+        let item = Item {
+            key: "manga-a".into(),
+            ..Default::default()
+        };
+        shelf.insert_item(item.clone()).unwrap();
+
+        let mut plan = ImportPlan::default();
+        plan.push(Some(&item), item.clone());
+        plan.push(
+            Some(&item),
+            Item {
+                status: Status::Completed,
+                ..item.clone()
+            },
+        );
+        plan.push(
+            None,
+            Item {
+                key: "manga-b".into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(Action::Unchanged, plan.changes[0].action);
+        assert_eq!(Action::Update, plan.changes[1].action);
+        assert_eq!(vec!["status".to_owned()], plan.changes[1].fields);
+        assert_eq!(Action::Insert, plan.changes[2].action);
+        assert!(plan.contains("manga-b"));
+
+        let report = plan.apply(&mut shelf);
+        assert_eq!(
+            ImportReport {
+                people: 0,
+                series: 0,
+                blobs: 0,
+                inserted: 1,
+                updated: 0,
+                unchanged: 1,
+                errors: vec!["manga-a: imported twice".to_owned()],
+            },
+            report
+        );
+        assert!(shelf.get_item("manga-b").is_some());
+    }
+
+    #[test]
+    fn test_person() {
+        let mut shelf = Shelf::new();
+        shelf.insert_person(Person {
+            key: "person-ursula-k-le-guin".into(),
+            name: Alternatives::new("English", "Ursula K. Le Guin"),
+            external_ids: Default::default(),
+        });
+        // Same key, different person
+        shelf.insert_person(Person {
+            key: "person-ted-chiang".into(),
+            name: Alternatives::new("English", "Someone Else"),
+            external_ids: Default::default(),
+        });
+
+        let mut plan = ImportPlan::default();
+        assert_eq!(
+            "person-ursula-k-le-guin",
+            plan.person(&shelf, "Ursula K Le Guin")
+        );
+        assert_eq!("person-ted-chiang-2", plan.person(&shelf, "Ted Chiang"));
+        assert_eq!("person-ted-chiang-2", plan.person(&shelf, "ted  chiang"));
+        assert_eq!(1, plan.people.len());
+        assert_eq!(1, plan.apply(&mut shelf).people);
+    }
+}
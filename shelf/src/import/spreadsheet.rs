@@ -0,0 +1,500 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Import arbitrary CSV or TSV spreadsheets, given a mapping (YAML)
+//! from columns to item fields. For example:
+//!
+//! ```yaml
+//! delimiter: "\t"
+//! kind: Novel
+//! columns:
+//!   Title: name
+//!   Author: { people: { role: Author, separator: "&" } }
+//!   State: status
+//!   Stars: rating
+//!   Finished: completed
+//!   ISBN: { external: Isbn }
+//! statuses:
+//!   read: Completed
+//!   to-read: Planned
+//! date_formats: ["%d/%m/%Y"]
+//! rating_scale: FiveStar
+//! ```
+//!
+//! Rows are matched to items by key (if a column holds it), then
+//! external ID, then the key their kind and name would give. Mapped
+//! cells overwrite the item's fields, except tags, which are added;
+//! empty cells are ignored. Rows that don't make a valid item are
+//! skipped with a warning.
+
+use std::collections::BTreeMap;
+
+use super::{ImportError, ImportPlan};
+use crate::common::{DateBool, Kind, Role, Status};
+use crate::external::Provider;
+use crate::item::Item;
+use crate::key::title_to_key;
+use crate::rating::{Rating, Scale};
+use crate::shelf::Shelf;
+
+/// The item field a column holds.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Key,
+    /// The name in English.
+    Name,
+    Kind,
+    Status,
+    Rating,
+    Started,
+    Completed,
+    Added,
+    Tags {
+        /// Defaults to a comma.
+        #[serde(default)]
+        separator: Option<String>,
+    },
+    Synopsis,
+    Comments,
+    /// Names of people, which are added to the shelf if missing.
+    People {
+        role: Role,
+        /// Defaults to a comma.
+        #[serde(default)]
+        separator: Option<String>,
+        /// Whether names are written "Last, First". Only one name is
+        /// read per cell unless another separator is given.
+        #[serde(default)]
+        last_first: bool,
+    },
+    External(Provider),
+    /// A field of `Item::extra`.
+    Extra(String),
+}
+
+fn default_kind() -> Kind {
+    Kind::Novel
+}
+
+/// How to read a spreadsheet.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Mapping {
+    /// Defaults to a comma; use a tab for TSV.
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    /// The kind of items without a kind column.
+    #[serde(default = "default_kind")]
+    pub kind: Kind,
+    /// What each column holds, by header. Other columns are ignored.
+    pub columns: BTreeMap<String, Column>,
+    /// Statuses by cell value, for values that aren't status names.
+    #[serde(default)]
+    pub statuses: BTreeMap<String, Status>,
+    /// Kinds by cell value, for values that aren't kind names.
+    #[serde(default)]
+    pub kinds: BTreeMap<String, Kind>,
+    /// `strftime` formats for dates, tried in order before the formats
+    /// the shelf itself uses.
+    #[serde(default)]
+    pub date_formats: Vec<String>,
+    /// The scale of the rating column. Five-star ratings are given in
+    /// stars, e.g. 3.5.
+    #[serde(default)]
+    pub rating_scale: Option<Scale>,
+}
+
+impl Mapping {
+    pub fn parse(yaml: &str) -> Result<Mapping, ImportError> {
+        let mapping: Mapping = serde_yaml::from_str(yaml)
+            .map_err(|err| ImportError::Parse(format!("Invalid mapping: {}", err)))?;
+        mapping.delimiter()?;
+        Ok(mapping)
+    }
+
+    /// The delimiter as a byte. Mappings built without `parse` may
+    /// have a delimiter that doesn't fit in one.
+    fn delimiter(&self) -> Result<u8, ImportError> {
+        match self.delimiter {
+            None => Ok(b','),
+            Some(delimiter) if delimiter.is_ascii() => Ok(delimiter as u8),
+            Some(delimiter) => Err(ImportError::Parse(format!(
+                "Invalid mapping: delimiter {:?} is not ASCII",
+                delimiter
+            ))),
+        }
+    }
+
+    /// Look up a cell in a value map, falling back to the enum's own
+    /// names (e.g. `InProgress`).
+    fn value<T>(&self, map: &BTreeMap<String, T>, value: &str) -> Result<T, String>
+    where
+        T: Clone + serde::de::DeserializeOwned,
+    {
+        map.get(value)
+            .cloned()
+            .or_else(|| serde_yaml::from_value(serde_yaml::Value::String(value.to_owned())).ok())
+            .ok_or_else(|| format!("unknown value {:?}", value))
+    }
+
+    fn date(&self, value: &str) -> Result<DateBool, String> {
+        self.date_formats
+            .iter()
+            .find_map(|format| chrono::NaiveDate::parse_from_str(value, format).ok())
+            .map(DateBool::Date)
+            .or_else(|| serde_yaml::from_value(serde_yaml::Value::String(value.to_owned())).ok())
+            .ok_or_else(|| format!("invalid date {:?}", value))
+    }
+
+    fn rating(&self, value: &str) -> Result<Rating, String> {
+        let scale = self.rating_scale.unwrap_or(Scale::Ten);
+        let steps_per_point = if scale == Scale::FiveStar { 2.0 } else { 1.0 };
+        value
+            .parse::<f64>()
+            .ok()
+            .map(|value| value * steps_per_point)
+            .filter(|score| *score >= 0.0 && score.fract() == 0.0)
+            .map(|score| Rating::new(score as u32, scale))
+            .ok_or_else(|| format!("invalid rating {:?}", value))
+    }
+}
+
+/// Split a cell into names, turning "Last, First" around if needed.
+fn names(value: &str, separator: Option<&str>, last_first: bool) -> Vec<String> {
+    let split: Vec<&str> = match separator {
+        Some(separator) => value.split(separator).collect(),
+        None if last_first => vec![value],
+        None => value.split(',').collect(),
+    };
+    split
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.split_once(',') {
+            Some((last, first)) if last_first => format!("{} {}", first.trim(), last.trim()),
+            _ => name.to_owned(),
+        })
+        .collect()
+}
+
+/// Apply one mapped cell to an item.
+fn apply(
+    shelf: &Shelf,
+    plan: &mut ImportPlan,
+    mapping: &Mapping,
+    item: &mut Item,
+    column: &Column,
+    value: &str,
+) -> Result<(), String> {
+    let settings = shelf.settings();
+    match column {
+        // Used for matching
+        Column::Key | Column::Kind => {}
+        Column::Name => {
+            item.name
+                .alternatives
+                .insert("English".into(), value.into());
+        }
+        Column::Status => item.status = mapping.value(&mapping.statuses, value)?,
+        Column::Rating => {
+            let rating = mapping.rating(value)?;
+            // Keep a rating on another scale that converts to the same score
+            if item.rating.as_ref().map(|r| r.convert(rating.scale).score) != Some(rating.score) {
+                item.rating = Some(rating);
+            }
+        }
+        Column::Started => item.started = mapping.date(value)?,
+        Column::Completed => item.completed = mapping.date(value)?,
+        Column::Added => {
+            item.added = match mapping.date(value)? {
+                DateBool::Timestamp(added) => added,
+                date => date
+                    .date()
+                    .and_then(|date| settings.midnight(date))
+                    .ok_or_else(|| format!("invalid date {:?}", value))?,
+            }
+        }
+        Column::Tags { separator } => {
+            for tag in value.split(separator.as_deref().unwrap_or(",")) {
+                let tag = tag.trim().to_owned();
+                if !tag.is_empty() && !item.tags.contains(&tag) {
+                    item.tags.push(tag);
+                }
+            }
+        }
+        Column::Synopsis => item.synopsis = value.to_owned(),
+        Column::Comments => item.comments = value.to_owned(),
+        Column::People {
+            role,
+            separator,
+            last_first,
+        } => {
+            let people: Vec<_> = names(value, separator.as_deref(), *last_first)
+                .iter()
+                .map(|name| (*role, plan.person(shelf, name)))
+                .collect();
+            if !people.is_empty() {
+                item.people.retain(|(other, _)| other != role);
+                item.people.extend(people);
+            }
+        }
+        Column::External(provider) => {
+            let id = provider
+                .canonicalize(value)
+                .ok_or_else(|| format!("invalid {:?} ID {:?}", provider, value))?;
+            item.external_ids.insert(*provider, id);
+        }
+        Column::Extra(field) => {
+            if item.extra.is_null() {
+                item.extra = serde_yaml::Value::Mapping(Default::default());
+            }
+            let value = match value.parse::<i64>() {
+                Ok(number) => serde_yaml::Value::Number(number.into()),
+                Err(_) => serde_yaml::Value::String(value.to_owned()),
+            };
+            if let serde_yaml::Value::Mapping(extra) = &mut item.extra {
+                extra.insert(serde_yaml::Value::String(field.clone()), value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Plan one row: find or create its item, then apply each mapped cell.
+fn row(
+    shelf: &Shelf,
+    plan: &mut ImportPlan,
+    mapping: &Mapping,
+    cells: &[(&Column, &str)],
+) -> Result<(Option<Item>, Item), String> {
+    let cell = |wanted: &Column| {
+        cells
+            .iter()
+            .find(|(column, _)| *column == wanted)
+            .map(|(_, value)| *value)
+    };
+    let kind = match cell(&Column::Kind) {
+        Some(kind) => mapping.value(&mapping.kinds, kind)?,
+        None => mapping.kind,
+    };
+    let name = cell(&Column::Name);
+    let key = match (cell(&Column::Key), name) {
+        (Some(key), _) => key.to_owned(),
+        (None, Some(name)) => format!(
+            "{}-{}",
+            format!("{:?}", kind).to_lowercase(),
+            title_to_key(name)
+        ),
+        (None, None) => return Err("no key or name".to_owned()),
+    };
+    let existing = cells
+        .iter()
+        .filter_map(|(column, value)| match column {
+            Column::External(provider) => provider
+                .canonicalize(value)
+                .and_then(|id| shelf.find_item_by_external_id(*provider, &id)),
+            _ => None,
+        })
+        .next()
+        .or_else(|| shelf.get_item(&key));
+    if let Some(existing) = existing.filter(|item| plan.contains(&item.key)) {
+        return Err(format!("{} was already imported", existing.key));
+    }
+    let mut item = match existing {
+        Some(existing) => existing.clone(),
+        None if plan.contains(&key) => return Err(format!("{} was already imported", key)),
+        None => Item {
+            key,
+            kind,
+            ..Default::default()
+        },
+    };
+    for (column, value) in cells {
+        apply(shelf, plan, mapping, &mut item, column, value)?;
+    }
+    Ok((existing.cloned(), item))
+}
+
+/// Plan importing a spreadsheet into the shelf.
+pub fn plan(shelf: &Shelf, mapping: &Mapping, contents: &[u8]) -> Result<ImportPlan, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter()?)
+        .from_reader(contents);
+    let headers = reader
+        .headers()
+        .map_err(|err| ImportError::Parse(err.to_string()))?
+        .clone();
+    for header in mapping.columns.keys() {
+        if !headers.iter().any(|other| other.trim() == header) {
+            return Err(ImportError::Parse(format!("No column {:?}", header)));
+        }
+    }
+
+    let mut plan = ImportPlan::default();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| ImportError::Parse(err.to_string()))?;
+        let cells: Vec<(&Column, &str)> = headers
+            .iter()
+            .zip(record.iter())
+            .filter_map(|(header, value)| {
+                let value = value.trim();
+                match mapping.columns.get(header.trim()) {
+                    Some(column) if !value.is_empty() => Some((column, value)),
+                    _ => None,
+                }
+            })
+            .collect();
+        let people = plan.people.len();
+        let result = row(shelf, &mut plan, mapping, &cells).and_then(|(existing, item)| {
+            // People the plan adds only exist once it is applied
+            let mut check = item.clone();
+            check
+                .people
+                .retain(|(_, person)| !plan.people.iter().any(|new| new.key == *person));
+            shelf
+                .validate_item(&check)
+                .map(|_| (existing, item))
+                .map_err(|err| err.to_string())
+        });
+        match result {
+            Ok((existing, item)) => plan.push(existing.as_ref(), item),
+            Err(err) => {
+                // Don't add people for rows that are skipped
+                plan.people.truncate(people);
+                plan.warnings
+                    .push(format!("Skipping row {}: {}", line + 1, err));
+            }
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, Mapping};
+    use crate::common::{Alternatives, DateBool, Kind, Role, Status};
+    use crate::external::Provider;
+    use crate::import::Action;
+    use crate::item::Item;
+    use crate::rating::{Rating, Scale};
+    use crate::Shelf;
+
+    const MAPPING: &str = r#"
+delimiter: "\t"
+columns:
+  Title: name
+  Type: kind
+  Author: { people: { role: Author, separator: ";", last_first: true } }
+  State: status
+  Stars: rating
+  Finished: completed
+  Shelves: { tags: { separator: "|" } }
+  ISBN: { external: Isbn }
+  Pages: { extra: pages }
+statuses:
+  read: Completed
+  reading: InProgress
+kinds:
+  essay: NonFiction
+date_formats: ["%d/%m/%Y"]
+rating_scale: FiveStar
+"#;
+
+    const SHEET: &str = "\
+Title\tType\tAuthor\tState\tStars\tFinished\tShelves\tISBN\tPages\tIgnored
+A Wizard of Earthsea\t\tLe Guin, Ursula K.\tread\t4.5\t03/02/2020\tfantasy|classics\t\t183\tx
+Consider the Lobster\tessay\tWallace, David Foster\treading\t\t\t\t0-316-15611-6\t\t
+Bad Status\t\t\tunknown\t\t\t\t\t\t
+Bad Date\t\t\tread\t\tyesterday\t\t\t\t
+";
+
+    #[test]
+    fn test_plan() {
+        let mapping = Mapping::parse(MAPPING).unwrap();
+        let mut shelf = Shelf::new();
+        shelf
+            .insert_item(Item {
+                key: "nonfiction-lobster".into(),
+                kind: Kind::NonFiction,
+                name: Alternatives::new("Original", "Lobster"),
+                external_ids: vec![(Provider::Isbn, "0316156116".to_owned())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let plan = plan(&shelf, &mapping, SHEET.as_bytes()).unwrap();
+        assert_eq!(
+            vec![
+                "Skipping row 3: unknown value \"unknown\"".to_owned(),
+                "Skipping row 4: invalid date \"yesterday\"".to_owned(),
+            ],
+            plan.warnings
+        );
+        assert_eq!(
+            vec!["person-ursula-k-le-guin", "person-david-foster-wallace"],
+            plan.people
+                .iter()
+                .map(|person| person.key.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let earthsea = &plan.changes[0];
+        assert_eq!(Action::Insert, earthsea.action);
+        assert_eq!("novel-a-wizard-of-earthsea", earthsea.item.key);
+        assert_eq!(Status::Completed, earthsea.item.status);
+        assert_eq!(Some(Rating::new(9, Scale::FiveStar)), earthsea.item.rating);
+        assert_eq!(
+            DateBool::Date(chrono::NaiveDate::from_ymd_opt(2020, 2, 3).unwrap()),
+            earthsea.item.completed
+        );
+        assert_eq!(vec!["fantasy", "classics"], earthsea.item.tags);
+        assert_eq!(Some(183), earthsea.item.extra["pages"].as_u64());
+        assert_eq!(
+            vec![(Role::Author, "person-ursula-k-le-guin".to_owned())],
+            earthsea.item.people
+        );
+
+        let lobster = &plan.changes[1];
+        assert_eq!(Action::Update, lobster.action);
+        assert_eq!("nonfiction-lobster", lobster.item.key);
+        assert_eq!(Status::InProgress, lobster.item.status);
+        // Other names are kept
+        assert_eq!(Some(&"Lobster".to_owned()), lobster.item.name.get_default());
+        assert_eq!(
+            "Consider the Lobster",
+            lobster.item.name.alternatives["English"]
+        );
+
+        plan.apply(&mut shelf);
+        let again = super::plan(&shelf, &mapping, SHEET.as_bytes()).unwrap();
+        assert_eq!(2, again.count(Action::Unchanged));
+        assert!(again.people.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        let mapping = Mapping::parse("columns:\n  Title: name\n").unwrap();
+        assert!(plan(&Shelf::new(), &mapping, b"Name\nA\n").is_err());
+        assert!(Mapping::parse("columns:\n  Title: title\n").is_err());
+        assert!(Mapping::parse("delimiter: \"→\"\ncolumns:\n  Title: name\n").is_err());
+        // U+012C would become a comma if truncated to a byte
+        let mapping = Mapping {
+            delimiter: Some('\u{12c}'),
+            ..mapping
+        };
+        assert!(plan(&Shelf::new(), &mapping, b"Title\nA\n").is_err());
+    }
+}
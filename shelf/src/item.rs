@@ -13,14 +13,20 @@
 //     limitations under the License.
 
 use chrono;
+use chrono::Datelike;
 use serde_yaml;
 
+use crate::activity::DiaryNote;
 use crate::common::Alternatives;
 use crate::common::DateBool;
 use crate::common::Kind;
 use crate::common::PersonIdx;
 use crate::common::Role;
+use crate::common::Season;
 use crate::common::Status;
+use crate::duration::Length;
+use crate::external::ExternalIds;
+use crate::rating::Rating;
 
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub enum PublicationStatus {
@@ -28,6 +34,50 @@ pub enum PublicationStatus {
     Complete,
 }
 
+/// How soon a planned item should be read/watched.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+/// The broadcast schedule of a TV series.
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct Broadcast {
+    /// The year and season (cour) the series premiered in.
+    pub year: u32,
+    pub season: Season,
+    /// When the first episode aired, in the broadcaster's time zone.
+    /// Later episodes are assumed to air weekly at the same time.
+    #[serde(default)]
+    pub premiere: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// The number of episodes planned, if announced.
+    #[serde(default)]
+    pub episodes: Option<u32>,
+}
+
+impl Broadcast {
+    /// The day of the week the series airs on.
+    pub fn weekday(&self) -> Option<chrono::Weekday> {
+        self.premiere.map(|premiere| premiere.weekday())
+    }
+
+    /// The time of day the series airs at, in the broadcaster's time zone.
+    pub fn time(&self) -> Option<chrono::NaiveTime> {
+        self.premiere.map(|premiere| premiere.time())
+    }
+
+    /// When an episode (numbered from 1) is scheduled to air.
+    pub fn scheduled(&self, number: u32) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        if number == 0 || matches!(self.episodes, Some(episodes) if number > episodes) {
+            return None;
+        }
+        self.premiere
+            .map(|premiere| premiere + chrono::Duration::weeks(i64::from(number - 1)))
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Cover {
     pub key: String,
@@ -41,11 +91,21 @@ pub struct Item {
     pub name: Alternatives<String>,
     pub people: Vec<(Role, PersonIdx)>,
     pub season: Option<String>,
+    /// The broadcast schedule, for airing TV series.
+    #[serde(default)]
+    pub broadcast: Option<Broadcast>,
     pub entries: Vec<Entry>,
     pub status: Status,
-    pub rating: Option<u32>,
+    /// The backlog priority, for planned/on-hold items.
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    pub rating: Option<Rating>,
     #[serde(default)]
     pub tags: Vec<String>,
+    /// The length of each entry, or of the whole work if it has no
+    /// entries. Entries may override this.
+    #[serde(default)]
+    pub length: Option<Length>,
     pub added: chrono::DateTime<chrono::FixedOffset>,
     #[serde(default)]
     pub started: DateBool,
@@ -60,8 +120,14 @@ pub struct Item {
     pub synopsis: String,
     #[serde(default)]
     pub comments: String,
+    /// Notes on individual activity events (starting, completing,
+    /// finishing an entry).
+    #[serde(default)]
+    pub diary: Vec<DiaryNote>,
     #[serde(default)]
     pub covers: Vec<Cover>,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
 }
 
 impl Default for Item {
@@ -72,10 +138,13 @@ impl Default for Item {
             name: Alternatives::new("English", ""),
             people: Vec::new(),
             season: None,
+            broadcast: None,
             entries: Vec::new(),
             status: Status::Planned,
+            priority: None,
             rating: None,
             tags: Vec::new(),
+            length: None,
             added: chrono::prelude::Local::now().into(),
             started: DateBool::False,
             completed: DateBool::False,
@@ -84,7 +153,9 @@ impl Default for Item {
             series: None,
             synopsis: "".into(),
             comments: "".into(),
+            diary: Vec::new(),
             covers: Vec::new(),
+            external_ids: ExternalIds::new(),
         }
     }
 }
@@ -99,6 +170,17 @@ pub struct Entry {
     pub number: Option<u32>,
     pub volume: Option<u32>,
     pub completed: DateBool,
+    /// When the entry aired or was published.
+    #[serde(default)]
+    pub aired: DateBool,
+    #[serde(default)]
+    pub rating: Option<Rating>,
+    #[serde(default)]
+    pub length: Option<Length>,
+    /// A free-text diary note about completing this entry. Kept on the
+    /// entry (rather than in `Item::diary`) so it moves with it.
+    #[serde(default)]
+    pub note: Option<String>,
     #[serde(default = "default_extras")]
     pub extra: serde_yaml::Value,
 }
@@ -0,0 +1,260 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Generating keys from titles and names.
+//!
+//! This follows `titleToKey` in the web frontend: kana are romanized,
+//! other scripts are transliterated to ASCII, and the result is
+//! lowercased and hyphenated.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CharClass {
+    Letter,
+    Digit,
+    Space,
+    Hiragana,
+    Katakana,
+    Kanji,
+    Other,
+}
+
+fn classify(c: char) -> CharClass {
+    match c {
+        // The prolonged sound mark is romanized as a separator
+        'ー' => CharClass::Other,
+        '\u{3041}'..='\u{309f}' => CharClass::Hiragana,
+        '\u{30a0}'..='\u{30ff}' => CharClass::Katakana,
+        '\u{3005}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' => CharClass::Kanji,
+        c if c.is_whitespace() => CharClass::Space,
+        c if c.is_ascii_digit() => CharClass::Digit,
+        c if c.is_alphabetic() && !c.is_ascii_punctuation() => CharClass::Letter,
+        _ => CharClass::Other,
+    }
+}
+
+/// Split text into runs of the same kind of character.
+fn tokenize(s: &str) -> Vec<(CharClass, String)> {
+    let mut tokens: Vec<(CharClass, String)> = Vec::new();
+    for c in s.chars() {
+        let class = classify(c);
+        match tokens.last_mut() {
+            Some((last, token)) if *last == class && class != CharClass::Other => token.push(c),
+            _ => tokens.push((class, c.to_string())),
+        }
+    }
+    tokens
+}
+
+/// The Hepburn romanization of a single hiragana.
+fn hiragana_romaji(c: char) -> Option<&'static str> {
+    Some(match c {
+        'あ' | 'ぁ' => "a",
+        'い' | 'ぃ' => "i",
+        'う' | 'ぅ' => "u",
+        'え' | 'ぇ' => "e",
+        'お' | 'ぉ' => "o",
+        'か' => "ka",
+        'き' => "ki",
+        'く' => "ku",
+        'け' => "ke",
+        'こ' => "ko",
+        'が' => "ga",
+        'ぎ' => "gi",
+        'ぐ' => "gu",
+        'げ' => "ge",
+        'ご' => "go",
+        'さ' => "sa",
+        'し' => "shi",
+        'す' => "su",
+        'せ' => "se",
+        'そ' => "so",
+        'ざ' => "za",
+        'じ' => "ji",
+        'ず' => "zu",
+        'ぜ' => "ze",
+        'ぞ' => "zo",
+        'た' => "ta",
+        'ち' => "chi",
+        'つ' => "tsu",
+        'て' => "te",
+        'と' => "to",
+        'だ' => "da",
+        'ぢ' => "ji",
+        'づ' => "zu",
+        'で' => "de",
+        'ど' => "do",
+        'な' => "na",
+        'に' => "ni",
+        'ぬ' => "nu",
+        'ね' => "ne",
+        'の' => "no",
+        'は' => "ha",
+        'ひ' => "hi",
+        'ふ' => "fu",
+        'へ' => "he",
+        'ほ' => "ho",
+        'ば' => "ba",
+        'び' => "bi",
+        'ぶ' => "bu",
+        'べ' => "be",
+        'ぼ' => "bo",
+        'ぱ' => "pa",
+        'ぴ' => "pi",
+        'ぷ' => "pu",
+        'ぺ' => "pe",
+        'ぽ' => "po",
+        'ま' => "ma",
+        'み' => "mi",
+        'む' => "mu",
+        'め' => "me",
+        'も' => "mo",
+        'や' | 'ゃ' => "ya",
+        'ゆ' | 'ゅ' => "yu",
+        'よ' | 'ょ' => "yo",
+        'ら' => "ra",
+        'り' => "ri",
+        'る' => "ru",
+        'れ' => "re",
+        'ろ' => "ro",
+        'わ' | 'ゎ' => "wa",
+        'ゐ' => "wi",
+        'ゑ' => "we",
+        'を' => "wo",
+        'ん' => "n",
+        'ゔ' => "vu",
+        _ => return None,
+    })
+}
+
+fn is_small_vowel(c: char) -> bool {
+    matches!(c, 'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ')
+}
+
+fn is_small_y(c: char) -> bool {
+    matches!(c, 'ゃ' | 'ゅ' | 'ょ')
+}
+
+/// Romanize kana, combining digraphs (きゃ → kya) and doubling
+/// consonants after a small tsu (がっこう → gakkou).
+fn romanize_kana(s: &str) -> String {
+    // Treat katakana as the equivalent hiragana
+    let chars: Vec<char> = s
+        .chars()
+        .map(|c| match c {
+            '\u{30a1}'..='\u{30f6}' => std::char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            c => c,
+        })
+        .collect();
+    let mut result = String::new();
+    let mut double_next = false;
+    for &c in chars.iter() {
+        if c == 'っ' {
+            double_next = true;
+            continue;
+        }
+        let romaji = match hiragana_romaji(c) {
+            Some(romaji) => romaji,
+            None => {
+                result.push_str(&any_ascii::any_ascii(&c.to_string()));
+                continue;
+            }
+        };
+        let vowel = &romaji[romaji.len() - 1..];
+        if is_small_y(c) && result.ends_with('i') && !result.ends_with("ii") {
+            // きゃ → kya, しゃ → sha
+            result.pop();
+            if !(result.ends_with("sh") || result.ends_with("ch") || result.ends_with('j')) {
+                result.push('y');
+            }
+            result.push_str(vowel);
+            continue;
+        }
+        if is_small_vowel(c) && result.ends_with(|c: char| "aiueo".contains(c)) {
+            // ファ → fa, ティ → ti, ウィ → wi
+            if result == "u" || result.ends_with(" u") {
+                result.pop();
+                result.push('w');
+            } else {
+                result.pop();
+            }
+            result.push_str(vowel);
+            continue;
+        }
+        if double_next {
+            if romaji.starts_with("ch") {
+                result.push('t');
+            } else if let Some(first) = romaji.chars().next().filter(|c| !"aiueon".contains(*c)) {
+                result.push(first);
+            }
+            double_next = false;
+        }
+        result.push_str(romaji);
+    }
+    result
+}
+
+/// Turn a title or name into a key fragment, e.g. "Jitsu wa Watashi
+/// wa!" becomes "jitsu-wa-watashi-wa".
+pub fn title_to_key(title: &str) -> String {
+    let romanized: Vec<String> = tokenize(title)
+        .into_iter()
+        .map(|(class, token)| match class {
+            CharClass::Hiragana | CharClass::Katakana => romanize_kana(&token),
+            _ => any_ascii::any_ascii(&token),
+        })
+        .collect();
+    let lower = romanized.join(" ").to_lowercase();
+    let cleaned: String = lower
+        .chars()
+        .filter(|c| *c == ' ' || c.is_ascii_lowercase() || c.is_ascii_digit())
+        .collect();
+    cleaned.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{romanize_kana, title_to_key};
+
+    #[test]
+    fn test_title_to_key() {
+        assert_eq!("jitsu-wa-watashi-wa", title_to_key("Jitsu wa Watashi wa!"));
+        assert_eq!("39-music", title_to_key("39 Music!"));
+        assert_eq!("white-space", title_to_key(" white  space  "));
+        assert_eq!(
+            "kobayashi-san-chi-no-maid-dragon",
+            title_to_key("Kobayashi-san Chi no Maid Dragon")
+        );
+    }
+
+    #[test]
+    fn test_title_to_key_unicode() {
+        assert_eq!("shi-ha-si-ha", title_to_key("実は私は…"));
+        assert_eq!("chenxinyun", title_to_key("陈心云"));
+        assert_eq!("kimi-no-shou-niha", title_to_key("キミの手には"));
+        assert_eq!(
+            "haikarori-apa-to-niao-tomato-yanben-kuuyou",
+            title_to_key("ハイカロリーアパート（鳥トマト/岩本くうよう）")
+        );
+    }
+
+    #[test]
+    fn test_romanize_kana() {
+        assert_eq!("kyatto", romanize_kana("きゃっと"));
+        assert_eq!("gakkou", romanize_kana("がっこう"));
+        assert_eq!("matcha", romanize_kana("まっちゃ"));
+        assert_eq!("shashin", romanize_kana("しゃしん"));
+        assert_eq!("fan", romanize_kana("ファン"));
+        assert_eq!("wo", romanize_kana("を"));
+    }
+}
@@ -19,11 +19,27 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_yaml;
 
+pub mod activity;
+pub mod airing;
+pub mod archive;
 pub mod common;
+pub mod duration;
+pub mod export;
+pub mod external;
+pub mod filter;
+pub mod goal;
+pub mod import;
 pub mod item;
+pub mod key;
+pub mod list;
+pub mod rating;
 pub mod save;
+pub mod schema;
 pub mod series;
+pub mod settings;
 pub mod shelf;
+pub mod stats;
+pub mod suggest;
 
 pub use crate::shelf::Shelf;
 
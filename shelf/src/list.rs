@@ -0,0 +1,172 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Ordered, user-curated lists of items (rankings, queues, ...).
+
+use crate::schema::FieldError;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ListEntry {
+    /// The item key.
+    pub item: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl ListEntry {
+    pub fn new<S: Into<String>>(item: S) -> ListEntry {
+        ListEntry {
+            item: item.into(),
+            note: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct List {
+    pub key: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The entries, in order. An item appears at most once.
+    #[serde(default)]
+    pub items: Vec<ListEntry>,
+    #[serde(default)]
+    pub public: bool,
+}
+
+impl List {
+    pub fn new<S: Into<String>, S0: Into<String>>(key: S, name: S0) -> List {
+        List {
+            key: key.into(),
+            name: name.into(),
+            description: String::new(),
+            items: Vec::new(),
+            public: false,
+        }
+    }
+
+    /// The position of an item in the list.
+    pub fn position(&self, item: &str) -> Option<usize> {
+        self.items.iter().position(|entry| entry.item == item)
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.position(item).is_some()
+    }
+
+    /// Insert an entry at the given position (or the end).
+    ///
+    /// If the item is already in the list, it is moved instead and its
+    /// note is replaced.
+    pub fn insert(&mut self, index: Option<usize>, entry: ListEntry) {
+        if let Some(old) = self.position(&entry.item) {
+            self.items.remove(old);
+        }
+        let index = index.unwrap_or(self.items.len()).min(self.items.len());
+        self.items.insert(index, entry);
+    }
+
+    /// Remove an item from the list, returning its entry if present.
+    pub fn remove(&mut self, item: &str) -> Option<ListEntry> {
+        self.position(item).map(|index| self.items.remove(index))
+    }
+
+    /// Move an item to a new position (clamped to the end of the list).
+    ///
+    /// Returns false if the item is not in the list.
+    pub fn move_item(&mut self, item: &str, to: usize) -> bool {
+        match self.remove(item) {
+            Some(entry) => {
+                let to = to.min(self.items.len());
+                self.items.insert(to, entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace references to an item key. Returns true if the list
+    /// changed.
+    pub fn rename_item(&mut self, from: &str, to: &str) -> bool {
+        let mut changed = false;
+        for entry in self.items.iter_mut() {
+            if entry.item == from {
+                entry.item = to.to_owned();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Check that no item appears twice.
+    pub fn validate(&self) -> Result<(), FieldError> {
+        if self.key.is_empty() {
+            return Err(FieldError::new("key", "list key must not be empty"));
+        }
+        for (index, entry) in self.items.iter().enumerate() {
+            if self.items[..index].iter().any(|e| e.item == entry.item) {
+                return Err(FieldError::new(
+                    "items",
+                    format!("item '{}' appears more than once", entry.item),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{List, ListEntry};
+
+    fn keys(list: &List) -> Vec<&str> {
+        list.items.iter().map(|e| e.item.as_str()).collect()
+    }
+
+    #[test]
+    fn test_list_ops() {
+        let mut list = List::new("list-top", "Top");
+        list.insert(None, ListEntry::new("a"));
+        list.insert(None, ListEntry::new("b"));
+        list.insert(Some(0), ListEntry::new("c"));
+        assert_eq!(vec!["c", "a", "b"], keys(&list));
+
+        // Re-inserting moves the item and replaces the note
+        list.insert(
+            Some(10),
+            ListEntry {
+                item: "c".into(),
+                note: Some("Actually the best".into()),
+            },
+        );
+        assert_eq!(vec!["a", "b", "c"], keys(&list));
+        assert_eq!(Some("Actually the best".into()), list.items[2].note);
+
+        assert!(list.move_item("c", 0));
+        assert_eq!(vec!["c", "a", "b"], keys(&list));
+        assert!(!list.move_item("d", 0));
+
+        assert!(list.rename_item("a", "d"));
+        assert_eq!(Some(ListEntry::new("d")), list.remove("d"));
+        assert_eq!(None, list.remove("d"));
+        assert_eq!(vec!["c", "b"], keys(&list));
+        assert!(list.validate().is_ok());
+
+        list.items.push(ListEntry::new("c"));
+        assert_eq!("items", list.validate().unwrap_err().field);
+        list.key = "".into();
+        assert_eq!("key", list.validate().unwrap_err().field);
+    }
+}
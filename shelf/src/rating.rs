@@ -0,0 +1,268 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Ratings on configurable scales, with optional named sub-scores.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The scale a rating is given on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Scale {
+    /// 0 to 10.
+    Ten,
+    /// 0 to 100.
+    Hundred,
+    /// 0 to 5 stars, in half-star increments.
+    FiveStar,
+    /// Thumbs down (0) or thumbs up (1).
+    Thumbs,
+}
+
+impl Scale {
+    /// The number of steps on the scale. A score is an integer between
+    /// 0 and this value (inclusive).
+    pub fn steps(self) -> u32 {
+        match self {
+            Scale::Ten => 10,
+            Scale::Hundred => 100,
+            Scale::FiveStar => 10,
+            Scale::Thumbs => 1,
+        }
+    }
+}
+
+/// A rating, plus optional sub-scores (e.g. story, art, music) on the
+/// same scale.
+///
+/// A plain 10-point rating without sub-scores serializes as a bare
+/// integer, as `Item::rating` used to be.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "RatingRepr", into = "RatingRepr")]
+pub struct Rating {
+    pub score: u32,
+    pub scale: Scale,
+    pub axes: BTreeMap<String, u32>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum RatingRepr {
+    Simple(u32),
+    Full {
+        score: u32,
+        scale: Scale,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        axes: BTreeMap<String, u32>,
+    },
+}
+
+impl From<RatingRepr> for Rating {
+    fn from(repr: RatingRepr) -> Rating {
+        match repr {
+            RatingRepr::Simple(score) => Rating::new(score, Scale::Ten),
+            RatingRepr::Full { score, scale, axes } => Rating { score, scale, axes },
+        }
+    }
+}
+
+impl From<Rating> for RatingRepr {
+    fn from(rating: Rating) -> RatingRepr {
+        if rating.scale == Scale::Ten && rating.axes.is_empty() {
+            RatingRepr::Simple(rating.score)
+        } else {
+            RatingRepr::Full {
+                score: rating.score,
+                scale: rating.scale,
+                axes: rating.axes,
+            }
+        }
+    }
+}
+
+impl Rating {
+    pub fn new(score: u32, scale: Scale) -> Rating {
+        Rating {
+            score,
+            scale,
+            axes: BTreeMap::new(),
+        }
+    }
+
+    /// Check that all scores are within the scale.
+    pub fn validate(&self) -> Result<(), String> {
+        let max = self.scale.steps();
+        if self.score > max {
+            return Err(format!(
+                "score {} is out of range for {:?}",
+                self.score, self.scale
+            ));
+        }
+        for (axis, score) in self.axes.iter() {
+            if *score > max {
+                return Err(format!(
+                    "score {} for '{}' is out of range for {:?}",
+                    score, axis, self.scale
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The score as a fraction of the maximum, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        f64::from(self.score) / f64::from(self.scale.steps())
+    }
+
+    /// Convert to another scale, if this can be done without rounding.
+    pub fn convert_exact(&self, scale: Scale) -> Option<Rating> {
+        let score = convert_score(self.score, self.scale, scale, false)?;
+        let mut axes = BTreeMap::new();
+        for (axis, score) in self.axes.iter() {
+            axes.insert(
+                axis.clone(),
+                convert_score(*score, self.scale, scale, false)?,
+            );
+        }
+        Some(Rating { score, scale, axes })
+    }
+
+    /// Convert to another scale, rounding to the nearest step.
+    pub fn convert(&self, scale: Scale) -> Rating {
+        // Only scores far above the maximum overflow
+        let round = |score| convert_score(score, self.scale, scale, true).unwrap_or(scale.steps());
+        Rating {
+            score: round(self.score),
+            scale,
+            axes: self
+                .axes
+                .iter()
+                .map(|(axis, score)| (axis.clone(), round(*score)))
+                .collect(),
+        }
+    }
+}
+
+fn convert_score(score: u32, from: Scale, to: Scale, round: bool) -> Option<u32> {
+    let numerator = score.checked_mul(to.steps())?;
+    let denominator = from.steps();
+    if numerator % denominator == 0 {
+        Some(numerator / denominator)
+    } else if round {
+        Some(numerator.checked_add(denominator / 2)? / denominator)
+    } else {
+        None
+    }
+}
+
+fn format_score(f: &mut fmt::Formatter, score: u32, scale: Scale) -> fmt::Result {
+    match scale {
+        Scale::Ten => write!(f, "{}/10", score),
+        Scale::Hundred => write!(f, "{}/100", score),
+        Scale::FiveStar => match score % 2 {
+            0 => write!(f, "{}/5", score / 2),
+            _ => write!(f, "{}.5/5", score / 2),
+        },
+        Scale::Thumbs if score > 0 => write!(f, "Thumbs up"),
+        Scale::Thumbs => write!(f, "Thumbs down"),
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_score(f, self.score, self.scale)?;
+        if !self.axes.is_empty() {
+            write!(f, " (")?;
+            for (i, (axis, score)) in self.axes.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: ", axis)?;
+                format_score(f, *score, self.scale)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rating, Scale};
+
+    #[test]
+    fn test_rating_ser_de() {
+        let rating: Rating = serde_yaml::from_str("8").unwrap();
+        assert_eq!(Rating::new(8, Scale::Ten), rating);
+        assert_eq!("---\n8", serde_yaml::to_string(&rating).unwrap().trim());
+
+        let mut rating = Rating::new(7, Scale::FiveStar);
+        rating.axes.insert("Art".into(), 10);
+        let yaml = serde_yaml::to_string(&rating).unwrap();
+        assert_eq!(rating, serde_yaml::from_str(&yaml).unwrap());
+
+        let rating: Rating = serde_yaml::from_str("{score: 85, scale: Hundred}").unwrap();
+        assert_eq!(Rating::new(85, Scale::Hundred), rating);
+    }
+
+    #[test]
+    fn test_rating_convert() {
+        let rating = Rating::new(7, Scale::FiveStar);
+        assert_eq!(
+            Some(Rating::new(7, Scale::Ten)),
+            rating.convert_exact(Scale::Ten)
+        );
+        assert_eq!(
+            Some(Rating::new(70, Scale::Hundred)),
+            rating.convert_exact(Scale::Hundred)
+        );
+        assert_eq!(None, rating.convert_exact(Scale::Thumbs));
+        assert_eq!(Rating::new(1, Scale::Thumbs), rating.convert(Scale::Thumbs));
+
+        let rating = Rating::new(85, Scale::Hundred);
+        assert_eq!(None, rating.convert_exact(Scale::Ten));
+        assert_eq!(Rating::new(9, Scale::Ten), rating.convert(Scale::Ten));
+        // Unvalidated scores too large to convert
+        let rating = Rating::new(u32::MAX, Scale::Ten);
+        assert_eq!(None, rating.convert_exact(Scale::Hundred));
+        assert_eq!(
+            Rating::new(100, Scale::Hundred),
+            rating.convert(Scale::Hundred)
+        );
+        // Ten -> Hundred -> Ten is lossless
+        let rating = Rating::new(6, Scale::Ten);
+        assert_eq!(
+            Some(rating.clone()),
+            rating
+                .convert_exact(Scale::Hundred)
+                .and_then(|r| r.convert_exact(Scale::Ten))
+        );
+    }
+
+    #[test]
+    fn test_rating_display_validate() {
+        assert_eq!("4.5/5", Rating::new(9, Scale::FiveStar).to_string());
+        assert_eq!("4/5", Rating::new(8, Scale::FiveStar).to_string());
+        assert_eq!("Thumbs up", Rating::new(1, Scale::Thumbs).to_string());
+        let mut rating = Rating::new(8, Scale::Ten);
+        rating.axes.insert("Music".into(), 10);
+        rating.axes.insert("Story".into(), 6);
+        assert_eq!("8/10 (Music: 10/10, Story: 6/10)", rating.to_string());
+
+        assert!(rating.validate().is_ok());
+        rating.axes.insert("Art".into(), 11);
+        assert!(rating.validate().is_err());
+        assert!(Rating::new(2, Scale::Thumbs).validate().is_err());
+    }
+}
@@ -20,7 +20,7 @@ use std::path;
 use git2;
 use serde_yaml;
 
-use crate::shelf::Shelf;
+use crate::shelf::{EntityType, Shelf};
 
 pub struct DirectoryShelf {
     directory: path::PathBuf,
@@ -70,6 +70,23 @@ impl ::std::error::Error for SaveError {}
 
 const BLOBS_PATH: &'static str = "blobs";
 const BLOBS_INDEX: &'static str = "index.yaml";
+const SCHEMA_PATH: &str = "schema.yaml";
+// Used in commit messages when the schema changes
+const SCHEMA_KEY: &str = "schema";
+const SETTINGS_PATH: &str = "settings.yaml";
+// Used in commit messages when the settings change
+const SETTINGS_KEY: &str = "settings";
+
+/// The filename prefix of an entity stored as an individual file.
+fn entity_prefix(ty: EntityType) -> &'static str {
+    match ty {
+        EntityType::Person => "person--",
+        EntityType::Series => "series--",
+        EntityType::Item => "item--",
+        EntityType::Goal => "goal--",
+        EntityType::List => "list--",
+    }
+}
 
 impl DirectoryShelf {
     pub fn new<P: Into<path::PathBuf>>(p: P) -> Result<DirectoryShelf, SaveError> {
@@ -152,7 +169,18 @@ impl DirectoryShelf {
         )?;
 
         let wrote = {
-            let mut updated = vec![];
+            let mut updated: Vec<&str> = vec![];
+
+            for (ty, key) in shelf.query_removed() {
+                let path = self
+                    .directory
+                    .join(format!("{}{}.yaml", entity_prefix(ty), key));
+                if path.is_file() {
+                    fs::remove_file(&path)?;
+                    index.remove_path(path.strip_prefix(&self.directory)?)?;
+                    updated.push(key);
+                }
+            }
 
             for person in shelf.query_people() {
                 if !shelf.is_dirty(&person.key) {
@@ -199,6 +227,36 @@ impl DirectoryShelf {
                 updated.push(&item.1.key);
             }
 
+            for list in shelf.query_lists() {
+                if !shelf.is_dirty(&list.key) {
+                    continue;
+                }
+                let filename = format!("list--{}.yaml", list.key);
+                let path = self.directory.join(filename);
+                let file = File::create(&path)?;
+                serde_yaml::to_writer(&file, list)?;
+                file.sync_all()?;
+
+                index.add_path(path.strip_prefix(&self.directory)?)?;
+
+                updated.push(&list.key);
+            }
+
+            for goal in shelf.query_goals() {
+                if !shelf.is_dirty(&goal.key) {
+                    continue;
+                }
+                let filename = format!("goal--{}.yaml", goal.key);
+                let path = self.directory.join(filename);
+                let file = File::create(&path)?;
+                serde_yaml::to_writer(&file, goal)?;
+                file.sync_all()?;
+
+                index.add_path(path.strip_prefix(&self.directory)?)?;
+
+                updated.push(&goal.key);
+            }
+
             let mut blob_modified = false;
             for blob in shelf.query_blobs() {
                 if !shelf.is_dirty(&blob.key) {
@@ -222,6 +280,24 @@ impl DirectoryShelf {
                 index.add_path(path.strip_prefix(&self.directory)?)?;
             }
 
+            if shelf.is_schema_dirty() {
+                let path = self.directory.join(SCHEMA_PATH);
+                let file = File::create(&path)?;
+                serde_yaml::to_writer(&file, shelf.schema())?;
+                file.sync_all()?;
+                index.add_path(path.strip_prefix(&self.directory)?)?;
+                updated.push(SCHEMA_KEY);
+            }
+
+            if shelf.is_settings_dirty() {
+                let path = self.directory.join(SETTINGS_PATH);
+                let file = File::create(&path)?;
+                serde_yaml::to_writer(&file, shelf.settings())?;
+                file.sync_all()?;
+                index.add_path(path.strip_prefix(&self.directory)?)?;
+                updated.push(SETTINGS_KEY);
+            }
+
             let tree_id = index.write_tree()?;
 
             let tree = self.repository.find_tree(tree_id)?;
@@ -255,8 +331,10 @@ impl DirectoryShelf {
 
     pub fn load(&self, shelf: &mut Shelf) -> Result<(), SaveError> {
         let mut people: Vec<crate::common::Person> = vec![];
-        let mut items: Vec<crate::item::Item> = vec![];
+        let mut items: Vec<(String, crate::item::Item)> = vec![];
         let mut series: Vec<crate::series::Series> = vec![];
+        let mut goals: Vec<crate::goal::Goal> = vec![];
+        let mut lists: Vec<crate::list::List> = vec![];
 
         for entry in self.directory.read_dir()? {
             if let Ok(entry) = entry {
@@ -266,15 +344,36 @@ impl DirectoryShelf {
                         people.push(serde_yaml::from_reader(file)?);
                     } else if name.starts_with("item--") {
                         let file = File::open(entry.path())?;
-                        items.push(serde_yaml::from_reader(file)?);
+                        items.push((name.to_owned(), serde_yaml::from_reader(file)?));
                     } else if name.starts_with("series--") {
                         let file = File::open(entry.path())?;
                         series.push(serde_yaml::from_reader(file)?);
+                    } else if name.starts_with("goal--") {
+                        let file = File::open(entry.path())?;
+                        goals.push(serde_yaml::from_reader(file)?);
+                    } else if name.starts_with("list--") {
+                        let file = File::open(entry.path())?;
+                        lists.push(serde_yaml::from_reader(file)?);
                     }
                 }
             }
         }
 
+        let settings_path = self.directory.join(SETTINGS_PATH);
+        if settings_path.is_file() {
+            let file = File::open(settings_path)?;
+            shelf.set_settings(serde_yaml::from_reader(file)?);
+        }
+
+        let schema_path = self.directory.join(SCHEMA_PATH);
+        if schema_path.is_file() {
+            let file = File::open(schema_path)?;
+            let schema: crate::schema::Schema = serde_yaml::from_reader(file)?;
+            shelf
+                .set_schema(schema)
+                .map_err(|err| SaveError::SerializationError(format!("Invalid schema: {}", err)))?;
+        }
+
         let blobs_index = self.directory.join(BLOBS_PATH).join(BLOBS_INDEX);
         if blobs_index.is_file() {
             let file = File::open(blobs_index)?;
@@ -283,6 +382,10 @@ impl DirectoryShelf {
                 shelf.insert_blob(blob).map_err(|err| match err {
                     crate::shelf::ShelfError::InvalidReference(key) => SaveError::InvalidKey(key),
                     crate::shelf::ShelfError::InvalidKey(key) => SaveError::InvalidKey(key),
+                    crate::shelf::ShelfError::InvalidField(key, _) => SaveError::InvalidKey(key),
+                    crate::shelf::ShelfError::DuplicateExternalId(key) => {
+                        SaveError::InvalidKey(key)
+                    }
                 })?;
             }
         }
@@ -293,9 +396,21 @@ impl DirectoryShelf {
         series.into_iter().for_each(|p| {
             let _ = shelf.insert_series(p);
         });
-        items
-            .into_iter()
-            .for_each(|p| shelf.insert_item(p).unwrap());
+        for (name, item) in items {
+            shelf.load_item(item).map_err(|err| {
+                SaveError::SerializationError(format!("Invalid item in {}: {}", name, err))
+            })?;
+        }
+        for list in lists {
+            shelf
+                .insert_list(list)
+                .map_err(|err| SaveError::SerializationError(format!("Invalid list: {}", err)))?;
+        }
+        for goal in goals {
+            shelf
+                .insert_goal(goal)
+                .map_err(|err| SaveError::SerializationError(format!("Invalid goal: {}", err)))?;
+        }
 
         shelf.clear_all_dirty();
 
@@ -339,6 +454,46 @@ mod tests {
         assert!(saver.save(&mut shelf).is_ok());
     }
 
+    #[test]
+    fn load_invalid_item() {
+        let tmp_dir = Builder::new()
+            .prefix("shelf-test-")
+            .tempdir()
+            .expect("Could not make temp dir");
+        let saver = DirectoryShelf::new(tmp_dir.path()).expect("Could not make temp shelf");
+        let item = crate::item::Item {
+            key: "manga-foo".into(),
+            extra: serde_yaml::from_str("{mal_id: foo, external_url: 'example.com'}").unwrap(),
+            ..Default::default()
+        };
+        std::fs::write(
+            tmp_dir.path().join("item--manga-foo.yaml"),
+            serde_yaml::to_string(&item).unwrap(),
+        )
+        .unwrap();
+
+        // Saved before the built-in schema was enforced
+        let mut shelf = Shelf::new();
+        saver.load(&mut shelf).unwrap();
+        assert_eq!(Some(&item), shelf.get_item("manga-foo"));
+
+        // A library's own schema is enforced
+        let mut schema = crate::schema::Schema::default();
+        schema.fields.push(crate::schema::Field::new(
+            "pages",
+            crate::schema::FieldType::Int,
+        ));
+        std::fs::write(
+            tmp_dir.path().join("schema.yaml"),
+            serde_yaml::to_string(&schema).unwrap(),
+        )
+        .unwrap();
+        let mut shelf = Shelf::new();
+        let err = saver.load(&mut shelf).unwrap_err().to_string();
+        assert!(err.contains("item--manga-foo.yaml"), "{}", err);
+        assert!(err.contains("external_url"), "{}", err);
+    }
+
     #[test]
     fn roundtrip_blob() {
         let tmp_dir = Builder::new()
@@ -373,4 +528,130 @@ mod tests {
             .next();
         assert!(blob.is_some());
     }
+
+    #[test]
+    fn roundtrip_schema() {
+        use crate::schema::{Field, FieldType};
+
+        let tmp_dir = Builder::new()
+            .prefix("shelf-test-")
+            .tempdir()
+            .expect("Could not make temp dir");
+        let saver = DirectoryShelf::new(tmp_dir.path()).expect("Could not make temp shelf");
+
+        let mut shelf = Shelf::new();
+        let mut schema = shelf.schema().clone();
+        schema.fields.push(Field::new("anilist_id", FieldType::Int));
+        shelf.set_schema(schema.clone()).unwrap();
+        assert_eq!(1, saver.save(&mut shelf).unwrap());
+        assert!(!shelf.is_schema_dirty());
+
+        let mut shelf = Shelf::new();
+        assert!(saver.load(&mut shelf).is_ok());
+        assert_eq!(&schema, shelf.schema());
+        assert!(!shelf.is_schema_dirty());
+    }
+
+    #[test]
+    fn roundtrip_settings() {
+        use crate::settings::Settings;
+
+        let tmp_dir = Builder::new()
+            .prefix("shelf-test-")
+            .tempdir()
+            .expect("Could not make temp dir");
+        let saver = DirectoryShelf::new(tmp_dir.path()).expect("Could not make temp shelf");
+
+        let settings = Settings {
+            time_zone: Some(chrono_tz::Tz::Asia__Tokyo.into()),
+        };
+        let mut shelf = Shelf::new();
+        shelf.set_settings(settings);
+        assert_eq!(1, saver.save(&mut shelf).unwrap());
+        assert!(!shelf.is_settings_dirty());
+
+        let mut shelf = Shelf::new();
+        assert!(saver.load(&mut shelf).is_ok());
+        assert_eq!(&settings, shelf.settings());
+        assert!(!shelf.is_settings_dirty());
+    }
+
+    #[test]
+    fn roundtrip_goal() {
+        use crate::goal::{Goal, Metric};
+        use chrono::NaiveDate;
+
+        let tmp_dir = Builder::new()
+            .prefix("shelf-test-")
+            .tempdir()
+            .expect("Could not make temp dir");
+        let saver = DirectoryShelf::new(tmp_dir.path()).expect("Could not make temp shelf");
+
+        let goal = Goal {
+            key: "goal-2020-books".into(),
+            name: "50 books in 2020".into(),
+            target: 50,
+            metric: Metric::Items,
+            filter: Default::default(),
+            start: NaiveDate::from_ymd(2020, 1, 1),
+            end: NaiveDate::from_ymd(2020, 12, 31),
+        };
+        let mut shelf = Shelf::new();
+        assert!(shelf.insert_goal(goal.clone()).unwrap());
+        assert_eq!(1, saver.save(&mut shelf).unwrap());
+
+        let mut shelf = Shelf::new();
+        assert!(saver.load(&mut shelf).is_ok());
+        assert_eq!(Some(&goal), shelf.get_goal("goal-2020-books"));
+    }
+
+    #[test]
+    fn roundtrip_list_remove_item() {
+        use crate::item::Item;
+        use crate::list::{List, ListEntry};
+
+        let tmp_dir = Builder::new()
+            .prefix("shelf-test-")
+            .tempdir()
+            .expect("Could not make temp dir");
+        let saver = DirectoryShelf::new(tmp_dir.path()).expect("Could not make temp shelf");
+
+        let mut shelf = Shelf::new();
+        for key in ["manga-a", "manga-b"].iter() {
+            shelf
+                .insert_item(Item {
+                    key: key.to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        // A person may share a key with an item
+        shelf.insert_person(crate::common::Person {
+            key: "manga-b".into(),
+            name: crate::common::Alternatives::new("English", "B"),
+            external_ids: Default::default(),
+        });
+        let mut list = List::new("list-queue", "Queue");
+        list.insert(None, ListEntry::new("manga-b"));
+        list.insert(None, ListEntry::new("manga-a"));
+        shelf.insert_list(list).unwrap();
+        assert_eq!(4, saver.save(&mut shelf).unwrap());
+
+        shelf.remove_item("manga-b").unwrap();
+        shelf.rename_item("manga-a", "manga-c").unwrap();
+        saver.save(&mut shelf).unwrap();
+        assert!(!tmp_dir.path().join("item--manga-a.yaml").exists());
+        assert!(!tmp_dir.path().join("item--manga-b.yaml").exists());
+        assert!(tmp_dir.path().join("person--manga-b.yaml").exists());
+        assert!(tmp_dir.path().join("item--manga-c.yaml").exists());
+
+        let mut shelf = Shelf::new();
+        assert!(saver.load(&mut shelf).is_ok());
+        assert_eq!(1, shelf.all_items().len());
+        assert_eq!(1, shelf.query_people().count());
+        assert_eq!(
+            vec![ListEntry::new("manga-c")],
+            shelf.get_list("list-queue").unwrap().items
+        );
+    }
 }
@@ -0,0 +1,351 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! A schema for the custom fields stored in `Item::extra` and
+//! `Entry::extra`.
+//!
+//! Fields that are not part of the schema are left alone, so that
+//! clients can still stash arbitrary data in `extra`.
+
+use serde_yaml;
+
+use crate::common::DateBool;
+use crate::common::Kind;
+use crate::item::Item;
+
+/// The type of a custom field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FieldType {
+    String,
+    Url,
+    /// An integer. Strings containing an integer are also accepted,
+    /// since some importers store IDs as strings.
+    Int,
+    /// Anything that can be deserialized as a `DateBool`.
+    Date,
+    /// One of a fixed set of strings.
+    Enum(Vec<String>),
+    List(Box<FieldType>),
+}
+
+/// Whether a field lives in `Item::extra` or in `Entry::extra`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldScope {
+    #[default]
+    Item,
+    Entry,
+}
+
+/// A custom field definition.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub scope: FieldScope,
+    /// The kinds this field applies to. If empty, applies to all kinds.
+    #[serde(default)]
+    pub kinds: Vec<Kind>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Field {
+    pub fn new<S: Into<String>>(name: S, field_type: FieldType) -> Field {
+        Field {
+            name: name.into(),
+            field_type,
+            scope: FieldScope::Item,
+            kinds: Vec::new(),
+            required: false,
+            description: "".into(),
+        }
+    }
+
+    pub fn applies_to(&self, kind: Kind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+}
+
+/// A field failed validation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub(crate) fn new<S: Into<String>>(field: &str, reason: S) -> FieldError {
+        FieldError {
+            field: field.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The set of custom fields known to a shelf.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+impl Default for Schema {
+    /// The fields used by the built-in importers.
+    fn default() -> Schema {
+        let mut isbn = Field::new("isbn", FieldType::String);
+        isbn.description = "ISBN-10 or ISBN-13".into();
+        let mut repeats = Field::new("repeats", FieldType::Int);
+        repeats.description = "Times rewatched or reread, after the first time".into();
+        let mut progress = Field::new("progress", FieldType::Int);
+        progress.description = "Percent read, as synced from an e-reader".into();
+        let mut time_spent = Field::new("time_spent", FieldType::Int);
+        time_spent.description = "Minutes spent reading, as synced from an e-reader".into();
+        let mut last_read = Field::new("last_read", FieldType::Date);
+        last_read.description = "When it was last read, as synced from an e-reader".into();
+        Schema {
+            fields: vec![
+                Field::new("external_url", FieldType::Url),
+                Field::new("mangadex_url", FieldType::Url),
+                Field::new("mal_id", FieldType::Int),
+                isbn,
+                repeats,
+                progress,
+                time_spent,
+                last_read,
+            ],
+        }
+    }
+}
+
+impl Schema {
+    pub fn get(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Validate the custom fields of an item and its entries.
+    pub fn validate_item(&self, item: &Item) -> Result<(), FieldError> {
+        self.validate_extra(item.kind, FieldScope::Item, &item.extra)?;
+        for entry in item.entries.iter() {
+            self.validate_extra(item.kind, FieldScope::Entry, &entry.extra)?;
+        }
+        Ok(())
+    }
+
+    /// Validate a single `extra` value against the fields for the given
+    /// kind and scope.
+    pub fn validate_extra(
+        &self,
+        kind: Kind,
+        scope: FieldScope,
+        extra: &serde_yaml::Value,
+    ) -> Result<(), FieldError> {
+        let empty = serde_yaml::Mapping::new();
+        let mapping = match extra {
+            serde_yaml::Value::Null => &empty,
+            serde_yaml::Value::Mapping(m) => m,
+            _ => return Err(FieldError::new("extra", "must be a mapping")),
+        };
+
+        for field in self.fields.iter().filter(|f| f.scope == scope) {
+            let value = mapping.get(&serde_yaml::Value::String(field.name.clone()));
+            // Editors write an empty string when a field is cleared
+            let unset = match value {
+                None | Some(serde_yaml::Value::Null) => true,
+                Some(serde_yaml::Value::String(s)) => s.is_empty(),
+                Some(_) => false,
+            };
+            match value {
+                Some(value) if !unset => {
+                    if !field.applies_to(kind) {
+                        return Err(FieldError::new(
+                            &field.name,
+                            format!("does not apply to {:?}", kind),
+                        ));
+                    }
+                    check_type(&field.name, &field.field_type, value)?;
+                }
+                _ => {
+                    if field.required && field.applies_to(kind) {
+                        return Err(FieldError::new(&field.name, "is required"));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rename a field definition. Returns false if the field does not
+    /// exist or the new name is already taken.
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        if self.get(to).is_some() {
+            return false;
+        }
+        match self.fields.iter_mut().find(|f| f.name == from) {
+            Some(field) => {
+                field.name = to.to_owned();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn check_type(name: &str, typ: &FieldType, value: &serde_yaml::Value) -> Result<(), FieldError> {
+    use serde_yaml::Value;
+    match (typ, value) {
+        (FieldType::String, Value::String(_)) => Ok(()),
+        (FieldType::Url, Value::String(s)) => {
+            if is_url(s) {
+                Ok(())
+            } else {
+                Err(FieldError::new(name, format!("'{}' is not a URL", s)))
+            }
+        }
+        (FieldType::Int, Value::Number(n)) if n.is_i64() || n.is_u64() => Ok(()),
+        (FieldType::Int, Value::String(s)) if s.parse::<i64>().is_ok() => Ok(()),
+        (FieldType::Date, Value::String(_)) => serde_yaml::from_value::<DateBool>(value.clone())
+            .map(|_| ())
+            .map_err(|e| FieldError::new(name, format!("{}", e))),
+        (FieldType::Enum(options), Value::String(s)) => {
+            if options.contains(s) {
+                Ok(())
+            } else {
+                Err(FieldError::new(
+                    name,
+                    format!("'{}' is not one of {:?}", s, options),
+                ))
+            }
+        }
+        (FieldType::List(inner), Value::Sequence(values)) => {
+            for value in values {
+                check_type(name, inner, value)?;
+            }
+            Ok(())
+        }
+        (typ, value) => Err(FieldError::new(
+            name,
+            format!("expected {:?}, got {:?}", typ, value),
+        )),
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    let rest = if let Some(rest) = s.strip_prefix("https://") {
+        rest
+    } else if let Some(rest) = s.strip_prefix("http://") {
+        rest
+    } else {
+        return false;
+    };
+    match rest.split('/').next() {
+        Some(host) => !host.is_empty() && !host.contains(char::is_whitespace),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, FieldScope, FieldType, Schema};
+    use crate::common::Kind;
+    use crate::item::{Entry, Item};
+
+    fn extra(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_schema_validate_types() {
+        let schema = Schema::default();
+        let mut item: Item = Default::default();
+        assert!(schema.validate_item(&item).is_ok());
+
+        item.extra = extra("{mal_id: 1234, external_url: 'https://example.com/foo'}");
+        assert!(schema.validate_item(&item).is_ok());
+        item.extra = extra("{mal_id: '1234'}");
+        assert!(schema.validate_item(&item).is_ok());
+        // Empty and null values are not set
+        item.extra = extra("{mal_id: '', external_url: '', last_read: ~}");
+        assert!(schema.validate_item(&item).is_ok());
+        // Unknown fields are allowed
+        item.extra = extra("{editing: {name: foo}}");
+        assert!(schema.validate_item(&item).is_ok());
+
+        item.extra = extra("{mal_id: foo}");
+        assert_eq!("mal_id", schema.validate_item(&item).unwrap_err().field);
+        item.extra = extra("{external_url: 'not a url'}");
+        assert_eq!(
+            "external_url",
+            schema.validate_item(&item).unwrap_err().field
+        );
+        item.extra = extra("[1, 2]");
+        assert!(schema.validate_item(&item).is_err());
+    }
+
+    #[test]
+    fn test_schema_validate_kinds_and_scope() {
+        let mut schema = Schema::default();
+        let mut volumes = Field::new("volumes", FieldType::Int);
+        volumes.kinds = vec![Kind::Manga];
+        volumes.required = true;
+        let mut source = Field::new(
+            "source",
+            FieldType::List(Box::new(FieldType::Enum(vec![
+                "web".into(),
+                "print".into(),
+            ]))),
+        );
+        source.scope = FieldScope::Entry;
+        schema.fields.push(volumes);
+        schema.fields.push(source);
+
+        let mut item: Item = Default::default();
+        assert_eq!(Kind::Manga, item.kind);
+        assert_eq!("volumes", schema.validate_item(&item).unwrap_err().field);
+        item.extra = extra("{volumes: 3}");
+        assert!(schema.validate_item(&item).is_ok());
+
+        item.kind = Kind::Novel;
+        assert!(schema.validate_item(&item).is_err());
+        item.extra = serde_yaml::Value::Null;
+        assert!(schema.validate_item(&item).is_ok());
+
+        item.entries.push(Entry {
+            name: None,
+            number: Some(1),
+            volume: None,
+            completed: Default::default(),
+            aired: Default::default(),
+            rating: None,
+            length: None,
+            note: None,
+            extra: extra("{source: [web, print]}"),
+        });
+        assert!(schema.validate_item(&item).is_ok());
+        item.entries[0].extra = extra("{source: [radio]}");
+        assert_eq!("source", schema.validate_item(&item).unwrap_err().field);
+    }
+
+    #[test]
+    fn test_schema_rename() {
+        let mut schema = Schema::default();
+        assert!(schema.rename("mangadex_url", "md_url"));
+        assert!(schema.get("md_url").is_some());
+        assert!(schema.get("mangadex_url").is_none());
+        assert!(!schema.rename("md_url", "external_url"));
+        assert!(!schema.rename("nonexistent", "foo"));
+    }
+}
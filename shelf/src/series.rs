@@ -15,10 +15,13 @@
 use crate::common::Alternatives;
 use crate::common::PersonIdx;
 use crate::common::Role;
+use crate::external::ExternalIds;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Series {
     pub key: String,
     pub name: Alternatives<String>,
     pub people: Vec<(Role, PersonIdx)>,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
 }
@@ -0,0 +1,242 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Library-wide settings, stored alongside the shelf.
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use chrono_tz::Tz;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::common::fixed_offset;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Settings {
+    /// The time zone used to decide which calendar day a timestamp
+    /// falls on, as an IANA name like "America/New_York" or a fixed UTC
+    /// offset like "+05:30". If unset, each timestamp's own offset is
+    /// used.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_time_zone",
+        deserialize_with = "deserialize_time_zone"
+    )]
+    pub time_zone: Option<Zone>,
+}
+
+impl Settings {
+    /// Today's date in the configured time zone (or the system's).
+    pub fn today(&self) -> chrono::NaiveDate {
+        let now = chrono::Local::now();
+        match self.time_zone {
+            Some(tz) => tz.convert(&now).naive_local().date(),
+            None => now.naive_local().date(),
+        }
+    }
+
+    /// Midnight at the start of a day in the configured time zone (or
+    /// UTC), for dates that have to become timestamps.
+    pub fn midnight(&self, date: chrono::NaiveDate) -> Option<DateTime<FixedOffset>> {
+        self.time_zone
+            .unwrap_or(Zone::Named(Tz::UTC))
+            .midnight(date)
+    }
+}
+
+/// A time zone: an IANA zone, whose offset depends on daylight saving
+/// time, or a fixed UTC offset, as older settings files used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// The same moment in this zone, using the offset in effect then.
+    pub fn convert<T: TimeZone>(&self, time: &DateTime<T>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Named(tz) => fixed_offset(time.with_timezone(tz)),
+            Zone::Fixed(offset) => time.with_timezone(offset),
+        }
+    }
+
+    /// Midnight at the start of a day in this zone.
+    pub fn midnight(&self, date: chrono::NaiveDate) -> Option<DateTime<FixedOffset>> {
+        let midnight = date.and_hms_opt(0, 0, 0)?;
+        match self {
+            Zone::Named(tz) => tz
+                .from_local_datetime(&midnight)
+                .earliest()
+                .map(fixed_offset),
+            Zone::Fixed(offset) => offset.from_local_datetime(&midnight).earliest(),
+        }
+    }
+}
+
+impl From<Tz> for Zone {
+    fn from(tz: Tz) -> Zone {
+        Zone::Named(tz)
+    }
+}
+
+impl From<FixedOffset> for Zone {
+    fn from(offset: FixedOffset) -> Zone {
+        Zone::Fixed(offset)
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Named(tz) => f.write_str(tz.name()),
+            Zone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+/// Parse a time zone: an IANA name like "Asia/Tokyo", or a UTC offset.
+pub fn parse_time_zone(s: &str) -> Option<Zone> {
+    let s = s.trim();
+    match s.parse::<Tz>() {
+        Ok(tz) => Some(Zone::Named(tz)),
+        Err(_) => parse_offset(s).map(Zone::Fixed),
+    }
+}
+
+/// Parse a UTC offset: "Z", "UTC", "+09:00", "-0530", or "+9".
+pub fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let s = s.trim();
+    if s == "Z" || s.eq_ignore_ascii_case("UTC") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, rest) = match s.chars().next()? {
+        '+' => (1, &s[1..]),
+        '-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn serialize_time_zone<S: Serializer>(tz: &Option<Zone>, serializer: S) -> Result<S::Ok, S::Error> {
+    match tz {
+        Some(tz) => serializer.serialize_str(&tz.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_time_zone<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Zone>, D::Error> {
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value {
+        Some(value) => parse_time_zone(&value)
+            .map(Some)
+            .ok_or_else(|| de::Error::custom(format!("invalid time zone: {}", value))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_offset, parse_time_zone, Settings, Zone};
+    use chrono::{FixedOffset, NaiveDate};
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(Some(FixedOffset::east(0)), parse_offset("Z"));
+        assert_eq!(Some(FixedOffset::east(9 * 3600)), parse_offset("+09:00"));
+        assert_eq!(
+            Some(FixedOffset::west(5 * 3600 + 1800)),
+            parse_offset("-0530")
+        );
+        assert_eq!(Some(FixedOffset::east(3600)), parse_offset("+1"));
+        assert_eq!(None, parse_offset("09:00"));
+        assert_eq!(None, parse_offset("+25:00"));
+    }
+
+    #[test]
+    fn test_parse_time_zone() {
+        assert_eq!(
+            Some(Zone::Named(Tz::Asia__Tokyo)),
+            parse_time_zone("Asia/Tokyo")
+        );
+        assert_eq!(Some(Zone::Named(Tz::UTC)), parse_time_zone("UTC"));
+        assert_eq!(
+            Some(Zone::Fixed(FixedOffset::east(0))),
+            parse_time_zone("Z")
+        );
+        assert_eq!(
+            Some(Zone::Fixed(FixedOffset::east(9 * 3600))),
+            parse_time_zone("+09:00")
+        );
+        assert_eq!(
+            Some(Zone::Fixed(FixedOffset::west(5 * 3600 + 1800))),
+            parse_time_zone("-0530")
+        );
+        assert_eq!(None, parse_time_zone("Tokyo"));
+    }
+
+    #[test]
+    fn test_settings_ser_de() {
+        let settings = Settings {
+            time_zone: Some(Tz::America__New_York.into()),
+        };
+        let yaml = serde_yaml::to_string(&settings).unwrap();
+        assert!(yaml.contains("America/New_York"));
+        assert_eq!(settings, serde_yaml::from_str(&yaml).unwrap());
+
+        // Offsets written by older versions still load, and are kept
+        let settings: Settings = serde_yaml::from_str("time_zone: \"+05:30\"").unwrap();
+        assert_eq!(
+            Some(Zone::Fixed(FixedOffset::east(5 * 3600 + 1800))),
+            settings.time_zone
+        );
+        assert!(serde_yaml::to_string(&settings).unwrap().contains("+05:30"));
+
+        let settings: Settings = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(None, settings.time_zone);
+        assert!(serde_yaml::from_str::<Settings>("time_zone: Tokyo").is_err());
+    }
+
+    #[test]
+    fn test_settings_midnight() {
+        let settings = Settings {
+            time_zone: Some(Tz::America__New_York.into()),
+        };
+        let winter = settings.midnight(NaiveDate::from_ymd(2020, 1, 1)).unwrap();
+        assert_eq!("2020-01-01T00:00:00-05:00", winter.to_rfc3339());
+        let summer = settings.midnight(NaiveDate::from_ymd(2020, 7, 1)).unwrap();
+        assert_eq!("2020-07-01T00:00:00-04:00", summer.to_rfc3339());
+
+        let settings = Settings {
+            time_zone: Some(FixedOffset::east(5 * 3600 + 1800).into()),
+        };
+        let midnight = settings.midnight(NaiveDate::from_ymd(2020, 7, 1)).unwrap();
+        assert_eq!("2020-07-01T00:00:00+05:30", midnight.to_rfc3339());
+    }
+}
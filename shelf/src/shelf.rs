@@ -14,14 +14,24 @@
 
 use std::collections::{HashMap, HashSet};
 
+use crate::activity::Event;
 use crate::common::{Blob, Person};
+use crate::external::{ExternalIndex, Provider};
+use crate::goal::Goal;
 use crate::item::Item;
+use crate::list::List;
+use crate::schema::Schema;
 use crate::series::Series;
+use crate::settings::Settings;
 
 #[derive(Debug)]
 pub enum ShelfError {
     InvalidReference(String),
     InvalidKey(String),
+    /// A custom field failed validation (field name, reason).
+    InvalidField(String, String),
+    /// An external ID is already used by another entity.
+    DuplicateExternalId(String),
 }
 
 impl std::fmt::Display for ShelfError {
@@ -34,6 +44,16 @@ impl std::error::Error for ShelfError {}
 
 pub type Result<T> = ::std::result::Result<T, ShelfError>;
 
+/// The kinds of entity that are stored individually.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntityType {
+    Person,
+    Series,
+    Item,
+    Goal,
+    List,
+}
+
 #[derive(Default)]
 pub struct Shelf {
     people: HashMap<String, Person>,
@@ -41,6 +61,17 @@ pub struct Shelf {
     series: HashMap<String, Series>,
     dirty: HashSet<String>,
     blobs: HashMap<String, Blob>,
+    goals: HashMap<String, Goal>,
+    lists: HashMap<String, List>,
+    /// Entities removed since the last save.
+    removed: HashSet<(EntityType, String)>,
+    schema: Schema,
+    schema_dirty: bool,
+    settings: Settings,
+    settings_dirty: bool,
+    item_ids: ExternalIndex,
+    person_ids: ExternalIndex,
+    series_ids: ExternalIndex,
 }
 
 pub struct ItemRef<'a>(pub &'a Shelf, pub &'a Item);
@@ -79,6 +110,10 @@ impl Shelf {
     /// Returns true if the person did not previously exist.
     pub fn insert_person(&mut self, person: Person) -> bool {
         self.dirty.insert(person.key.clone());
+        if let Some(old) = self.people.get(&person.key) {
+            self.person_ids.remove(&old.key, &old.external_ids);
+        }
+        self.person_ids.insert(&person.key, &person.external_ids);
         self.people.insert(person.key.clone(), person).is_none()
     }
 
@@ -88,6 +123,10 @@ impl Shelf {
     pub fn insert_series(&mut self, series: Series) -> bool {
         // TODO: validate people
         self.dirty.insert(series.key.clone());
+        if let Some(old) = self.series.get(&series.key) {
+            self.series_ids.remove(&old.key, &old.external_ids);
+        }
+        self.series_ids.insert(&series.key, &series.external_ids);
         self.series.insert(series.key.clone(), series).is_none()
     }
 
@@ -99,7 +138,152 @@ impl Shelf {
         Ok(self.blobs.insert(blob.key.clone(), blob).is_none())
     }
 
+    pub fn query_goals(&self) -> impl Iterator<Item = &Goal> {
+        self.goals.values()
+    }
+
+    pub fn get_goal(&self, key: &str) -> Option<&Goal> {
+        self.goals.get(key)
+    }
+
+    /// Insert or update a goal.
+    ///
+    /// Returns true if the goal did not previously exist.
+    pub fn insert_goal(&mut self, goal: Goal) -> Result<bool> {
+        if !goal.key.starts_with("goal-") {
+            return Err(ShelfError::InvalidKey(goal.key.clone()));
+        }
+        goal.validate()
+            .map_err(|e| ShelfError::InvalidField(e.field, e.reason))?;
+        self.dirty.insert(goal.key.clone());
+        Ok(self.goals.insert(goal.key.clone(), goal).is_none())
+    }
+
+    pub fn query_lists(&self) -> impl Iterator<Item = &List> {
+        self.lists.values()
+    }
+
+    pub fn get_list(&self, key: &str) -> Option<&List> {
+        self.lists.get(key)
+    }
+
+    /// Insert or update a list.
+    ///
+    /// Returns true if the list did not previously exist.
+    pub fn insert_list(&mut self, list: List) -> Result<bool> {
+        if !list.key.starts_with("list-") {
+            return Err(ShelfError::InvalidKey(list.key.clone()));
+        }
+        list.validate()
+            .map_err(|e| ShelfError::InvalidField(e.field, e.reason))?;
+        for entry in list.items.iter() {
+            if self.get_item(&entry.item).is_none() {
+                return Err(ShelfError::InvalidReference(entry.item.clone()));
+            }
+        }
+        self.dirty.insert(list.key.clone());
+        Ok(self.lists.insert(list.key.clone(), list).is_none())
+    }
+
+    pub fn remove_list(&mut self, key: &str) -> Option<List> {
+        let list = self.lists.remove(key)?;
+        self.dirty.remove(key);
+        self.removed.insert((EntityType::List, key.to_owned()));
+        Some(list)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+        self.settings_dirty = true;
+    }
+
+    pub fn is_settings_dirty(&self) -> bool {
+        self.settings_dirty
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Replace the custom field schema.
+    ///
+    /// Fails if any existing item does not conform to the new schema.
+    pub fn set_schema(&mut self, schema: Schema) -> Result<()> {
+        for item in self.items.iter() {
+            schema
+                .validate_item(item)
+                .map_err(|e| ShelfError::InvalidField(e.field, e.reason))?;
+        }
+        self.schema = schema;
+        self.schema_dirty = true;
+        Ok(())
+    }
+
+    /// Rename a custom field in the schema and in every item and entry.
+    ///
+    /// Returns the number of items that were modified.
+    pub fn rename_field(&mut self, from: &str, to: &str) -> Result<usize> {
+        let has = |extra: &serde_yaml::Value, name: &str| extra.get(name).is_some();
+        let in_items = |name: &str| {
+            self.items.iter().any(|item| {
+                has(&item.extra, name) || item.entries.iter().any(|entry| has(&entry.extra, name))
+            })
+        };
+        if self.schema.get(from).is_none() && !in_items(from) {
+            return Err(ShelfError::InvalidField(
+                from.to_owned(),
+                "does not exist".to_owned(),
+            ));
+        }
+        if self.schema.get(to).is_some() || in_items(to) {
+            return Err(ShelfError::InvalidField(
+                to.to_owned(),
+                "already exists".to_owned(),
+            ));
+        }
+        if self.schema.rename(from, to) {
+            self.schema_dirty = true;
+        }
+
+        let from = serde_yaml::Value::String(from.to_owned());
+        let to = serde_yaml::Value::String(to.to_owned());
+        let rename = |extra: &mut serde_yaml::Value| -> bool {
+            if let serde_yaml::Value::Mapping(mapping) = extra {
+                if let Some(value) = mapping.remove(&from) {
+                    mapping.insert(to.clone(), value);
+                    return true;
+                }
+            }
+            false
+        };
+
+        let mut updated = 0;
+        for item in self.items.iter_mut() {
+            let mut changed = rename(&mut item.extra);
+            for entry in item.entries.iter_mut() {
+                changed = rename(&mut entry.extra) || changed;
+            }
+            if changed {
+                self.dirty.insert(item.key.clone());
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
+    pub fn is_schema_dirty(&self) -> bool {
+        self.schema_dirty
+    }
+
     pub fn validate_item(&self, item: &Item) -> Result<()> {
+        self.check_item(item, true)
+    }
+
+    fn check_item(&self, item: &Item, check_schema: bool) -> Result<()> {
         for (_, person) in item.people.iter() {
             if !self.people.contains_key(person) {
                 return Err(ShelfError::InvalidReference(person.to_owned()));
@@ -118,12 +302,64 @@ impl Shelf {
             }
         }
 
+        if check_schema {
+            self.schema
+                .validate_item(item)
+                .map_err(|e| ShelfError::InvalidField(e.field, e.reason))?;
+        }
+
+        let ratings = item
+            .rating
+            .iter()
+            .chain(item.entries.iter().filter_map(|e| e.rating.as_ref()));
+        for rating in ratings {
+            rating
+                .validate()
+                .map_err(|e| ShelfError::InvalidField("rating".to_owned(), e))?;
+        }
+
+        if item
+            .diary
+            .iter()
+            .any(|note| matches!(note.event, Event::Entry(_)))
+        {
+            return Err(ShelfError::InvalidField(
+                "diary".to_owned(),
+                "notes about entries belong on the entry".to_owned(),
+            ));
+        }
+
+        if let Some((provider, id)) = self.item_ids.find_conflict(&item.key, &item.external_ids) {
+            return Err(ShelfError::DuplicateExternalId(format!(
+                "{:?}:{}",
+                provider, id
+            )));
+        }
+
         Ok(())
     }
 
     pub fn insert_item(&mut self, item: Item) -> Result<()> {
         self.validate_item(&item)?;
         self.dirty.insert(item.key.clone());
+        self.item_ids.insert(&item.key, &item.external_ids);
+        self.items.push(item);
+        Ok(())
+    }
+
+    /// Insert an item read from disk. Libraries without their own
+    /// schema were saved before the built-in one was enforced, so with
+    /// the default schema, fields that don't conform are only logged.
+    pub(crate) fn load_item(&mut self, item: Item) -> Result<()> {
+        let check_schema = self.schema != Schema::default();
+        if !check_schema {
+            if let Err(e) = self.schema.validate_item(&item) {
+                log::warn!("{}: field {}: {}", item.key, e.field, e.reason);
+            }
+        }
+        self.check_item(&item, check_schema)?;
+        self.dirty.insert(item.key.clone());
+        self.item_ids.insert(&item.key, &item.external_ids);
         self.items.push(item);
         Ok(())
     }
@@ -138,13 +374,142 @@ impl Shelf {
             .map(|(idx, _)| idx);
         if let Some(idx) = idx {
             self.dirty.insert(item.key.clone());
-            self.items[idx] = item;
+            let old = std::mem::replace(&mut self.items[idx], item);
+            self.item_ids.remove(&old.key, &old.external_ids);
+            let item = &self.items[idx];
+            self.item_ids.insert(&item.key, &item.external_ids);
         } else {
             return self.insert_item(item);
         }
         Ok(())
     }
 
+    pub fn get_item(&self, key: &str) -> Option<&Item> {
+        self.items.iter().find(|item| item.key == key)
+    }
+
+    pub fn get_person(&self, key: &str) -> Option<&Person> {
+        self.people.get(key)
+    }
+
+    pub fn get_series(&self, key: &str) -> Option<&Series> {
+        self.series.get(key)
+    }
+
+    /// Remove an item, and remove it from any lists.
+    pub fn remove_item(&mut self, key: &str) -> Result<Item> {
+        let idx = self
+            .items
+            .iter()
+            .position(|item| item.key == key)
+            .ok_or_else(|| ShelfError::InvalidReference(key.to_owned()))?;
+        let item = self.items.remove(idx);
+        self.item_ids.remove(&item.key, &item.external_ids);
+        for list in self.lists.values_mut() {
+            if list.remove(key).is_some() {
+                self.dirty.insert(list.key.clone());
+            }
+        }
+        self.dirty.remove(key);
+        self.removed.insert((EntityType::Item, key.to_owned()));
+        Ok(item)
+    }
+
+    /// Change the key of an item, updating any lists that contain it.
+    ///
+    /// The new key must start with the item's kind, like "manga-", and
+    /// must be usable as a file name.
+    pub fn rename_item(&mut self, from: &str, to: &str) -> Result<()> {
+        let kind = self
+            .get_item(from)
+            .ok_or_else(|| ShelfError::InvalidReference(from.to_owned()))?
+            .kind;
+        let prefix = format!("{:?}-", kind).to_lowercase();
+        let valid = to.len() > prefix.len()
+            && to.starts_with(&prefix)
+            && !to.contains(&['/', '\\'][..])
+            && !to.contains("..");
+        if !valid || self.get_item(to).is_some() {
+            return Err(ShelfError::InvalidKey(to.to_owned()));
+        }
+        let item = self
+            .items
+            .iter_mut()
+            .find(|item| item.key == from)
+            .ok_or_else(|| ShelfError::InvalidReference(from.to_owned()))?;
+        self.item_ids.remove(&item.key, &item.external_ids);
+        item.key = to.to_owned();
+        self.item_ids.insert(&item.key, &item.external_ids);
+        for list in self.lists.values_mut() {
+            if list.rename_item(from, to) {
+                self.dirty.insert(list.key.clone());
+            }
+        }
+        self.dirty.remove(from);
+        self.dirty.insert(to.to_owned());
+        self.removed.insert((EntityType::Item, from.to_owned()));
+        Ok(())
+    }
+
+    pub fn find_item_by_external_id(&self, provider: Provider, id: &str) -> Option<&Item> {
+        let key = self.item_ids.get(provider, id)?;
+        self.get_item(key)
+    }
+
+    /// Find an item by a URL to one of its external IDs.
+    pub fn find_item_by_url(&self, url: &str) -> Option<&Item> {
+        let (provider, id) = Provider::parse_url(url)?;
+        self.find_item_by_external_id(provider, &id)
+    }
+
+    pub fn find_person_by_external_id(&self, provider: Provider, id: &str) -> Option<&Person> {
+        self.person_ids
+            .get(provider, id)
+            .and_then(|key| self.people.get(key))
+    }
+
+    pub fn find_series_by_external_id(&self, provider: Provider, id: &str) -> Option<&Series> {
+        self.series_ids
+            .get(provider, id)
+            .and_then(|key| self.series.get(key))
+    }
+
+    /// Populate `Item::external_ids` from the legacy `extra` keys.
+    ///
+    /// IDs that are already claimed by another item are skipped.
+    /// Returns the number of items that were modified.
+    pub fn migrate_external_ids(&mut self) -> usize {
+        let mut updated = 0;
+        for item in self.items.iter_mut() {
+            let mut changed = false;
+            for (provider, id) in crate::external::ids_from_extra(item.kind, &item.extra) {
+                if item.external_ids.contains_key(&provider) {
+                    continue;
+                }
+                if self.item_ids.get(provider, &id).is_some() {
+                    log::warn!(
+                        "Not migrating {:?}:{} for {}: already in use",
+                        provider,
+                        id,
+                        item.key
+                    );
+                    continue;
+                }
+                self.item_ids.insert(
+                    &item.key,
+                    &std::iter::once((provider, id.clone())).collect(),
+                );
+                item.external_ids.insert(provider, id);
+                changed = true;
+            }
+            if changed {
+                self.dirty.insert(item.key.clone());
+                updated += 1;
+            }
+        }
+        updated
+    }
+
     pub fn is_dirty(&self, key: &str) -> bool {
         self.dirty.contains(key)
     }
@@ -153,14 +518,22 @@ impl Shelf {
         self.dirty.remove(key);
     }
 
+    /// Entities removed since the last save.
+    pub fn query_removed(&self) -> impl Iterator<Item = (EntityType, &str)> {
+        self.removed.iter().map(|(ty, key)| (*ty, key.as_str()))
+    }
+
     pub fn clear_all_dirty(&mut self) {
-        self.dirty.clear()
+        self.dirty.clear();
+        self.removed.clear();
+        self.schema_dirty = false;
+        self.settings_dirty = false;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Shelf;
+    use super::{EntityType, Shelf, ShelfError};
     use crate::common::{Alternatives, Blob, Person};
     use crate::item::{Cover, Item};
     use crate::series::Series;
@@ -171,6 +544,7 @@ mod tests {
         let person = Person {
             key: "person-makoto-shinkai".into(),
             name: Alternatives::new("English", "Makoto Shinkai"),
+            external_ids: Default::default(),
         };
 
         assert_eq!(0, shelf.query_people().count());
@@ -188,6 +562,7 @@ mod tests {
         let person_updated = Person {
             key: "person-makoto-shinkai".into(),
             name: Alternatives::new("English", "The Best Director"),
+            external_ids: Default::default(),
         };
         assert!(!shelf.insert_person(person_updated.clone()));
         assert_eq!(1, shelf.query_people().count());
@@ -197,6 +572,7 @@ mod tests {
         let someone_else = Person {
             key: "person-mizu-sahara".into(),
             name: Alternatives::new("English", "The Best Mangaka"),
+            external_ids: Default::default(),
         };
         assert!(shelf.insert_person(someone_else.clone()));
         assert_eq!(2, shelf.query_people().count());
@@ -209,6 +585,7 @@ mod tests {
             key: "series-the-best".into(),
             name: Alternatives::new("Japanese (Romaji)", "Kara no Kyoukai"),
             people: Vec::new(),
+            external_ids: Default::default(),
         };
 
         assert_eq!(0, shelf.query_series().count());
@@ -227,6 +604,7 @@ mod tests {
             key: "series-the-best".into(),
             name: Alternatives::new("English", "Garden of Sinners"),
             people: Vec::new(),
+            external_ids: Default::default(),
         };
         assert!(!shelf.insert_series(series_updated.clone()));
         assert_eq!(1, shelf.query_series().count());
@@ -237,6 +615,7 @@ mod tests {
             key: "series-the-poppy-war".into(),
             name: Alternatives::new("English", "The Poppy War"),
             people: Vec::new(),
+            external_ids: Default::default(),
         };
         assert!(shelf.insert_series(someone_else.clone()));
         assert_eq!(2, shelf.query_series().count());
@@ -261,4 +640,209 @@ mod tests {
             .unwrap());
         assert!(shelf.insert_item(item).is_ok());
     }
+
+    #[test]
+    fn test_shelf_rename_field() {
+        let mut shelf = Shelf::new();
+        shelf
+            .insert_item(Item {
+                key: "manga-foo".into(),
+                extra: serde_yaml::from_str("{mangadex_url: 'https://mangadex.org/title/1'}")
+                    .unwrap(),
+                ..Default::default()
+            })
+            .unwrap();
+        shelf
+            .insert_item(Item {
+                key: "manga-bar".into(),
+                ..Default::default()
+            })
+            .unwrap();
+        shelf.clear_all_dirty();
+
+        assert_eq!(1, shelf.rename_field("mangadex_url", "md_url").unwrap());
+        assert!(shelf.is_dirty("manga-foo"));
+        assert!(!shelf.is_dirty("manga-bar"));
+        assert!(shelf.is_schema_dirty());
+        assert!(shelf.schema().get("md_url").is_some());
+        assert_eq!(
+            Some(&serde_yaml::Value::String(
+                "https://mangadex.org/title/1".into()
+            )),
+            shelf.all_items()[0].extra.get("md_url")
+        );
+
+        assert!(shelf.rename_field("md_url", "external_url").is_err());
+        assert!(shelf.rename_field("no_such_field", "other").is_err());
+        // Refuse to overwrite a key that only exists on items
+        shelf
+            .insert_item(Item {
+                key: "manga-baz".into(),
+                extra: serde_yaml::from_str("{md_url: 'https://mangadex.org/title/2', note: a}")
+                    .unwrap(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(shelf.rename_field("md_url", "note").is_err());
+        assert_eq!(
+            Some(&serde_yaml::Value::String("a".into())),
+            shelf.get_item("manga-baz").unwrap().extra.get("note")
+        );
+    }
+
+    #[test]
+    fn test_shelf_external_ids() {
+        use crate::external::Provider;
+
+        let mut shelf = Shelf::new();
+        let mut item = Item {
+            key: "manga-dora-yome".into(),
+            extra: serde_yaml::from_str(
+                "{mangadex_url: 'https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a/dora-yome', mal_id: 12}",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        shelf.insert_item(item.clone()).unwrap();
+        assert!(shelf
+            .find_item_by_url("https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a")
+            .is_none());
+
+        assert_eq!(1, shelf.migrate_external_ids());
+        assert_eq!(0, shelf.migrate_external_ids());
+        let found = shelf
+            .find_item_by_url("https://mangadex.org/title/8280d386-8195-40da-8cbb-e8763ba3a93a")
+            .unwrap();
+        assert_eq!("manga-dora-yome", found.key);
+        assert!(shelf
+            .find_item_by_external_id(Provider::MyAnimeList, "manga/12")
+            .is_some());
+
+        // IDs must be unique across items
+        let duplicate = Item {
+            key: "manga-other".into(),
+            external_ids: vec![(Provider::MyAnimeList, "manga/12".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(shelf.insert_item(duplicate).is_err());
+
+        // Replacing an item updates the index
+        item.external_ids
+            .insert(Provider::MyAnimeList, "manga/13".into());
+        shelf.replace_item(item).unwrap();
+        assert!(shelf
+            .find_item_by_external_id(Provider::MyAnimeList, "manga/12")
+            .is_none());
+        assert!(shelf
+            .find_item_by_external_id(Provider::MyAnimeList, "manga/13")
+            .is_some());
+        assert!(shelf
+            .find_item_by_external_id(Provider::MangaDex, "8280d386-8195-40da-8cbb-e8763ba3a93a")
+            .is_none());
+
+        // An ISBN-10 saved before normalization matches its ISBN-13
+        let book = Item {
+            key: "novel-book".into(),
+            external_ids: vec![(Provider::Isbn, "0060934344".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        shelf.insert_item(book).unwrap();
+        assert!(shelf
+            .find_item_by_external_id(Provider::Isbn, "9780060934347")
+            .is_some());
+        let duplicate = Item {
+            key: "novel-other".into(),
+            external_ids: vec![(Provider::Isbn, "9780060934347".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(shelf.insert_item(duplicate).is_err());
+        let garbled = Item {
+            key: "novel-garbled".into(),
+            external_ids: vec![(Provider::Isbn, "12345678é".to_owned())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let _ = shelf.insert_item(garbled);
+    }
+
+    #[test]
+    fn test_shelf_lists_cascade() {
+        use crate::list::{List, ListEntry};
+
+        let mut shelf = Shelf::new();
+        for key in ["manga-a", "manga-b", "manga-c"].iter() {
+            shelf
+                .insert_item(Item {
+                    key: key.to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let mut list = List::new("list-top", "Top");
+        list.insert(None, ListEntry::new("manga-b"));
+        list.insert(None, ListEntry::new("manga-a"));
+        assert!(shelf.insert_list(list.clone()).unwrap());
+
+        let mut bad = list.clone();
+        bad.insert(None, ListEntry::new("manga-z"));
+        assert!(shelf.insert_list(bad).is_err());
+        let mut bad = list.clone();
+        bad.key = "top".into();
+        assert!(shelf.insert_list(bad).is_err());
+
+        shelf.clear_all_dirty();
+        shelf.rename_item("manga-a", "manga-d").unwrap();
+        assert!(shelf.rename_item("manga-b", "manga-c").is_err());
+        for bad in [
+            "",
+            "manga-",
+            "novel-b",
+            "b",
+            "manga-a/b",
+            "manga-a\\b",
+            "manga-..a",
+        ]
+        .iter()
+        {
+            match shelf.rename_item("manga-b", bad) {
+                Err(ShelfError::InvalidKey(key)) => assert_eq!(*bad, key),
+                other => panic!("Expected {} to be invalid, got {:?}", bad, other),
+            }
+        }
+        assert!(shelf.is_dirty("list-top"));
+        assert!(shelf.is_dirty("manga-d"));
+        assert!(shelf
+            .query_removed()
+            .any(|removed| removed == (EntityType::Item, "manga-a")));
+        assert_eq!(
+            1,
+            shelf
+                .get_list("list-top")
+                .unwrap()
+                .position("manga-d")
+                .unwrap()
+        );
+
+        shelf.clear_all_dirty();
+        shelf.remove_item("manga-b").unwrap();
+        assert!(shelf.remove_item("manga-b").is_err());
+        assert!(shelf.is_dirty("list-top"));
+        assert_eq!(
+            vec![ListEntry::new("manga-d")],
+            shelf.get_list("list-top").unwrap().items
+        );
+        assert_eq!(2, shelf.all_items().len());
+
+        assert!(shelf.remove_list("list-top").is_some());
+        assert!(shelf.get_list("list-top").is_none());
+        assert!(!shelf.is_dirty("list-top"));
+    }
 }
@@ -0,0 +1,312 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! Aggregate statistics over a shelf.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+
+use crate::common::{DateBool, Kind, Status};
+use crate::duration::{consumed, TimeStats};
+use crate::item::Item;
+use crate::settings::Zone;
+use crate::shelf::Shelf;
+
+/// A run of consecutive days with at least one completion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// The year these statistics are restricted to, if any.
+    pub year: Option<i32>,
+    /// The number of items counted.
+    pub items: usize,
+    pub by_status: BTreeMap<Status, usize>,
+    pub by_kind: BTreeMap<Kind, usize>,
+    pub by_tag: BTreeMap<String, usize>,
+    /// Keyed by person key.
+    pub by_person: BTreeMap<String, usize>,
+    /// Items completed, keyed by month ("YYYY-MM"). Items completed
+    /// without a known month are not included.
+    pub items_completed_by_month: BTreeMap<String, usize>,
+    /// Entries completed, keyed by month ("YYYY-MM").
+    pub entries_completed_by_month: BTreeMap<String, usize>,
+    /// The average rating of rated items, on a 10-point scale.
+    pub average_rating: Option<f64>,
+    /// The longest streak of days with a completion. Only completions
+    /// with a day-precision date count.
+    pub longest_streak: Option<Streak>,
+    pub time: TimeStats,
+}
+
+fn month_key(date: &DateBool) -> Option<String> {
+    date.year_month()
+        .map(|(year, month)| format!("{:04}-{:02}", year, month))
+}
+
+fn in_year(date: &DateBool, year: Option<i32>) -> bool {
+    match year {
+        Some(year) => date.year() == Some(year),
+        None => true,
+    }
+}
+
+/// Whether an item had any activity in the given year.
+fn active_in(item: &Item, year: i32, tz: Option<Zone>) -> bool {
+    let year = Some(year);
+    in_year(&item.started.with_time_zone(tz), year)
+        || in_year(&item.completed.with_time_zone(tz), year)
+        || item
+            .entries
+            .iter()
+            .any(|entry| in_year(&entry.completed.with_time_zone(tz), year))
+}
+
+/// Find the longest run of consecutive days.
+fn longest_streak(days: &BTreeSet<NaiveDate>) -> Option<Streak> {
+    let mut best: Option<Streak> = None;
+    let mut current: Option<Streak> = None;
+    for day in days.iter() {
+        current = match current {
+            Some(streak) if streak.end.succ_opt() == Some(*day) => Some(Streak {
+                start: streak.start,
+                end: *day,
+                days: streak.days + 1,
+            }),
+            _ => Some(Streak {
+                start: *day,
+                end: *day,
+                days: 1,
+            }),
+        };
+        if best.map(|b| b.days).unwrap_or(0) < current.map(|c| c.days).unwrap_or(0) {
+            best = current;
+        }
+    }
+    best
+}
+
+/// Compute statistics for a set of items.
+///
+/// If a year is given, only items with activity (started, completed,
+/// or an entry completed) that year are counted, and completions are
+/// restricted to that year.
+///
+/// Timestamps are bucketed by their calendar day in `tz` (or in their
+/// own offset, if `None`).
+pub fn compute<'a, I: IntoIterator<Item = &'a Item>>(
+    items: I,
+    year: Option<i32>,
+    tz: Option<Zone>,
+) -> Stats {
+    let mut stats = Stats {
+        year,
+        ..Default::default()
+    };
+    let mut days = BTreeSet::new();
+    let mut rating_total = 0.0;
+    let mut rated = 0;
+
+    for item in items {
+        if let Some(year) = year {
+            if !active_in(item, year, tz) {
+                continue;
+            }
+        }
+
+        stats.items += 1;
+        *stats.by_status.entry(item.status).or_default() += 1;
+        *stats.by_kind.entry(item.kind).or_default() += 1;
+        for tag in item.tags.iter() {
+            *stats.by_tag.entry(tag.clone()).or_default() += 1;
+        }
+        for (_, person) in item.people.iter() {
+            *stats.by_person.entry(person.clone()).or_default() += 1;
+        }
+        if let Some(rating) = &item.rating {
+            rating_total += rating.fraction() * 10.0;
+            rated += 1;
+        }
+
+        let completed = item.completed.with_time_zone(tz);
+        if in_year(&completed, year) {
+            if let Some(key) = month_key(&completed) {
+                *stats.items_completed_by_month.entry(key).or_default() += 1;
+            }
+            if let Some(day) = completed.date() {
+                days.insert(day);
+            }
+        }
+        for entry in item.entries.iter() {
+            let completed = entry.completed.with_time_zone(tz);
+            if !in_year(&completed, year) {
+                continue;
+            }
+            if let Some(key) = month_key(&completed) {
+                *stats.entries_completed_by_month.entry(key).or_default() += 1;
+            }
+            if let Some(day) = completed.date() {
+                days.insert(day);
+            }
+        }
+
+        for consumption in consumed(item, tz) {
+            if in_year(&consumption.completed, year) {
+                stats.time.add(item, &consumption);
+            }
+        }
+    }
+
+    if rated > 0 {
+        stats.average_rating = Some(rating_total / f64::from(rated));
+    }
+    stats.longest_streak = longest_streak(&days);
+    stats
+}
+
+impl Shelf {
+    pub fn stats(&self, year: Option<i32>) -> Stats {
+        compute(self.all_items(), year, self.settings().time_zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compute;
+    use crate::common::{DateBool, Kind, Status};
+    use crate::item::{Entry, Item};
+    use crate::rating::{Rating, Scale};
+    use chrono::NaiveDate;
+
+    fn entry(completed: DateBool) -> Entry {
+        Entry {
+            name: None,
+            number: None,
+            volume: None,
+            completed,
+            aired: DateBool::False,
+            rating: None,
+            length: None,
+            note: None,
+            extra: serde_yaml::Value::Null,
+        }
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> DateBool {
+        DateBool::Date(NaiveDate::from_ymd(y, m, d))
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                key: "tv".into(),
+                kind: Kind::TV,
+                status: Status::Completed,
+                rating: Some(Rating::new(8, Scale::Ten)),
+                tags: vec!["Yuri".into()],
+                completed: day(2020, 1, 3),
+                entries: vec![
+                    entry(day(2019, 12, 31)),
+                    entry(day(2020, 1, 1)),
+                    entry(day(2020, 1, 2)),
+                    entry(DateBool::YearMonth(2020, 2)),
+                ],
+                ..Default::default()
+            },
+            Item {
+                key: "novel".into(),
+                kind: Kind::Novel,
+                status: Status::Completed,
+                rating: Some(Rating::new(7, Scale::FiveStar)),
+                completed: DateBool::YearMonth(2019, 6),
+                ..Default::default()
+            },
+            Item {
+                key: "manga".into(),
+                kind: Kind::Manga,
+                status: Status::Planned,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_stats_all() {
+        let items = items();
+        let stats = compute(&items, None, None);
+        assert_eq!(3, stats.items);
+        assert_eq!(2, stats.by_status[&Status::Completed]);
+        assert_eq!(1, stats.by_kind[&Kind::Manga]);
+        assert_eq!(1, stats.by_tag["Yuri"]);
+        assert_eq!(1, stats.items_completed_by_month["2019-06"]);
+        assert_eq!(1, stats.items_completed_by_month["2020-01"]);
+        assert_eq!(2, stats.entries_completed_by_month["2020-01"]);
+        assert_eq!(1, stats.entries_completed_by_month["2020-02"]);
+        assert!((stats.average_rating.unwrap() - 7.5).abs() < 1e-9);
+
+        let streak = stats.longest_streak.unwrap();
+        assert_eq!(4, streak.days);
+        assert_eq!(NaiveDate::from_ymd(2019, 12, 31), streak.start);
+        assert_eq!(NaiveDate::from_ymd(2020, 1, 3), streak.end);
+        assert_eq!(5, stats.time.total.completions);
+    }
+
+    #[test]
+    fn test_stats_year() {
+        let items = items();
+        let stats = compute(&items, Some(2020), None);
+        assert_eq!(Some(2020), stats.year);
+        assert_eq!(1, stats.items);
+        assert_eq!(None, stats.by_kind.get(&Kind::Novel));
+        assert_eq!(3, stats.longest_streak.unwrap().days);
+        assert_eq!(3, stats.time.total.completions);
+        assert!(!stats.entries_completed_by_month.contains_key("2019-12"));
+
+        let stats = compute(&items, Some(2019), None);
+        assert_eq!(2, stats.items);
+        assert_eq!(2, stats.time.total.completions);
+    }
+
+    #[test]
+    fn test_stats_time_zone() {
+        // 2019-12-31 23:30 in New York is 2020-01-01 in UTC
+        let completed = DateBool::Timestamp(
+            chrono::DateTime::parse_from_rfc3339("2019-12-31T23:30:00-05:00").unwrap(),
+        );
+        let items = vec![Item {
+            key: "film".into(),
+            kind: Kind::Film,
+            completed,
+            ..Default::default()
+        }];
+
+        let stats = compute(&items, Some(2019), None);
+        assert_eq!(1, stats.items_completed_by_month["2019-12"]);
+        let utc = Some(chrono_tz::Tz::UTC.into());
+        let stats = compute(&items, Some(2019), utc);
+        assert_eq!(0, stats.items);
+        let stats = compute(&items, Some(2020), utc);
+        assert_eq!(1, stats.items_completed_by_month["2020-01"]);
+        assert_eq!(
+            NaiveDate::from_ymd(2020, 1, 1),
+            stats.longest_streak.unwrap().start
+        );
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2020 David Li <li.davidm96@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+//     Unless required by applicable law or agreed to in writing, software
+//     distributed under the License is distributed on an "AS IS" BASIS,
+//     WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//     See the License for the specific language governing permissions and
+//     limitations under the License.
+
+//! "What should I read next?" suggestions for the backlog.
+//!
+//! Each planned or on-hold item is scored by a handful of signals, each
+//! between 0 and 1, combined with configurable weights.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::common::{DateBool, Status};
+use crate::duration::Length;
+use crate::filter::ItemFilter;
+use crate::item::{Item, Priority};
+use crate::shelf::Shelf;
+
+/// Items rated at least this fraction of their scale count towards
+/// tag/person affinity.
+const LIKED_THRESHOLD: f64 = 0.7;
+
+/// Rough conversions for estimating reading time.
+const MINUTES_PER_PAGE: f64 = 1.5;
+const WORDS_PER_MINUTE: f64 = 250.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Signal {
+    Priority,
+    Series,
+    TagAffinity,
+    PersonAffinity,
+    Length,
+    Age,
+}
+
+/// How much each signal counts. A weight of 0 disables a signal.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Weights {
+    pub priority: f64,
+    pub series: f64,
+    pub tag_affinity: f64,
+    pub person_affinity: f64,
+    /// Favors shorter items.
+    pub length: f64,
+    /// Favors items that have been on the shelf longer.
+    pub age: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights {
+            priority: 3.0,
+            series: 2.0,
+            tag_affinity: 1.0,
+            person_affinity: 1.0,
+            length: 0.5,
+            age: 0.5,
+        }
+    }
+}
+
+impl Weights {
+    fn get(&self, signal: Signal) -> f64 {
+        match signal {
+            Signal::Priority => self.priority,
+            Signal::Series => self.series,
+            Signal::TagAffinity => self.tag_affinity,
+            Signal::PersonAffinity => self.person_affinity,
+            Signal::Length => self.length,
+            Signal::Age => self.age,
+        }
+    }
+}
+
+/// Why an item was ranked where it was.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Reason {
+    pub signal: Signal,
+    /// The weighted contribution to the total score.
+    pub score: f64,
+    pub explanation: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Suggestion<'a> {
+    pub item: &'a Item,
+    pub score: f64,
+    /// Reasons, most significant first.
+    pub reasons: Vec<Reason>,
+}
+
+/// Preferences derived from completed items.
+#[derive(Default)]
+struct Profile<'a> {
+    /// Normalized 0-1 affinity, and the number of liked items.
+    tags: HashMap<&'a str, (f64, usize)>,
+    people: HashMap<&'a str, (f64, usize)>,
+    /// Series key to number of completed items.
+    series: HashMap<&'a str, usize>,
+}
+
+fn normalize(counts: HashMap<&str, (f64, usize)>) -> HashMap<&str, (f64, usize)> {
+    let max = counts.values().map(|(v, _)| *v).fold(0.0, f64::max);
+    counts
+        .into_iter()
+        .map(|(k, (v, n))| (k, (if max > 0.0 { v / max } else { 0.0 }, n)))
+        .collect()
+}
+
+impl<'a> Profile<'a> {
+    fn new(items: &'a [Item]) -> Profile<'a> {
+        let mut profile = Profile::default();
+        let mut tags = HashMap::new();
+        let mut people = HashMap::new();
+        for item in items.iter() {
+            if item.status != Status::Completed {
+                continue;
+            }
+            if let Some((series, _)) = &item.series {
+                *profile.series.entry(series.as_str()).or_default() += 1;
+            }
+            let fraction = match &item.rating {
+                Some(rating) if rating.fraction() >= LIKED_THRESHOLD => rating.fraction(),
+                _ => continue,
+            };
+            for tag in item.tags.iter() {
+                let entry: &mut (f64, usize) = tags.entry(tag.as_str()).or_default();
+                entry.0 += fraction;
+                entry.1 += 1;
+            }
+            for (_, person) in item.people.iter() {
+                let entry: &mut (f64, usize) = people.entry(person.as_str()).or_default();
+                entry.0 += fraction;
+                entry.1 += 1;
+            }
+        }
+        profile.tags = normalize(tags);
+        profile.people = normalize(people);
+        profile
+    }
+}
+
+fn length_minutes(length: &Length) -> Option<f64> {
+    if let Some(minutes) = length.minutes {
+        Some(f64::from(minutes))
+    } else if let Some(pages) = length.pages {
+        Some(f64::from(pages) * MINUTES_PER_PAGE)
+    } else {
+        length
+            .words
+            .map(|words| f64::from(words) / WORDS_PER_MINUTE)
+    }
+}
+
+/// Estimate how long the rest of an item takes, in minutes.
+fn remaining_minutes(item: &Item) -> Option<f64> {
+    if item.entries.is_empty() {
+        return item.length.as_ref().and_then(length_minutes);
+    }
+    let mut total = 0.0;
+    for entry in item.entries.iter() {
+        if entry.completed != DateBool::False {
+            continue;
+        }
+        total += entry
+            .length
+            .or(item.length)
+            .as_ref()
+            .and_then(length_minutes)?;
+    }
+    Some(total)
+}
+
+/// Find the value with the highest affinity.
+fn best_affinity<'a, I: Iterator<Item = &'a str>>(
+    values: I,
+    affinity: &HashMap<&str, (f64, usize)>,
+) -> Option<(&'a str, f64, usize)> {
+    values
+        .filter_map(|v| affinity.get(v).map(|(a, n)| (v, *a, *n)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// Options for ranking suggestions.
+#[derive(Clone, Debug, Default)]
+pub struct SuggestOptions {
+    pub weights: Weights,
+    /// Restrict suggestions further. Only planned and on-hold items
+    /// are ever suggested.
+    pub filter: ItemFilter,
+    /// The date to measure time on the shelf from.
+    pub today: Option<NaiveDate>,
+}
+
+fn score_item<'a>(item: &'a Item, profile: &Profile, options: &SuggestOptions) -> Suggestion<'a> {
+    let mut signals: Vec<(Signal, f64, String)> = Vec::new();
+
+    let priority = match item.priority {
+        Some(Priority::High) => 1.0,
+        Some(Priority::Medium) | None => 0.5,
+        Some(Priority::Low) => 0.0,
+    };
+    if let Some(p) = item.priority {
+        signals.push((Signal::Priority, priority, format!("{:?} priority", p)));
+    } else {
+        signals.push((Signal::Priority, priority, "No priority set".to_owned()));
+    }
+
+    if let Some((series, _)) = &item.series {
+        if let Some(count) = profile.series.get(series.as_str()) {
+            signals.push((
+                Signal::Series,
+                1.0,
+                format!("Continues a series you've completed {} of", count),
+            ));
+        }
+    }
+
+    let tags = item.tags.iter().map(|t| t.as_str());
+    if let Some((tag, affinity, count)) = best_affinity(tags, &profile.tags) {
+        signals.push((
+            Signal::TagAffinity,
+            affinity,
+            format!("You rated {} items tagged {} highly", count, tag),
+        ));
+    }
+    let people = item.people.iter().map(|(_, p)| p.as_str());
+    if let Some((person, affinity, count)) = best_affinity(people, &profile.people) {
+        signals.push((
+            Signal::PersonAffinity,
+            affinity,
+            format!("You rated {} items by {} highly", count, person),
+        ));
+    }
+
+    if let Some(minutes) = remaining_minutes(item) {
+        // 1 for very short items, 0.5 at 10 hours, tending towards 0
+        let score = 1.0 / (1.0 + minutes / 600.0);
+        signals.push((
+            Signal::Length,
+            score,
+            format!("About {:.1} hours left", minutes / 60.0),
+        ));
+    }
+
+    if let Some(today) = options.today {
+        let days = (today - item.added.naive_local().date()).num_days();
+        if days > 0 {
+            signals.push((
+                Signal::Age,
+                (days as f64 / 365.0).min(1.0),
+                format!("On the shelf for {} days", days),
+            ));
+        }
+    }
+
+    let mut reasons: Vec<Reason> = signals
+        .into_iter()
+        .map(|(signal, value, explanation)| Reason {
+            signal,
+            score: value * options.weights.get(signal),
+            explanation,
+        })
+        .filter(|reason| reason.score > 0.0)
+        .collect();
+    reasons.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Suggestion {
+        item,
+        score: reasons.iter().map(|r| r.score).sum(),
+        reasons,
+    }
+}
+
+/// Rank planned and on-hold items, best first.
+pub fn suggest<'a>(items: &'a [Item], options: &SuggestOptions) -> Vec<Suggestion<'a>> {
+    let profile = Profile::new(items);
+    let mut suggestions: Vec<Suggestion> = items
+        .iter()
+        .filter(|item| item.status == Status::Planned || item.status == Status::OnHold)
+        .filter(|item| options.filter.matches(item))
+        .map(|item| score_item(item, &profile, options))
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.item.key.cmp(&b.item.key))
+    });
+    suggestions
+}
+
+impl Shelf {
+    pub fn suggest(&self, options: &SuggestOptions) -> Vec<Suggestion<'_>> {
+        suggest(self.all_items(), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{suggest, Signal, SuggestOptions, Weights};
+    use crate::common::{Kind, Role, Status};
+    use crate::duration::Length;
+    use crate::item::{Item, Priority};
+    use crate::rating::{Rating, Scale};
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                key: "novel-liked".into(),
+                kind: Kind::Novel,
+                status: Status::Completed,
+                rating: Some(Rating::new(9, Scale::Ten)),
+                tags: vec!["Yuri".into()],
+                people: vec![(Role::Author, "person-foo".into())],
+                series: Some(("series-foo".into(), None)),
+                ..Default::default()
+            },
+            Item {
+                key: "novel-sequel".into(),
+                kind: Kind::Novel,
+                status: Status::Planned,
+                series: Some(("series-foo".into(), None)),
+                ..Default::default()
+            },
+            Item {
+                key: "novel-tagged".into(),
+                kind: Kind::Novel,
+                status: Status::OnHold,
+                tags: vec!["Yuri".into()],
+                length: Some(Length::pages(200)),
+                ..Default::default()
+            },
+            Item {
+                key: "novel-urgent".into(),
+                kind: Kind::Novel,
+                status: Status::Planned,
+                priority: Some(Priority::High),
+                ..Default::default()
+            },
+            Item {
+                key: "manga-dropped".into(),
+                status: Status::Dropped,
+                priority: Some(Priority::High),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_suggest_ranking() {
+        let items = items();
+        let suggestions = suggest(&items, &SuggestOptions::default());
+        let keys: Vec<&str> = suggestions.iter().map(|s| s.item.key.as_str()).collect();
+        assert_eq!(vec!["novel-sequel", "novel-urgent", "novel-tagged"], keys);
+
+        let sequel = &suggestions[0];
+        assert_eq!(Signal::Series, sequel.reasons[0].signal);
+        assert!((sequel.score - 3.5).abs() < 1e-9);
+
+        let tagged = &suggestions[2];
+        assert!(tagged
+            .reasons
+            .iter()
+            .any(|r| r.signal == Signal::TagAffinity && r.explanation.contains("Yuri")));
+        assert!(tagged.reasons.iter().any(|r| r.signal == Signal::Length));
+    }
+
+    #[test]
+    fn test_suggest_weights() {
+        let items = items();
+        let options = SuggestOptions {
+            weights: Weights {
+                priority: 0.0,
+                series: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let suggestions = suggest(&items, &options);
+        assert_eq!("novel-tagged", suggestions[0].item.key);
+        assert!(suggestions
+            .iter()
+            .all(|s| s.reasons.iter().all(|r| r.signal != Signal::Priority)));
+    }
+}